@@ -0,0 +1,238 @@
+/// Code/Data Logger
+///
+/// One flag byte per ROM byte, recording how the game used it. Regions
+/// (PRG then CHR on the NES) are stored back to back, which for the NES is
+/// the FCEUX `.cdl` layout. Logs from several sessions merge by OR-ing the
+/// flags together.
+
+use anyhow::Result;
+
+// Program ROM flags (FCEUX)
+pub const CODE: u8 = 0x01;
+pub const DATA: u8 = 0x02;
+/// Bits 2-3: CPU window the byte was mapped to when last accessed
+pub const BANK_MASK: u8 = 0x0C;
+pub const INDIRECT_CODE: u8 = 0x10;
+pub const INDIRECT_DATA: u8 = 0x20;
+pub const PCM_AUDIO: u8 = 0x40;
+
+// Graphics ROM flags (FCEUX)
+pub const RENDERED: u8 = 0x01;
+pub const READ: u8 = 0x02;
+
+/// How a region's flags are interpreted in coverage reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Program,
+    Graphics,
+}
+
+struct Region {
+    name: &'static str,
+    kind: RegionKind,
+    bank_size: usize,
+    flags: Vec<u8>,
+}
+
+/// Coverage of one bank of a region
+#[derive(Debug, Clone)]
+pub struct BankCoverage {
+    pub region: &'static str,
+    pub bank: usize,
+    pub size: usize,
+    /// Bytes used as code (program) or drawn (graphics)
+    pub primary: usize,
+    /// Bytes read as data (program and graphics)
+    pub data: usize,
+    /// Bytes with any flag set
+    pub touched: usize,
+}
+
+impl BankCoverage {
+    pub fn percent(&self) -> f32 {
+        percent(self.touched, self.size)
+    }
+}
+
+#[derive(Default)]
+pub struct CodeDataLogger {
+    regions: Vec<Region>,
+}
+
+impl CodeDataLogger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a region of `size` bytes; returns the index to pass to `mark`
+    pub fn add_region(&mut self, name: &'static str, kind: RegionKind, size: usize, bank_size: usize) -> usize {
+        self.regions.push(Region {
+            name,
+            kind,
+            bank_size: bank_size.max(1),
+            flags: vec![0; size],
+        });
+        self.regions.len() - 1
+    }
+
+    /// OR `flags` into the byte at `offset`; the bank bits are replaced
+    #[inline]
+    pub fn mark(&mut self, region: usize, offset: usize, flags: u8) {
+        if let Some(byte) = self.regions.get_mut(region).and_then(|r| r.flags.get_mut(offset)) {
+            if flags & BANK_MASK != 0 {
+                *byte &= !BANK_MASK;
+            }
+            *byte |= flags;
+        }
+    }
+
+    pub fn flags(&self, region: usize) -> &[u8] {
+        self.regions.get(region).map_or(&[], |r| &r.flags)
+    }
+
+    /// All regions back to back, ready to write as a `.cdl` file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.regions.iter().flat_map(|r| r.flags.iter().copied()).collect()
+    }
+
+    /// Merge a previously saved log; its size must match this cartridge
+    pub fn merge(&mut self, data: &[u8]) -> Result<()> {
+        let expected: usize = self.regions.iter().map(|r| r.flags.len()).sum();
+        if data.len() != expected {
+            anyhow::bail!("CDL file is {} bytes, expected {} for this ROM", data.len(), expected);
+        }
+
+        let mut offset = 0;
+        for region in &mut self.regions {
+            for (byte, saved) in region.flags.iter_mut().zip(&data[offset..]) {
+                *byte |= saved;
+            }
+            offset += region.flags.len();
+        }
+        Ok(())
+    }
+
+    pub fn coverage(&self) -> Vec<BankCoverage> {
+        let primary_flag = CODE; // == RENDERED
+        let mut banks = Vec::new();
+        for region in &self.regions {
+            for (bank, chunk) in region.flags.chunks(region.bank_size).enumerate() {
+                banks.push(BankCoverage {
+                    region: region.name,
+                    bank,
+                    size: chunk.len(),
+                    primary: chunk.iter().filter(|&&f| f & primary_flag != 0).count(),
+                    data: chunk.iter().filter(|&&f| f & DATA != 0).count(),
+                    touched: chunk.iter().filter(|&&f| f & !BANK_MASK != 0).count(),
+                });
+            }
+        }
+        banks
+    }
+
+    /// Human readable coverage per bank and per region
+    pub fn report(&self) -> String {
+        let mut text = String::new();
+        let banks = self.coverage();
+        for region in &self.regions {
+            let (primary, data) = match region.kind {
+                RegionKind::Program => ("code", "data"),
+                RegionKind::Graphics => ("drawn", "read"),
+            };
+            let region_banks: Vec<_> = banks.iter().filter(|b| b.region == region.name).collect();
+            let touched: usize = region_banks.iter().map(|b| b.touched).sum();
+            text.push_str(&format!("{}: {:.1}% of {} KB\n", region.name,
+                                   percent(touched, region.flags.len()), region.flags.len() / 1024));
+            for bank in region_banks {
+                text.push_str(&format!("  bank {:3}: {:5.1}% ({} {:.1}%, {} {:.1}%)\n",
+                                       bank.bank, bank.percent(),
+                                       primary, percent(bank.primary, bank.size),
+                                       data, percent(bank.data, bank.size)));
+            }
+        }
+        text
+    }
+}
+
+fn percent(count: usize, total: usize) -> f32 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f32 * 100.0 / total as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nes_logger() -> CodeDataLogger {
+        let mut logger = CodeDataLogger::new();
+        logger.add_region("PRG", RegionKind::Program, 0x8000, 0x4000);
+        logger.add_region("CHR", RegionKind::Graphics, 0x2000, 0x2000);
+        logger
+    }
+
+    #[test]
+    fn mark_replaces_bank_bits() {
+        let mut logger = nes_logger();
+        logger.mark(0, 0x10, CODE | 0x04);
+        logger.mark(0, 0x10, DATA | 0x08);
+        logger.mark(0, 0x10, INDIRECT_DATA);
+        assert_eq!(logger.flags(0)[0x10], CODE | DATA | INDIRECT_DATA | 0x08);
+
+        // Out of range offsets and regions are ignored
+        logger.mark(0, 0x8000, CODE);
+        logger.mark(2, 0, CODE);
+        assert!(logger.flags(2).is_empty());
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut logger = nes_logger();
+        logger.mark(0, 0, CODE | 0x0C);
+        logger.mark(0, 0x7FFF, DATA | PCM_AUDIO);
+        logger.mark(1, 0x1FFF, RENDERED | READ);
+
+        let bytes = logger.to_bytes();
+        assert_eq!(bytes.len(), 0xA000);
+        assert_eq!(bytes[0], CODE | 0x0C);
+        assert_eq!(bytes[0x7FFF], DATA | PCM_AUDIO);
+        assert_eq!(bytes[0x9FFF], RENDERED | READ);
+
+        let mut loaded = nes_logger();
+        loaded.merge(&bytes).unwrap();
+        assert_eq!(loaded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn merge_ors_sessions() {
+        let mut first = nes_logger();
+        first.mark(0, 1, CODE);
+        let mut second = nes_logger();
+        second.mark(0, 1, DATA);
+        second.mark(1, 2, RENDERED);
+
+        first.merge(&second.to_bytes()).unwrap();
+        assert_eq!(first.flags(0)[1], CODE | DATA);
+        assert_eq!(first.flags(1)[2], RENDERED);
+        assert!(first.merge(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn coverage_per_bank() {
+        let mut logger = nes_logger();
+        logger.mark(0, 0, CODE);
+        logger.mark(0, 1, DATA);
+        logger.mark(0, 0x4000, 0x04);
+        logger.mark(1, 0, RENDERED);
+
+        let banks = logger.coverage();
+        assert_eq!(banks.len(), 3);
+        assert_eq!((banks[0].primary, banks[0].data, banks[0].touched), (1, 1, 2));
+        // Bank bits alone don't count as coverage
+        assert_eq!(banks[1].touched, 0);
+        assert_eq!((banks[2].region, banks[2].primary), ("CHR", 1));
+        assert!(logger.report().starts_with("PRG:"));
+    }
+}
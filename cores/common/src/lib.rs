@@ -0,0 +1,6 @@
+/// Shared Emulator Core Utilities
+///
+/// Pieces every system core can use, independent of the hardware:
+/// - CDL: Code/Data Logger for ROM coverage and disassembly
+
+pub mod cdl;
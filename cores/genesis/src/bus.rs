@@ -0,0 +1,199 @@
+/// Genesis 68000 Bus
+///
+/// - $000000-$3FFFFF: cartridge ROM
+/// - $A00000-$A0FFFF: Z80 address space (8KB sound RAM, mirrored)
+/// - $A10000-$A1001F: version and I/O port registers
+/// - $A11100/$A11200: Z80 bus request and reset
+/// - $C00000-$C0001F: VDP ports; only register writes to the control port
+///   are decoded so far, everything else reads back as zero
+/// - $E00000-$FFFFFF: 64KB work RAM, mirrored
+///
+/// The bus arbiter doesn't support read-modify-write cycles, so the write
+/// half of TAS never lands; some games rely on it.
+
+use crate::m68k::M68kBus;
+
+const WORK_RAM_SIZE: usize = 0x10000;
+const Z80_RAM_SIZE: usize = 0x2000;
+
+/// $A10001: overseas, NTSC, no expansion unit
+const VERSION: u8 = 0xA0;
+
+/// VDP register 0 bit 4: H-interrupt enable
+const VDP_HINT_ENABLE: u8 = 0x10;
+/// VDP register 1 bit 5: V-interrupt enable
+const VDP_VINT_ENABLE: u8 = 0x20;
+/// VDP register 10: lines between H-interrupts
+const VDP_HINT_COUNTER: usize = 10;
+
+pub struct Bus {
+    rom: Vec<u8>,
+    work_ram: Vec<u8>,
+    z80_ram: Vec<u8>,
+    /// $A11100: the 68000 asked for the Z80 bus
+    z80_bus_request: bool,
+    /// $A11200: Z80 held in reset
+    z80_reset: bool,
+
+    /// VDP mode registers, written through the control port
+    vdp_registers: [u8; 24],
+    /// The control port holds the first word of a two-word command
+    vdp_command_pending: bool,
+    /// Lines left until the next H-interrupt
+    hint_counter: u8,
+
+    /// VDP interrupt requests: vertical (level 6) and horizontal (level 4).
+    /// They stay pending while masked in the VDP and fire once enabled.
+    pub vint_pending: bool,
+    pub hint_pending: bool,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self {
+            rom: Vec::new(),
+            work_ram: vec![0; WORK_RAM_SIZE],
+            z80_ram: vec![0; Z80_RAM_SIZE],
+            z80_bus_request: false,
+            z80_reset: true,
+            vdp_registers: [0; 24],
+            vdp_command_pending: false,
+            hint_counter: 0,
+            vint_pending: false,
+            hint_pending: false,
+        }
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        self.rom = rom.to_vec();
+    }
+
+    pub fn reset(&mut self) {
+        self.work_ram.fill(0);
+        self.z80_ram.fill(0);
+        self.z80_bus_request = false;
+        self.z80_reset = true;
+        self.vdp_registers = [0; 24];
+        self.vdp_command_pending = false;
+        self.hint_counter = 0;
+        self.vint_pending = false;
+        self.hint_pending = false;
+    }
+
+    /// Level on the 68000's IPL pins
+    pub fn interrupt_level(&self) -> u8 {
+        if self.vint_pending && self.vdp_registers[1] & VDP_VINT_ENABLE != 0 {
+            6
+        } else if self.hint_pending && self.vdp_registers[0] & VDP_HINT_ENABLE != 0 {
+            4
+        } else {
+            0
+        }
+    }
+
+    /// Clock the H-interrupt counter at the end of a line. It counts down
+    /// through the active display and raises HINT when it underflows, and
+    /// is reloaded from register 10 at each underflow and during vblank.
+    pub fn clock_hint_counter(&mut self, active_display: bool) {
+        if active_display && self.hint_counter > 0 {
+            self.hint_counter -= 1;
+            return;
+        }
+        if active_display {
+            self.hint_pending = true;
+        }
+        self.hint_counter = self.vdp_registers[VDP_HINT_COUNTER];
+    }
+
+    /// $C00004: `100r rrrr dddd dddd` sets a register; anything else is
+    /// half of a two-word address command, which isn't emulated yet
+    fn write_vdp_control(&mut self, value: u16) {
+        if self.vdp_command_pending {
+            self.vdp_command_pending = false;
+        } else if value & 0xE000 == 0x8000 {
+            let register = ((value >> 8) & 0x1F) as usize;
+            if let Some(slot) = self.vdp_registers.get_mut(register) {
+                *slot = value as u8;
+            }
+        } else {
+            self.vdp_command_pending = true;
+        }
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl M68kBus for Bus {
+    fn read_byte(&mut self, addr: u32) -> u8 {
+        match addr {
+            0x00_0000..=0x3F_FFFF => self.rom.get(addr as usize).copied().unwrap_or(0xFF),
+            0xA0_0000..=0xA0_FFFF => self.z80_ram[addr as usize & (Z80_RAM_SIZE - 1)],
+            0xA1_0000..=0xA1_001F => match addr & 0x1F {
+                0x01 => VERSION,
+                _ => 0,
+            },
+            // Bit 0 clear once the Z80 has let go of its bus
+            0xA1_1100 => (!self.z80_bus_request || self.z80_reset) as u8,
+            // Reading the status register ends a half-written command
+            0xC0_0004..=0xC0_0007 => {
+                self.vdp_command_pending = false;
+                0
+            }
+            0xE0_0000..=0xFF_FFFF => self.work_ram[addr as usize & (WORK_RAM_SIZE - 1)],
+            _ => 0,
+        }
+    }
+
+    fn read_word(&mut self, addr: u32) -> u16 {
+        match addr {
+            // The Z80 side is 8 bits wide; the byte shows on both halves
+            0xA0_0000..=0xA0_FFFF => {
+                let value = self.read_byte(addr) as u16;
+                (value << 8) | value
+            }
+            0xA1_0000..=0xA1_001F => self.read_byte(addr | 1) as u16,
+            0xA1_1100 => (self.read_byte(addr) as u16) << 8,
+            _ => ((self.read_byte(addr) as u16) << 8) | self.read_byte(addr | 1) as u16,
+        }
+    }
+
+    fn write_byte(&mut self, addr: u32, value: u8) {
+        match addr {
+            0xA0_0000..=0xA0_FFFF => self.z80_ram[addr as usize & (Z80_RAM_SIZE - 1)] = value,
+            0xA1_1100 => self.z80_bus_request = value & 0x01 != 0,
+            0xA1_1200 => self.z80_reset = value & 0x01 == 0,
+            // The VDP sees the byte on both halves of the bus
+            0xC0_0004..=0xC0_0007 => self.write_vdp_control(((value as u16) << 8) | value as u16),
+            0xE0_0000..=0xFF_FFFF => self.work_ram[addr as usize & (WORK_RAM_SIZE - 1)] = value,
+            _ => {}
+        }
+    }
+
+    fn write_word(&mut self, addr: u32, value: u16) {
+        match addr {
+            // Only the high byte reaches the Z80 side and its control lines
+            0xA0_0000..=0xA0_FFFF | 0xA1_1100 | 0xA1_1200 => self.write_byte(addr, (value >> 8) as u8),
+            0xC0_0004..=0xC0_0007 => self.write_vdp_control(value),
+            _ => {
+                self.write_byte(addr, (value >> 8) as u8);
+                self.write_byte(addr | 1, value as u8);
+            }
+        }
+    }
+
+    fn acknowledge_interrupt(&mut self, level: u8) {
+        match level {
+            6 => self.vint_pending = false,
+            4 => self.hint_pending = false,
+            _ => {}
+        }
+    }
+
+    fn tas_write_back(&mut self, _addr: u32) -> bool {
+        false
+    }
+}
@@ -5,10 +5,29 @@
 /// - Sound CPU: Zilog Z80 @ 3.58 MHz
 /// - VDP: Video Display Processor
 /// - Audio: Yamaha YM2612 (FM) + SN76489 (PSG)
+///
+/// Only the 68000 and its bus run so far. Of the VDP there are just the
+/// mode registers and its interrupts: VINT at the start of vblank and the
+/// HINT line counter, each gated by its enable bit. The screen is a test
+/// pattern.
+
+pub mod bus;
+pub mod m68k;
 
 use anyhow::Result;
 
+use bus::Bus;
+use m68k::M68000;
+
+/// 68000 cycles per scanline (3420 master cycles / 7)
+const CYCLES_PER_LINE: u64 = 488;
+const LINES_PER_FRAME: u16 = 262;
+/// First vblank line in 224-line mode
+const VBLANK_LINE: u16 = 224;
+
 pub struct Genesis {
+    pub cpu: M68000,
+    pub bus: Bus,
     cpu_cycles: u64,
     framebuffer: Vec<u8>,
 }
@@ -16,24 +35,40 @@ pub struct Genesis {
 impl Genesis {
     pub fn new() -> Self {
         Self {
+            cpu: M68000::new(),
+            bus: Bus::new(),
             cpu_cycles: 0,
             framebuffer: vec![0; 320 * 224 * 4], // 320x224 RGBA
         }
     }
     
-    pub fn load_rom(&mut self, _rom_data: &[u8]) -> Result<()> {
-        log::info!("Genesis ROM loaded");
+    pub fn load_rom(&mut self, rom_data: &[u8]) -> Result<()> {
+        anyhow::ensure!(rom_data.len() >= 8, "Genesis ROM is too small for the reset vectors");
+        self.bus.load_rom(rom_data);
+        log::info!("Genesis ROM loaded ({} KB)", rom_data.len() / 1024);
         self.reset();
         Ok(())
     }
     
     pub fn reset(&mut self) {
         self.cpu_cycles = 0;
+        self.bus.reset();
+        self.cpu.reset(&mut self.bus);
         self.render_test_pattern();
     }
     
     pub fn run_frame(&mut self) {
-        self.cpu_cycles += 127840;
+        for line in 0..LINES_PER_FRAME {
+            if line == VBLANK_LINE {
+                self.bus.vint_pending = true;
+            }
+            let end = self.cpu_cycles + CYCLES_PER_LINE;
+            while self.cpu_cycles < end {
+                self.cpu.set_interrupt_level(self.bus.interrupt_level());
+                self.cpu_cycles += self.cpu.step(&mut self.bus) as u64;
+            }
+            self.bus.clock_hint_counter(line < VBLANK_LINE);
+        }
         self.render_test_pattern();
     }
     
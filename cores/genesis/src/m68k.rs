@@ -0,0 +1,1829 @@
+/// Motorola 68000 CPU Core
+///
+/// 32-bit registers on a 16-bit data bus with a 24-bit address space:
+/// - D0-D7 and A0-A7; A7 is the user or supervisor stack pointer depending
+///   on the S bit, the other one is kept aside until the mode changes
+/// - Two-word prefetch queue (IRD/IRC): the word after the opcode is read
+///   before the instruction runs, so code that overwrites it sees the old
+///   value until the next jump
+/// - Address error (group 0), trace, interrupt, illegal, privilege and
+///   line A/F (group 1), TRAP, TRAPV, CHK and divide by zero (group 2)
+///   exceptions, with the supervisor stack frames of the real chip
+///
+/// Every word access costs 4 cycles; each instruction adds its internal
+/// operations on top, which together give the timings of the 68000 user's
+/// manual. Interrupts are autovectored; `M68kBus` is told when one is taken.
+
+use bitflags::bitflags;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct StatusRegister: u16 {
+        const CARRY          = 0x0001;  // C
+        const OVERFLOW       = 0x0002;  // V
+        const ZERO           = 0x0004;  // Z
+        const NEGATIVE       = 0x0008;  // N
+        const EXTEND         = 0x0010;  // X
+        const INTERRUPT_MASK = 0x0700;  // I2-I0
+        const SUPERVISOR     = 0x2000;  // S
+        const TRACE          = 0x8000;  // T
+    }
+}
+
+/// Memory interface seen by the 68000; addresses are 24-bit and word
+/// accesses are always even
+pub trait M68kBus {
+    fn read_byte(&mut self, addr: u32) -> u8;
+    fn read_word(&mut self, addr: u32) -> u16;
+    fn write_byte(&mut self, addr: u32, value: u8);
+    fn write_word(&mut self, addr: u32, value: u16);
+
+    /// Interrupt acknowledge cycle for `level`; the device clears its request
+    fn acknowledge_interrupt(&mut self, _level: u8) {}
+
+    /// RESET instruction: the reset line of the other chips is pulsed
+    fn reset_devices(&mut self) {}
+
+    /// Whether the write of TAS's read-modify-write cycle reaches memory
+    fn tas_write_back(&mut self, _addr: u32) -> bool {
+        true
+    }
+}
+
+// Exception vectors
+const RESET_SSP_VECTOR: u32 = 0;
+const RESET_PC_VECTOR: u32 = 1;
+const ADDRESS_ERROR_VECTOR: u32 = 3;
+const ILLEGAL_VECTOR: u32 = 4;
+const ZERO_DIVIDE_VECTOR: u32 = 5;
+const CHK_VECTOR: u32 = 6;
+const TRAPV_VECTOR: u32 = 7;
+const PRIVILEGE_VECTOR: u32 = 8;
+const TRACE_VECTOR: u32 = 9;
+const LINE_A_VECTOR: u32 = 10;
+const LINE_F_VECTOR: u32 = 11;
+/// Level n autovector is this plus n
+const AUTOVECTOR_BASE: u32 = 24;
+const TRAP_VECTOR_BASE: u32 = 32;
+
+/// Bits of the status register that exist on the 68000
+const SR_MASK: u16 = 0xA71F;
+
+// Addressing mode categories, one bit per mode as numbered by `mode_index`
+const EA_DATA_REG: u16 = 1 << 0;
+const EA_ADDR_REG: u16 = 1 << 1;
+const EA_POSTINC: u16 = 1 << 3;
+const EA_PREDEC: u16 = 1 << 4;
+const EA_IMMEDIATE: u16 = 1 << 11;
+const EA_ALL: u16 = 0x0FFF;
+const EA_DATA: u16 = EA_ALL & !EA_ADDR_REG;
+const EA_MEMORY: u16 = EA_DATA & !EA_DATA_REG;
+const EA_CONTROL: u16 = EA_MEMORY & !(EA_POSTINC | EA_PREDEC | EA_IMMEDIATE);
+const EA_ALTERABLE: u16 = 0x01FF;
+const EA_DATA_ALTERABLE: u16 = EA_ALTERABLE & EA_DATA;
+const EA_MEMORY_ALTERABLE: u16 = EA_ALTERABLE & EA_MEMORY;
+const EA_CONTROL_ALTERABLE: u16 = EA_ALTERABLE & EA_CONTROL;
+
+/// Mode 0-6, then 7 for abs.W, 8 abs.L, 9 d16(PC), 10 d8(PC,Xn), 11 #imm
+fn mode_index(mode: u16, reg: usize) -> u16 {
+    if mode < 7 { mode } else { 7 + reg as u16 }
+}
+
+fn mode_allowed(mode: u16, reg: usize, allowed: u16) -> bool {
+    let index = mode_index(mode, reg);
+    index < 12 && allowed & (1 << index) != 0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Size {
+    Byte,
+    Word,
+    Long,
+}
+
+impl Size {
+    /// Size field of most instructions (bits 6-7)
+    fn from_bits(bits: u16) -> Option<Self> {
+        match bits & 0x03 {
+            0 => Some(Size::Byte),
+            1 => Some(Size::Word),
+            2 => Some(Size::Long),
+            _ => None,
+        }
+    }
+
+    fn bytes(self) -> u32 {
+        match self {
+            Size::Byte => 1,
+            Size::Word => 2,
+            Size::Long => 4,
+        }
+    }
+
+    fn mask(self) -> u32 {
+        match self {
+            Size::Byte => 0xFF,
+            Size::Word => 0xFFFF,
+            Size::Long => 0xFFFF_FFFF,
+        }
+    }
+
+    fn msb(self) -> u32 {
+        match self {
+            Size::Byte => 0x80,
+            Size::Word => 0x8000,
+            Size::Long => 0x8000_0000,
+        }
+    }
+
+    fn sign_extend(self, value: u32) -> u32 {
+        match self {
+            Size::Byte => value as u8 as i8 as i32 as u32,
+            Size::Word => value as u16 as i16 as i32 as u32,
+            Size::Long => value,
+        }
+    }
+}
+
+/// Resolved effective address
+#[derive(Debug, Clone, Copy)]
+enum Ea {
+    Data(usize),
+    Address(usize),
+    Memory(u32),
+    Immediate(u32),
+}
+
+/// Two-operand ALU instructions of lines 8, 9, B, C and D
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Alu {
+    Or,
+    And,
+    Eor,
+    Add,
+    Sub,
+    Cmp,
+}
+
+/// Odd word or long access; aborts the instruction
+#[derive(Debug, Clone, Copy)]
+struct AddressError {
+    addr: u32,
+    write: bool,
+    program: bool,
+}
+
+type Access<T> = Result<T, AddressError>;
+
+pub struct M68000 {
+    // Registers
+    pub d: [u32; 8],
+    /// A7 is the stack pointer of the current mode
+    pub a: [u32; 8],
+    /// USP in supervisor mode, SSP in user mode
+    pub other_sp: u32,
+    pub sr: StatusRegister,
+    /// Address of the word in IRC; the opcode in IRD is the word before it
+    pc: u32,
+    /// Prefetch queue: opcode being executed and the word after it
+    ird: u16,
+    irc: u16,
+
+    // State
+    pub cycles: u64,
+    /// Set by STOP until an interrupt or reset
+    pub stopped: bool,
+    /// Double fault: an address error while stacking an address error
+    pub halted: bool,
+    /// Level on the IPL pins, 0-7
+    ipl: u8,
+    /// Level 7 is edge triggered and not masked
+    nmi_pending: bool,
+    /// T was set when the instruction started
+    trace_pending: bool,
+
+    step_cycles: u32,
+}
+
+impl M68000 {
+    pub fn new() -> Self {
+        Self {
+            d: [0; 8],
+            a: [0; 8],
+            other_sp: 0,
+            sr: StatusRegister::SUPERVISOR | StatusRegister::INTERRUPT_MASK,
+            pc: 0,
+            ird: 0,
+            irc: 0,
+            cycles: 0,
+            stopped: false,
+            halted: false,
+            ipl: 0,
+            nmi_pending: false,
+            trace_pending: false,
+            step_cycles: 0,
+        }
+    }
+
+    /// Load SSP and PC from the first two vectors and fill the queue
+    pub fn reset<B: M68kBus>(&mut self, bus: &mut B) {
+        self.step_cycles = 0;
+        if !self.supervisor() {
+            std::mem::swap(&mut self.a[7], &mut self.other_sp);
+        }
+        self.sr = StatusRegister::SUPERVISOR | StatusRegister::INTERRUPT_MASK;
+        self.stopped = false;
+        self.halted = false;
+        self.nmi_pending = false;
+
+        self.idle(16);
+        let result = (|| {
+            self.a[7] = self.read(bus, RESET_SSP_VECTOR * 4, Size::Long)?;
+            let pc = self.read(bus, RESET_PC_VECTOR * 4, Size::Long)?;
+            self.jump(bus, pc)
+        })();
+        self.halted = result.is_err();
+        self.cycles += self.step_cycles as u64;
+    }
+
+    /// Address of the next instruction
+    pub fn pc(&self) -> u32 {
+        self.pc.wrapping_sub(2)
+    }
+
+    /// Drive the IPL pins; levels above the mask are taken before the next
+    /// instruction, level 7 on its rising edge
+    pub fn set_interrupt_level(&mut self, level: u8) {
+        let level = level & 0x07;
+        if level == 7 && self.ipl != 7 {
+            self.nmi_pending = true;
+        }
+        self.ipl = level;
+    }
+
+    pub fn supervisor(&self) -> bool {
+        self.sr.contains(StatusRegister::SUPERVISOR)
+    }
+
+    fn interrupt_mask(&self) -> u8 {
+        ((self.sr.bits() >> 8) & 0x07) as u8
+    }
+
+    /// Execute one instruction or take one exception; returns CPU cycles
+    pub fn step<B: M68kBus>(&mut self, bus: &mut B) -> u32 {
+        self.step_cycles = 0;
+
+        let interrupt = (self.ipl == 7 && self.nmi_pending) || self.ipl > self.interrupt_mask();
+        let result = if self.halted {
+            self.idle(4);
+            Ok(())
+        } else if interrupt {
+            self.interrupt(bus)
+        } else if self.stopped {
+            self.idle(4);
+            Ok(())
+        } else {
+            self.trace_pending = self.sr.contains(StatusRegister::TRACE);
+            let opcode = self.ird;
+            self.execute(bus, opcode).and_then(|()| {
+                if self.trace_pending {
+                    self.exception(bus, TRACE_VECTOR, self.pc())
+                } else {
+                    Ok(())
+                }
+            })
+        };
+        if let Err(fault) = result {
+            self.address_error(bus, fault);
+        }
+
+        self.cycles += self.step_cycles as u64;
+        self.step_cycles
+    }
+
+    fn idle(&mut self, cycles: u32) {
+        self.step_cycles += cycles;
+    }
+
+    // Bus access
+
+    fn read<B: M68kBus>(&mut self, bus: &mut B, addr: u32, size: Size) -> Access<u32> {
+        let addr = addr & 0x00FF_FFFF;
+        if size != Size::Byte && addr & 1 != 0 {
+            return Err(AddressError { addr, write: false, program: false });
+        }
+        Ok(match size {
+            Size::Byte => {
+                self.step_cycles += 4;
+                bus.read_byte(addr) as u32
+            }
+            Size::Word => {
+                self.step_cycles += 4;
+                bus.read_word(addr) as u32
+            }
+            Size::Long => {
+                self.step_cycles += 8;
+                let high = bus.read_word(addr) as u32;
+                let low = bus.read_word((addr + 2) & 0x00FF_FFFF) as u32;
+                (high << 16) | low
+            }
+        })
+    }
+
+    fn write<B: M68kBus>(&mut self, bus: &mut B, addr: u32, size: Size, value: u32) -> Access<()> {
+        let addr = addr & 0x00FF_FFFF;
+        if size != Size::Byte && addr & 1 != 0 {
+            return Err(AddressError { addr, write: true, program: false });
+        }
+        match size {
+            Size::Byte => {
+                self.step_cycles += 4;
+                bus.write_byte(addr, value as u8);
+            }
+            Size::Word => {
+                self.step_cycles += 4;
+                bus.write_word(addr, value as u16);
+            }
+            Size::Long => {
+                self.step_cycles += 8;
+                bus.write_word(addr, (value >> 16) as u16);
+                bus.write_word((addr + 2) & 0x00FF_FFFF, value as u16);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_program<B: M68kBus>(&mut self, bus: &mut B, addr: u32) -> Access<u16> {
+        let addr = addr & 0x00FF_FFFF;
+        if addr & 1 != 0 {
+            return Err(AddressError { addr, write: false, program: true });
+        }
+        self.step_cycles += 4;
+        Ok(bus.read_word(addr))
+    }
+
+    /// Take the word in IRC and prefetch the one after it
+    fn next_word<B: M68kBus>(&mut self, bus: &mut B) -> Access<u16> {
+        let word = self.irc;
+        self.pc = self.pc.wrapping_add(2);
+        self.irc = self.read_program(bus, self.pc)?;
+        Ok(word)
+    }
+
+    fn next_long<B: M68kBus>(&mut self, bus: &mut B) -> Access<u32> {
+        let high = self.next_word(bus)? as u32;
+        let low = self.next_word(bus)? as u32;
+        Ok((high << 16) | low)
+    }
+
+    /// Take the word in IRC without refilling it, for jumps that reload the
+    /// queue anyway
+    fn take_word(&mut self) -> u16 {
+        self.pc = self.pc.wrapping_add(2);
+        self.irc
+    }
+
+    /// Move the next opcode into IRD; ends every instruction that doesn't jump
+    fn prefetch<B: M68kBus>(&mut self, bus: &mut B) -> Access<()> {
+        self.ird = self.next_word(bus)?;
+        Ok(())
+    }
+
+    /// Reload both queue words from `target`
+    fn jump<B: M68kBus>(&mut self, bus: &mut B, target: u32) -> Access<()> {
+        self.pc = target;
+        self.irc = self.read_program(bus, target)?;
+        self.prefetch(bus)
+    }
+
+    fn push16<B: M68kBus>(&mut self, bus: &mut B, value: u16) -> Access<()> {
+        self.a[7] = self.a[7].wrapping_sub(2);
+        self.write(bus, self.a[7], Size::Word, value as u32)
+    }
+
+    fn push32<B: M68kBus>(&mut self, bus: &mut B, value: u32) -> Access<()> {
+        self.a[7] = self.a[7].wrapping_sub(4);
+        self.write(bus, self.a[7], Size::Long, value)
+    }
+
+    fn pop16<B: M68kBus>(&mut self, bus: &mut B) -> Access<u16> {
+        let value = self.read(bus, self.a[7], Size::Word)? as u16;
+        self.a[7] = self.a[7].wrapping_add(2);
+        Ok(value)
+    }
+
+    fn pop32<B: M68kBus>(&mut self, bus: &mut B) -> Access<u32> {
+        let value = self.read(bus, self.a[7], Size::Long)?;
+        self.a[7] = self.a[7].wrapping_add(4);
+        Ok(value)
+    }
+
+    // Status register
+
+    /// Write SR, switching stack pointers when S changes
+    fn set_sr(&mut self, value: u16) {
+        let was_supervisor = self.supervisor();
+        self.sr = StatusRegister::from_bits_truncate(value & SR_MASK);
+        if was_supervisor != self.supervisor() {
+            std::mem::swap(&mut self.a[7], &mut self.other_sp);
+        }
+    }
+
+    fn set_ccr(&mut self, value: u8) {
+        self.set_sr((self.sr.bits() & 0xFF00) | value as u16);
+    }
+
+    fn flag(&self, flag: StatusRegister) -> bool {
+        self.sr.contains(flag)
+    }
+
+    fn set_nz(&mut self, value: u32, size: Size) {
+        self.sr.set(StatusRegister::NEGATIVE, value & size.msb() != 0);
+        self.sr.set(StatusRegister::ZERO, value & size.mask() == 0);
+    }
+
+    /// N and Z from the result, V and C cleared
+    fn set_logic_flags(&mut self, value: u32, size: Size) {
+        self.set_nz(value, size);
+        self.sr.remove(StatusRegister::OVERFLOW | StatusRegister::CARRY);
+    }
+
+    /// dst + src; `extend` adds X and only clears Z (ADDX)
+    fn addition(&mut self, src: u32, dst: u32, size: Size, extend: bool) -> u32 {
+        let (mask, msb) = (size.mask(), size.msb());
+        let x = (extend && self.flag(StatusRegister::EXTEND)) as u64;
+        let sum = (src & mask) as u64 + (dst & mask) as u64 + x;
+        let result = sum as u32 & mask;
+        let carry = sum > mask as u64;
+        self.sr.set(StatusRegister::CARRY | StatusRegister::EXTEND, carry);
+        self.sr.set(StatusRegister::OVERFLOW, !(src ^ dst) & (src ^ result) & msb != 0);
+        self.sr.set(StatusRegister::NEGATIVE, result & msb != 0);
+        if !extend || result != 0 {
+            self.sr.set(StatusRegister::ZERO, result == 0);
+        }
+        result
+    }
+
+    /// dst - src; `extend` subtracts X and only clears Z (SUBX, NEGX)
+    fn subtraction(&mut self, src: u32, dst: u32, size: Size, extend: bool) -> u32 {
+        let (mask, msb) = (size.mask(), size.msb());
+        let x = (extend && self.flag(StatusRegister::EXTEND)) as u64;
+        let result = (dst & mask).wrapping_sub(src & mask).wrapping_sub(x as u32) & mask;
+        let borrow = (src & mask) as u64 + x > (dst & mask) as u64;
+        self.sr.set(StatusRegister::CARRY | StatusRegister::EXTEND, borrow);
+        self.sr.set(StatusRegister::OVERFLOW, (src ^ dst) & (dst ^ result) & msb != 0);
+        self.sr.set(StatusRegister::NEGATIVE, result & msb != 0);
+        if !extend || result != 0 {
+            self.sr.set(StatusRegister::ZERO, result == 0);
+        }
+        result
+    }
+
+    /// Flags of dst - src, leaving X alone
+    fn compare(&mut self, src: u32, dst: u32, size: Size) {
+        let extend = self.flag(StatusRegister::EXTEND);
+        self.subtraction(src, dst, size, false);
+        self.sr.set(StatusRegister::EXTEND, extend);
+    }
+
+    fn abcd(&mut self, src: u32, dst: u32) -> u32 {
+        let x = self.flag(StatusRegister::EXTEND) as u32;
+        let mut result = (src & 0x0F) + (dst & 0x0F) + x;
+        // V is undefined; this is what the chip leaves there
+        let mut overflow = !result;
+        if result > 9 {
+            result += 6;
+        }
+        result += (src & 0xF0) + (dst & 0xF0);
+        let carry = result > 0x99;
+        if carry {
+            result -= 0xA0;
+        }
+        overflow &= result;
+        self.set_bcd_flags(result & 0xFF, carry, overflow & 0x80 != 0);
+        result & 0xFF
+    }
+
+    fn sbcd(&mut self, src: u32, dst: u32) -> u32 {
+        let x = self.flag(StatusRegister::EXTEND) as u32;
+        let mut result = (dst & 0x0F).wrapping_sub(src & 0x0F).wrapping_sub(x);
+        let mut overflow = !result;
+        if result > 9 {
+            result = result.wrapping_sub(6);
+        }
+        result = result.wrapping_add(dst & 0xF0).wrapping_sub(src & 0xF0);
+        let carry = result > 0x99;
+        if carry {
+            result = result.wrapping_add(0xA0);
+        }
+        let result = result & 0xFF;
+        overflow &= result;
+        self.set_bcd_flags(result, carry, overflow & 0x80 != 0);
+        result
+    }
+
+    fn set_bcd_flags(&mut self, result: u32, carry: bool, overflow: bool) {
+        self.sr.set(StatusRegister::CARRY | StatusRegister::EXTEND, carry);
+        self.sr.set(StatusRegister::OVERFLOW, overflow);
+        self.sr.set(StatusRegister::NEGATIVE, result & 0x80 != 0);
+        if result != 0 {
+            self.sr.remove(StatusRegister::ZERO);
+        }
+    }
+
+    fn condition(&self, cc: u16) -> bool {
+        let c = self.flag(StatusRegister::CARRY);
+        let v = self.flag(StatusRegister::OVERFLOW);
+        let z = self.flag(StatusRegister::ZERO);
+        let n = self.flag(StatusRegister::NEGATIVE);
+        match cc & 0x0F {
+            0x0 => true,
+            0x1 => false,
+            0x2 => !c && !z, // HI
+            0x3 => c || z,   // LS
+            0x4 => !c,       // CC
+            0x5 => c,        // CS
+            0x6 => !z,       // NE
+            0x7 => z,        // EQ
+            0x8 => !v,       // VC
+            0x9 => v,        // VS
+            0xA => !n,       // PL
+            0xB => n,        // MI
+            0xC => n == v,   // GE
+            0xD => n != v,   // LT
+            0xE => !z && n == v,
+            _ => z || n != v,
+        }
+    }
+
+    // Effective addresses
+
+    fn ea<B: M68kBus>(&mut self, bus: &mut B, mode: u16, reg: usize, size: Size) -> Access<Ea> {
+        self.ea_with(bus, mode, reg, size, true)
+    }
+
+    /// `predec_delay`: -(An) takes 2 extra cycles, except as a MOVE or
+    /// MOVEM destination
+    fn ea_with<B: M68kBus>(&mut self, bus: &mut B, mode: u16, reg: usize, size: Size, predec_delay: bool) -> Access<Ea> {
+        // A7 stays word aligned for byte accesses
+        let step = if reg == 7 && size == Size::Byte { 2 } else { size.bytes() };
+        Ok(match mode {
+            0 => Ea::Data(reg),
+            1 => Ea::Address(reg),
+            2 => Ea::Memory(self.a[reg]),
+            3 => {
+                let addr = self.a[reg];
+                self.a[reg] = addr.wrapping_add(step);
+                Ea::Memory(addr)
+            }
+            4 => {
+                if predec_delay {
+                    self.idle(2);
+                }
+                self.a[reg] = self.a[reg].wrapping_sub(step);
+                Ea::Memory(self.a[reg])
+            }
+            5 => {
+                let offset = self.next_word(bus)? as i16 as u32;
+                Ea::Memory(self.a[reg].wrapping_add(offset))
+            }
+            6 => {
+                let ext = self.next_word(bus)?;
+                self.idle(2);
+                Ea::Memory(self.indexed(self.a[reg], ext))
+            }
+            _ => match reg {
+                0 => Ea::Memory(self.next_word(bus)? as i16 as u32),
+                1 => Ea::Memory(self.next_long(bus)?),
+                2 => {
+                    let base = self.pc;
+                    let offset = self.next_word(bus)? as i16 as u32;
+                    Ea::Memory(base.wrapping_add(offset))
+                }
+                3 => {
+                    let base = self.pc;
+                    let ext = self.next_word(bus)?;
+                    self.idle(2);
+                    Ea::Memory(self.indexed(base, ext))
+                }
+                _ => Ea::Immediate(match size {
+                    Size::Byte => self.next_word(bus)? as u32 & 0xFF,
+                    Size::Word => self.next_word(bus)? as u32,
+                    Size::Long => self.next_long(bus)?,
+                }),
+            },
+        })
+    }
+
+    /// Base plus 8-bit displacement and a word or long index register
+    fn indexed(&self, base: u32, ext: u16) -> u32 {
+        let reg = ((ext >> 12) & 0x07) as usize;
+        let index = if ext & 0x8000 != 0 { self.a[reg] } else { self.d[reg] };
+        let index = if ext & 0x0800 != 0 { index } else { index as i16 as u32 };
+        base.wrapping_add(index).wrapping_add(ext as i8 as u32)
+    }
+
+    /// Address of a control mode for LEA and PEA: indexed modes take 2
+    /// cycles more than for an operand
+    fn control_address<B: M68kBus>(&mut self, bus: &mut B, mode: u16, reg: usize) -> Access<u32> {
+        if mode == 6 || (mode == 7 && reg == 3) {
+            self.idle(2);
+        }
+        match self.ea_with(bus, mode, reg, Size::Long, false)? {
+            Ea::Memory(addr) => Ok(addr),
+            _ => unreachable!("control modes are memory operands"),
+        }
+    }
+
+    /// Target of JMP and JSR; the last extension word is taken straight
+    /// from IRC since the queue is reloaded at the target
+    fn jump_target<B: M68kBus>(&mut self, bus: &mut B, mode: u16, reg: usize) -> Access<u32> {
+        Ok(match (mode, reg) {
+            (2, _) => self.a[reg],
+            (5, _) => {
+                self.idle(2);
+                self.a[reg].wrapping_add(self.take_word() as i16 as u32)
+            }
+            (6, _) => {
+                self.idle(6);
+                let ext = self.take_word();
+                self.indexed(self.a[reg], ext)
+            }
+            (7, 0) => {
+                self.idle(2);
+                self.take_word() as i16 as u32
+            }
+            (7, 1) => {
+                let high = self.next_word(bus)? as u32;
+                (high << 16) | self.take_word() as u32
+            }
+            (7, 2) => {
+                self.idle(2);
+                let base = self.pc;
+                base.wrapping_add(self.take_word() as i16 as u32)
+            }
+            _ => {
+                self.idle(6);
+                let base = self.pc;
+                let ext = self.take_word();
+                self.indexed(base, ext)
+            }
+        })
+    }
+
+    fn read_ea<B: M68kBus>(&mut self, bus: &mut B, ea: Ea, size: Size) -> Access<u32> {
+        match ea {
+            Ea::Data(reg) => Ok(self.d[reg] & size.mask()),
+            Ea::Address(reg) => Ok(self.a[reg] & size.mask()),
+            Ea::Memory(addr) => self.read(bus, addr, size),
+            Ea::Immediate(value) => Ok(value),
+        }
+    }
+
+    fn write_ea<B: M68kBus>(&mut self, bus: &mut B, ea: Ea, size: Size, value: u32) -> Access<()> {
+        match ea {
+            Ea::Data(reg) => {
+                self.d[reg] = (self.d[reg] & !size.mask()) | (value & size.mask());
+                Ok(())
+            }
+            // Address registers are always written whole
+            Ea::Address(reg) => {
+                self.a[reg] = size.sign_extend(value);
+                Ok(())
+            }
+            Ea::Memory(addr) => self.write(bus, addr, size, value),
+            Ea::Immediate(_) => Ok(()),
+        }
+    }
+
+    /// Register operands (Dn, An, #imm) make long ALU operations 2 cycles slower
+    fn register_operand(mode: u16, reg: usize) -> bool {
+        mode < 2 || (mode == 7 && reg == 4)
+    }
+
+    // Exceptions
+
+    /// Enter supervisor mode with tracing off; returns the old SR
+    fn enter_supervisor(&mut self) -> u16 {
+        let sr = self.sr.bits();
+        self.set_sr((sr | StatusRegister::SUPERVISOR.bits()) & !StatusRegister::TRACE.bits());
+        sr
+    }
+
+    /// Group 1/2 exception: stack PC and SR, then continue at the vector
+    fn exception<B: M68kBus>(&mut self, bus: &mut B, vector: u32, return_pc: u32) -> Access<()> {
+        let sr = self.enter_supervisor();
+        self.idle(6);
+        self.push32(bus, return_pc)?;
+        self.push16(bus, sr)?;
+        let target = self.read(bus, vector * 4, Size::Long)?;
+        self.jump(bus, target)
+    }
+
+    /// Illegal, privilege and line A/F exceptions point back at the
+    /// instruction and are not traced
+    fn reject<B: M68kBus>(&mut self, bus: &mut B, vector: u32) -> Access<()> {
+        self.trace_pending = false;
+        self.exception(bus, vector, self.pc())
+    }
+
+    fn illegal<B: M68kBus>(&mut self, bus: &mut B) -> Access<()> {
+        self.reject(bus, ILLEGAL_VECTOR)
+    }
+
+    fn privilege_violation<B: M68kBus>(&mut self, bus: &mut B) -> Access<()> {
+        self.reject(bus, PRIVILEGE_VECTOR)
+    }
+
+    fn interrupt<B: M68kBus>(&mut self, bus: &mut B) -> Access<()> {
+        let level = self.ipl;
+        if level == 7 {
+            self.nmi_pending = false;
+        }
+        self.stopped = false;
+        let sr = self.enter_supervisor();
+        self.sr = StatusRegister::from_bits_truncate((self.sr.bits() & !0x0700) | (level as u16) << 8);
+
+        // Acknowledge cycle, answered with an autovector
+        self.idle(10);
+        bus.acknowledge_interrupt(level);
+        self.idle(6);
+
+        self.push32(bus, self.pc())?;
+        self.push16(bus, sr)?;
+        let target = self.read(bus, (AUTOVECTOR_BASE + level as u32) * 4, Size::Long)?;
+        self.jump(bus, target)
+    }
+
+    /// Group 0 frame: PC, SR, the opcode, the faulting address and an access
+    /// word with R/W, I/N and the function code. A second fault halts.
+    fn address_error<B: M68kBus>(&mut self, bus: &mut B, fault: AddressError) {
+        let function_code = match (self.supervisor(), fault.program) {
+            (false, false) => 1,
+            (false, true) => 2,
+            (true, false) => 5,
+            (true, true) => 6,
+        };
+        let access = (self.ird & 0xFFE0) | ((!fault.write as u16) << 4) | function_code;
+        self.stopped = false;
+        let sr = self.enter_supervisor();
+        self.idle(6);
+
+        let result = (|| {
+            self.push32(bus, self.pc)?;
+            self.push16(bus, sr)?;
+            self.push16(bus, self.ird)?;
+            self.push32(bus, fault.addr)?;
+            self.push16(bus, access)?;
+            let target = self.read(bus, ADDRESS_ERROR_VECTOR * 4, Size::Long)?;
+            self.jump(bus, target)
+        })();
+        if result.is_err() {
+            log::warn!("68000 halted: double fault at {:06X}", fault.addr);
+            self.halted = true;
+        }
+    }
+
+    // Decoding
+
+    fn execute<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        match opcode >> 12 {
+            0x0 => self.line_0(bus, opcode),
+            0x1..=0x3 => self.op_move(bus, opcode),
+            0x4 => self.line_4(bus, opcode),
+            0x5 => self.line_5(bus, opcode),
+            0x6 => self.op_branch(bus, opcode),
+            0x7 => {
+                if opcode & 0x0100 != 0 {
+                    return self.illegal(bus);
+                }
+                let value = opcode as i8 as u32;
+                self.d[((opcode >> 9) & 0x07) as usize] = value;
+                self.set_logic_flags(value, Size::Long);
+                self.prefetch(bus)
+            }
+            0x8 => self.line_8(bus, opcode),
+            0x9 => self.line_add_sub(bus, opcode, Alu::Sub),
+            0xA => self.reject(bus, LINE_A_VECTOR),
+            0xB => self.line_b(bus, opcode),
+            0xC => self.line_c(bus, opcode),
+            0xD => self.line_add_sub(bus, opcode, Alu::Add),
+            0xE => self.line_e(bus, opcode),
+            _ => self.reject(bus, LINE_F_VECTOR),
+        }
+    }
+
+    /// Bit operations, MOVEP and the immediate instructions
+    fn line_0<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        if opcode & 0x0138 == 0x0108 {
+            return self.op_movep(bus, opcode);
+        }
+        if opcode & 0x0100 != 0 {
+            let bit = self.d[((opcode >> 9) & 0x07) as usize];
+            return self.op_bit(bus, opcode, Some(bit));
+        }
+        match (opcode >> 9) & 0x07 {
+            0 => self.op_immediate(bus, opcode, Alu::Or),
+            1 => self.op_immediate(bus, opcode, Alu::And),
+            2 => self.op_immediate(bus, opcode, Alu::Sub),
+            3 => self.op_immediate(bus, opcode, Alu::Add),
+            4 => self.op_bit(bus, opcode, None),
+            5 => self.op_immediate(bus, opcode, Alu::Eor),
+            6 => self.op_immediate(bus, opcode, Alu::Cmp),
+            _ => self.illegal(bus),
+        }
+    }
+
+    /// BTST/BCHG/BCLR/BSET with the bit number in a register (`bit`) or
+    /// an immediate word
+    fn op_bit<B: M68kBus>(&mut self, bus: &mut B, opcode: u16, bit: Option<u32>) -> Access<()> {
+        let kind = (opcode >> 6) & 0x03;
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        let allowed = match (kind, bit) {
+            (0, Some(_)) => EA_DATA,
+            (0, None) => EA_DATA & !EA_IMMEDIATE,
+            _ => EA_DATA_ALTERABLE,
+        };
+        if !mode_allowed(mode, reg, allowed) {
+            return self.illegal(bus);
+        }
+        let bit = match bit {
+            Some(bit) => bit,
+            None => self.next_word(bus)? as u32,
+        };
+
+        if mode == 0 {
+            let bit = bit & 31;
+            let value = self.d[reg];
+            self.sr.set(StatusRegister::ZERO, value & (1 << bit) == 0);
+            let high = bit >= 16;
+            let (value, cycles) = match kind {
+                0 => (value, 2),
+                1 => (value ^ (1 << bit), if high { 4 } else { 2 }),
+                2 => (value & !(1 << bit), if high { 6 } else { 4 }),
+                _ => (value | (1 << bit), if high { 4 } else { 2 }),
+            };
+            self.d[reg] = value;
+            self.idle(cycles);
+        } else {
+            let bit = bit & 7;
+            let ea = self.ea(bus, mode, reg, Size::Byte)?;
+            let value = self.read_ea(bus, ea, Size::Byte)?;
+            self.sr.set(StatusRegister::ZERO, value & (1 << bit) == 0);
+            let value = match kind {
+                0 => None,
+                1 => Some(value ^ (1 << bit)),
+                2 => Some(value & !(1 << bit)),
+                _ => Some(value | (1 << bit)),
+            };
+            if let Some(value) = value {
+                self.write_ea(bus, ea, Size::Byte, value)?;
+            }
+        }
+        self.prefetch(bus)
+    }
+
+    /// Bytes of a register to or from every other address (8-bit peripherals)
+    fn op_movep<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let dn = ((opcode >> 9) & 0x07) as usize;
+        let an = (opcode & 0x07) as usize;
+        let offset = self.next_word(bus)? as i16 as u32;
+        let addr = self.a[an].wrapping_add(offset);
+        let count = if opcode & 0x0040 != 0 { 4 } else { 2 };
+
+        if opcode & 0x0080 != 0 {
+            for i in 0..count {
+                let byte = self.d[dn] >> ((count - 1 - i) * 8);
+                self.write(bus, addr.wrapping_add(i * 2), Size::Byte, byte)?;
+            }
+        } else {
+            let mut value = 0;
+            for i in 0..count {
+                value = (value << 8) | self.read(bus, addr.wrapping_add(i * 2), Size::Byte)?;
+            }
+            let size = if count == 4 { Size::Long } else { Size::Word };
+            self.write_ea(bus, Ea::Data(dn), size, value)?;
+        }
+        self.prefetch(bus)
+    }
+
+    /// ORI/ANDI/SUBI/ADDI/EORI/CMPI, and ORI/ANDI/EORI to CCR and SR
+    fn op_immediate<B: M68kBus>(&mut self, bus: &mut B, opcode: u16, op: Alu) -> Access<()> {
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        let Some(size) = Size::from_bits(opcode >> 6) else { return self.illegal(bus) };
+
+        if mode == 7 && reg == 4 && matches!(op, Alu::Or | Alu::And | Alu::Eor) {
+            return self.op_immediate_sr(bus, op, size);
+        }
+        if !mode_allowed(mode, reg, EA_DATA_ALTERABLE) {
+            return self.illegal(bus);
+        }
+
+        let src = match size {
+            Size::Byte => self.next_word(bus)? as u32 & 0xFF,
+            Size::Word => self.next_word(bus)? as u32,
+            Size::Long => self.next_long(bus)?,
+        };
+        let ea = self.ea(bus, mode, reg, size)?;
+        let dst = self.read_ea(bus, ea, size)?;
+        let result = self.alu(op, src, dst, size);
+        if size == Size::Long && mode == 0 {
+            self.idle(if op == Alu::Cmp { 2 } else { 4 });
+        }
+        if op != Alu::Cmp {
+            self.write_ea(bus, ea, size, result)?;
+        }
+        self.prefetch(bus)
+    }
+
+    fn op_immediate_sr<B: M68kBus>(&mut self, bus: &mut B, op: Alu, size: Size) -> Access<()> {
+        let to_sr = match size {
+            Size::Byte => false,
+            Size::Word => true,
+            Size::Long => return self.illegal(bus),
+        };
+        if to_sr && !self.supervisor() {
+            return self.privilege_violation(bus);
+        }
+        let value = self.next_word(bus)?;
+        let current = self.sr.bits();
+        let result = match op {
+            Alu::Or => current | value,
+            Alu::And => current & value,
+            _ => current ^ value,
+        };
+        if to_sr {
+            self.set_sr(result);
+        } else {
+            self.set_ccr(result as u8);
+        }
+        // The queue is refetched after a change to SR
+        self.idle(8);
+        self.irc = self.read_program(bus, self.pc)?;
+        self.prefetch(bus)
+    }
+
+    /// Apply a two-operand ALU instruction, setting flags
+    fn alu(&mut self, op: Alu, src: u32, dst: u32, size: Size) -> u32 {
+        match op {
+            Alu::Or | Alu::And | Alu::Eor => {
+                let result = match op {
+                    Alu::Or => dst | src,
+                    Alu::And => dst & src,
+                    _ => dst ^ src,
+                } & size.mask();
+                self.set_logic_flags(result, size);
+                result
+            }
+            Alu::Add => self.addition(src, dst, size, false),
+            Alu::Sub => self.subtraction(src, dst, size, false),
+            Alu::Cmp => {
+                self.compare(src, dst, size);
+                dst
+            }
+        }
+    }
+
+    fn op_move<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let size = match opcode >> 12 {
+            1 => Size::Byte,
+            3 => Size::Word,
+            _ => Size::Long,
+        };
+        let (src_mode, src_reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        let (dst_mode, dst_reg) = ((opcode >> 6) & 0x07, ((opcode >> 9) & 0x07) as usize);
+        let byte_address = size == Size::Byte && (src_mode == 1 || dst_mode == 1);
+        if byte_address
+            || !mode_allowed(src_mode, src_reg, EA_ALL)
+            || !mode_allowed(dst_mode, dst_reg, EA_ALTERABLE)
+        {
+            return self.illegal(bus);
+        }
+
+        let src = self.ea(bus, src_mode, src_reg, size)?;
+        let value = self.read_ea(bus, src, size)?;
+        if dst_mode == 1 {
+            // MOVEA: sign extended, no flags
+            self.a[dst_reg] = size.sign_extend(value);
+            return self.prefetch(bus);
+        }
+        let dst = self.ea_with(bus, dst_mode, dst_reg, size, false)?;
+        self.set_logic_flags(value, size);
+        self.write_ea(bus, dst, size, value)?;
+        self.prefetch(bus)
+    }
+
+    /// Miscellaneous instructions
+    fn line_4<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        if opcode & 0x01C0 == 0x01C0 {
+            return self.op_lea(bus, opcode);
+        }
+        if opcode & 0x01C0 == 0x0180 {
+            return self.op_chk(bus, opcode);
+        }
+        if opcode & 0x0100 != 0 {
+            return self.illegal(bus);
+        }
+
+        let size_bits = (opcode >> 6) & 0x03;
+        match (opcode >> 8) & 0x0F {
+            0x0 if size_bits == 3 => self.op_move_from_sr(bus, mode, reg),
+            0x4 if size_bits == 3 => self.op_move_to_sr(bus, mode, reg, false),
+            0x6 if size_bits == 3 => self.op_move_to_sr(bus, mode, reg, true),
+            0x0 | 0x2 | 0x4 | 0x6 => self.op_unary(bus, opcode),
+            0x8 => match size_bits {
+                0 => self.op_nbcd(bus, mode, reg),
+                1 if mode == 0 => {
+                    self.d[reg] = self.d[reg].rotate_left(16);
+                    self.set_logic_flags(self.d[reg], Size::Long);
+                    self.prefetch(bus)
+                }
+                1 => self.op_pea(bus, mode, reg),
+                _ if mode == 0 => {
+                    let size = if size_bits == 3 { Size::Long } else { Size::Word };
+                    let value = if size == Size::Long {
+                        self.d[reg] as u16 as i16 as u32
+                    } else {
+                        self.d[reg] as u8 as i8 as u16 as u32
+                    };
+                    self.write_ea(bus, Ea::Data(reg), size, value)?;
+                    self.set_logic_flags(value, size);
+                    self.prefetch(bus)
+                }
+                _ => self.op_movem(bus, opcode, true),
+            },
+            0xA if opcode == 0x4AFC => self.illegal(bus),
+            0xA if size_bits == 3 => self.op_tas(bus, mode, reg),
+            0xA => {
+                let size = Size::from_bits(size_bits).unwrap_or(Size::Long);
+                if !mode_allowed(mode, reg, EA_DATA_ALTERABLE) {
+                    return self.illegal(bus);
+                }
+                let ea = self.ea(bus, mode, reg, size)?;
+                let value = self.read_ea(bus, ea, size)?;
+                self.set_logic_flags(value, size);
+                self.prefetch(bus)
+            }
+            0xC if size_bits >= 2 => self.op_movem(bus, opcode, false),
+            0xE if size_bits == 1 => self.line_4e(bus, opcode),
+            0xE if size_bits >= 2 => {
+                if !mode_allowed(mode, reg, EA_CONTROL) {
+                    return self.illegal(bus);
+                }
+                let target = self.jump_target(bus, mode, reg)?;
+                if size_bits == 2 {
+                    // JSR: return to the word after the operand
+                    self.push32(bus, self.pc)?;
+                }
+                self.jump(bus, target)
+            }
+            _ => self.illegal(bus),
+        }
+    }
+
+    /// TRAP, LINK, UNLK, MOVE USP and the fixed opcodes at $4E70-$4E77
+    fn line_4e<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let reg = (opcode & 0x07) as usize;
+        match opcode & 0x3F {
+            0x00..=0x0F => self.exception(bus, TRAP_VECTOR_BASE + (opcode & 0x0F) as u32, self.pc),
+            0x10..=0x17 => {
+                let offset = self.next_word(bus)? as i16 as u32;
+                self.a[7] = self.a[7].wrapping_sub(4);
+                // LINK A7 stores the decremented stack pointer
+                self.write(bus, self.a[7], Size::Long, self.a[reg])?;
+                self.a[reg] = self.a[7];
+                self.a[7] = self.a[7].wrapping_add(offset);
+                self.prefetch(bus)
+            }
+            0x18..=0x1F => {
+                self.a[7] = self.a[reg];
+                self.a[reg] = self.pop32(bus)?;
+                self.prefetch(bus)
+            }
+            0x20..=0x2F if !self.supervisor() => self.privilege_violation(bus),
+            0x20..=0x27 => {
+                self.other_sp = self.a[reg];
+                self.prefetch(bus)
+            }
+            0x28..=0x2F => {
+                self.a[reg] = self.other_sp;
+                self.prefetch(bus)
+            }
+            0x30 | 0x32 | 0x33 if !self.supervisor() => self.privilege_violation(bus),
+            0x30 => {
+                // RESET
+                bus.reset_devices();
+                self.idle(128);
+                self.prefetch(bus)
+            }
+            0x31 => self.prefetch(bus),
+            0x32 => {
+                // STOP: load SR and wait for an interrupt
+                let value = self.next_word(bus)?;
+                self.set_sr(value);
+                self.stopped = true;
+                self.prefetch(bus)
+            }
+            0x33 => {
+                // RTE
+                let sr = self.pop16(bus)?;
+                let pc = self.pop32(bus)?;
+                self.set_sr(sr);
+                self.jump(bus, pc)
+            }
+            0x35 => {
+                let pc = self.pop32(bus)?;
+                self.jump(bus, pc)
+            }
+            0x36 => {
+                if self.flag(StatusRegister::OVERFLOW) {
+                    self.exception(bus, TRAPV_VECTOR, self.pc)
+                } else {
+                    self.prefetch(bus)
+                }
+            }
+            0x37 => {
+                // RTR
+                let ccr = self.pop16(bus)?;
+                let pc = self.pop32(bus)?;
+                self.set_ccr(ccr as u8);
+                self.jump(bus, pc)
+            }
+            _ => self.illegal(bus),
+        }
+    }
+
+    fn op_lea<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        if !mode_allowed(mode, reg, EA_CONTROL) {
+            return self.illegal(bus);
+        }
+        let addr = self.control_address(bus, mode, reg)?;
+        self.a[((opcode >> 9) & 0x07) as usize] = addr;
+        self.prefetch(bus)
+    }
+
+    fn op_pea<B: M68kBus>(&mut self, bus: &mut B, mode: u16, reg: usize) -> Access<()> {
+        if !mode_allowed(mode, reg, EA_CONTROL) {
+            return self.illegal(bus);
+        }
+        let addr = self.control_address(bus, mode, reg)?;
+        self.push32(bus, addr)?;
+        self.prefetch(bus)
+    }
+
+    /// CHK: trap unless 0 <= Dn.W <= bound
+    fn op_chk<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        if !mode_allowed(mode, reg, EA_DATA) {
+            return self.illegal(bus);
+        }
+        let ea = self.ea(bus, mode, reg, Size::Word)?;
+        let bound = self.read_ea(bus, ea, Size::Word)? as i16;
+        let value = self.d[((opcode >> 9) & 0x07) as usize] as i16;
+        self.idle(6);
+
+        self.sr.set(StatusRegister::ZERO, value == 0);
+        self.sr.remove(StatusRegister::OVERFLOW | StatusRegister::CARRY);
+        if value < 0 || value > bound {
+            self.sr.set(StatusRegister::NEGATIVE, value < 0);
+            return self.exception(bus, CHK_VECTOR, self.pc);
+        }
+        self.prefetch(bus)
+    }
+
+    /// NEGX, CLR, NEG and NOT
+    fn op_unary<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        let Some(size) = Size::from_bits(opcode >> 6) else { return self.illegal(bus) };
+        if !mode_allowed(mode, reg, EA_DATA_ALTERABLE) {
+            return self.illegal(bus);
+        }
+
+        let ea = self.ea(bus, mode, reg, size)?;
+        // CLR reads its operand too on the 68000
+        let value = self.read_ea(bus, ea, size)?;
+        let result = match (opcode >> 9) & 0x03 {
+            0 => self.subtraction(value, 0, size, true),
+            1 => {
+                self.set_logic_flags(0, size);
+                0
+            }
+            2 => self.subtraction(value, 0, size, false),
+            _ => {
+                let result = !value & size.mask();
+                self.set_logic_flags(result, size);
+                result
+            }
+        };
+        if mode == 0 && size == Size::Long {
+            self.idle(2);
+        }
+        self.write_ea(bus, ea, size, result)?;
+        self.prefetch(bus)
+    }
+
+    fn op_nbcd<B: M68kBus>(&mut self, bus: &mut B, mode: u16, reg: usize) -> Access<()> {
+        if !mode_allowed(mode, reg, EA_DATA_ALTERABLE) {
+            return self.illegal(bus);
+        }
+        let ea = self.ea(bus, mode, reg, Size::Byte)?;
+        let value = self.read_ea(bus, ea, Size::Byte)?;
+        let result = self.sbcd(value, 0);
+        if mode == 0 {
+            self.idle(2);
+        }
+        self.write_ea(bus, ea, Size::Byte, result)?;
+        self.prefetch(bus)
+    }
+
+    /// MOVE from SR; not privileged on the 68000
+    fn op_move_from_sr<B: M68kBus>(&mut self, bus: &mut B, mode: u16, reg: usize) -> Access<()> {
+        if !mode_allowed(mode, reg, EA_DATA_ALTERABLE) {
+            return self.illegal(bus);
+        }
+        let ea = self.ea(bus, mode, reg, Size::Word)?;
+        if mode == 0 {
+            self.idle(2);
+        } else {
+            // Read before the write, like CLR
+            self.read_ea(bus, ea, Size::Word)?;
+        }
+        self.write_ea(bus, ea, Size::Word, self.sr.bits() as u32)?;
+        self.prefetch(bus)
+    }
+
+    /// MOVE to CCR, or to SR (privileged)
+    fn op_move_to_sr<B: M68kBus>(&mut self, bus: &mut B, mode: u16, reg: usize, to_sr: bool) -> Access<()> {
+        if !mode_allowed(mode, reg, EA_DATA) {
+            return self.illegal(bus);
+        }
+        if to_sr && !self.supervisor() {
+            return self.privilege_violation(bus);
+        }
+        let ea = self.ea(bus, mode, reg, Size::Word)?;
+        let value = self.read_ea(bus, ea, Size::Word)? as u16;
+        if to_sr {
+            self.set_sr(value);
+        } else {
+            self.set_ccr(value as u8);
+        }
+        self.idle(4);
+        self.irc = self.read_program(bus, self.pc)?;
+        self.prefetch(bus)
+    }
+
+    /// TAS: test and set bit 7 in one indivisible read-modify-write cycle
+    fn op_tas<B: M68kBus>(&mut self, bus: &mut B, mode: u16, reg: usize) -> Access<()> {
+        if !mode_allowed(mode, reg, EA_DATA_ALTERABLE) {
+            return self.illegal(bus);
+        }
+        let ea = self.ea(bus, mode, reg, Size::Byte)?;
+        let value = self.read_ea(bus, ea, Size::Byte)?;
+        self.set_logic_flags(value, Size::Byte);
+        match ea {
+            Ea::Memory(addr) => {
+                self.idle(2);
+                if bus.tas_write_back(addr & 0x00FF_FFFF) {
+                    self.write(bus, addr, Size::Byte, value | 0x80)?;
+                } else {
+                    // The write cycle still runs, nothing latches it
+                    self.idle(4);
+                }
+            }
+            _ => self.write_ea(bus, ea, Size::Byte, value | 0x80)?,
+        }
+        self.prefetch(bus)
+    }
+
+    /// MOVEM: registers D0-D7/A0-A7 picked by a mask word. Predecrement
+    /// stores A7 down to D0 with the mask reversed; loads read one word more
+    /// than they need.
+    fn op_movem<B: M68kBus>(&mut self, bus: &mut B, opcode: u16, to_memory: bool) -> Access<()> {
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        let size = if opcode & 0x0040 != 0 { Size::Long } else { Size::Word };
+        let allowed = if to_memory { EA_CONTROL_ALTERABLE | EA_PREDEC } else { EA_CONTROL | EA_POSTINC };
+        if !mode_allowed(mode, reg, allowed) {
+            return self.illegal(bus);
+        }
+        let mask = self.next_word(bus)?;
+
+        if mode == 4 {
+            let mut addr = self.a[reg];
+            for i in (0..16).filter(|i| mask & (1 << i) != 0) {
+                let value = self.register(15 - i);
+                addr = addr.wrapping_sub(size.bytes());
+                self.write(bus, addr, size, value)?;
+            }
+            self.a[reg] = addr;
+            return self.prefetch(bus);
+        }
+
+        let mut addr = match mode {
+            3 => self.a[reg],
+            _ => match self.ea_with(bus, mode, reg, size, false)? {
+                Ea::Memory(addr) => addr,
+                _ => unreachable!("MOVEM operands are in memory"),
+            },
+        };
+        for i in (0..16).filter(|i| mask & (1 << i) != 0) {
+            if to_memory {
+                let value = self.register(i);
+                self.write(bus, addr, size, value)?;
+            } else {
+                let value = size.sign_extend(self.read(bus, addr, size)?);
+                self.set_register(i, value);
+            }
+            addr = addr.wrapping_add(size.bytes());
+        }
+        if !to_memory {
+            self.read(bus, addr, Size::Word)?;
+        }
+        if mode == 3 {
+            self.a[reg] = addr;
+        }
+        self.prefetch(bus)
+    }
+
+    /// D0-D7 then A0-A7
+    fn register(&self, index: usize) -> u32 {
+        if index < 8 { self.d[index] } else { self.a[index - 8] }
+    }
+
+    fn set_register(&mut self, index: usize, value: u32) {
+        if index < 8 {
+            self.d[index] = value;
+        } else {
+            self.a[index - 8] = value;
+        }
+    }
+
+    /// ADDQ/SUBQ, Scc and DBcc
+    fn line_5<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        let Some(size) = Size::from_bits(opcode >> 6) else {
+            return if mode == 1 { self.op_dbcc(bus, opcode) } else { self.op_scc(bus, opcode) };
+        };
+        if !mode_allowed(mode, reg, EA_ALTERABLE) || (mode == 1 && size == Size::Byte) {
+            return self.illegal(bus);
+        }
+
+        let data = match (opcode >> 9) & 0x07 {
+            0 => 8,
+            n => n as u32,
+        };
+        let subtract = opcode & 0x0100 != 0;
+        if mode == 1 {
+            // Whole address register, no flags
+            self.a[reg] = if subtract { self.a[reg].wrapping_sub(data) } else { self.a[reg].wrapping_add(data) };
+            self.idle(4);
+            return self.prefetch(bus);
+        }
+
+        let ea = self.ea(bus, mode, reg, size)?;
+        let value = self.read_ea(bus, ea, size)?;
+        let result = if subtract {
+            self.subtraction(data, value, size, false)
+        } else {
+            self.addition(data, value, size, false)
+        };
+        if mode == 0 && size == Size::Long {
+            self.idle(4);
+        }
+        self.write_ea(bus, ea, size, result)?;
+        self.prefetch(bus)
+    }
+
+    fn op_scc<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        if !mode_allowed(mode, reg, EA_DATA_ALTERABLE) {
+            return self.illegal(bus);
+        }
+        let set = self.condition(opcode >> 8);
+        let ea = self.ea(bus, mode, reg, Size::Byte)?;
+        if mode == 0 {
+            if set {
+                self.idle(2);
+            }
+        } else {
+            self.read_ea(bus, ea, Size::Byte)?;
+        }
+        self.write_ea(bus, ea, Size::Byte, if set { 0xFF } else { 0 })?;
+        self.prefetch(bus)
+    }
+
+    /// DBcc: unless the condition holds, decrement Dn.W and branch until it
+    /// reaches -1
+    fn op_dbcc<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let reg = (opcode & 0x07) as usize;
+        let base = self.pc;
+        if self.condition(opcode >> 8) {
+            self.idle(4);
+            self.next_word(bus)?;
+            return self.prefetch(bus);
+        }
+
+        let counter = (self.d[reg] as u16).wrapping_sub(1);
+        self.d[reg] = (self.d[reg] & 0xFFFF_0000) | counter as u32;
+        let offset = self.irc as i16 as u32;
+        self.idle(2);
+        if counter != 0xFFFF {
+            return self.jump(bus, base.wrapping_add(offset));
+        }
+        // Expired: the branch target is fetched and thrown away
+        self.read_program(bus, base.wrapping_add(offset))?;
+        self.next_word(bus)?;
+        self.prefetch(bus)
+    }
+
+    /// Bcc, BRA and BSR with an 8-bit displacement, or a 16-bit one when
+    /// that is zero
+    fn op_branch<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let cc = (opcode >> 8) & 0x0F;
+        let base = self.pc;
+        let short = opcode as u8 as i8 as u32;
+
+        if cc == 1 {
+            let offset = if short == 0 { self.take_word() as i16 as u32 } else { short };
+            self.idle(2);
+            self.push32(bus, self.pc)?;
+            return self.jump(bus, base.wrapping_add(offset));
+        }
+        if cc == 0 || self.condition(cc) {
+            let offset = if short == 0 { self.take_word() as i16 as u32 } else { short };
+            self.idle(2);
+            return self.jump(bus, base.wrapping_add(offset));
+        }
+
+        self.idle(4);
+        if short == 0 {
+            self.next_word(bus)?;
+        }
+        self.prefetch(bus)
+    }
+
+    /// OR, DIVU/DIVS and SBCD
+    fn line_8<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        match (opcode >> 6) & 0x07 {
+            3 => self.op_divide(bus, opcode, false),
+            7 => self.op_divide(bus, opcode, true),
+            _ if opcode & 0x01F0 == 0x0100 => self.op_extend(bus, opcode, |cpu, src, dst, _| cpu.sbcd(src, dst)),
+            _ => self.op_alu(bus, opcode, Alu::Or),
+        }
+    }
+
+    /// ADD/SUB, ADDA/SUBA and ADDX/SUBX
+    fn line_add_sub<B: M68kBus>(&mut self, bus: &mut B, opcode: u16, op: Alu) -> Access<()> {
+        match (opcode >> 6) & 0x07 {
+            3 | 7 => self.op_address(bus, opcode, op),
+            _ if opcode & 0x0130 == 0x0100 => {
+                if op == Alu::Add {
+                    self.op_extend(bus, opcode, |cpu, src, dst, size| cpu.addition(src, dst, size, true))
+                } else {
+                    self.op_extend(bus, opcode, |cpu, src, dst, size| cpu.subtraction(src, dst, size, true))
+                }
+            }
+            _ => self.op_alu(bus, opcode, op),
+        }
+    }
+
+    /// CMP, CMPA, CMPM and EOR
+    fn line_b<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        match (opcode >> 6) & 0x07 {
+            3 | 7 => self.op_address(bus, opcode, Alu::Cmp),
+            0..=2 => self.op_alu(bus, opcode, Alu::Cmp),
+            _ if (opcode >> 3) & 0x07 == 1 => {
+                let size = Size::from_bits(opcode >> 6).unwrap_or(Size::Long);
+                let src = self.ea(bus, 3, (opcode & 0x07) as usize, size)?;
+                let src = self.read_ea(bus, src, size)?;
+                let dst = self.ea(bus, 3, ((opcode >> 9) & 0x07) as usize, size)?;
+                let dst = self.read_ea(bus, dst, size)?;
+                self.compare(src, dst, size);
+                self.prefetch(bus)
+            }
+            _ => self.op_alu(bus, opcode, Alu::Eor),
+        }
+    }
+
+    /// AND, MULU/MULS, ABCD and EXG
+    fn line_c<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let (rx, ry) = (((opcode >> 9) & 0x07) as usize, (opcode & 0x07) as usize);
+        match (opcode >> 6) & 0x07 {
+            3 => self.op_multiply(bus, opcode, false),
+            7 => self.op_multiply(bus, opcode, true),
+            _ if opcode & 0x01F0 == 0x0100 => self.op_extend(bus, opcode, |cpu, src, dst, _| cpu.abcd(src, dst)),
+            _ if opcode & 0x01F8 == 0x0140 => {
+                self.d.swap(rx, ry);
+                self.idle(2);
+                self.prefetch(bus)
+            }
+            _ if opcode & 0x01F8 == 0x0148 => {
+                self.a.swap(rx, ry);
+                self.idle(2);
+                self.prefetch(bus)
+            }
+            _ if opcode & 0x01F8 == 0x0188 => {
+                std::mem::swap(&mut self.d[rx], &mut self.a[ry]);
+                self.idle(2);
+                self.prefetch(bus)
+            }
+            _ => self.op_alu(bus, opcode, Alu::And),
+        }
+    }
+
+    /// `<ea> op Dn -> Dn` (bit 8 clear) or `Dn op <ea> -> <ea>` (bit 8 set)
+    fn op_alu<B: M68kBus>(&mut self, bus: &mut B, opcode: u16, op: Alu) -> Access<()> {
+        let dn = ((opcode >> 9) & 0x07) as usize;
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        let Some(size) = Size::from_bits(opcode >> 6) else { return self.illegal(bus) };
+        let to_ea = opcode & 0x0100 != 0;
+
+        let allowed = match (op, to_ea) {
+            (Alu::Eor, _) => EA_DATA_ALTERABLE,
+            (_, true) => EA_MEMORY_ALTERABLE,
+            (Alu::Or | Alu::And, false) => EA_DATA,
+            _ => EA_ALL,
+        };
+        if !mode_allowed(mode, reg, allowed) || (mode == 1 && size == Size::Byte) {
+            return self.illegal(bus);
+        }
+
+        let ea = self.ea(bus, mode, reg, size)?;
+        let value = self.read_ea(bus, ea, size)?;
+        if to_ea {
+            let result = self.alu(op, self.d[dn], value, size);
+            if mode == 0 && size == Size::Long {
+                self.idle(4);
+            }
+            self.write_ea(bus, ea, size, result)?;
+        } else {
+            let result = self.alu(op, value, self.d[dn], size);
+            if size == Size::Long {
+                let register = Self::register_operand(mode, reg) && op != Alu::Cmp;
+                self.idle(if register { 4 } else { 2 });
+            }
+            if op != Alu::Cmp {
+                self.write_ea(bus, Ea::Data(dn), size, result)?;
+            }
+        }
+        self.prefetch(bus)
+    }
+
+    /// ADDA, SUBA and CMPA: word sources are sign extended, ADDA/SUBA
+    /// leave the flags alone
+    fn op_address<B: M68kBus>(&mut self, bus: &mut B, opcode: u16, op: Alu) -> Access<()> {
+        let an = ((opcode >> 9) & 0x07) as usize;
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        let size = if opcode & 0x0100 != 0 { Size::Long } else { Size::Word };
+        if !mode_allowed(mode, reg, EA_ALL) {
+            return self.illegal(bus);
+        }
+
+        let ea = self.ea(bus, mode, reg, size)?;
+        let src = size.sign_extend(self.read_ea(bus, ea, size)?);
+        match op {
+            Alu::Cmp => {
+                self.compare(src, self.a[an], Size::Long);
+                self.idle(2);
+            }
+            _ => {
+                self.a[an] = if op == Alu::Add { self.a[an].wrapping_add(src) } else { self.a[an].wrapping_sub(src) };
+                let fast = size == Size::Long && !Self::register_operand(mode, reg);
+                self.idle(if fast { 2 } else { 4 });
+            }
+        }
+        self.prefetch(bus)
+    }
+
+    /// ADDX/SUBX/ABCD/SBCD between data registers or with -(Ay),-(Ax)
+    fn op_extend<B: M68kBus>(
+        &mut self,
+        bus: &mut B,
+        opcode: u16,
+        op: impl Fn(&mut Self, u32, u32, Size) -> u32,
+    ) -> Access<()> {
+        let (rx, ry) = ((opcode & 0x07) as usize, ((opcode >> 9) & 0x07) as usize);
+        let bcd = matches!(opcode >> 12, 0x8 | 0xC);
+        let size = if bcd { Size::Byte } else { Size::from_bits(opcode >> 6).unwrap_or(Size::Long) };
+
+        if opcode & 0x0008 == 0 {
+            let result = op(self, self.d[rx] & size.mask(), self.d[ry] & size.mask(), size);
+            self.write_ea(bus, Ea::Data(ry), size, result)?;
+            if bcd {
+                self.idle(2);
+            } else if size == Size::Long {
+                self.idle(4);
+            }
+        } else {
+            let src = self.ea(bus, 4, rx, size)?;
+            let src = self.read_ea(bus, src, size)?;
+            let dst = self.ea_with(bus, 4, ry, size, false)?;
+            let value = self.read_ea(bus, dst, size)?;
+            let result = op(self, src, value, size);
+            self.write_ea(bus, dst, size, result)?;
+        }
+        self.prefetch(bus)
+    }
+
+    /// MULU/MULS: 16x16 -> 32. 38 cycles plus 2 per one bit of the source
+    /// (MULU) or per 01/10 pair in it (MULS).
+    fn op_multiply<B: M68kBus>(&mut self, bus: &mut B, opcode: u16, signed: bool) -> Access<()> {
+        let dn = ((opcode >> 9) & 0x07) as usize;
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        if !mode_allowed(mode, reg, EA_DATA) {
+            return self.illegal(bus);
+        }
+        let ea = self.ea(bus, mode, reg, Size::Word)?;
+        let src = self.read_ea(bus, ea, Size::Word)?;
+
+        let (result, bits) = if signed {
+            let result = (self.d[dn] as i16 as i32).wrapping_mul(src as i16 as i32) as u32;
+            let pairs = src << 1;
+            (result, ((pairs ^ (pairs >> 1)) & 0xFFFF).count_ones())
+        } else {
+            ((self.d[dn] & 0xFFFF) * src, src.count_ones())
+        };
+        self.d[dn] = result;
+        self.set_logic_flags(result, Size::Long);
+        self.idle(34 + 2 * bits);
+        self.prefetch(bus)
+    }
+
+    /// DIVU/DIVS: 32 / 16 -> 16-bit quotient and remainder; V on overflow
+    /// leaves Dn unchanged
+    fn op_divide<B: M68kBus>(&mut self, bus: &mut B, opcode: u16, signed: bool) -> Access<()> {
+        let dn = ((opcode >> 9) & 0x07) as usize;
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+        if !mode_allowed(mode, reg, EA_DATA) {
+            return self.illegal(bus);
+        }
+        let ea = self.ea(bus, mode, reg, Size::Word)?;
+        let divisor = self.read_ea(bus, ea, Size::Word)?;
+        let dividend = self.d[dn];
+
+        self.sr.remove(StatusRegister::CARRY);
+        if divisor == 0 {
+            self.idle(4);
+            return self.exception(bus, ZERO_DIVIDE_VECTOR, self.pc);
+        }
+
+        let result = if signed {
+            self.idle(divs_cycles(dividend as i32, divisor as i16) - 4);
+            let (dividend, divisor) = (dividend as i32 as i64, divisor as i16 as i64);
+            let quotient = dividend / divisor;
+            let remainder = dividend % divisor;
+            (quotient == quotient as i16 as i64).then_some((quotient as u32, remainder as u32))
+        } else {
+            self.idle(divu_cycles(dividend, divisor as u16) - 4);
+            let quotient = dividend / divisor;
+            (quotient <= 0xFFFF).then_some((quotient, dividend % divisor))
+        };
+
+        match result {
+            Some((quotient, remainder)) => {
+                self.d[dn] = (remainder << 16) | (quotient & 0xFFFF);
+                self.set_logic_flags(quotient, Size::Word);
+            }
+            None => {
+                self.sr.insert(StatusRegister::OVERFLOW | StatusRegister::NEGATIVE);
+                self.sr.remove(StatusRegister::ZERO);
+            }
+        }
+        self.prefetch(bus)
+    }
+
+    /// Shifts and rotates of a data register, or of a memory word by one
+    fn line_e<B: M68kBus>(&mut self, bus: &mut B, opcode: u16) -> Access<()> {
+        let left = opcode & 0x0100 != 0;
+        let (mode, reg) = ((opcode >> 3) & 0x07, (opcode & 0x07) as usize);
+
+        let Some(size) = Size::from_bits(opcode >> 6) else {
+            if opcode & 0x0800 != 0 || !mode_allowed(mode, reg, EA_MEMORY_ALTERABLE) {
+                return self.illegal(bus);
+            }
+            let ea = self.ea(bus, mode, reg, Size::Word)?;
+            let value = self.read_ea(bus, ea, Size::Word)?;
+            let result = self.shift((opcode >> 9) & 0x03, left, value, Size::Word, 1);
+            self.write_ea(bus, ea, Size::Word, result)?;
+            return self.prefetch(bus);
+        };
+
+        let count = if opcode & 0x0020 != 0 {
+            self.d[((opcode >> 9) & 0x07) as usize] % 64
+        } else {
+            match (opcode >> 9) & 0x07 {
+                0 => 8,
+                n => n as u32,
+            }
+        };
+        let result = self.shift((opcode >> 3) & 0x03, left, self.d[reg], size, count);
+        self.write_ea(bus, Ea::Data(reg), size, result)?;
+        self.idle(2 + 2 * count + if size == Size::Long { 2 } else { 0 });
+        self.prefetch(bus)
+    }
+
+    /// `kind`: 0 arithmetic, 1 logical, 2 rotate through X, 3 rotate
+    fn shift(&mut self, kind: u16, left: bool, value: u32, size: Size, count: u32) -> u32 {
+        let (mask, msb) = (size.mask(), size.msb());
+        let mut value = value & mask;
+        let mut extend = self.flag(StatusRegister::EXTEND);
+        let mut carry = false;
+        let mut overflow = false;
+
+        for _ in 0..count {
+            if left {
+                carry = value & msb != 0;
+                // ASL sets V if the sign bit changes at any point
+                overflow |= kind == 0 && (value ^ (value << 1)) & msb != 0;
+                let shifted = (value << 1) & mask;
+                value = match kind {
+                    2 => shifted | extend as u32,
+                    3 => shifted | carry as u32,
+                    _ => shifted,
+                };
+            } else {
+                carry = value & 1 != 0;
+                let high = match kind {
+                    0 => value & msb,
+                    2 if extend => msb,
+                    3 if carry => msb,
+                    _ => 0,
+                };
+                value = (value >> 1) | high;
+            }
+            if kind != 3 {
+                extend = carry;
+            }
+        }
+
+        self.set_nz(value, size);
+        self.sr.set(StatusRegister::OVERFLOW, overflow);
+        if count == 0 {
+            // Only ROXL/ROXR copy X into C with nothing to shift
+            self.sr.set(StatusRegister::CARRY, kind == 2 && extend);
+        } else {
+            self.sr.set(StatusRegister::CARRY, carry);
+            if kind != 3 {
+                self.sr.set(StatusRegister::EXTEND, extend);
+            }
+        }
+        value
+    }
+}
+
+impl Default for M68000 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// DIVU cycles without the effective address: the microcode loop takes
+/// longer for every quotient bit that needs no subtraction
+fn divu_cycles(dividend: u32, divisor: u16) -> u32 {
+    if dividend >> 16 >= divisor as u32 {
+        return 10;
+    }
+    let divisor = (divisor as u32) << 16;
+    let mut dividend = dividend;
+    let mut cycles = 38;
+    for _ in 0..15 {
+        let carry = dividend & 0x8000_0000 != 0;
+        dividend <<= 1;
+        if carry {
+            dividend = dividend.wrapping_sub(divisor);
+        } else {
+            cycles += 2;
+            if dividend >= divisor {
+                dividend -= divisor;
+                cycles -= 1;
+            }
+        }
+    }
+    cycles * 2
+}
+
+/// DIVS cycles without the effective address, after the signs and the
+/// bits of the absolute quotient
+fn divs_cycles(dividend: i32, divisor: i16) -> u32 {
+    let mut cycles = if dividend < 0 { 7 } else { 6 };
+    if dividend.unsigned_abs() >> 16 >= divisor.unsigned_abs() as u32 {
+        return (cycles + 2) * 2;
+    }
+    let mut quotient = dividend.unsigned_abs() / divisor.unsigned_abs() as u32;
+    cycles += 55;
+    if divisor >= 0 {
+        if dividend >= 0 {
+            cycles -= 1;
+        } else {
+            cycles += 1;
+        }
+    }
+    for _ in 0..15 {
+        if quotient & 0x8000 == 0 {
+            cycles += 1;
+        }
+        quotient <<= 1;
+    }
+    cycles * 2
+}
@@ -4,11 +4,18 @@
 /// - 1 Triangle wave
 /// - 1 Noise
 /// - 1 DMC (Delta Modulation Channel)
+///
+/// Only the DMC memory reader is emulated so far: it fetches sample bytes
+/// from the cartridge through the bus, which the console has to service.
+
+/// NTSC DMC timer periods in CPU cycles
+const DMC_PERIODS: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
 
 pub struct APU {
     pub audio_buffer: Vec<i16>,
     sample_rate: f32,
     time: f32,
+    dmc: Dmc,
 }
 
 impl APU {
@@ -17,20 +24,174 @@ impl APU {
             audio_buffer: Vec::new(),
             sample_rate: 44100.0,
             time: 0.0,
+            dmc: Dmc::new(),
         }
     }
-    
+
     pub fn reset(&mut self) {
         self.audio_buffer.clear();
         self.time = 0.0;
+        self.dmc = Dmc::new();
     }
-    
+
     pub fn step(&mut self) {
         // Simplified audio generation
         // TODO: Implement proper APU channels
+        self.dmc.step();
     }
-    
+
     pub fn get_samples(&mut self) -> &[i16] {
         &self.audio_buffer
     }
+
+    /// CPU write to $4000-$4013, $4015 or $4017
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4010..=0x4013 => self.dmc.write(addr, value),
+            0x4015 => self.dmc.set_enabled(value & 0x10 != 0),
+            // TODO: Pulse, triangle, noise and frame counter
+            _ => {}
+        }
+    }
+
+    /// $4015: channel length counters and interrupt flags
+    pub fn read_status(&self) -> u8 {
+        ((self.dmc.bytes_remaining > 0) as u8) << 4 | (self.dmc.irq as u8) << 7
+    }
+
+    /// DMC output level (0-127)
+    pub fn dmc_output(&self) -> u8 {
+        self.dmc.output_level
+    }
+
+    /// Address the DMC wants its next sample byte from, if its buffer is empty
+    pub fn dmc_fetch_address(&self) -> Option<u16> {
+        self.dmc.fetch_address()
+    }
+
+    /// Hand the DMC the byte read from `dmc_fetch_address`
+    pub fn dmc_fill(&mut self, value: u8) {
+        self.dmc.fill(value);
+    }
+}
+
+/// Delta modulation channel: a 1-bit delta decoder fed by a memory reader
+struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    period: u16,
+    timer: u16,
+    output_level: u8,
+
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+    irq: bool,
+}
+
+impl Dmc {
+    fn new() -> Self {
+        Self {
+            irq_enabled: false,
+            looping: false,
+            period: DMC_PERIODS[0],
+            timer: DMC_PERIODS[0],
+            output_level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            irq: false,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4010 => {
+                self.irq_enabled = value & 0x80 != 0;
+                self.looping = value & 0x40 != 0;
+                self.period = DMC_PERIODS[(value & 0x0F) as usize];
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            0x4011 => self.output_level = value & 0x7F,
+            // Samples start at $C000 + A * 64 and are L * 16 + 1 bytes long
+            0x4012 => self.sample_address = 0xC000 | ((value as u16) << 6),
+            _ => self.sample_length = ((value as u16) << 4) | 1,
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    fn fetch_address(&self) -> Option<u16> {
+        (self.sample_buffer.is_none() && self.bytes_remaining > 0).then_some(self.current_address)
+    }
+
+    fn fill(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        // The address wraps from $FFFF to $8000
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period;
+
+        if !self.silence {
+            if self.shift & 0x01 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.shift = sample;
+                    self.silence = false;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
 }
@@ -2,113 +2,389 @@
 /// Handles memory mapping and cartridge access
 
 use anyhow::Result;
+use emu_common::cdl::{self, CodeDataLogger, RegionKind};
+use crate::cartridge::RomHeader;
+use crate::controller::{Buttons, Controller};
+use crate::apu::APU;
+use crate::cpu::CpuBus;
+use crate::mapper::{self, Mapper, Mirroring};
+use crate::ppu::PPU;
+use crate::vs_system::VsSystem;
+
+/// CPU cycles the CPU is halted for during OAM DMA
+const OAM_DMA_CYCLES: u32 = 513;
+/// CPU cycles lost to each DMC sample fetch
+const DMC_DMA_CYCLES: u32 = 4;
+
+/// Code/Data Logger regions, in FCEUX `.cdl` order
+pub const CDL_PRG: usize = 0;
+pub const CDL_CHR: usize = 1;
 
 pub struct Bus {
     // Internal RAM (2KB, mirrored to 0x2000)
     ram: [u8; 0x800],
-    
+
     // Cartridge ROM/RAM
-    prg_rom: Vec<u8>,
-    chr_rom: Vec<u8>,
+    mapper: Option<Box<dyn Mapper>>,
+
+    controllers: [Controller; 2],
+    pub vs: Option<VsSystem>,
+
+    // Code/Data Logger, when recording
+    pub cdl: Option<CodeDataLogger>,
+    prg_rom_size: usize,
+    chr_rom_size: usize,
+
+    dma_cycles: u32,
 }
 
 impl Bus {
     pub fn new() -> Self {
         Self {
             ram: [0; 0x800],
-            prg_rom: Vec::new(),
-            chr_rom: Vec::new(),
+            mapper: None,
+            controllers: [Controller::new(), Controller::new()],
+            vs: None,
+            cdl: None,
+            prg_rom_size: 0,
+            chr_rom_size: 0,
+            dma_cycles: 0,
         }
     }
-    
-    pub fn load_cartridge(&mut self, rom_data: &[u8]) -> Result<()> {
+
+    pub fn load_cartridge(&mut self, rom_data: &[u8]) -> Result<RomHeader> {
         // Parse iNES format header
-        if rom_data.len() < 16 {
-            anyhow::bail!("ROM too small");
-        }
-        
-        // Check for "NES\x1A" magic number
-        if &rom_data[0..4] != b"NES\x1A" {
-            anyhow::bail!("Invalid NES ROM format");
-        }
-        
-        let prg_rom_size = rom_data[4] as usize * 16384; // 16KB units
-        let chr_rom_size = rom_data[5] as usize * 8192;  // 8KB units
-        
-        let prg_start = 16; // After header
-        let chr_start = prg_start + prg_rom_size;
-        
-        self.prg_rom = rom_data[prg_start..prg_start + prg_rom_size].to_vec();
-        
-        if chr_rom_size > 0 {
-            self.chr_rom = rom_data[chr_start..chr_start + chr_rom_size].to_vec();
-        }
-        
-        log::info!("Loaded NES ROM: PRG={} KB, CHR={} KB", 
-                   prg_rom_size / 1024, chr_rom_size / 1024);
-        
-        Ok(())
-    }
-    
-    pub fn read(&self, addr: u16) -> u8 {
+        let header = RomHeader::parse(rom_data)?;
+
+        let prg_start = header.prg_offset();
+        let chr_start = prg_start + header.prg_rom_size;
+        let chr_end = chr_start + header.chr_rom_size;
+        if rom_data.len() < chr_end {
+            anyhow::bail!("ROM truncated: expected {} bytes, got {}", chr_end, rom_data.len());
+        }
+
+        let prg_rom = rom_data[prg_start..chr_start].to_vec();
+        let chr_rom = rom_data[chr_start..chr_end].to_vec();
+
+        self.mapper = Some(mapper::create(header.mapper_id, prg_rom, chr_rom, header.mirroring)?);
+        self.prg_rom_size = header.prg_rom_size;
+        self.chr_rom_size = header.chr_rom_size;
+        self.cdl = None;
+
+        log::info!("Loaded NES ROM: mapper {}, PRG={} KB, CHR={} KB",
+                   header.mapper_id, header.prg_rom_size / 1024, header.chr_rom_size / 1024);
+
+        Ok(header)
+    }
+
+    /// Latch a player's buttons into the port their controller is wired to
+    pub fn set_buttons(&mut self, player: usize, buttons: Buttons) {
+        let port = match &self.vs {
+            Some(vs) => vs.port_for_player(player),
+            None => player,
+        };
+        if let Some(controller) = self.controllers.get_mut(port) {
+            controller.set_buttons(buttons);
+        }
+    }
+
+    /// Start a Code/Data Log sized for the loaded cartridge. CHR RAM boards
+    /// get an empty CHR region, as in FCEUX.
+    pub fn start_cdl(&mut self) {
+        let mut logger = CodeDataLogger::new();
+        logger.add_region("PRG", RegionKind::Program, self.prg_rom_size, 0x4000);
+        logger.add_region("CHR", RegionKind::Graphics, self.chr_rom_size, 0x2000);
+        self.cdl = Some(logger);
+    }
+
+    /// Cycles stolen by DMA since the last call
+    pub fn take_dma_cycles(&mut self) -> u32 {
+        std::mem::take(&mut self.dma_cycles)
+    }
+
+    pub fn read(&mut self, addr: u16) -> u8 {
+        self.read_as(addr, cdl::DATA)
+    }
+
+    /// Opcode/operand fetch, logged as code
+    pub fn fetch(&mut self, addr: u16) -> u8 {
+        self.read_as(addr, cdl::CODE)
+    }
+
+    /// Read through a pointer, logged as indirect data
+    pub fn read_indirect(&mut self, addr: u16) -> u8 {
+        self.read_as(addr, cdl::DATA | cdl::INDIRECT_DATA)
+    }
+
+    /// DMC sample fetch, logged as PCM audio; stalls the CPU
+    pub fn read_dpcm(&mut self, addr: u16) -> u8 {
+        self.dma_cycles += DMC_DMA_CYCLES;
+        self.read_as(addr, cdl::DATA | cdl::PCM_AUDIO)
+    }
+
+    /// Log the target of JMP (indirect) without reading it
+    pub fn log_indirect_code(&mut self, addr: u16) {
+        if let (Some(logger), Some(mapper)) = (&mut self.cdl, &self.mapper) {
+            if let Some(offset) = mapper.prg_rom_offset(addr) {
+                let window = (((addr >> 13) & 0x03) as u8) << 2;
+                logger.mark(CDL_PRG, offset, cdl::INDIRECT_CODE | window);
+            }
+        }
+    }
+
+    fn read_as(&mut self, addr: u16, cdl_flags: u8) -> u8 {
         match addr {
             // RAM (mirrored)
             0x0000..=0x1FFF => self.ram[(addr & 0x07FF) as usize],
-            
-            // PPU registers (mirrored)
-            0x2000..=0x3FFF => {
-                // TODO: Read from PPU
-                0
-            }
-            
-            // APU and I/O registers
-            0x4000..=0x4017 => {
-                // TODO: Read from APU/controller
-                0
+
+            // Controllers, plus coin/DIP inputs on the Vs. System
+            0x4016 | 0x4017 => {
+                let port = (addr - 0x4016) as usize;
+                let serial = self.controllers[port].read();
+                match &self.vs {
+                    Some(vs) if port == 0 => serial | vs.read_4016(),
+                    Some(vs) => serial | vs.read_4017(),
+                    // Upper bits are open bus, usually $40 from the address
+                    None => serial | 0x40,
+                }
             }
-            
+
+            // APU registers are decoded by SystemBus
+            0x4000..=0x4015 => 0,
+
             // Cartridge space
-            0x8000..=0xFFFF => {
-                if self.prg_rom.is_empty() {
-                    0
-                } else {
-                    let addr = (addr - 0x8000) as usize;
-                    if addr < self.prg_rom.len() {
-                        self.prg_rom[addr]
-                    } else {
-                        // Mirror if ROM is 16KB
-                        self.prg_rom[addr % self.prg_rom.len()]
+            0x4020..=0xFFFF => match &self.mapper {
+                Some(mapper) => {
+                    if let Some(logger) = &mut self.cdl {
+                        if let Some(offset) = mapper.prg_rom_offset(addr) {
+                            let window = (((addr >> 13) & 0x03) as u8) << 2;
+                            logger.mark(CDL_PRG, offset, cdl_flags | window);
+                        }
                     }
+                    mapper.read(addr)
                 }
-            }
-            
+                None => 0,
+            },
+
             _ => 0,
         }
     }
-    
+
+    /// Side-effect free read of RAM and cartridge space, for debug views
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.ram[(addr & 0x07FF) as usize],
+            0x4020..=0xFFFF => match &self.mapper {
+                Some(mapper) => mapper.read(addr),
+                None => 0,
+            },
+            _ => 0,
+        }
+    }
+
     pub fn write(&mut self, addr: u16, value: u8) {
         match addr {
             // RAM (mirrored)
             0x0000..=0x1FFF => {
                 self.ram[(addr & 0x07FF) as usize] = value;
             }
-            
-            // PPU registers (mirrored)
-            0x2000..=0x3FFF => {
-                // TODO: Write to PPU
+
+            // Controller strobe; Vs. boards also latch the CHR bank here
+            0x4016 => {
+                for controller in &mut self.controllers {
+                    controller.write_strobe(value);
+                }
+                if self.vs.is_some() {
+                    if let Some(mapper) = &mut self.mapper {
+                        mapper.write(addr, value);
+                    }
+                }
             }
-            
-            // APU and I/O registers
-            0x4000..=0x4017 => {
-                // TODO: Write to APU/controller
+
+            // APU registers are decoded by SystemBus
+            0x4000..=0x4017 => {}
+
+            // Vs. System coin counter
+            0x4020 if self.vs.is_some() => {
+                if let Some(vs) = &mut self.vs {
+                    vs.write_4020(value);
+                }
             }
-            
+
             // Cartridge space (usually ROM, but some mappers allow writes)
-            0x8000..=0xFFFF => {
-                // TODO: Handle mapper writes
+            0x4020..=0xFFFF => {
+                if let Some(mapper) = &mut self.mapper {
+                    mapper.write(addr, value);
+                }
             }
-            
+
             _ => {}
         }
     }
+
+    // PPU-side cartridge access
+
+    pub fn read_chr(&self, addr: u16) -> u8 {
+        match &self.mapper {
+            Some(mapper) => mapper.read_chr(addr & 0x1FFF),
+            None => 0,
+        }
+    }
+
+    /// Pattern fetch by the renderer, logged as drawn
+    pub fn fetch_chr(&mut self, addr: u16) -> u8 {
+        self.log_chr(addr, cdl::RENDERED);
+        self.read_chr(addr)
+    }
+
+    pub fn log_chr(&mut self, addr: u16, flags: u8) {
+        if let (Some(logger), Some(mapper)) = (&mut self.cdl, &self.mapper) {
+            if let Some(offset) = mapper.chr_rom_offset(addr & 0x1FFF) {
+                logger.mark(CDL_CHR, offset, flags);
+            }
+        }
+    }
+
+    pub fn write_chr(&mut self, addr: u16, value: u8) {
+        if let Some(mapper) = &mut self.mapper {
+            mapper.write_chr(addr & 0x1FFF, value);
+        }
+    }
+
+    pub fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        self.mapper.as_ref().and_then(|mapper| mapper.chr_rom_offset(addr & 0x1FFF))
+    }
+
+    pub fn chr_data(&self) -> &[u8] {
+        match &self.mapper {
+            Some(mapper) => mapper.chr_data(),
+            None => &[],
+        }
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        match &self.mapper {
+            Some(mapper) => mapper.mirroring(),
+            None => Mirroring::Horizontal,
+        }
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// CPU view of the console: PPU registers and OAM DMA go to the PPU,
+/// APU registers to the APU, everything else to `Bus`
+pub struct SystemBus<'a> {
+    pub bus: &'a mut Bus,
+    pub ppu: &'a mut PPU,
+    pub apu: &'a mut APU,
+}
+
+impl<'a> SystemBus<'a> {
+    pub fn new(bus: &'a mut Bus, ppu: &'a mut PPU, apu: &'a mut APU) -> Self {
+        Self { bus, ppu, apu }
+    }
+}
+
+impl CpuBus for SystemBus<'_> {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // PPU registers (mirrored)
+            0x2000..=0x3FFF => self.ppu.read_register(addr, self.bus),
+            0x4015 => self.apu.read_status(),
+            _ => self.bus.read(addr),
+        }
+    }
+
+    fn fetch(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x2000..=0x3FFF => self.read(addr),
+            _ => self.bus.fetch(addr),
+        }
+    }
+
+    fn read_indirect(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x2000..=0x3FFF => self.read(addr),
+            _ => self.bus.read_indirect(addr),
+        }
+    }
+
+    fn indirect_jump(&mut self, target: u16) {
+        self.bus.log_indirect_code(target);
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            // PPU registers (mirrored)
+            0x2000..=0x3FFF => self.ppu.write_register(addr, value, self.bus),
+
+            // OAM DMA: copy a whole CPU page into sprite memory
+            0x4014 => {
+                let page = (value as u16) << 8;
+                for offset in 0..256 {
+                    let byte = self.read(page | offset);
+                    self.ppu.write_oam_dma(byte);
+                }
+                self.bus.dma_cycles += OAM_DMA_CYCLES;
+            }
+
+            0x4000..=0x4013 | 0x4015 | 0x4017 => self.apu.write_register(addr, value),
+
+            _ => self.bus.write(addr, value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::NES;
+    use emu_common::cdl;
+
+    /// NROM-128 image with `program` at $C000 and the reset vector on it
+    fn rom(program: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut data = b"NES\x1A\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        let mut prg = vec![0xEA; 0x4000];
+        for (addr, bytes) in program {
+            let offset = (addr - 0xC000) as usize;
+            prg[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        prg[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        data.extend(prg);
+        data
+    }
+
+    #[test]
+    fn cdl_logs_dmc_samples_and_indirect_jumps() {
+        let mut nes = NES::new();
+        nes.load_rom(&rom(&[
+            (0xC000, &[
+                0xA9, 0x0F, 0x8D, 0x10, 0x40, // fastest rate, no loop
+                0xA9, 0x02, 0x8D, 0x12, 0x40, // sample at $C080
+                0xA9, 0x01, 0x8D, 0x13, 0x40, // 17 bytes long
+                0xA9, 0x10, 0x8D, 0x15, 0x40, // start the DMC
+                0x6C, 0x00, 0xC1,             // JMP ($C100)
+            ]),
+            (0xC100, &[0x10, 0xC2]),
+            (0xC210, &[0x4C, 0x10, 0xC2]),    // JMP $C210
+        ]))
+        .unwrap();
+        nes.bus.start_cdl();
+        // 17 bytes of 8 bits at 54 cycles per bit
+        for _ in 0..5000 {
+            nes.step();
+        }
+
+        let prg = nes.bus.cdl.as_ref().unwrap().flags(super::CDL_PRG);
+        let window = 0x08; // $C000-$DFFF
+        assert_eq!(prg[0x80..0x91], [cdl::DATA | cdl::PCM_AUDIO | window; 17]);
+        assert_eq!(prg[0x91], 0);
+        assert_eq!(prg[0x100], cdl::DATA | window);
+        assert_eq!(prg[0x210], cdl::CODE | cdl::INDIRECT_CODE | window);
+        assert_eq!(prg[0x213], 0);
+    }
 }
@@ -0,0 +1,108 @@
+/// iNES / NES 2.0 ROM Header
+/// Describes the cartridge layout and the console it was dumped from
+
+use anyhow::Result;
+use crate::mapper::Mirroring;
+
+/// Console type from header byte 7
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    PlayChoice10,
+    Extended(u8),
+}
+
+#[derive(Debug, Clone)]
+pub struct RomHeader {
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub mapper_id: u16,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    pub nes2: bool,
+    pub console_type: ConsoleType,
+    /// NES 2.0 byte 13 low nibble: Vs. PPU type
+    pub vs_ppu_type: u8,
+    /// NES 2.0 byte 13 high nibble: Vs. hardware type
+    pub vs_hardware_type: u8,
+}
+
+pub const HEADER_SIZE: usize = 16;
+pub const TRAINER_SIZE: usize = 512;
+
+impl RomHeader {
+    pub fn parse(rom_data: &[u8]) -> Result<Self> {
+        if rom_data.len() < HEADER_SIZE {
+            anyhow::bail!("ROM too small");
+        }
+
+        // Check for "NES\x1A" magic number
+        if &rom_data[0..4] != b"NES\x1A" {
+            anyhow::bail!("Invalid NES ROM format");
+        }
+
+        let flags6 = rom_data[6];
+        let flags7 = rom_data[7];
+        let nes2 = flags7 & 0x0C == 0x08;
+
+        let mut prg_units = rom_data[4] as usize;
+        let mut chr_units = rom_data[5] as usize;
+        let mut mapper_id = ((flags6 >> 4) | (flags7 & 0xF0)) as u16;
+        if nes2 {
+            // Size MSB nibbles; the exponent-multiplier form is only used by
+            // oddly sized homebrew and is not supported here
+            prg_units |= ((rom_data[9] & 0x0F) as usize) << 8;
+            chr_units |= ((rom_data[9] >> 4) as usize) << 8;
+            mapper_id |= ((rom_data[8] & 0x0F) as u16) << 8;
+        }
+
+        let mirroring = if flags6 & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if flags6 & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        let console_type = if nes2 {
+            match flags7 & 0x03 {
+                0 => ConsoleType::Nes,
+                1 => ConsoleType::VsSystem,
+                2 => ConsoleType::PlayChoice10,
+                _ => ConsoleType::Extended(rom_data[13] & 0x0F),
+            }
+        } else if flags7 & 0x01 != 0 {
+            ConsoleType::VsSystem
+        } else if flags7 & 0x02 != 0 {
+            ConsoleType::PlayChoice10
+        } else {
+            ConsoleType::Nes
+        };
+
+        let (vs_ppu_type, vs_hardware_type) = if nes2 && console_type == ConsoleType::VsSystem {
+            (rom_data[13] & 0x0F, rom_data[13] >> 4)
+        } else {
+            (0, 0)
+        };
+
+        Ok(Self {
+            prg_rom_size: prg_units * 16384, // 16KB units
+            chr_rom_size: chr_units * 8192,  // 8KB units
+            mapper_id,
+            mirroring,
+            battery: flags6 & 0x02 != 0,
+            trainer: flags6 & 0x04 != 0,
+            nes2,
+            console_type,
+            vs_ppu_type,
+            vs_hardware_type,
+        })
+    }
+
+    /// Offset of PRG ROM in the file
+    pub fn prg_offset(&self) -> usize {
+        HEADER_SIZE + if self.trainer { TRAINER_SIZE } else { 0 }
+    }
+}
@@ -1,13 +1,18 @@
 /// MOS Technology 6502 CPU Emulator
-/// 
+///
 /// 8-bit microprocessor with:
 /// - 3 general purpose registers (A, X, Y)
 /// - 8-bit stack pointer
 /// - 16-bit program counter
 /// - 7 status flags
+///
+/// The core is generic over the memory bus (`CpuBus`) and the chip variant
+/// (`Variant`). Both are resolved at compile time, so the NES build
+/// (`CPU6502<Ricoh2A03>` on `crate::bus::Bus`) pays nothing for the
+/// abstraction.
 
+use std::marker::PhantomData;
 use bitflags::bitflags;
-use crate::bus::Bus;
 
 bitflags! {
     #[derive(Debug, Clone, Copy)]
@@ -15,7 +20,7 @@ bitflags! {
         const CARRY     = 0b0000_0001;  // C
         const ZERO      = 0b0000_0010;  // Z
         const INTERRUPT = 0b0000_0100;  // I (Interrupt Disable)
-        const DECIMAL   = 0b0000_1000;  // D (Decimal Mode - not used in NES)
+        const DECIMAL   = 0b0000_1000;  // D (Decimal Mode - ignored by the 2A03)
         const BREAK     = 0b0001_0000;  // B
         const UNUSED    = 0b0010_0000;  // Always set to 1
         const OVERFLOW  = 0b0100_0000;  // V
@@ -23,7 +28,119 @@ bitflags! {
     }
 }
 
-pub struct CPU6502 {
+/// Memory interface seen by the CPU
+pub trait CpuBus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+
+    /// Opcode and operand fetches; override to tell code from data
+    fn fetch(&mut self, addr: u16) -> u8 {
+        self.read(addr)
+    }
+
+    /// Data read through a pointer: (zp,X), (zp),Y and (zp)
+    fn read_indirect(&mut self, addr: u16) -> u8 {
+        self.read(addr)
+    }
+
+    /// Called with the target of JMP (indirect) before it is fetched
+    fn indirect_jump(&mut self, _target: u16) {}
+
+    /// Called once per instruction (or interrupt) with the cycles it consumed
+    fn tick(&mut self, _cycles: u8) {}
+}
+
+/// Compile-time description of a 6502-family chip
+pub trait Variant {
+    /// Whether the D flag switches ADC/SBC to BCD arithmetic
+    const DECIMAL_MODE: bool;
+    /// 65C02 instruction set, timings and bug fixes
+    const CMOS: bool;
+}
+
+/// Ricoh 2A03/2A07 (NES): NMOS 6502 with the BCD adder disconnected
+pub struct Ricoh2A03;
+
+/// Original NMOS 6502 with working decimal mode
+pub struct Nmos6502;
+
+/// WDC 65C02 including the bit manipulation opcodes, WAI and STP
+pub struct Wdc65C02;
+
+impl Variant for Ricoh2A03 {
+    const DECIMAL_MODE: bool = false;
+    const CMOS: bool = false;
+}
+
+impl Variant for Nmos6502 {
+    const DECIMAL_MODE: bool = true;
+    const CMOS: bool = false;
+}
+
+impl Variant for Wdc65C02 {
+    const DECIMAL_MODE: bool = true;
+    const CMOS: bool = true;
+}
+
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
+
+/// Base cycle counts for the NMOS opcode matrix (undocumented opcodes included)
+const NMOS_CYCLES: [u8; 256] = [
+    7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+    2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5,
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+    2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4,
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+];
+
+/// Base cycle counts for the WDC 65C02 opcode matrix
+const CMOS_CYCLES: [u8; 256] = [
+    7, 6, 2, 1, 5, 3, 5, 5, 3, 2, 2, 1, 6, 4, 6, 5,
+    2, 5, 5, 1, 5, 4, 6, 5, 2, 4, 2, 1, 6, 4, 6, 5,
+    6, 6, 2, 1, 3, 3, 5, 5, 4, 2, 2, 1, 4, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 2, 1, 4, 4, 6, 5,
+    6, 6, 2, 1, 3, 3, 5, 5, 3, 2, 2, 1, 3, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 3, 1, 8, 4, 6, 5,
+    6, 6, 2, 1, 3, 3, 5, 5, 4, 2, 2, 1, 6, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 4, 1, 6, 4, 6, 5,
+    3, 6, 2, 1, 3, 3, 3, 5, 2, 2, 2, 1, 4, 4, 4, 5,
+    2, 6, 5, 1, 4, 4, 4, 5, 2, 5, 2, 1, 4, 5, 5, 5,
+    2, 6, 2, 1, 3, 3, 3, 5, 2, 2, 2, 1, 4, 4, 4, 5,
+    2, 5, 5, 1, 4, 4, 4, 5, 2, 4, 2, 1, 4, 4, 4, 5,
+    2, 6, 2, 1, 3, 3, 5, 5, 2, 2, 2, 3, 4, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 3, 3, 4, 4, 7, 5,
+    2, 6, 2, 1, 3, 3, 5, 5, 2, 2, 2, 1, 4, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 4, 1, 4, 4, 7, 5,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Imm,
+    Zp,
+    ZpX,
+    ZpY,
+    Abs,
+    AbsX,
+    AbsY,
+    IndX,
+    IndY,
+    ZpInd,
+}
+
+pub struct CPU6502<V: Variant = Ricoh2A03> {
     // Registers
     pub a: u8,          // Accumulator
     pub x: u8,          // X index register
@@ -31,12 +148,20 @@ pub struct CPU6502 {
     pub sp: u8,         // Stack pointer
     pub pc: u16,        // Program counter
     pub status: StatusFlags,
-    
+
     // State
     pub cycles: u64,
+    /// Set by JAM (NMOS) or STP (65C02); only reset recovers
+    pub halted: bool,
+    /// Set by WAI until the next interrupt
+    pub waiting: bool,
+
+    page_crossed: bool,
+    extra_cycles: u8,
+    _variant: PhantomData<V>,
 }
 
-impl CPU6502 {
+impl<V: Variant> CPU6502<V> {
     pub fn new() -> Self {
         Self {
             a: 0,
@@ -46,116 +171,1019 @@ impl CPU6502 {
             pc: 0,
             status: StatusFlags::UNUSED | StatusFlags::INTERRUPT,
             cycles: 0,
+            halted: false,
+            waiting: false,
+            page_crossed: false,
+            extra_cycles: 0,
+            _variant: PhantomData,
         }
     }
-    
-    pub fn reset(&mut self, bus: &mut Bus) {
+
+    pub fn reset<B: CpuBus>(&mut self, bus: &mut B) {
         self.a = 0;
         self.x = 0;
         self.y = 0;
         self.sp = 0xFD;
         self.status = StatusFlags::UNUSED | StatusFlags::INTERRUPT;
-        
+        self.halted = false;
+        self.waiting = false;
+
         // Read reset vector
-        let lo = bus.read(0xFFFC) as u16;
-        let hi = bus.read(0xFFFD) as u16;
-        self.pc = (hi << 8) | lo;
-        
+        self.pc = self.read_word(bus, RESET_VECTOR);
+
         self.cycles = 7; // Reset takes 7 cycles
     }
-    
-    pub fn step(&mut self, bus: &mut Bus) -> u8 {
-        let opcode = bus.read(self.pc);
+
+    pub fn step<B: CpuBus>(&mut self, bus: &mut B) -> u8 {
+        if self.halted || self.waiting {
+            bus.tick(1);
+            self.cycles += 1;
+            return 1;
+        }
+
+        let opcode = bus.fetch(self.pc);
         self.pc = self.pc.wrapping_add(1);
-        
+
         // Execute instruction based on opcode
-        let cycles = self.execute(opcode, bus);
+        self.page_crossed = false;
+        self.extra_cycles = 0;
+        self.execute(opcode, bus);
+
+        let base = if V::CMOS { CMOS_CYCLES[opcode as usize] } else { NMOS_CYCLES[opcode as usize] };
+        let cycles = base + self.extra_cycles;
+        bus.tick(cycles);
         self.cycles += cycles as u64;
-        
+
         cycles
     }
-    
-    fn execute(&mut self, opcode: u8, bus: &mut Bus) -> u8 {
-        // Simplified instruction set - implement key opcodes
+
+    /// Non-maskable interrupt (edge already detected by the caller)
+    pub fn nmi<B: CpuBus>(&mut self, bus: &mut B) -> u8 {
+        self.interrupt(bus, NMI_VECTOR)
+    }
+
+    /// Maskable interrupt; returns 0 if the I flag blocked it
+    pub fn irq<B: CpuBus>(&mut self, bus: &mut B) -> u8 {
+        if self.status.contains(StatusFlags::INTERRUPT) {
+            // WAI resumes even when the IRQ itself is masked
+            self.waiting = false;
+            return 0;
+        }
+        self.interrupt(bus, IRQ_VECTOR)
+    }
+
+    fn interrupt<B: CpuBus>(&mut self, bus: &mut B, vector: u16) -> u8 {
+        if self.halted {
+            return 0;
+        }
+        self.waiting = false;
+        self.push_word(bus, self.pc);
+        let status = (self.status | StatusFlags::UNUSED) - StatusFlags::BREAK;
+        self.push(bus, status.bits());
+        self.status.insert(StatusFlags::INTERRUPT);
+        if V::CMOS {
+            self.status.remove(StatusFlags::DECIMAL);
+        }
+        self.pc = self.read_word(bus, vector);
+
+        bus.tick(7);
+        self.cycles += 7;
+        7
+    }
+
+    fn execute<B: CpuBus>(&mut self, opcode: u8, bus: &mut B) {
+        if V::CMOS && self.execute_cmos(opcode, bus) {
+            return;
+        }
+
         match opcode {
             // LDA - Load Accumulator
-            0xA9 => { // Immediate
-                let value = self.read_immediate(bus);
-                self.a = value;
-                self.update_zero_and_negative_flags(value);
-                2
-            }
-            0xA5 => { // Zero Page
-                let addr = self.read_zero_page_addr(bus);
-                let value = bus.read(addr);
-                self.a = value;
-                self.update_zero_and_negative_flags(value);
-                3
-            }
-            
+            0xA9 => self.lda(bus, Mode::Imm),
+            0xA5 => self.lda(bus, Mode::Zp),
+            0xB5 => self.lda(bus, Mode::ZpX),
+            0xAD => self.lda(bus, Mode::Abs),
+            0xBD => self.lda(bus, Mode::AbsX),
+            0xB9 => self.lda(bus, Mode::AbsY),
+            0xA1 => self.lda(bus, Mode::IndX),
+            0xB1 => self.lda(bus, Mode::IndY),
+
             // LDX - Load X Register
-            0xA2 => { // Immediate
-                let value = self.read_immediate(bus);
-                self.x = value;
-                self.update_zero_and_negative_flags(value);
-                2
-            }
-            
+            0xA2 => self.ldx(bus, Mode::Imm),
+            0xA6 => self.ldx(bus, Mode::Zp),
+            0xB6 => self.ldx(bus, Mode::ZpY),
+            0xAE => self.ldx(bus, Mode::Abs),
+            0xBE => self.ldx(bus, Mode::AbsY),
+
             // LDY - Load Y Register
-            0xA0 => { // Immediate
-                let value = self.read_immediate(bus);
-                self.y = value;
-                self.update_zero_and_negative_flags(value);
-                2
-            }
-            
-            // STA - Store Accumulator
-            0x85 => { // Zero Page
-                let addr = self.read_zero_page_addr(bus);
-                bus.write(addr, self.a);
-                3
+            0xA0 => self.ldy(bus, Mode::Imm),
+            0xA4 => self.ldy(bus, Mode::Zp),
+            0xB4 => self.ldy(bus, Mode::ZpX),
+            0xAC => self.ldy(bus, Mode::Abs),
+            0xBC => self.ldy(bus, Mode::AbsX),
+
+            // STA / STX / STY - Store registers
+            0x85 => self.store(bus, Mode::Zp, self.a),
+            0x95 => self.store(bus, Mode::ZpX, self.a),
+            0x8D => self.store(bus, Mode::Abs, self.a),
+            0x9D => self.store(bus, Mode::AbsX, self.a),
+            0x99 => self.store(bus, Mode::AbsY, self.a),
+            0x81 => self.store(bus, Mode::IndX, self.a),
+            0x91 => self.store(bus, Mode::IndY, self.a),
+            0x86 => self.store(bus, Mode::Zp, self.x),
+            0x96 => self.store(bus, Mode::ZpY, self.x),
+            0x8E => self.store(bus, Mode::Abs, self.x),
+            0x84 => self.store(bus, Mode::Zp, self.y),
+            0x94 => self.store(bus, Mode::ZpX, self.y),
+            0x8C => self.store(bus, Mode::Abs, self.y),
+
+            // Register transfers
+            0xAA => { self.x = self.a; self.update_zero_and_negative_flags(self.x); }
+            0xA8 => { self.y = self.a; self.update_zero_and_negative_flags(self.y); }
+            0x8A => { self.a = self.x; self.update_zero_and_negative_flags(self.a); }
+            0x98 => { self.a = self.y; self.update_zero_and_negative_flags(self.a); }
+            0xBA => { self.x = self.sp; self.update_zero_and_negative_flags(self.x); }
+            0x9A => self.sp = self.x,
+
+            // Stack
+            0x48 => self.push(bus, self.a),
+            0x08 => {
+                let status = self.status | StatusFlags::BREAK | StatusFlags::UNUSED;
+                self.push(bus, status.bits());
             }
-            
+            0x68 => { self.a = self.pull(bus); self.update_zero_and_negative_flags(self.a); }
+            0x28 => { let value = self.pull(bus); self.set_status(value); }
+
+            // ADC / SBC
+            0x69 => self.adc_mode(bus, Mode::Imm),
+            0x65 => self.adc_mode(bus, Mode::Zp),
+            0x75 => self.adc_mode(bus, Mode::ZpX),
+            0x6D => self.adc_mode(bus, Mode::Abs),
+            0x7D => self.adc_mode(bus, Mode::AbsX),
+            0x79 => self.adc_mode(bus, Mode::AbsY),
+            0x61 => self.adc_mode(bus, Mode::IndX),
+            0x71 => self.adc_mode(bus, Mode::IndY),
+            0xE9 => self.sbc_mode(bus, Mode::Imm),
+            0xE5 => self.sbc_mode(bus, Mode::Zp),
+            0xF5 => self.sbc_mode(bus, Mode::ZpX),
+            0xED => self.sbc_mode(bus, Mode::Abs),
+            0xFD => self.sbc_mode(bus, Mode::AbsX),
+            0xF9 => self.sbc_mode(bus, Mode::AbsY),
+            0xE1 => self.sbc_mode(bus, Mode::IndX),
+            0xF1 => self.sbc_mode(bus, Mode::IndY),
+
+            // AND / ORA / EOR
+            0x29 => self.and(bus, Mode::Imm),
+            0x25 => self.and(bus, Mode::Zp),
+            0x35 => self.and(bus, Mode::ZpX),
+            0x2D => self.and(bus, Mode::Abs),
+            0x3D => self.and(bus, Mode::AbsX),
+            0x39 => self.and(bus, Mode::AbsY),
+            0x21 => self.and(bus, Mode::IndX),
+            0x31 => self.and(bus, Mode::IndY),
+            0x09 => self.ora(bus, Mode::Imm),
+            0x05 => self.ora(bus, Mode::Zp),
+            0x15 => self.ora(bus, Mode::ZpX),
+            0x0D => self.ora(bus, Mode::Abs),
+            0x1D => self.ora(bus, Mode::AbsX),
+            0x19 => self.ora(bus, Mode::AbsY),
+            0x01 => self.ora(bus, Mode::IndX),
+            0x11 => self.ora(bus, Mode::IndY),
+            0x49 => self.eor(bus, Mode::Imm),
+            0x45 => self.eor(bus, Mode::Zp),
+            0x55 => self.eor(bus, Mode::ZpX),
+            0x4D => self.eor(bus, Mode::Abs),
+            0x5D => self.eor(bus, Mode::AbsX),
+            0x59 => self.eor(bus, Mode::AbsY),
+            0x41 => self.eor(bus, Mode::IndX),
+            0x51 => self.eor(bus, Mode::IndY),
+
+            // CMP / CPX / CPY
+            0xC9 => self.compare(bus, Mode::Imm, self.a),
+            0xC5 => self.compare(bus, Mode::Zp, self.a),
+            0xD5 => self.compare(bus, Mode::ZpX, self.a),
+            0xCD => self.compare(bus, Mode::Abs, self.a),
+            0xDD => self.compare(bus, Mode::AbsX, self.a),
+            0xD9 => self.compare(bus, Mode::AbsY, self.a),
+            0xC1 => self.compare(bus, Mode::IndX, self.a),
+            0xD1 => self.compare(bus, Mode::IndY, self.a),
+            0xE0 => self.compare(bus, Mode::Imm, self.x),
+            0xE4 => self.compare(bus, Mode::Zp, self.x),
+            0xEC => self.compare(bus, Mode::Abs, self.x),
+            0xC0 => self.compare(bus, Mode::Imm, self.y),
+            0xC4 => self.compare(bus, Mode::Zp, self.y),
+            0xCC => self.compare(bus, Mode::Abs, self.y),
+
+            // BIT
+            0x24 => self.bit(bus, Mode::Zp),
+            0x2C => self.bit(bus, Mode::Abs),
+
+            // INC / DEC memory
+            0xE6 => { self.modify(bus, Mode::Zp, |_, v| v.wrapping_add(1)); }
+            0xF6 => { self.modify(bus, Mode::ZpX, |_, v| v.wrapping_add(1)); }
+            0xEE => { self.modify(bus, Mode::Abs, |_, v| v.wrapping_add(1)); }
+            0xFE => { self.modify(bus, Mode::AbsX, |_, v| v.wrapping_add(1)); }
+            0xC6 => { self.modify(bus, Mode::Zp, |_, v| v.wrapping_sub(1)); }
+            0xD6 => { self.modify(bus, Mode::ZpX, |_, v| v.wrapping_sub(1)); }
+            0xCE => { self.modify(bus, Mode::Abs, |_, v| v.wrapping_sub(1)); }
+            0xDE => { self.modify(bus, Mode::AbsX, |_, v| v.wrapping_sub(1)); }
+
+            // INX / INY / DEX / DEY
+            0xE8 => { self.x = self.x.wrapping_add(1); self.update_zero_and_negative_flags(self.x); }
+            0xC8 => { self.y = self.y.wrapping_add(1); self.update_zero_and_negative_flags(self.y); }
+            0xCA => { self.x = self.x.wrapping_sub(1); self.update_zero_and_negative_flags(self.x); }
+            0x88 => { self.y = self.y.wrapping_sub(1); self.update_zero_and_negative_flags(self.y); }
+
+            // Shifts and rotates
+            0x0A => self.a = self.asl(self.a),
+            0x06 => { self.modify(bus, Mode::Zp, Self::asl); }
+            0x16 => { self.modify(bus, Mode::ZpX, Self::asl); }
+            0x0E => { self.modify(bus, Mode::Abs, Self::asl); }
+            0x1E => { self.modify(bus, Mode::AbsX, Self::asl); }
+            0x4A => self.a = self.lsr(self.a),
+            0x46 => { self.modify(bus, Mode::Zp, Self::lsr); }
+            0x56 => { self.modify(bus, Mode::ZpX, Self::lsr); }
+            0x4E => { self.modify(bus, Mode::Abs, Self::lsr); }
+            0x5E => { self.modify(bus, Mode::AbsX, Self::lsr); }
+            0x2A => self.a = self.rol(self.a),
+            0x26 => { self.modify(bus, Mode::Zp, Self::rol); }
+            0x36 => { self.modify(bus, Mode::ZpX, Self::rol); }
+            0x2E => { self.modify(bus, Mode::Abs, Self::rol); }
+            0x3E => { self.modify(bus, Mode::AbsX, Self::rol); }
+            0x6A => self.a = self.ror(self.a),
+            0x66 => { self.modify(bus, Mode::Zp, Self::ror); }
+            0x76 => { self.modify(bus, Mode::ZpX, Self::ror); }
+            0x6E => { self.modify(bus, Mode::Abs, Self::ror); }
+            0x7E => { self.modify(bus, Mode::AbsX, Self::ror); }
+
             // JMP - Jump
             0x4C => { // Absolute
                 let addr = self.read_absolute_addr(bus);
                 self.pc = addr;
-                3
             }
-            
+            0x6C => { // Indirect
+                let ptr = self.read_absolute_addr(bus);
+                self.pc = if V::CMOS {
+                    self.read_word(bus, ptr)
+                } else {
+                    // NMOS bug: the high byte is fetched without carrying into the page
+                    let lo = bus.read(ptr) as u16;
+                    let hi = bus.read((ptr & 0xFF00) | (ptr.wrapping_add(1) & 0x00FF)) as u16;
+                    (hi << 8) | lo
+                };
+                bus.indirect_jump(self.pc);
+            }
+
+            // Subroutines and interrupts
+            0x20 => { // JSR
+                let addr = self.read_absolute_addr(bus);
+                self.push_word(bus, self.pc.wrapping_sub(1));
+                self.pc = addr;
+            }
+            0x60 => { // RTS
+                self.pc = self.pull_word(bus).wrapping_add(1);
+            }
+            0x40 => { // RTI
+                let value = self.pull(bus);
+                self.set_status(value);
+                self.pc = self.pull_word(bus);
+            }
+            0x00 => { // BRK
+                self.push_word(bus, self.pc.wrapping_add(1));
+                let status = self.status | StatusFlags::BREAK | StatusFlags::UNUSED;
+                self.push(bus, status.bits());
+                self.status.insert(StatusFlags::INTERRUPT);
+                if V::CMOS {
+                    self.status.remove(StatusFlags::DECIMAL);
+                }
+                self.pc = self.read_word(bus, IRQ_VECTOR);
+            }
+
+            // Branches
+            0x10 => self.branch(bus, !self.status.contains(StatusFlags::NEGATIVE)),
+            0x30 => self.branch(bus, self.status.contains(StatusFlags::NEGATIVE)),
+            0x50 => self.branch(bus, !self.status.contains(StatusFlags::OVERFLOW)),
+            0x70 => self.branch(bus, self.status.contains(StatusFlags::OVERFLOW)),
+            0x90 => self.branch(bus, !self.status.contains(StatusFlags::CARRY)),
+            0xB0 => self.branch(bus, self.status.contains(StatusFlags::CARRY)),
+            0xD0 => self.branch(bus, !self.status.contains(StatusFlags::ZERO)),
+            0xF0 => self.branch(bus, self.status.contains(StatusFlags::ZERO)),
+
+            // Flag instructions
+            0x18 => self.status.remove(StatusFlags::CARRY),
+            0x38 => self.status.insert(StatusFlags::CARRY),
+            0x58 => self.status.remove(StatusFlags::INTERRUPT),
+            0x78 => self.status.insert(StatusFlags::INTERRUPT),
+            0xB8 => self.status.remove(StatusFlags::OVERFLOW),
+            0xD8 => self.status.remove(StatusFlags::DECIMAL),
+            0xF8 => self.status.insert(StatusFlags::DECIMAL),
+
             // NOP - No Operation
-            0xEA => 2,
-            
-            // Default - unimplemented
+            0xEA => {}
+
+            _ if V::CMOS => self.cmos_nop(opcode, bus),
+            _ => self.execute_undocumented(opcode, bus),
+        }
+    }
+
+    /// 65C02 additions and changed encodings; returns false to fall through
+    /// to the shared NMOS decoder
+    fn execute_cmos<B: CpuBus>(&mut self, opcode: u8, bus: &mut B) -> bool {
+        match opcode {
+            // (zp) addressing for the ALU group
+            0x12 => self.ora(bus, Mode::ZpInd),
+            0x32 => self.and(bus, Mode::ZpInd),
+            0x52 => self.eor(bus, Mode::ZpInd),
+            0x72 => self.adc_mode(bus, Mode::ZpInd),
+            0x92 => self.store(bus, Mode::ZpInd, self.a),
+            0xB2 => self.lda(bus, Mode::ZpInd),
+            0xD2 => self.compare(bus, Mode::ZpInd, self.a),
+            0xF2 => self.sbc_mode(bus, Mode::ZpInd),
+
+            // BIT with new addressing modes; immediate only touches Z
+            0x89 => {
+                let value = self.read_operand(bus, Mode::Imm);
+                self.status.set(StatusFlags::ZERO, self.a & value == 0);
+            }
+            0x34 => self.bit(bus, Mode::ZpX),
+            0x3C => self.bit(bus, Mode::AbsX),
+
+            // STZ - Store zero
+            0x64 => self.store(bus, Mode::Zp, 0),
+            0x74 => self.store(bus, Mode::ZpX, 0),
+            0x9C => self.store(bus, Mode::Abs, 0),
+            0x9E => self.store(bus, Mode::AbsX, 0),
+
+            // TSB / TRB - Test and set/reset bits
+            0x04 => self.test_bits(bus, Mode::Zp, true),
+            0x0C => self.test_bits(bus, Mode::Abs, true),
+            0x14 => self.test_bits(bus, Mode::Zp, false),
+            0x1C => self.test_bits(bus, Mode::Abs, false),
+
+            // INC A / DEC A
+            0x1A => { self.a = self.a.wrapping_add(1); self.update_zero_and_negative_flags(self.a); }
+            0x3A => { self.a = self.a.wrapping_sub(1); self.update_zero_and_negative_flags(self.a); }
+
+            // PHX / PHY / PLX / PLY
+            0xDA => self.push(bus, self.x),
+            0x5A => self.push(bus, self.y),
+            0xFA => { self.x = self.pull(bus); self.update_zero_and_negative_flags(self.x); }
+            0x7A => { self.y = self.pull(bus); self.update_zero_and_negative_flags(self.y); }
+
+            // BRA - Branch always
+            0x80 => {
+                self.branch(bus, true);
+                // The table already counts the taken-branch cycle
+                self.extra_cycles -= 1;
+            }
+
+            // JMP (abs,X)
+            0x7C => {
+                let ptr = self.read_absolute_addr(bus).wrapping_add(self.x as u16);
+                self.pc = self.read_word(bus, ptr);
+                bus.indirect_jump(self.pc);
+            }
+
+            // RMW abs,X shifts only take the extra cycle on a page crossing
+            0x1E | 0x3E | 0x5E | 0x7E => {
+                let op = match opcode {
+                    0x1E => Self::asl,
+                    0x3E => Self::rol,
+                    0x5E => Self::lsr,
+                    _ => Self::ror,
+                };
+                self.modify(bus, Mode::AbsX, op);
+                if self.page_crossed {
+                    self.extra_cycles += 1;
+                }
+            }
+
+            // RMB0-7 / SMB0-7
+            0x07 | 0x17 | 0x27 | 0x37 | 0x47 | 0x57 | 0x67 | 0x77 => {
+                let bit = opcode >> 4;
+                self.modify_quiet(bus, Mode::Zp, |v| v & !(1 << bit));
+            }
+            0x87 | 0x97 | 0xA7 | 0xB7 | 0xC7 | 0xD7 | 0xE7 | 0xF7 => {
+                let bit = (opcode >> 4) & 0x07;
+                self.modify_quiet(bus, Mode::Zp, |v| v | (1 << bit));
+            }
+
+            // BBR0-7 / BBS0-7
+            0x0F | 0x1F | 0x2F | 0x3F | 0x4F | 0x5F | 0x6F | 0x7F
+            | 0x8F | 0x9F | 0xAF | 0xBF | 0xCF | 0xDF | 0xEF | 0xFF => {
+                let addr = self.read_zero_page_addr(bus);
+                let value = bus.read(addr);
+                let bit = (opcode >> 4) & 0x07;
+                let set = value & (1 << bit) != 0;
+                self.branch(bus, set == (opcode & 0x80 != 0));
+            }
+
+            // WAI / STP
+            0xCB => self.waiting = true,
+            0xDB => self.halted = true,
+
+            _ => return false,
+        }
+        true
+    }
+
+    /// Undefined 65C02 opcodes are NOPs with fixed lengths
+    fn cmos_nop<B: CpuBus>(&mut self, opcode: u8, bus: &mut B) {
+        match opcode {
+            0x02 | 0x22 | 0x42 | 0x62 | 0x82 | 0xC2 | 0xE2 | 0x44 | 0x54 | 0xD4 | 0xF4 => {
+                self.pc = self.pc.wrapping_add(1);
+            }
+            0x5C | 0xDC | 0xFC => {
+                self.read_absolute_addr(bus);
+            }
+            _ => {}
+        }
+    }
+
+    /// Stable NMOS undocumented opcodes; the unstable ones use their common
+    /// "magic constant" approximations
+    fn execute_undocumented<B: CpuBus>(&mut self, opcode: u8, bus: &mut B) {
+        match opcode {
+            // NOPs with operands
+            0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => {}
+            0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 => { self.read_operand(bus, Mode::Imm); }
+            0x04 | 0x44 | 0x64 => { self.read_operand(bus, Mode::Zp); }
+            0x14 | 0x34 | 0x54 | 0x74 | 0xD4 | 0xF4 => { self.read_operand(bus, Mode::ZpX); }
+            0x0C => { self.read_operand(bus, Mode::Abs); }
+            0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => { self.read_operand(bus, Mode::AbsX); }
+
+            // LAX - LDA + LDX
+            0xA7 => self.lax(bus, Mode::Zp),
+            0xB7 => self.lax(bus, Mode::ZpY),
+            0xAF => self.lax(bus, Mode::Abs),
+            0xBF => self.lax(bus, Mode::AbsY),
+            0xA3 => self.lax(bus, Mode::IndX),
+            0xB3 => self.lax(bus, Mode::IndY),
+            0xAB => { // LXA (unstable)
+                let value = self.read_operand(bus, Mode::Imm);
+                self.a = (self.a | 0xEE) & value;
+                self.x = self.a;
+                self.update_zero_and_negative_flags(self.a);
+            }
+
+            // SAX - Store A & X
+            0x87 => self.store(bus, Mode::Zp, self.a & self.x),
+            0x97 => self.store(bus, Mode::ZpY, self.a & self.x),
+            0x8F => self.store(bus, Mode::Abs, self.a & self.x),
+            0x83 => self.store(bus, Mode::IndX, self.a & self.x),
+
+            // DCP - DEC + CMP
+            0xC7 => self.dcp(bus, Mode::Zp),
+            0xD7 => self.dcp(bus, Mode::ZpX),
+            0xCF => self.dcp(bus, Mode::Abs),
+            0xDF => self.dcp(bus, Mode::AbsX),
+            0xDB => self.dcp(bus, Mode::AbsY),
+            0xC3 => self.dcp(bus, Mode::IndX),
+            0xD3 => self.dcp(bus, Mode::IndY),
+
+            // ISC - INC + SBC
+            0xE7 => self.isc(bus, Mode::Zp),
+            0xF7 => self.isc(bus, Mode::ZpX),
+            0xEF => self.isc(bus, Mode::Abs),
+            0xFF => self.isc(bus, Mode::AbsX),
+            0xFB => self.isc(bus, Mode::AbsY),
+            0xE3 => self.isc(bus, Mode::IndX),
+            0xF3 => self.isc(bus, Mode::IndY),
+
+            // SLO - ASL + ORA
+            0x07 => self.slo(bus, Mode::Zp),
+            0x17 => self.slo(bus, Mode::ZpX),
+            0x0F => self.slo(bus, Mode::Abs),
+            0x1F => self.slo(bus, Mode::AbsX),
+            0x1B => self.slo(bus, Mode::AbsY),
+            0x03 => self.slo(bus, Mode::IndX),
+            0x13 => self.slo(bus, Mode::IndY),
+
+            // RLA - ROL + AND
+            0x27 => self.rla(bus, Mode::Zp),
+            0x37 => self.rla(bus, Mode::ZpX),
+            0x2F => self.rla(bus, Mode::Abs),
+            0x3F => self.rla(bus, Mode::AbsX),
+            0x3B => self.rla(bus, Mode::AbsY),
+            0x23 => self.rla(bus, Mode::IndX),
+            0x33 => self.rla(bus, Mode::IndY),
+
+            // SRE - LSR + EOR
+            0x47 => self.sre(bus, Mode::Zp),
+            0x57 => self.sre(bus, Mode::ZpX),
+            0x4F => self.sre(bus, Mode::Abs),
+            0x5F => self.sre(bus, Mode::AbsX),
+            0x5B => self.sre(bus, Mode::AbsY),
+            0x43 => self.sre(bus, Mode::IndX),
+            0x53 => self.sre(bus, Mode::IndY),
+
+            // RRA - ROR + ADC
+            0x67 => self.rra(bus, Mode::Zp),
+            0x77 => self.rra(bus, Mode::ZpX),
+            0x6F => self.rra(bus, Mode::Abs),
+            0x7F => self.rra(bus, Mode::AbsX),
+            0x7B => self.rra(bus, Mode::AbsY),
+            0x63 => self.rra(bus, Mode::IndX),
+            0x73 => self.rra(bus, Mode::IndY),
+
+            // Immediate combinations
+            0x0B | 0x2B => { // ANC
+                self.and(bus, Mode::Imm);
+                self.status.set(StatusFlags::CARRY, self.a & 0x80 != 0);
+            }
+            0x4B => { // ALR
+                self.and(bus, Mode::Imm);
+                self.a = self.lsr(self.a);
+            }
+            0x6B => { // ARR
+                let value = self.read_operand(bus, Mode::Imm);
+                self.arr(value);
+            }
+            0xCB => { // SBX (AXS)
+                let value = self.read_operand(bus, Mode::Imm);
+                let and = self.a & self.x;
+                self.status.set(StatusFlags::CARRY, and >= value);
+                self.x = and.wrapping_sub(value);
+                self.update_zero_and_negative_flags(self.x);
+            }
+            0xEB => self.sbc_mode(bus, Mode::Imm),
+            0x8B => { // ANE (unstable)
+                let value = self.read_operand(bus, Mode::Imm);
+                self.a = (self.a | 0xEE) & self.x & value;
+                self.update_zero_and_negative_flags(self.a);
+            }
+
+            // Stores ANDed with the high address byte + 1 (unstable)
+            0x9C => self.store_high_and(bus, Mode::AbsX, self.y),
+            0x9E => self.store_high_and(bus, Mode::AbsY, self.x),
+            0x9F => self.store_high_and(bus, Mode::AbsY, self.a & self.x),
+            0x93 => self.store_high_and(bus, Mode::IndY, self.a & self.x),
+            0x9B => { // TAS
+                self.sp = self.a & self.x;
+                self.store_high_and(bus, Mode::AbsY, self.sp);
+            }
+            0xBB => { // LAS
+                let value = self.read_operand(bus, Mode::AbsY) & self.sp;
+                self.a = value;
+                self.x = value;
+                self.sp = value;
+                self.update_zero_and_negative_flags(value);
+            }
+
+            // JAM - locks up the CPU
             _ => {
-                2
+                log::warn!("CPU jammed by opcode 0x{:02X} at PC: 0x{:04X}", opcode, self.pc.wrapping_sub(1));
+                self.halted = true;
             }
         }
     }
-    
+
     // Addressing modes
-    fn read_immediate(&mut self, bus: &Bus) -> u8 {
-        let value = bus.read(self.pc);
+    fn read_immediate<B: CpuBus>(&mut self, bus: &mut B) -> u8 {
+        let value = bus.fetch(self.pc);
         self.pc = self.pc.wrapping_add(1);
         value
     }
-    
-    fn read_zero_page_addr(&mut self, bus: &Bus) -> u16 {
-        let addr = bus.read(self.pc) as u16;
+
+    fn read_zero_page_addr<B: CpuBus>(&mut self, bus: &mut B) -> u16 {
+        let addr = bus.fetch(self.pc) as u16;
         self.pc = self.pc.wrapping_add(1);
         addr
     }
-    
-    fn read_absolute_addr(&mut self, bus: &Bus) -> u16 {
-        let lo = bus.read(self.pc) as u16;
-        let hi = bus.read(self.pc.wrapping_add(1)) as u16;
+
+    fn read_absolute_addr<B: CpuBus>(&mut self, bus: &mut B) -> u16 {
+        let lo = bus.fetch(self.pc) as u16;
+        let hi = bus.fetch(self.pc.wrapping_add(1)) as u16;
         self.pc = self.pc.wrapping_add(2);
         (hi << 8) | lo
     }
-    
+
+    /// Word read that wraps within the zero page
+    fn read_zero_page_word<B: CpuBus>(&mut self, bus: &mut B, ptr: u8) -> u16 {
+        let lo = bus.read(ptr as u16) as u16;
+        let hi = bus.read(ptr.wrapping_add(1) as u16) as u16;
+        (hi << 8) | lo
+    }
+
+    fn read_word<B: CpuBus>(&mut self, bus: &mut B, addr: u16) -> u16 {
+        let lo = bus.read(addr) as u16;
+        let hi = bus.read(addr.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    /// Effective address for a memory mode; sets `page_crossed` for indexed modes
+    fn operand_addr<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) -> u16 {
+        match mode {
+            Mode::Imm => {
+                let addr = self.pc;
+                self.pc = self.pc.wrapping_add(1);
+                addr
+            }
+            Mode::Zp => self.read_zero_page_addr(bus),
+            Mode::ZpX => (self.read_zero_page_addr(bus) as u8).wrapping_add(self.x) as u16,
+            Mode::ZpY => (self.read_zero_page_addr(bus) as u8).wrapping_add(self.y) as u16,
+            Mode::Abs => self.read_absolute_addr(bus),
+            Mode::AbsX => {
+                let base = self.read_absolute_addr(bus);
+                self.indexed(base, self.x)
+            }
+            Mode::AbsY => {
+                let base = self.read_absolute_addr(bus);
+                self.indexed(base, self.y)
+            }
+            Mode::IndX => {
+                let ptr = (self.read_zero_page_addr(bus) as u8).wrapping_add(self.x);
+                self.read_zero_page_word(bus, ptr)
+            }
+            Mode::IndY => {
+                let ptr = self.read_zero_page_addr(bus) as u8;
+                let base = self.read_zero_page_word(bus, ptr);
+                self.indexed(base, self.y)
+            }
+            Mode::ZpInd => {
+                let ptr = self.read_zero_page_addr(bus) as u8;
+                self.read_zero_page_word(bus, ptr)
+            }
+        }
+    }
+
+    fn indexed(&mut self, base: u16, index: u8) -> u16 {
+        let addr = base.wrapping_add(index as u16);
+        self.page_crossed = (base & 0xFF00) != (addr & 0xFF00);
+        addr
+    }
+
+    /// Read an operand, charging the page-crossing cycle where the hardware does
+    fn read_operand<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) -> u8 {
+        if mode == Mode::Imm {
+            return self.read_immediate(bus);
+        }
+        let addr = self.operand_addr(bus, mode);
+        if self.page_crossed {
+            self.extra_cycles += 1;
+        }
+        match mode {
+            Mode::IndX | Mode::IndY | Mode::ZpInd => bus.read_indirect(addr),
+            _ => bus.read(addr),
+        }
+    }
+
+    // Instructions
+    fn lda<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        self.a = self.read_operand(bus, mode);
+        self.update_zero_and_negative_flags(self.a);
+    }
+
+    fn ldx<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        self.x = self.read_operand(bus, mode);
+        self.update_zero_and_negative_flags(self.x);
+    }
+
+    fn ldy<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        self.y = self.read_operand(bus, mode);
+        self.update_zero_and_negative_flags(self.y);
+    }
+
+    fn lax<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        self.a = self.read_operand(bus, mode);
+        self.x = self.a;
+        self.update_zero_and_negative_flags(self.a);
+    }
+
+    fn store<B: CpuBus>(&mut self, bus: &mut B, mode: Mode, value: u8) {
+        let addr = self.operand_addr(bus, mode);
+        bus.write(addr, value);
+    }
+
+    fn store_high_and<B: CpuBus>(&mut self, bus: &mut B, mode: Mode, value: u8) {
+        let addr = self.operand_addr(bus, mode);
+        let value = value & ((addr >> 8) as u8).wrapping_add(1);
+        bus.write(addr, value);
+    }
+
+    fn and<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        self.a &= self.read_operand(bus, mode);
+        self.update_zero_and_negative_flags(self.a);
+    }
+
+    fn ora<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        self.a |= self.read_operand(bus, mode);
+        self.update_zero_and_negative_flags(self.a);
+    }
+
+    fn eor<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        self.a ^= self.read_operand(bus, mode);
+        self.update_zero_and_negative_flags(self.a);
+    }
+
+    fn compare<B: CpuBus>(&mut self, bus: &mut B, mode: Mode, register: u8) {
+        let value = self.read_operand(bus, mode);
+        self.status.set(StatusFlags::CARRY, register >= value);
+        self.update_zero_and_negative_flags(register.wrapping_sub(value));
+    }
+
+    fn bit<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        let value = self.read_operand(bus, mode);
+        self.status.set(StatusFlags::ZERO, self.a & value == 0);
+        self.status.set(StatusFlags::OVERFLOW, value & 0x40 != 0);
+        self.status.set(StatusFlags::NEGATIVE, value & 0x80 != 0);
+    }
+
+    fn test_bits<B: CpuBus>(&mut self, bus: &mut B, mode: Mode, set: bool) {
+        let addr = self.operand_addr(bus, mode);
+        let value = bus.read(addr);
+        self.status.set(StatusFlags::ZERO, self.a & value == 0);
+        let result = if set { value | self.a } else { value & !self.a };
+        bus.write(addr, result);
+    }
+
+    /// Read-modify-write on memory; updates N/Z from the result
+    fn modify<B: CpuBus>(&mut self, bus: &mut B, mode: Mode, op: fn(&mut Self, u8) -> u8) -> u8 {
+        let addr = self.operand_addr(bus, mode);
+        let value = bus.read(addr);
+        if !V::CMOS {
+            // NMOS writes the unmodified value back first
+            bus.write(addr, value);
+        }
+        let result = op(self, value);
+        bus.write(addr, result);
+        self.update_zero_and_negative_flags(result);
+        result
+    }
+
+    /// Read-modify-write without touching any flags (RMB/SMB)
+    fn modify_quiet<B: CpuBus>(&mut self, bus: &mut B, mode: Mode, op: impl Fn(u8) -> u8) {
+        let addr = self.operand_addr(bus, mode);
+        let value = bus.read(addr);
+        bus.write(addr, op(value));
+    }
+
+    fn asl(&mut self, value: u8) -> u8 {
+        self.status.set(StatusFlags::CARRY, value & 0x80 != 0);
+        let result = value << 1;
+        self.update_zero_and_negative_flags(result);
+        result
+    }
+
+    fn lsr(&mut self, value: u8) -> u8 {
+        self.status.set(StatusFlags::CARRY, value & 0x01 != 0);
+        let result = value >> 1;
+        self.update_zero_and_negative_flags(result);
+        result
+    }
+
+    fn rol(&mut self, value: u8) -> u8 {
+        let carry = self.status.contains(StatusFlags::CARRY) as u8;
+        self.status.set(StatusFlags::CARRY, value & 0x80 != 0);
+        let result = (value << 1) | carry;
+        self.update_zero_and_negative_flags(result);
+        result
+    }
+
+    fn ror(&mut self, value: u8) -> u8 {
+        let carry = self.status.contains(StatusFlags::CARRY) as u8;
+        self.status.set(StatusFlags::CARRY, value & 0x01 != 0);
+        let result = (value >> 1) | (carry << 7);
+        self.update_zero_and_negative_flags(result);
+        result
+    }
+
+    fn dcp<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        let value = self.modify(bus, mode, |_, v| v.wrapping_sub(1));
+        self.status.set(StatusFlags::CARRY, self.a >= value);
+        self.update_zero_and_negative_flags(self.a.wrapping_sub(value));
+    }
+
+    fn isc<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        let value = self.modify(bus, mode, |_, v| v.wrapping_add(1));
+        self.sbc(value);
+    }
+
+    fn slo<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        let value = self.modify(bus, mode, Self::asl);
+        self.a |= value;
+        self.update_zero_and_negative_flags(self.a);
+    }
+
+    fn rla<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        let value = self.modify(bus, mode, Self::rol);
+        self.a &= value;
+        self.update_zero_and_negative_flags(self.a);
+    }
+
+    fn sre<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        let value = self.modify(bus, mode, Self::lsr);
+        self.a ^= value;
+        self.update_zero_and_negative_flags(self.a);
+    }
+
+    fn rra<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        let value = self.modify(bus, mode, Self::ror);
+        self.adc(value);
+    }
+
+    fn arr(&mut self, value: u8) {
+        let carry = self.status.contains(StatusFlags::CARRY) as u8;
+        let and = self.a & value;
+        self.a = (and >> 1) | (carry << 7);
+        self.update_zero_and_negative_flags(self.a);
+        self.status.set(StatusFlags::CARRY, self.a & 0x40 != 0);
+        self.status.set(StatusFlags::OVERFLOW, ((self.a >> 6) ^ (self.a >> 5)) & 0x01 != 0);
+    }
+
+    fn adc_mode<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        let value = self.read_operand(bus, mode);
+        self.adc(value);
+    }
+
+    fn sbc_mode<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        let value = self.read_operand(bus, mode);
+        self.sbc(value);
+    }
+
+    fn decimal_active(&self) -> bool {
+        V::DECIMAL_MODE && self.status.contains(StatusFlags::DECIMAL)
+    }
+
+    fn adc(&mut self, value: u8) {
+        if self.decimal_active() {
+            if V::CMOS {
+                self.adc_decimal_cmos(value);
+            } else {
+                self.adc_decimal_nmos(value);
+            }
+            return;
+        }
+
+        self.add_binary(value);
+    }
+
+    fn sbc(&mut self, value: u8) {
+        if self.decimal_active() {
+            if V::CMOS {
+                self.sbc_decimal_cmos(value);
+            } else {
+                self.sbc_decimal_nmos(value);
+            }
+            return;
+        }
+
+        self.add_binary(!value);
+    }
+
+    /// Binary add with carry; SBC is the same operation on the inverted operand
+    fn add_binary(&mut self, value: u8) {
+        let carry = self.status.contains(StatusFlags::CARRY) as u16;
+        let sum = self.a as u16 + value as u16 + carry;
+        let result = sum as u8;
+        self.status.set(StatusFlags::CARRY, sum > 0xFF);
+        self.status.set(StatusFlags::OVERFLOW, (self.a ^ result) & (value ^ result) & 0x80 != 0);
+        self.a = result;
+        self.update_zero_and_negative_flags(result);
+    }
+
+    /// NMOS BCD add: Z comes from the binary sum, N and V from the
+    /// intermediate result before the high nibble is adjusted
+    fn adc_decimal_nmos(&mut self, value: u8) {
+        let a = self.a as u16;
+        let m = value as u16;
+        let carry = self.status.contains(StatusFlags::CARRY) as u16;
+
+        let mut lo = (a & 0x0F) + (m & 0x0F) + carry;
+        if lo > 0x09 {
+            lo += 0x06;
+        }
+        let mut hi = (a >> 4) + (m >> 4) + (lo > 0x0F) as u16;
+
+        self.status.set(StatusFlags::ZERO, (a + m + carry) & 0xFF == 0);
+        self.status.set(StatusFlags::NEGATIVE, hi & 0x08 != 0);
+        let intermediate = ((hi << 4) & 0xF0) as u8;
+        self.status.set(
+            StatusFlags::OVERFLOW,
+            (self.a ^ intermediate) & 0x80 != 0 && (self.a ^ value) & 0x80 == 0,
+        );
+
+        if hi > 0x09 {
+            hi += 0x06;
+        }
+        self.status.set(StatusFlags::CARRY, hi > 0x0F);
+        self.a = (((hi << 4) & 0xF0) | (lo & 0x0F)) as u8;
+    }
+
+    /// NMOS BCD subtract: all flags come from the binary difference
+    fn sbc_decimal_nmos(&mut self, value: u8) {
+        let a = self.a as i16;
+        let m = value as i16;
+        let borrow = 1 - self.status.contains(StatusFlags::CARRY) as i16;
+
+        let mut lo = (a & 0x0F) - (m & 0x0F) - borrow;
+        let mut hi = (a >> 4) - (m >> 4);
+        if lo & 0x10 != 0 {
+            lo -= 0x06;
+            hi -= 1;
+        }
+        if hi & 0x10 != 0 {
+            hi -= 0x06;
+        }
+        let result = (((hi << 4) & 0xF0) | (lo & 0x0F)) as u8;
+
+        self.add_binary(!value);
+        self.a = result;
+    }
+
+    /// 65C02 BCD add: N and Z are valid for the decimal result, one extra cycle
+    fn adc_decimal_cmos(&mut self, value: u8) {
+        let a = self.a as i16;
+        let m = value as i16;
+        let carry = self.status.contains(StatusFlags::CARRY) as i16;
+
+        let mut lo = (a & 0x0F) + (m & 0x0F) + carry;
+        if lo >= 0x0A {
+            lo = ((lo + 0x06) & 0x0F) + 0x10;
+        }
+        let mut sum = (a & 0xF0) + (m & 0xF0) + lo;
+        let intermediate = sum as u8;
+        self.status.set(
+            StatusFlags::OVERFLOW,
+            (self.a ^ intermediate) & (value ^ intermediate) & 0x80 != 0,
+        );
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        self.status.set(StatusFlags::CARRY, sum >= 0x100);
+        self.a = sum as u8;
+        self.update_zero_and_negative_flags(self.a);
+        self.extra_cycles += 1;
+    }
+
+    /// 65C02 BCD subtract: C and V from the binary difference, N and Z from
+    /// the decimal result, one extra cycle
+    fn sbc_decimal_cmos(&mut self, value: u8) {
+        let a = self.a as i16;
+        let m = value as i16;
+        let carry = self.status.contains(StatusFlags::CARRY) as i16;
+
+        let lo = (a & 0x0F) - (m & 0x0F) + carry - 1;
+        let mut diff = a - m + carry - 1;
+        if diff < 0 {
+            diff -= 0x60;
+        }
+        if lo < 0 {
+            diff -= 0x06;
+        }
+
+        self.add_binary(!value);
+        self.a = diff as u8;
+        self.update_zero_and_negative_flags(self.a);
+        self.extra_cycles += 1;
+    }
+
+    fn branch<B: CpuBus>(&mut self, bus: &mut B, condition: bool) {
+        let offset = self.read_immediate(bus) as i8;
+        if condition {
+            let target = self.pc.wrapping_add(offset as u16);
+            self.extra_cycles += 1;
+            if (target & 0xFF00) != (self.pc & 0xFF00) {
+                self.extra_cycles += 1;
+            }
+            self.pc = target;
+        }
+    }
+
+    // Stack helpers
+    fn push<B: CpuBus>(&mut self, bus: &mut B, value: u8) {
+        bus.write(0x0100 | self.sp as u16, value);
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    fn pull<B: CpuBus>(&mut self, bus: &mut B) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        bus.read(0x0100 | self.sp as u16)
+    }
+
+    fn push_word<B: CpuBus>(&mut self, bus: &mut B, value: u16) {
+        self.push(bus, (value >> 8) as u8);
+        self.push(bus, value as u8);
+    }
+
+    fn pull_word<B: CpuBus>(&mut self, bus: &mut B) -> u16 {
+        let lo = self.pull(bus) as u16;
+        let hi = self.pull(bus) as u16;
+        (hi << 8) | lo
+    }
+
     // Helper functions
+    fn set_status(&mut self, value: u8) {
+        self.status = (StatusFlags::from_bits_truncate(value) | StatusFlags::UNUSED) - StatusFlags::BREAK;
+    }
+
     fn update_zero_and_negative_flags(&mut self, value: u8) {
         self.status.set(StatusFlags::ZERO, value == 0);
         self.status.set(StatusFlags::NEGATIVE, (value & 0x80) != 0);
     }
 }
+
+impl<V: Variant> Default for CPU6502<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
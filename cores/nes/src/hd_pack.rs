@@ -0,0 +1,738 @@
+/// NES HD Packs
+///
+/// Mesen-style packs: a `hires.txt` rule file plus images that replace
+/// 8x8 CHR tiles, matched by tile and palette, with higher resolution art.
+/// The PPU records which tile, palette and bank produced every pixel
+/// (`HdFrame`); `HdPack::compose` turns that into a scaled framebuffer.
+/// Image decoding is left to the caller so the core stays codec-free.
+
+use std::collections::{HashMap, HashSet};
+use anyhow::{Context, Result};
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+/// Tile identity: CHR ROM tile index, or the tile's 16 bytes on CHR RAM games
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TileKey {
+    #[default]
+    None,
+    Rom(u32),
+    Ram([u8; 16]),
+}
+
+/// The tile one layer contributed to a pixel
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HdTileInfo {
+    pub key: TileKey,
+    /// The four palette RAM entries used, color 0 in the top byte
+    pub palette: u32,
+    /// Pixel position inside the unflipped tile
+    pub offset_x: u8,
+    pub offset_y: u8,
+    /// Native 2-bit pixel value (0 = transparent)
+    pub pixel: u8,
+    /// Native output color
+    pub color: [u8; 3],
+    pub hflip: bool,
+    pub vflip: bool,
+    /// Sprite drawn behind the background
+    pub behind_bg: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HdPixelInfo {
+    pub bg: HdTileInfo,
+    pub sprite: HdTileInfo,
+}
+
+/// Everything the PPU recorded about one frame
+pub struct HdFrame {
+    pub pixels: Vec<HdPixelInfo>,
+    /// Universal background color per scanline
+    pub backdrop: Vec<[u8; 3]>,
+    pub scroll_x: u16,
+    pub scroll_y: u16,
+    pub frame: u64,
+}
+
+impl HdFrame {
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![HdPixelInfo::default(); SCREEN_WIDTH * SCREEN_HEIGHT],
+            backdrop: vec![[0; 3]; SCREEN_HEIGHT],
+            scroll_x: 0,
+            scroll_y: 0,
+            frame: 0,
+        }
+    }
+
+    fn pixel(&self, x: i32, y: i32) -> Option<&HdPixelInfo> {
+        if (0..SCREEN_WIDTH as i32).contains(&x) && (0..SCREEN_HEIGHT as i32).contains(&y) {
+            Some(&self.pixels[y as usize * SCREEN_WIDTH + x as usize])
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for HdFrame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decoded RGBA image
+pub struct HdImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl HdImage {
+    fn rgba(&self, x: i32, y: i32) -> Option<[u8; 4]> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return None;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        Some([self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]])
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+    GreaterEqual,
+    LessEqual,
+}
+
+impl Operator {
+    fn parse(text: &str) -> Result<Self> {
+        Ok(match text {
+            "==" => Self::Equal,
+            "!=" => Self::NotEqual,
+            ">" => Self::Greater,
+            "<" => Self::Less,
+            ">=" => Self::GreaterEqual,
+            "<=" => Self::LessEqual,
+            _ => anyhow::bail!("Unknown operator '{}'", text),
+        })
+    }
+
+    fn apply(self, a: u8, b: u8) -> bool {
+        match self {
+            Self::Equal => a == b,
+            Self::NotEqual => a != b,
+            Self::Greater => a > b,
+            Self::Less => a < b,
+            Self::GreaterEqual => a >= b,
+            Self::LessEqual => a <= b,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    TileAtPosition { x: i32, y: i32, key: TileKey, palette: u32 },
+    SpriteAtPosition { x: i32, y: i32, key: TileKey, palette: u32 },
+    TileNearby { x: i32, y: i32, key: TileKey, palette: u32 },
+    SpriteNearby { x: i32, y: i32, key: TileKey, palette: u32 },
+    MemoryCheck { a: u16, op: Operator, b: u16, mask: u8 },
+    MemoryCheckConstant { addr: u16, op: Operator, value: u8, mask: u8 },
+    FrameRange { divisor: u64, compare: u64 },
+    HorizontalMirror,
+    VerticalMirror,
+    BgPriority,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ConditionRef {
+    index: usize,
+    negate: bool,
+}
+
+#[derive(Debug, Clone)]
+struct TileRule {
+    image: usize,
+    x: u32,
+    y: u32,
+    brightness: f32,
+    conditions: Vec<ConditionRef>,
+}
+
+#[derive(Debug, Clone)]
+struct BackgroundRule {
+    image: usize,
+    brightness: f32,
+    h_scroll_ratio: f32,
+    v_scroll_ratio: f32,
+    left: i32,
+    top: i32,
+    conditions: Vec<ConditionRef>,
+}
+
+/// State a condition is evaluated against
+struct EvalContext<'a> {
+    frame: &'a HdFrame,
+    memory: &'a dyn Fn(u16) -> u8,
+    x: i32,
+    y: i32,
+    tile: Option<&'a HdTileInfo>,
+}
+
+pub struct HdPack {
+    pub scale: u32,
+    /// `<img>` sheets, indexed by `<tile>` rules in declaration order
+    images: Vec<HdImage>,
+    /// `<background>` images, kept apart so they don't shift those indices
+    background_images: Vec<HdImage>,
+    conditions: Vec<Condition>,
+    tiles: HashMap<(TileKey, u32), Vec<TileRule>>,
+    /// Rules flagged as default apply to the tile under any palette
+    default_tiles: HashMap<TileKey, Vec<TileRule>>,
+    backgrounds: Vec<BackgroundRule>,
+}
+
+impl HdPack {
+    /// Parse a `hires.txt`; `load_image` resolves `<img>`/`<background>` file names
+    pub fn parse(text: &str, mut load_image: impl FnMut(&str) -> Result<HdImage>) -> Result<Self> {
+        let mut pack = Self {
+            scale: 1,
+            images: Vec::new(),
+            background_images: Vec::new(),
+            conditions: vec![Condition::HorizontalMirror, Condition::VerticalMirror, Condition::BgPriority],
+            tiles: HashMap::new(),
+            default_tiles: HashMap::new(),
+            backgrounds: Vec::new(),
+        };
+        let mut names: HashMap<String, usize> = HashMap::new();
+        names.insert("hmirror".to_string(), 0);
+        names.insert("vmirror".to_string(), 1);
+        names.insert("bgpriority".to_string(), 2);
+
+        for (number, line) in text.lines().enumerate() {
+            pack.parse_line(line.trim(), &mut names, &mut load_image)
+                .with_context(|| format!("hires.txt line {}", number + 1))?;
+        }
+
+        log::info!("HD pack: scale {}x, {} images, {} tile rules, {} backgrounds",
+                   pack.scale, pack.images.len(),
+                   pack.tiles.values().map(Vec::len).sum::<usize>(), pack.backgrounds.len());
+        Ok(pack)
+    }
+
+    fn parse_line(
+        &mut self,
+        line: &str,
+        names: &mut HashMap<String, usize>,
+        load_image: &mut impl FnMut(&str) -> Result<HdImage>,
+    ) -> Result<()> {
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+
+        // Optional [cond1&!cond2] prefix
+        let (conditions, line) = match line.strip_prefix('[') {
+            Some(rest) => {
+                let end = rest.find(']').context("Unterminated condition list")?;
+                let refs = rest[..end]
+                    .split('&')
+                    .map(|name| {
+                        let name = name.trim();
+                        let (negate, name) = match name.strip_prefix('!') {
+                            Some(name) => (true, name),
+                            None => (false, name),
+                        };
+                        let index = *names.get(name).with_context(|| format!("Unknown condition '{}'", name))?;
+                        Ok(ConditionRef { index, negate })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                (refs, &rest[end + 1..])
+            }
+            None => (Vec::new(), line),
+        };
+
+        let rest = line.strip_prefix('<').context("Expected a <tag>")?;
+        let end = rest.find('>').context("Unterminated tag")?;
+        let (tag, args) = (&rest[..end], &rest[end + 1..]);
+        let fields: Vec<&str> = args.split(',').map(str::trim).collect();
+
+        match tag {
+            "ver" => {}
+            "scale" => {
+                self.scale = args.trim().parse().context("Invalid scale")?;
+                if self.scale == 0 {
+                    anyhow::bail!("Scale must be at least 1");
+                }
+            }
+            "img" => {
+                let image = load_image(args.trim())?;
+                self.images.push(image);
+            }
+            "condition" => {
+                let name = fields.first().context("Condition needs a name")?.to_string();
+                let kind = fields.get(1).context("Condition needs a type")?;
+                let condition = Self::parse_condition(kind, &fields[2..])?;
+                self.conditions.push(condition);
+                names.insert(name, self.conditions.len() - 1);
+            }
+            "tile" => {
+                if fields.len() < 7 {
+                    anyhow::bail!("<tile> needs 7 fields");
+                }
+                let image: usize = fields[0].parse().context("Invalid image index")?;
+                if image >= self.images.len() {
+                    anyhow::bail!("Image index {} out of range", image);
+                }
+                let key = parse_tile_key(fields[1])?;
+                let palette = parse_hex(fields[2])?;
+                let rule = TileRule {
+                    image,
+                    x: fields[3].parse().context("Invalid x")?,
+                    y: fields[4].parse().context("Invalid y")?,
+                    brightness: fields[5].parse().context("Invalid brightness")?,
+                    conditions,
+                };
+                if fields[6].eq_ignore_ascii_case("Y") {
+                    self.default_tiles.entry(key).or_default().push(rule.clone());
+                }
+                self.tiles.entry((key, palette)).or_default().push(rule);
+            }
+            "background" => {
+                let file = fields.first().context("<background> needs a file")?;
+                let image = load_image(file)?;
+                self.background_images.push(image);
+                let float = |i: usize, default: f32| -> Result<f32> {
+                    match fields.get(i) {
+                        Some(value) if !value.is_empty() => Ok(value.parse()?),
+                        _ => Ok(default),
+                    }
+                };
+                self.backgrounds.push(BackgroundRule {
+                    image: self.background_images.len() - 1,
+                    brightness: float(1, 1.0)?,
+                    h_scroll_ratio: float(2, 0.0)?,
+                    v_scroll_ratio: float(3, 0.0)?,
+                    left: float(5, 0.0)? as i32,
+                    top: float(6, 0.0)? as i32,
+                    conditions,
+                });
+            }
+            // Audio replacement, ROM patches and overscan are not supported
+            other => log::debug!("HD pack: ignoring <{}>", other),
+        }
+        Ok(())
+    }
+
+    fn parse_condition(kind: &str, args: &[&str]) -> Result<Condition> {
+        let int = |i: usize| -> Result<i32> {
+            args.get(i).context("Missing condition argument")?.parse().context("Invalid number")
+        };
+        let hex = |i: usize| -> Result<u32> { parse_hex(args.get(i).context("Missing condition argument")?) };
+        let tile = |i: usize| -> Result<TileKey> { parse_tile_key(args.get(i).context("Missing tile")?) };
+        let mask = || -> Result<u8> {
+            match args.get(3) {
+                Some(mask) => Ok(parse_hex(mask)? as u8),
+                None => Ok(0xFF),
+            }
+        };
+
+        Ok(match kind {
+            "tileAtPosition" => Condition::TileAtPosition { x: int(0)?, y: int(1)?, key: tile(2)?, palette: hex(3)? },
+            "spriteAtPosition" => Condition::SpriteAtPosition { x: int(0)?, y: int(1)?, key: tile(2)?, palette: hex(3)? },
+            "tileNearby" => Condition::TileNearby { x: int(0)?, y: int(1)?, key: tile(2)?, palette: hex(3)? },
+            "spriteNearby" => Condition::SpriteNearby { x: int(0)?, y: int(1)?, key: tile(2)?, palette: hex(3)? },
+            "memoryCheck" => Condition::MemoryCheck {
+                a: hex(0)? as u16,
+                op: Operator::parse(args.get(1).context("Missing operator")?)?,
+                b: hex(2)? as u16,
+                mask: mask()?,
+            },
+            "memoryCheckConstant" => Condition::MemoryCheckConstant {
+                addr: hex(0)? as u16,
+                op: Operator::parse(args.get(1).context("Missing operator")?)?,
+                value: hex(2)? as u8,
+                mask: mask()?,
+            },
+            "frameRange" => Condition::FrameRange {
+                divisor: int(0)?.max(1) as u64,
+                compare: int(1)?.max(0) as u64,
+            },
+            _ => anyhow::bail!("Unknown condition type '{}'", kind),
+        })
+    }
+
+    fn check(&self, refs: &[ConditionRef], ctx: &EvalContext) -> bool {
+        refs.iter().all(|r| self.evaluate(&self.conditions[r.index], ctx) != r.negate)
+    }
+
+    fn evaluate(&self, condition: &Condition, ctx: &EvalContext) -> bool {
+        let matches = |tile: &HdTileInfo, key: &TileKey, palette: u32| tile.key == *key && tile.palette == palette;
+        // Top-left of the tile being drawn, for the relative conditions
+        let (origin_x, origin_y) = match ctx.tile {
+            Some(tile) => (ctx.x - tile.offset_x as i32, ctx.y - tile.offset_y as i32),
+            None => (ctx.x, ctx.y),
+        };
+
+        match condition {
+            Condition::TileAtPosition { x, y, key, palette } => {
+                ctx.frame.pixel(*x, *y).is_some_and(|p| matches(&p.bg, key, *palette))
+            }
+            Condition::SpriteAtPosition { x, y, key, palette } => {
+                ctx.frame.pixel(*x, *y).is_some_and(|p| matches(&p.sprite, key, *palette))
+            }
+            Condition::TileNearby { x, y, key, palette } => {
+                ctx.frame.pixel(origin_x + x, origin_y + y).is_some_and(|p| matches(&p.bg, key, *palette))
+            }
+            Condition::SpriteNearby { x, y, key, palette } => {
+                ctx.frame.pixel(origin_x + x, origin_y + y).is_some_and(|p| matches(&p.sprite, key, *palette))
+            }
+            Condition::MemoryCheck { a, op, b, mask } => {
+                op.apply((ctx.memory)(*a) & mask, (ctx.memory)(*b) & mask)
+            }
+            Condition::MemoryCheckConstant { addr, op, value, mask } => {
+                op.apply((ctx.memory)(*addr) & mask, *value)
+            }
+            Condition::FrameRange { divisor, compare } => ctx.frame.frame % divisor >= *compare,
+            Condition::HorizontalMirror => ctx.tile.is_some_and(|t| t.hflip),
+            Condition::VerticalMirror => ctx.tile.is_some_and(|t| t.vflip),
+            Condition::BgPriority => ctx.tile.is_some_and(|t| t.behind_bg),
+        }
+    }
+
+    fn find_tile(&self, ctx: &EvalContext, tile: &HdTileInfo) -> Option<&TileRule> {
+        let exact = self.tiles.get(&(tile.key, tile.palette)).into_iter().flatten();
+        let fallback = self.default_tiles.get(&tile.key).into_iter().flatten();
+        exact.chain(fallback).find(|rule| self.check(&rule.conditions, ctx))
+    }
+
+    /// Output dimensions for this pack's scale
+    pub fn frame_size(&self) -> (u32, u32) {
+        (SCREEN_WIDTH as u32 * self.scale, SCREEN_HEIGHT as u32 * self.scale)
+    }
+
+    /// Build the scaled RGBA frame. `memory` reads CPU address space
+    /// without side effects, for the memory check conditions.
+    pub fn compose(&self, frame: &HdFrame, memory: &dyn Fn(u16) -> u8, output: &mut Vec<u8>) {
+        let scale = self.scale as usize;
+        let width = SCREEN_WIDTH * scale;
+        output.resize(width * SCREEN_HEIGHT * scale * 4, 0);
+
+        // Background images are whole-frame layers; resolve their conditions once
+        let frame_ctx = EvalContext { frame, memory, x: 0, y: 0, tile: None };
+        let backgrounds: Vec<&BackgroundRule> = self
+            .backgrounds
+            .iter()
+            .filter(|bg| self.check(&bg.conditions, &frame_ctx))
+            .collect();
+
+        let mut target = Target { pixels: output, width, scale };
+
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let info = &frame.pixels[y * SCREEN_WIDTH + x];
+                target.fill(x, y, frame.backdrop[y]);
+
+                for bg in &backgrounds {
+                    self.draw_background(&mut target, bg, frame, x, y);
+                }
+
+                let has_sprite = info.sprite.key != TileKey::None;
+                if has_sprite && info.sprite.behind_bg {
+                    self.draw_tile(&mut target, frame, memory, x, y, &info.sprite);
+                }
+                if info.bg.key != TileKey::None {
+                    self.draw_tile(&mut target, frame, memory, x, y, &info.bg);
+                }
+                if has_sprite && !info.sprite.behind_bg {
+                    self.draw_tile(&mut target, frame, memory, x, y, &info.sprite);
+                }
+            }
+        }
+    }
+
+    fn draw_tile(
+        &self,
+        target: &mut Target,
+        frame: &HdFrame,
+        memory: &dyn Fn(u16) -> u8,
+        x: usize,
+        y: usize,
+        tile: &HdTileInfo,
+    ) {
+        let ctx = EvalContext { frame, memory, x: x as i32, y: y as i32, tile: Some(tile) };
+        let Some(rule) = self.find_tile(&ctx, tile) else {
+            if tile.pixel != 0 {
+                target.fill(x, y, tile.color);
+            }
+            return;
+        };
+
+        let image = &self.images[rule.image];
+        let scale = self.scale as i32;
+        let base_x = rule.x as i32 + tile.offset_x as i32 * scale;
+        let base_y = rule.y as i32 + tile.offset_y as i32 * scale;
+        for sy in 0..scale {
+            for sx in 0..scale {
+                // The whole tile is mirrored, so mirror inside the block too
+                let src_x = base_x + if tile.hflip { scale - 1 - sx } else { sx };
+                let src_y = base_y + if tile.vflip { scale - 1 - sy } else { sy };
+                if let Some(rgba) = image.rgba(src_x, src_y) {
+                    target.blend(x, y, sx as usize, sy as usize, rgba, rule.brightness);
+                }
+            }
+        }
+    }
+
+    fn draw_background(&self, target: &mut Target, bg: &BackgroundRule, frame: &HdFrame, x: usize, y: usize) {
+        let image = &self.background_images[bg.image];
+        let scale = self.scale as i32;
+        let scroll_x = (frame.scroll_x as f32 * bg.h_scroll_ratio * scale as f32) as i32;
+        let scroll_y = (frame.scroll_y as f32 * bg.v_scroll_ratio * scale as f32) as i32;
+        for sy in 0..scale {
+            for sx in 0..scale {
+                let src_x = x as i32 * scale + sx + scroll_x - bg.left;
+                let src_y = y as i32 * scale + sy + scroll_y - bg.top;
+                if let Some(rgba) = image.rgba(src_x, src_y) {
+                    target.blend(x, y, sx as usize, sy as usize, rgba, bg.brightness);
+                }
+            }
+        }
+    }
+}
+
+/// Scaled output buffer addressed by native pixel + sub-pixel
+struct Target<'a> {
+    pixels: &'a mut Vec<u8>,
+    width: usize,
+    scale: usize,
+}
+
+impl Target<'_> {
+    fn fill(&mut self, x: usize, y: usize, rgb: [u8; 3]) {
+        for sy in 0..self.scale {
+            for sx in 0..self.scale {
+                let i = self.index(x, y, sx, sy);
+                self.pixels[i..i + 4].copy_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+            }
+        }
+    }
+
+    fn blend(&mut self, x: usize, y: usize, sx: usize, sy: usize, rgba: [u8; 4], brightness: f32) {
+        if rgba[3] == 0 {
+            return;
+        }
+        let i = self.index(x, y, sx, sy);
+        let alpha = rgba[3] as f32 / 255.0;
+        for (dst, &src) in self.pixels[i..i + 3].iter_mut().zip(&rgba[..3]) {
+            let src = (src as f32 * brightness).min(255.0);
+            *dst = (src * alpha + *dst as f32 * (1.0 - alpha)) as u8;
+        }
+        self.pixels[i + 3] = 255;
+    }
+
+    fn index(&self, x: usize, y: usize, sx: usize, sy: usize) -> usize {
+        ((y * self.scale + sy) * self.width + x * self.scale + sx) * 4
+    }
+}
+
+/// Collects every tile/palette combination drawn while playing, to
+/// bootstrap a new pack
+#[derive(Default)]
+pub struct HdTileDumper {
+    seen: HashSet<(TileKey, u32)>,
+    tiles: Vec<(TileKey, u32)>,
+}
+
+/// Tiles per dumped sheet (16x16 grid of 8x8 tiles)
+const TILES_PER_SHEET: usize = 256;
+
+impl HdTileDumper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, frame: &HdFrame) {
+        for info in &frame.pixels {
+            for tile in [&info.bg, &info.sprite] {
+                if tile.key != TileKey::None && self.seen.insert((tile.key, tile.palette)) {
+                    self.tiles.push((tile.key, tile.palette));
+                }
+            }
+        }
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Build a 1x `hires.txt` and the tile sheets it references (`tiles_N.png`).
+    /// `chr` is the CHR ROM used to draw ROM tiles, `color` maps a palette
+    /// entry to RGB.
+    pub fn build(&self, chr: &[u8], color: impl Fn(u8) -> [u8; 3]) -> (String, Vec<HdImage>) {
+        let mut text = String::from("<ver>106\n<scale>1\n");
+        let mut sheets = Vec::new();
+
+        for (sheet_index, chunk) in self.tiles.chunks(TILES_PER_SHEET).enumerate() {
+            text.push_str(&format!("<img>tiles_{}.png\n", sheet_index));
+            let mut sheet = HdImage { width: 128, height: 128, pixels: vec![0; 128 * 128 * 4] };
+
+            for (i, (key, palette)) in chunk.iter().enumerate() {
+                let (tile_x, tile_y) = ((i % 16) * 8, (i / 16) * 8);
+                let data = match key {
+                    TileKey::Rom(index) => {
+                        let start = *index as usize * 16;
+                        let mut data = [0u8; 16];
+                        if let Some(bytes) = chr.get(start..start + 16) {
+                            data.copy_from_slice(bytes);
+                        }
+                        data
+                    }
+                    TileKey::Ram(data) => *data,
+                    TileKey::None => continue,
+                };
+
+                for row in 0..8 {
+                    for col in 0..8 {
+                        let shift = 7 - col;
+                        let pixel = ((data[row] >> shift) & 0x01) | (((data[row + 8] >> shift) & 0x01) << 1);
+                        if pixel == 0 {
+                            continue; // transparent, like the native layer
+                        }
+                        let entry = (palette >> (24 - pixel as u32 * 8)) as u8;
+                        let [r, g, b] = color(entry);
+                        let o = ((tile_y + row) * 128 + tile_x + col) * 4;
+                        sheet.pixels[o..o + 4].copy_from_slice(&[r, g, b, 255]);
+                    }
+                }
+
+                let tile_data = match key {
+                    TileKey::Rom(index) => format!("{:X}", index),
+                    TileKey::Ram(data) => data.iter().map(|b| format!("{:02X}", b)).collect(),
+                    TileKey::None => unreachable!(),
+                };
+                text.push_str(&format!("<tile>{},{},{:08X},{},{},1,N\n",
+                                       sheet_index, tile_data, palette, tile_x, tile_y));
+            }
+            sheets.push(sheet);
+        }
+
+        (text, sheets)
+    }
+}
+
+fn parse_hex(text: &str) -> Result<u32> {
+    let text = text.trim().trim_start_matches("0x").trim_start_matches('$');
+    u32::from_str_radix(text, 16).with_context(|| format!("Invalid hex value '{}'", text))
+}
+
+/// CHR ROM tile index (hex) or 32 hex digits of CHR RAM tile data
+fn parse_tile_key(text: &str) -> Result<TileKey> {
+    let text = text.trim();
+    if text.len() == 32 && text.is_ascii() {
+        let mut data = [0u8; 16];
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16)
+                .with_context(|| format!("Invalid tile data '{}'", text))?;
+        }
+        Ok(TileKey::Ram(data))
+    } else {
+        Ok(TileKey::Rom(parse_hex(text)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1-pixel-high images told apart by their width
+    fn parse(text: &str) -> Result<HdPack> {
+        HdPack::parse(text, |file| {
+            let width = match file {
+                "a.png" => 1,
+                "bg.png" => 2,
+                "b.png" => 3,
+                _ => anyhow::bail!("No such image {}", file),
+            };
+            Ok(HdImage { width, height: 1, pixels: vec![0; width as usize * 4] })
+        })
+    }
+
+    #[test]
+    fn backgrounds_keep_tile_image_indices() {
+        let pack = parse(
+            "<ver>100\n\
+             <scale>2\n\
+             <img>a.png\n\
+             <background>bg.png,0.5,0.25\n\
+             <img>b.png\n\
+             <tile>1,1A,0F162736,8,16,1,N\n",
+        )
+        .unwrap();
+
+        assert_eq!(pack.scale, 2);
+        let rule = &pack.tiles[&(TileKey::Rom(0x1A), 0x0F162736)][0];
+        assert_eq!(pack.images[rule.image].width, 3);
+        assert_eq!((rule.x, rule.y), (8, 16));
+
+        let bg = &pack.backgrounds[0];
+        assert_eq!(pack.background_images[bg.image].width, 2);
+        assert_eq!(bg.brightness, 0.5);
+        assert_eq!(bg.h_scroll_ratio, 0.25);
+        assert_eq!(bg.v_scroll_ratio, 0.0);
+    }
+
+    #[test]
+    fn background_is_not_a_tile_image() {
+        match parse("<img>a.png\n<background>bg.png\n<tile>1,1A,0F162736,0,0,1,N\n") {
+            Err(err) => assert!(format!("{:#}", err).contains("out of range")),
+            Ok(_) => panic!("<tile> used the <background> image"),
+        }
+    }
+
+    #[test]
+    fn default_tiles_and_conditions() {
+        let pack = parse("<img>a.png\n[!hmirror&vmirror]<tile>0,2,0F000000,0,0,1,Y\n").unwrap();
+        let rule = &pack.default_tiles[&TileKey::Rom(2)][0];
+        assert_eq!(rule.conditions.len(), 2);
+        assert!(rule.conditions[0].negate);
+        assert_eq!(rule.conditions[0].index, 0);
+        assert!(!rule.conditions[1].negate);
+        assert_eq!(rule.conditions[1].index, 1);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        assert!(parse("# comment\n\n<ver>100\n<overscan>0,0,0,0\n").is_ok());
+        assert!(parse("<scale>0").is_err());
+        assert!(parse("<scale>x").is_err());
+        assert!(parse("tile").is_err());
+        assert!(parse("<tile").is_err());
+        assert!(parse("[hmirror<tile>").is_err());
+        assert!(parse("[nope]<tile>0,1,0,0,0,1,N").is_err());
+        assert!(parse("<img>a.png\n<tile>0,1,0F").is_err());
+        assert!(parse("<img>missing.png").is_err());
+        assert!(parse("<background>").is_err());
+    }
+
+    #[test]
+    fn tile_keys() {
+        assert_eq!(parse_tile_key("1A").unwrap(), TileKey::Rom(0x1A));
+        assert_eq!(parse_tile_key(" 0x1a ").unwrap(), TileKey::Rom(0x1A));
+
+        let mut data = [0u8; 16];
+        data[0] = 0xFF;
+        data[15] = 0x01;
+        let text = format!("FF{}01", "00".repeat(14));
+        assert_eq!(parse_tile_key(&text).unwrap(), TileKey::Ram(data));
+
+        assert!(parse_tile_key(&"zz".repeat(16)).is_err());
+        assert!(parse_tile_key("").is_err());
+        // 32 bytes but not 32 characters
+        assert!(parse_tile_key(&"é".repeat(16)).is_err());
+        assert!(parse_tile_key(&format!("é{}", "0".repeat(30))).is_err());
+    }
+}
@@ -1,18 +1,32 @@
 /// NES (Nintendo Entertainment System) Emulator Core
-/// 
+///
 /// Architecture:
 /// - CPU: MOS Technology 6502 @ 1.79 MHz
 /// - PPU: Picture Processing Unit (2C02)
 /// - APU: Audio Processing Unit (5 channels)
 /// - Memory: 2KB RAM + cartridge ROM/RAM
+///
+/// Also runs Vs. System arcade boards (RGB PPUs, coin inputs, DIP switches)
+/// and can replace tiles with HD pack artwork.
 
 pub mod cpu;
 pub mod ppu;
 pub mod apu;
 pub mod mapper;
 pub mod bus;
+pub mod cartridge;
+pub mod controller;
+pub mod palette;
+pub mod vs_system;
+pub mod hd_pack;
+pub mod ppu_viewer;
 
 use anyhow::Result;
+use bus::SystemBus;
+use cartridge::ConsoleType;
+use controller::Buttons;
+use hd_pack::{HdImage, HdPack, HdTileDumper};
+use vs_system::{VsConfig, VsSystem};
 
 pub struct NES {
     pub cpu: cpu::CPU6502,
@@ -20,6 +34,10 @@ pub struct NES {
     pub apu: apu::APU,
     pub bus: bus::Bus,
     cycles: u64,
+
+    hd_pack: Option<HdPack>,
+    hd_framebuffer: Vec<u8>,
+    tile_dumper: Option<HdTileDumper>,
 }
 
 impl NES {
@@ -30,47 +48,147 @@ impl NES {
             apu: apu::APU::new(),
             bus: bus::Bus::new(),
             cycles: 0,
+            hd_pack: None,
+            hd_framebuffer: Vec::new(),
+            tile_dumper: None,
         }
     }
-    
+
     pub fn load_rom(&mut self, rom_data: &[u8]) -> Result<()> {
-        self.bus.load_cartridge(rom_data)?;
+        let header = self.bus.load_cartridge(rom_data)?;
+
+        if header.console_type == ConsoleType::VsSystem {
+            self.configure_vs_system(VsConfig::from_header(&header));
+        } else {
+            self.bus.vs = None;
+            self.ppu.model = ppu::PpuModel::Rp2C02;
+        }
+
         self.reset();
         Ok(())
     }
-    
+
+    /// Switch to Vs. System hardware, e.g. with settings from the game library
+    pub fn configure_vs_system(&mut self, config: VsConfig) {
+        log::info!("Vs. System: {:?} PPU, {:?}, DIP switches {:08b}",
+                   config.ppu, config.hardware, config.dip_switches);
+        self.ppu.model = config.ppu.ppu_model();
+        self.bus.vs = Some(VsSystem::new(config));
+    }
+
+    /// Set the buttons held by player 1 (0) or player 2 (1)
+    pub fn set_buttons(&mut self, player: usize, buttons: Buttons) {
+        self.bus.set_buttons(player, buttons);
+    }
+
+    /// Coin slots and service button; ignored unless running a Vs. board
+    pub fn set_vs_inputs(&mut self, coin1: bool, coin2: bool, service: bool) {
+        if let Some(vs) = &mut self.bus.vs {
+            vs.coin1 = coin1;
+            vs.coin2 = coin2;
+            vs.service = service;
+        }
+    }
+
+    /// Draw frames with an HD pack from now on
+    pub fn load_hd_pack(&mut self, pack: HdPack) {
+        self.hd_pack = Some(pack);
+        self.ppu.set_hd_recording(true);
+    }
+
+    /// Start collecting every tile/palette combination that gets drawn
+    pub fn start_tile_dump(&mut self) {
+        self.tile_dumper = Some(HdTileDumper::new());
+        self.ppu.set_hd_recording(true);
+    }
+
+    /// Stop dumping and return a 1x `hires.txt` with its tile sheets
+    pub fn finish_tile_dump(&mut self) -> Option<(String, Vec<HdImage>)> {
+        let dumper = self.tile_dumper.take()?;
+        self.ppu.set_hd_recording(self.hd_pack.is_some());
+        log::info!("Dumped {} tiles", dumper.tile_count());
+        Some(dumper.build(self.bus.chr_data(), |color| self.ppu.palette_rgb(color)))
+    }
+
+    /// Start logging PRG/CHR usage; see `bus.cdl` for the log
+    pub fn start_cdl(&mut self) {
+        self.bus.start_cdl();
+    }
+
+    /// Scaled HD frame when a pack is loaded, the PPU output otherwise
+    pub fn get_framebuffer(&self) -> &[u8] {
+        match self.hd_pack {
+            Some(_) => &self.hd_framebuffer,
+            None => self.ppu.get_framebuffer(),
+        }
+    }
+
+    pub fn frame_size(&self) -> (u32, u32) {
+        match &self.hd_pack {
+            Some(pack) => pack.frame_size(),
+            None => (256, 240),
+        }
+    }
+
     pub fn reset(&mut self) {
-        self.cpu.reset(&mut self.bus);
+        self.cpu.reset(&mut SystemBus::new(&mut self.bus, &mut self.ppu, &mut self.apu));
         self.ppu.reset();
         self.apu.reset();
         self.cycles = 0;
     }
-    
-    pub fn step(&mut self) -> u8 {
+
+    pub fn step(&mut self) -> u32 {
         // Execute one CPU instruction
-        let cpu_cycles = self.cpu.step(&mut self.bus);
-        
+        let mut cpu_cycles = self.cpu.step(&mut SystemBus::new(&mut self.bus, &mut self.ppu, &mut self.apu)) as u32;
+        cpu_cycles += self.bus.take_dma_cycles();
+        self.clock_peripherals(cpu_cycles);
+
+        if self.ppu.take_nmi() {
+            let nmi_cycles = self.cpu.nmi(&mut SystemBus::new(&mut self.bus, &mut self.ppu, &mut self.apu)) as u32;
+            self.clock_peripherals(nmi_cycles);
+            cpu_cycles += nmi_cycles;
+        }
+
+        self.cycles += cpu_cycles as u64;
+        cpu_cycles
+    }
+
+    fn clock_peripherals(&mut self, cpu_cycles: u32) {
         // PPU runs 3 times faster than CPU
         for _ in 0..(cpu_cycles * 3) {
             self.ppu.step(&mut self.bus);
         }
-        
-        // APU runs at CPU speed
+
+        // APU runs at CPU speed; the DMC reads its samples over the CPU bus
         for _ in 0..cpu_cycles {
             self.apu.step();
+            if let Some(addr) = self.apu.dmc_fetch_address() {
+                let sample = self.bus.read_dpcm(addr);
+                self.apu.dmc_fill(sample);
+            }
         }
-        
-        self.cycles += cpu_cycles as u64;
-        cpu_cycles
     }
-    
+
     pub fn run_frame(&mut self) {
-        // NES runs at ~60 Hz, 1 frame = 29780.5 CPU cycles
-        const CYCLES_PER_FRAME: u32 = 29781;
-        let target = self.cycles + CYCLES_PER_FRAME as u64;
-        
-        while self.cycles < target {
+        // Run until the PPU enters vblank (~29780 CPU cycles at 60 Hz)
+        while !self.ppu.take_frame_complete() {
             self.step();
         }
+
+        if let Some(frame) = self.ppu.hd_frame() {
+            if let Some(dumper) = &mut self.tile_dumper {
+                dumper.record(frame);
+            }
+            if let Some(pack) = &self.hd_pack {
+                let bus = &self.bus;
+                pack.compose(frame, &|addr| bus.peek(addr), &mut self.hd_framebuffer);
+            }
+        }
+    }
+}
+
+impl Default for NES {
+    fn default() -> Self {
+        Self::new()
     }
 }
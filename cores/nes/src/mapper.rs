@@ -1,39 +1,283 @@
 /// NES Cartridge Mappers
 /// Different games use different memory mappers to expand ROM/RAM
 
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Nametable arrangement selected by the cartridge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenLower,
+    SingleScreenUpper,
+    FourScreen,
+}
+
 pub trait Mapper {
+    /// CPU read in $4020-$FFFF
     fn read(&self, addr: u16) -> u8;
+    /// CPU write in $4020-$FFFF (and $4016 on Vs. System boards)
     fn write(&mut self, addr: u16, value: u8);
+
+    /// Absolute PRG ROM offset a CPU address is currently banked to;
+    /// `None` for RAM and unmapped space
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize>;
+
+    /// PPU read from the pattern tables ($0000-$1FFF)
+    fn read_chr(&self, addr: u16) -> u8;
+    /// PPU write to the pattern tables (only lands on CHR RAM)
+    fn write_chr(&mut self, _addr: u16, _value: u8) {}
+
+    /// Absolute CHR ROM offset a pattern table address is currently banked
+    /// to; `None` on CHR RAM boards
+    fn chr_rom_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
+    /// Whole CHR ROM (or CHR RAM) contents
+    fn chr_data(&self) -> &[u8];
+
+    fn mirroring(&self) -> Mirroring;
+}
+
+/// Build the mapper for an iNES mapper number
+pub fn create(
+    mapper_id: u16,
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    mirroring: Mirroring,
+) -> Result<Box<dyn Mapper>> {
+    if prg_rom.is_empty() {
+        anyhow::bail!("Cartridge has no PRG ROM");
+    }
+
+    let mapper: Box<dyn Mapper> = match mapper_id {
+        0 => Box::new(Mapper0::new(prg_rom, chr_rom, mirroring)),
+        2 => Box::new(Mapper2::new(prg_rom, chr_rom, mirroring)),
+        99 => Box::new(Mapper99::new(prg_rom, chr_rom)),
+        _ => {
+            log::warn!("Mapper {} not supported, falling back to NROM", mapper_id);
+            Box::new(Mapper0::new(prg_rom, chr_rom, mirroring))
+        }
+    };
+    Ok(mapper)
+}
+
+/// CHR ROM, or 8KB of CHR RAM when the cartridge has none
+fn chr_or_ram(chr_rom: Vec<u8>) -> (Vec<u8>, bool) {
+    if chr_rom.is_empty() {
+        (vec![0; 0x2000], true)
+    } else {
+        (chr_rom, false)
+    }
 }
 
 /// Mapper 0 - NROM (No mapper, direct mapping)
 pub struct Mapper0 {
     prg_rom: Vec<u8>,
     chr_rom: Vec<u8>,
+    chr_is_ram: bool,
+    prg_ram: [u8; 0x2000],
+    mirroring: Mirroring,
 }
 
 impl Mapper0 {
-    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
-        Self { prg_rom, chr_rom }
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let (chr_rom, chr_is_ram) = chr_or_ram(chr_rom);
+        Self {
+            prg_rom,
+            chr_rom,
+            chr_is_ram,
+            prg_ram: [0; 0x2000],
+            mirroring,
+        }
     }
 }
 
 impl Mapper for Mapper0 {
     fn read(&self, addr: u16) -> u8 {
         match addr {
-            0x8000..=0xFFFF => {
-                let addr = (addr - 0x8000) as usize;
-                if addr < self.prg_rom.len() {
-                    self.prg_rom[addr]
-                } else {
-                    self.prg_rom[addr % self.prg_rom.len()]
-                }
-            }
-            _ => 0,
-        }
-    }
-    
-    fn write(&mut self, _addr: u16, _value: u8) {
-        // NROM is read-only
+            0x6000..=0x7FFF => self.prg_ram[(addr & 0x1FFF) as usize],
+            _ => self.prg_rom_offset(addr).map_or(0, |offset| self.prg_rom[offset]),
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        // 16KB carts are mirrored into $C000
+        (addr >= 0x8000).then(|| (addr - 0x8000) as usize % self.prg_rom.len())
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        // NROM is read-only apart from the optional Family BASIC work RAM
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram[(addr & 0x1FFF) as usize] = value;
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_rom[addr as usize % self.chr_rom.len()]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_is_ram {
+            self.chr_rom[(addr & 0x1FFF) as usize] = value;
+        }
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_is_ram).then(|| addr as usize % self.chr_rom.len())
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr_rom
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+/// Mapper 2 - UxROM (16KB switchable bank at $8000, last bank fixed at $C000)
+pub struct Mapper2 {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    chr_is_ram: bool,
+    bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Mapper2 {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let (chr_rom, chr_is_ram) = chr_or_ram(chr_rom);
+        Self {
+            prg_rom,
+            chr_rom,
+            chr_is_ram,
+            bank: 0,
+            mirroring,
+        }
+    }
+
+    fn bank_count(&self) -> usize {
+        (self.prg_rom.len() / 0x4000).max(1)
+    }
+}
+
+impl Mapper for Mapper2 {
+    fn read(&self, addr: u16) -> u8 {
+        self.prg_rom_offset(addr).map_or(0, |offset| self.prg_rom[offset])
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        let bank = match addr {
+            0x8000..=0xBFFF => self.bank,
+            0xC000..=0xFFFF => self.bank_count() - 1,
+            _ => return None,
+        };
+        Some((bank * 0x4000 + (addr & 0x3FFF) as usize) % self.prg_rom.len())
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr >= 0x8000 {
+            self.bank = value as usize % self.bank_count();
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_rom[addr as usize % self.chr_rom.len()]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_is_ram {
+            self.chr_rom[(addr & 0x1FFF) as usize] = value;
+        }
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_is_ram).then(|| addr as usize % self.chr_rom.len())
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr_rom
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+/// Mapper 99 - Vs. UniSystem
+///
+/// Bit 2 of every $4016 write selects the 8KB CHR bank and, on the 40KB
+/// boards (Vs. Gumshoe), the 8KB PRG bank at $8000. The board carries 2KB
+/// of work RAM at $6000 and enough VRAM for four-screen nametables.
+pub struct Mapper99 {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    chr_is_ram: bool,
+    prg_ram: [u8; 0x800],
+    bank: usize,
+}
+
+impl Mapper99 {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let (chr_rom, chr_is_ram) = chr_or_ram(chr_rom);
+        Self {
+            prg_rom,
+            chr_rom,
+            chr_is_ram,
+            prg_ram: [0; 0x800],
+            bank: 0,
+        }
+    }
+}
+
+impl Mapper for Mapper99 {
+    fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr & 0x07FF) as usize],
+            _ => self.prg_rom_offset(addr).map_or(0, |offset| self.prg_rom[offset]),
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        let offset = match addr {
+            0x8000..=0x9FFF if self.prg_rom.len() > 0x8000 => self.bank * 4 * 0x2000 + (addr & 0x1FFF) as usize,
+            0x8000..=0xFFFF => (addr - 0x8000) as usize,
+            _ => return None,
+        };
+        Some(offset % self.prg_rom.len())
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4016 => self.bank = ((value >> 2) & 0x01) as usize,
+            0x6000..=0x7FFF => self.prg_ram[(addr & 0x07FF) as usize] = value,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        let offset = self.bank * 0x2000 + (addr & 0x1FFF) as usize;
+        self.chr_rom[offset % self.chr_rom.len()]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_is_ram {
+            self.chr_rom[(addr & 0x1FFF) as usize] = value;
+        }
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_is_ram).then(|| (self.bank * 0x2000 + (addr & 0x1FFF) as usize) % self.chr_rom.len())
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr_rom
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::FourScreen
     }
 }
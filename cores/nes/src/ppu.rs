@@ -1,91 +1,748 @@
 /// NES Picture Processing Unit (PPU)
 /// Handles graphics rendering
+///
+/// Rendering is done a scanline at a time at dot 256, which is accurate
+/// enough for scroll splits done from NMI or sprite 0 hit loops.
+
+use emu_common::cdl;
+use crate::bus::Bus;
+use crate::hd_pack::{HdFrame, HdPixelInfo, HdTileInfo, TileKey};
+use crate::mapper::Mirroring;
+use crate::palette;
+use crate::ppu_viewer::PpuSnapshot;
+
+/// PPU chip variant; decides the master palette and a few register quirks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpuModel {
+    /// Composite NES/Famicom PPU
+    Rp2C02,
+    /// RGB PPU with the 2C02 palette order
+    Rp2C03,
+    /// RGB PPU with one of the four scrambled palette orders (1-4)
+    Rp2C04(u8),
+    /// RGB PPU with $2000/$2001 swapped and an ID in the low $2002 bits
+    Rc2C05 { id: u8, mask: u8 },
+}
+
+impl PpuModel {
+    fn is_rgb(self) -> bool {
+        self != PpuModel::Rp2C02
+    }
+}
+
+const SCREEN_WIDTH: usize = 256;
+const SCREEN_HEIGHT: usize = 240;
+const PRE_RENDER_LINE: u16 = 261;
+const VBLANK_LINE: u16 = 241;
+
+// PPUCTRL bits
+const CTRL_INCREMENT_32: u8 = 0x04;
+pub(crate) const CTRL_SPRITE_TABLE: u8 = 0x08;
+pub(crate) const CTRL_BG_TABLE: u8 = 0x10;
+pub(crate) const CTRL_SPRITE_16: u8 = 0x20;
+const CTRL_NMI: u8 = 0x80;
+
+// PPUMASK bits
+const MASK_GRAYSCALE: u8 = 0x01;
+const MASK_BG_LEFT: u8 = 0x02;
+const MASK_SPRITES_LEFT: u8 = 0x04;
+const MASK_BG: u8 = 0x08;
+const MASK_SPRITES: u8 = 0x10;
+
+// PPUSTATUS bits
+const STATUS_OVERFLOW: u8 = 0x20;
+const STATUS_SPRITE0: u8 = 0x40;
+const STATUS_VBLANK: u8 = 0x80;
 
 pub struct PPU {
-    // VRAM
-    vram: [u8; 0x4000],
+    // Nametable RAM (2KB on the console, 4KB with four-screen boards)
+    vram: [u8; 0x1000],
     oam: [u8; 256],  // Object Attribute Memory (sprites)
-    
+
     // Palette
     palette: [u8; 32],
-    
+
     // Registers
     ctrl: u8,
     mask: u8,
     status: u8,
     oam_addr: u8,
-    
+
+    // Loopy scroll registers
+    v: u16,
+    t: u16,
+    fine_x: u8,
+    write_toggle: bool,
+    read_buffer: u8,
+    open_bus: u8,
+
     // Internal
     scanline: u16,
     cycle: u16,
-    
+    odd_frame: bool,
+    nmi_pending: bool,
+    frame_complete: bool,
+
+    pub model: PpuModel,
+
     // Frame buffer (256x240 RGBA)
     pub framebuffer: Vec<u8>,
+
+    // Scroll position at the top of the current frame
+    frame_scroll: (u16, u16),
+
+    // Per-pixel tile/palette record for HD packs and tile dumping
+    hd_frame: Option<Box<HdFrame>>,
+
+    // Debugger viewer: memory copy taken at the start of a chosen scanline
+    viewer_scanline: Option<u16>,
+    snapshot: Option<Box<PpuSnapshot>>,
 }
 
 impl PPU {
     pub fn new() -> Self {
         Self {
-            vram: [0; 0x4000],
+            vram: [0; 0x1000],
             oam: [0; 256],
             palette: [0; 32],
             ctrl: 0,
             mask: 0,
             status: 0,
             oam_addr: 0,
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            write_toggle: false,
+            read_buffer: 0,
+            open_bus: 0,
             scanline: 0,
             cycle: 0,
-            framebuffer: vec![0; 256 * 240 * 4],
+            odd_frame: false,
+            nmi_pending: false,
+            frame_complete: false,
+            model: PpuModel::Rp2C02,
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            frame_scroll: (0, 0),
+            hd_frame: None,
+            viewer_scanline: None,
+            snapshot: None,
         }
     }
-    
+
     pub fn reset(&mut self) {
         self.ctrl = 0;
         self.mask = 0;
         self.status = 0;
         self.oam_addr = 0;
+        self.v = 0;
+        self.t = 0;
+        self.fine_x = 0;
+        self.write_toggle = false;
+        self.read_buffer = 0;
         self.scanline = 0;
         self.cycle = 0;
+        self.odd_frame = false;
+        self.nmi_pending = false;
+        self.frame_complete = false;
     }
-    
-    pub fn step(&mut self, _bus: &mut crate::bus::Bus) {
+
+    pub fn step(&mut self, bus: &mut Bus) {
+        let rendering = self.rendering_enabled();
+
+        if self.cycle == 0 && self.viewer_scanline == Some(self.scanline) {
+            self.snapshot = Some(Box::new(self.capture_snapshot(bus)));
+        }
+
+        if self.scanline < SCREEN_HEIGHT as u16 {
+            if self.cycle == 256 {
+                self.render_scanline(bus);
+                if rendering {
+                    self.increment_y();
+                }
+            } else if self.cycle == 257 && rendering {
+                self.copy_horizontal();
+                self.oam_addr = 0;
+            }
+        } else if self.scanline == VBLANK_LINE && self.cycle == 1 {
+            self.status |= STATUS_VBLANK;
+            if self.ctrl & CTRL_NMI != 0 {
+                self.nmi_pending = true;
+            }
+            self.frame_complete = true;
+            if let Some(hd) = &mut self.hd_frame {
+                hd.frame += 1;
+            }
+        } else if self.scanline == PRE_RENDER_LINE {
+            match self.cycle {
+                1 => self.status &= !(STATUS_VBLANK | STATUS_SPRITE0 | STATUS_OVERFLOW),
+                257 if rendering => {
+                    self.copy_horizontal();
+                    self.oam_addr = 0;
+                }
+                280 if rendering => self.copy_vertical(),
+                // Odd frames skip the last dot of the pre-render line
+                339 if rendering && self.odd_frame => self.cycle = 340,
+                _ => {}
+            }
+        }
+
         self.cycle += 1;
-        
+
         if self.cycle > 340 {
             self.cycle = 0;
             self.scanline += 1;
-            
-            if self.scanline > 261 {
+
+            if self.scanline > PRE_RENDER_LINE {
                 self.scanline = 0;
-                // Frame complete - render test pattern
-                self.render_test_pattern();
-            }
-        }
-    }
-    
-    fn render_test_pattern(&mut self) {
-        // Create a colorful test pattern to verify rendering works
-        for y in 0..240 {
-            for x in 0..256 {
-                let pixel_index = (y * 256 + x) * 4;
-                
-                // Create gradient pattern
-                let r = ((x as f32 / 256.0) * 255.0) as u8;
-                let g = ((y as f32 / 240.0) * 255.0) as u8;
-                let b = (((x + y) as f32 / 496.0) * 255.0) as u8;
-                
-                // Add some checkerboard pattern
-                let checker = if (x / 8 + y / 8) % 2 == 0 { 50 } else { 0 };
-                
-                self.framebuffer[pixel_index] = r.saturating_add(checker);
-                self.framebuffer[pixel_index + 1] = g.saturating_add(checker);
-                self.framebuffer[pixel_index + 2] = b.saturating_add(checker);
-                self.framebuffer[pixel_index + 3] = 255;
-            }
-        }
-    }
-    
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+
+    /// Returns and clears the NMI request raised at the start of vblank
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    /// Returns and clears the end-of-frame flag (set when vblank starts)
+    pub fn take_frame_complete(&mut self) -> bool {
+        std::mem::take(&mut self.frame_complete)
+    }
+
     pub fn get_framebuffer(&self) -> &[u8] {
         &self.framebuffer
     }
+
+    /// Record which tile, palette and CHR bank produced each pixel
+    pub fn set_hd_recording(&mut self, enabled: bool) {
+        if enabled != self.hd_frame.is_some() {
+            self.hd_frame = enabled.then(Box::default);
+        }
+    }
+
+    /// Last frame's per-pixel record, when recording is enabled
+    pub fn hd_frame(&self) -> Option<&HdFrame> {
+        self.hd_frame.as_deref()
+    }
+
+    /// Capture PPU memory for the viewer at the start of `scanline`
+    /// (0-261), or stop capturing with `None`
+    pub fn set_viewer_scanline(&mut self, scanline: Option<u16>) {
+        self.viewer_scanline = scanline.map(|line| line.min(PRE_RENDER_LINE));
+        if scanline.is_none() {
+            self.snapshot = None;
+        }
+    }
+
+    /// Most recent viewer capture
+    pub fn snapshot(&self) -> Option<&PpuSnapshot> {
+        self.snapshot.as_deref()
+    }
+
+    fn capture_snapshot(&self, bus: &Bus) -> PpuSnapshot {
+        let mut oam = [0u8; 256];
+        oam.copy_from_slice(&self.oam);
+        PpuSnapshot {
+            scanline: self.scanline,
+            chr: (0..0x2000).map(|addr| self.read_vram(addr, bus)).collect(),
+            nametables: (0x2000..0x3000).map(|addr| self.read_vram(addr, bus)).collect(),
+            palette: self.palette,
+            oam,
+            ctrl: self.ctrl,
+            mirroring: bus.mirroring(),
+            scroll_x: self.frame_scroll.0,
+            scroll_y: self.frame_scroll.1,
+            colors: std::array::from_fn(|color| self.palette_rgb(color as u8)),
+        }
+    }
+
+    /// Master palette color without emphasis, e.g. for dumping tiles
+    pub fn palette_rgb(&self, color: u8) -> [u8; 3] {
+        match self.model {
+            PpuModel::Rp2C02 => palette::ntsc_2c02(color),
+            PpuModel::Rp2C03 | PpuModel::Rc2C05 { .. } => palette::rgb_2c03(color),
+            PpuModel::Rp2C04(variant) => {
+                palette::rgb_2c03(palette::rp2c04_lut(variant)[(color & 0x3F) as usize])
+            }
+        }
+    }
+
+    // CPU-facing registers ($2000-$2007)
+
+    pub fn read_register(&mut self, reg: u16, bus: &mut Bus) -> u8 {
+        let value = match reg & 0x07 {
+            2 => {
+                let mut value = (self.status & 0xE0) | (self.open_bus & 0x1F);
+                if let PpuModel::Rc2C05 { id, mask } = self.model {
+                    value = (value & !mask) | id;
+                }
+                self.status &= !STATUS_VBLANK;
+                self.write_toggle = false;
+                value
+            }
+            4 => self.oam[self.oam_addr as usize],
+            7 => {
+                let addr = self.v & 0x3FFF;
+                let value = if addr >= 0x3F00 {
+                    // Palette reads are immediate; the buffer gets the nametable underneath
+                    self.read_buffer = self.read_vram(addr - 0x1000, bus);
+                    (self.read_vram(addr, bus) & 0x3F) | (self.open_bus & 0xC0)
+                } else {
+                    if addr < 0x2000 {
+                        bus.log_chr(addr, cdl::READ);
+                    }
+                    let buffered = self.read_buffer;
+                    self.read_buffer = self.read_vram(addr, bus);
+                    buffered
+                };
+                self.increment_v();
+                value
+            }
+            _ => self.open_bus,
+        };
+        self.open_bus = value;
+        value
+    }
+
+    pub fn write_register(&mut self, reg: u16, value: u8, bus: &mut Bus) {
+        self.open_bus = value;
+
+        let mut reg = reg & 0x07;
+        if let PpuModel::Rc2C05 { .. } = self.model {
+            // The 2C05 decodes PPUCTRL and PPUMASK the other way round
+            reg = match reg {
+                0 => 1,
+                1 => 0,
+                other => other,
+            };
+        }
+
+        match reg {
+            0 => {
+                let nmi_was_enabled = self.ctrl & CTRL_NMI != 0;
+                self.ctrl = value;
+                self.t = (self.t & 0xF3FF) | (((value & 0x03) as u16) << 10);
+                // Enabling NMI during vblank fires immediately
+                if !nmi_was_enabled && value & CTRL_NMI != 0 && self.status & STATUS_VBLANK != 0 {
+                    self.nmi_pending = true;
+                }
+            }
+            1 => self.mask = value,
+            3 => self.oam_addr = value,
+            4 => {
+                self.oam[self.oam_addr as usize] = value;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            5 => {
+                if !self.write_toggle {
+                    self.t = (self.t & 0xFFE0) | (value >> 3) as u16;
+                    self.fine_x = value & 0x07;
+                } else {
+                    self.t = (self.t & 0x8C1F)
+                        | (((value & 0x07) as u16) << 12)
+                        | (((value & 0xF8) as u16) << 2);
+                }
+                self.write_toggle = !self.write_toggle;
+            }
+            6 => {
+                if !self.write_toggle {
+                    self.t = (self.t & 0x00FF) | (((value & 0x3F) as u16) << 8);
+                } else {
+                    self.t = (self.t & 0xFF00) | value as u16;
+                    self.v = self.t;
+                }
+                self.write_toggle = !self.write_toggle;
+            }
+            7 => {
+                self.write_vram(self.v & 0x3FFF, value, bus);
+                self.increment_v();
+            }
+            _ => {}
+        }
+    }
+
+    /// OAM DMA ($4014) lands here one byte at a time
+    pub fn write_oam_dma(&mut self, value: u8) {
+        self.oam[self.oam_addr as usize] = value;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    // PPU address space
+
+    fn read_vram(&self, addr: u16, bus: &Bus) -> u8 {
+        match addr & 0x3FFF {
+            0x0000..=0x1FFF => bus.read_chr(addr),
+            0x2000..=0x3EFF => self.vram[Self::nametable_index(addr, bus.mirroring())],
+            _ => self.palette[Self::palette_index(addr)],
+        }
+    }
+
+    fn write_vram(&mut self, addr: u16, value: u8, bus: &mut Bus) {
+        match addr & 0x3FFF {
+            0x0000..=0x1FFF => bus.write_chr(addr, value),
+            0x2000..=0x3EFF => self.vram[Self::nametable_index(addr, bus.mirroring())] = value,
+            _ => self.palette[Self::palette_index(addr)] = value & 0x3F,
+        }
+    }
+
+    fn nametable_index(addr: u16, mirroring: Mirroring) -> usize {
+        let table = ((addr - 0x2000) / 0x400 % 4) as usize;
+        let offset = (addr & 0x03FF) as usize;
+        let physical = match mirroring {
+            Mirroring::Horizontal => table / 2,
+            Mirroring::Vertical => table % 2,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+            Mirroring::FourScreen => table,
+        };
+        physical * 0x400 + offset
+    }
+
+    fn palette_index(addr: u16) -> usize {
+        let index = (addr & 0x1F) as usize;
+        // $3F10/$3F14/$3F18/$3F1C mirror the background entries
+        if index & 0x13 == 0x10 {
+            index & !0x10
+        } else {
+            index
+        }
+    }
+
+    // Scrolling
+
+    fn rendering_enabled(&self) -> bool {
+        self.mask & (MASK_BG | MASK_SPRITES) != 0
+    }
+
+    fn increment_v(&mut self) {
+        let step = if self.ctrl & CTRL_INCREMENT_32 != 0 { 32 } else { 1 };
+        self.v = self.v.wrapping_add(step) & 0x7FFF;
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    fn copy_horizontal(&mut self) {
+        self.v = (self.v & !0x041F) | (self.t & 0x041F);
+    }
+
+    fn copy_vertical(&mut self) {
+        self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+    }
+
+    // Rendering
+
+    fn render_scanline(&mut self, bus: &mut Bus) {
+        let y = self.scanline as usize;
+        let backdrop = self.palette[0];
+        let backdrop_rgb = self.pixel_rgb(backdrop);
+        if y == 0 {
+            self.frame_scroll = self.scroll_position();
+        }
+        if let Some(hd) = &mut self.hd_frame {
+            hd.backdrop[y] = backdrop_rgb;
+            (hd.scroll_x, hd.scroll_y) = self.frame_scroll;
+        }
+
+        if !self.rendering_enabled() {
+            for x in 0..SCREEN_WIDTH {
+                self.put_pixel(x, y, backdrop);
+            }
+            if let Some(hd) = &mut self.hd_frame {
+                hd.pixels[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH].fill(HdPixelInfo::default());
+            }
+            return;
+        }
+
+        let background = self.render_background_line(bus);
+        let sprites = self.evaluate_sprites(bus);
+        let mut hd_line = self.hd_frame.is_some().then(|| vec![HdPixelInfo::default(); SCREEN_WIDTH]);
+        let mut keys = TileKeyCache::default();
+
+        for (x, bg) in background.iter().enumerate() {
+            let (bg_pixel, bg_palette) = (bg.pixel, bg.attribute);
+            let show_bg = self.mask & MASK_BG != 0 && (x >= 8 || self.mask & MASK_BG_LEFT != 0);
+            let show_sprites =
+                self.mask & MASK_SPRITES != 0 && (x >= 8 || self.mask & MASK_SPRITES_LEFT != 0);
+
+            let bg_pixel = if show_bg { bg_pixel } else { 0 };
+
+            let mut sprite = None;
+            if show_sprites {
+                for s in &sprites {
+                    let dx = x as i32 - s.x as i32;
+                    if !(0..8).contains(&dx) {
+                        continue;
+                    }
+                    let bit = if s.attributes & 0x40 != 0 { dx } else { 7 - dx };
+                    let pixel = ((s.pattern_lo >> bit) & 0x01) | (((s.pattern_hi >> bit) & 0x01) << 1);
+                    if pixel != 0 {
+                        sprite = Some((pixel, s));
+                        break;
+                    }
+                }
+            }
+
+            if let Some(line) = &mut hd_line {
+                let mut info = HdPixelInfo::default();
+                if show_bg {
+                    let attr = bg_palette << 2;
+                    info.bg = HdTileInfo {
+                        key: keys.get(bus, bg.tile_addr),
+                        palette: self.palette_key(attr),
+                        offset_x: bg.offset_x,
+                        offset_y: ((self.v >> 12) & 0x07) as u8,
+                        pixel: bg_pixel,
+                        color: self.pixel_rgb(self.palette[Self::palette_index((attr | bg_pixel) as u16)]),
+                        ..HdTileInfo::default()
+                    };
+                }
+                if let Some((pixel, s)) = sprite {
+                    let attr = 0x10 | ((s.attributes & 0x03) << 2);
+                    let hflip = s.attributes & 0x40 != 0;
+                    let dx = (x - s.x as usize) as u8;
+                    info.sprite = HdTileInfo {
+                        key: keys.get(bus, s.tile_addr),
+                        palette: self.palette_key(attr),
+                        offset_x: if hflip { 7 - dx } else { dx },
+                        offset_y: s.row,
+                        pixel,
+                        color: self.pixel_rgb(self.palette[Self::palette_index((attr | pixel) as u16)]),
+                        hflip,
+                        vflip: s.attributes & 0x80 != 0,
+                        behind_bg: s.attributes & 0x20 != 0,
+                    };
+                }
+                line[x] = info;
+            }
+
+            let palette_addr = match sprite {
+                Some((pixel, s)) => {
+                    if s.is_sprite0 && bg_pixel != 0 && x != 255 {
+                        self.status |= STATUS_SPRITE0;
+                    }
+                    if bg_pixel == 0 || s.attributes & 0x20 == 0 {
+                        0x10 | ((s.attributes & 0x03) << 2) | pixel
+                    } else {
+                        (bg_palette << 2) | bg_pixel
+                    }
+                }
+                None if bg_pixel != 0 => (bg_palette << 2) | bg_pixel,
+                None => 0,
+            };
+
+            let color = self.palette[Self::palette_index(palette_addr as u16)];
+            self.put_pixel(x, y, color);
+        }
+
+        if let (Some(hd), Some(line)) = (&mut self.hd_frame, hd_line) {
+            hd.pixels[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH].copy_from_slice(&line);
+        }
+    }
+
+    /// The four palette RAM entries of a palette, color 0 in the top byte
+    fn palette_key(&self, base: u8) -> u32 {
+        (0..4).fold(0u32, |key, i| {
+            (key << 8) | self.palette[Self::palette_index((base | i) as u16)] as u32
+        })
+    }
+
+    /// Scroll position of the top of the frame, in pixels across all nametables
+    fn scroll_position(&self) -> (u16, u16) {
+        let x = ((self.v >> 10) & 0x01) * 256 + (self.v & 0x1F) * 8 + self.fine_x as u16;
+        let y = ((self.v >> 11) & 0x01) * 240 + ((self.v >> 5) & 0x1F) * 8 + ((self.v >> 12) & 0x07);
+        (x, y)
+    }
+
+    /// 2-bit pixel and attribute palette for each of the 256 dots
+    fn render_background_line(&self, bus: &mut Bus) -> [BgPixel; SCREEN_WIDTH] {
+        let mut line = [BgPixel::default(); SCREEN_WIDTH];
+        let mut v = self.v;
+        let fine_y = (v >> 12) & 0x07;
+        let table = if self.ctrl & CTRL_BG_TABLE != 0 { 0x1000 } else { 0 };
+
+        let mut x = 0usize;
+        for tile in 0..33 {
+            let tile_id = self.read_vram(0x2000 | (v & 0x0FFF), bus) as u16;
+            let attr_addr = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+            let shift = ((v >> 4) & 0x04) | (v & 0x02);
+            let attribute = (self.read_vram(attr_addr, bus) >> shift) & 0x03;
+
+            let pattern_addr = table + tile_id * 16 + fine_y;
+            let lo = bus.fetch_chr(pattern_addr);
+            let hi = bus.fetch_chr(pattern_addr + 8);
+
+            let first = if tile == 0 { self.fine_x } else { 0 };
+            for bit in first..8 {
+                if x >= SCREEN_WIDTH {
+                    break;
+                }
+                let shift = 7 - bit;
+                let pixel = ((lo >> shift) & 0x01) | (((hi >> shift) & 0x01) << 1);
+                line[x] = BgPixel {
+                    pixel,
+                    attribute,
+                    tile_addr: pattern_addr - fine_y,
+                    offset_x: bit,
+                };
+                x += 1;
+            }
+
+            // Coarse X increment with horizontal nametable wrap
+            if v & 0x001F == 31 {
+                v &= !0x001F;
+                v ^= 0x0400;
+            } else {
+                v += 1;
+            }
+        }
+
+        line
+    }
+
+    /// Up to eight sprites on the current line, in OAM priority order
+    fn evaluate_sprites(&mut self, bus: &mut Bus) -> Vec<LineSprite> {
+        let y = self.scanline as i32;
+        let height = if self.ctrl & CTRL_SPRITE_16 != 0 { 16 } else { 8 };
+        let mut sprites = Vec::with_capacity(8);
+
+        for i in 0..64 {
+            let entry = &self.oam[i * 4..i * 4 + 4];
+            let row = y - entry[0] as i32 - 1;
+            if !(0..height).contains(&row) {
+                continue;
+            }
+            if sprites.len() == 8 {
+                self.status |= STATUS_OVERFLOW;
+                break;
+            }
+
+            let (tile, attributes, x) = (entry[1] as u16, entry[2], entry[3]);
+            let row = if attributes & 0x80 != 0 { height - 1 - row } else { row } as u16;
+
+            let pattern_addr = if height == 16 {
+                let table = (tile & 0x01) * 0x1000;
+                let tile = (tile & 0xFE) + if row >= 8 { 1 } else { 0 };
+                table + tile * 16 + (row & 0x07)
+            } else {
+                let table = if self.ctrl & CTRL_SPRITE_TABLE != 0 { 0x1000 } else { 0 };
+                table + tile * 16 + row
+            };
+
+            sprites.push(LineSprite {
+                x,
+                attributes,
+                tile_addr: pattern_addr & !0x07,
+                row: (row & 0x07) as u8,
+                pattern_lo: bus.fetch_chr(pattern_addr),
+                pattern_hi: bus.fetch_chr(pattern_addr + 8),
+                is_sprite0: i == 0,
+            });
+        }
+
+        sprites
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: u8) {
+        let [r, g, b] = self.pixel_rgb(color);
+        let pixel_index = (y * SCREEN_WIDTH + x) * 4;
+        self.framebuffer[pixel_index] = r;
+        self.framebuffer[pixel_index + 1] = g;
+        self.framebuffer[pixel_index + 2] = b;
+        self.framebuffer[pixel_index + 3] = 255;
+    }
+
+    /// Final RGB for a palette RAM value, after grayscale and emphasis
+    fn pixel_rgb(&self, color: u8) -> [u8; 3] {
+        let color = if self.mask & MASK_GRAYSCALE != 0 { color & 0x30 } else { color };
+        self.output_rgb(color)
+    }
+
+    /// Master palette lookup including the color emphasis bits
+    fn output_rgb(&self, color: u8) -> [u8; 3] {
+        let emphasis = self.mask >> 5;
+        let mut rgb = self.palette_rgb(color);
+
+        if emphasis != 0 {
+            for (channel, value) in rgb.iter_mut().enumerate() {
+                if self.model.is_rgb() {
+                    // RGB PPUs drive the emphasized channel to full intensity
+                    if emphasis & (1 << channel) != 0 {
+                        *value = 255;
+                    }
+                } else if emphasis & !(1 << channel) != 0 && color & 0x0F < 0x0E {
+                    // The 2C02 darkens the channels that are not emphasized
+                    *value = (*value as f32 * 0.816) as u8;
+                }
+            }
+        }
+
+        rgb
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct BgPixel {
+    pixel: u8,
+    attribute: u8,
+    /// Pattern address of the tile's first row
+    tile_addr: u16,
+    /// Column inside the tile
+    offset_x: u8,
+}
+
+struct LineSprite {
+    x: u8,
+    attributes: u8,
+    /// Pattern address of the 8x8 tile's first row, and the row drawn
+    tile_addr: u16,
+    row: u8,
+    pattern_lo: u8,
+    pattern_hi: u8,
+    is_sprite0: bool,
+}
+
+/// Tile identity of the last pattern address looked up; avoids re-reading
+/// CHR RAM tiles for every pixel
+#[derive(Default)]
+struct TileKeyCache {
+    last: Option<(u16, TileKey)>,
+}
+
+impl TileKeyCache {
+    fn get(&mut self, bus: &Bus, tile_addr: u16) -> TileKey {
+        if let Some((addr, key)) = self.last {
+            if addr == tile_addr {
+                return key;
+            }
+        }
+
+        let key = match bus.chr_rom_offset(tile_addr) {
+            Some(offset) => TileKey::Rom((offset / 16) as u32),
+            None => {
+                let mut data = [0u8; 16];
+                for (i, byte) in data.iter_mut().enumerate() {
+                    *byte = bus.read_chr(tile_addr + i as u16);
+                }
+                TileKey::Ram(data)
+            }
+        };
+        self.last = Some((tile_addr, key));
+        key
+    }
 }
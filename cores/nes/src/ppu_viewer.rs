@@ -0,0 +1,184 @@
+/// PPU Viewer
+///
+/// Copy of PPU memory taken at a chosen scanline, plus the images the
+/// debugger shows: pattern tables, nametables with the scroll viewport,
+/// OAM sprites and palette RAM. All images are RGBA.
+
+use crate::mapper::Mirroring;
+use crate::ppu::{CTRL_BG_TABLE, CTRL_SPRITE_16, CTRL_SPRITE_TABLE};
+
+pub const PATTERN_TABLE_SIZE: usize = 128;
+pub const NAMETABLES_WIDTH: usize = 512;
+pub const NAMETABLES_HEIGHT: usize = 480;
+
+/// Viewport outline drawn over the nametables
+const VIEWPORT_COLOR: [u8; 3] = [255, 255, 0];
+
+pub struct PpuSnapshot {
+    /// Scanline the copy was taken at
+    pub scanline: u16,
+    /// $0000-$1FFF as banked in at that moment
+    pub chr: Vec<u8>,
+    /// $2000-$2FFF with mirroring applied
+    pub nametables: Vec<u8>,
+    pub palette: [u8; 32],
+    pub oam: [u8; 256],
+    pub ctrl: u8,
+    pub mirroring: Mirroring,
+    /// Scroll at the top of the frame
+    pub scroll_x: u16,
+    pub scroll_y: u16,
+    /// Master palette of the PPU model
+    pub colors: [[u8; 3]; 64],
+}
+
+impl PpuSnapshot {
+    /// 2-bit pixel of a tile in the pattern tables
+    fn tile_pixel(&self, pattern_addr: usize, x: usize, y: usize) -> u8 {
+        let lo = self.chr[(pattern_addr + y) & 0x1FFF];
+        let hi = self.chr[(pattern_addr + y + 8) & 0x1FFF];
+        let shift = 7 - x;
+        ((lo >> shift) & 0x01) | (((hi >> shift) & 0x01) << 1)
+    }
+
+    /// Color of a pixel value in one of the 8 palettes (4-7 are sprites)
+    fn pixel_color(&self, palette: usize, pixel: u8) -> [u8; 3] {
+        let entry = if pixel == 0 { 0 } else { (palette & 0x07) * 4 + pixel as usize };
+        self.colors[(self.palette[entry] & 0x3F) as usize]
+    }
+
+    /// 128x128 image of pattern table 0 or 1 drawn with `palette` (0-7)
+    pub fn pattern_table(&self, table: usize, palette: usize) -> Vec<u8> {
+        let mut image = vec![0u8; PATTERN_TABLE_SIZE * PATTERN_TABLE_SIZE * 4];
+        for tile in 0..256 {
+            let pattern_addr = (table & 0x01) * 0x1000 + tile * 16;
+            for y in 0..8 {
+                for x in 0..8 {
+                    let rgb = self.pixel_color(palette, self.tile_pixel(pattern_addr, x, y));
+                    let px = (tile % 16) * 8 + x;
+                    let py = (tile / 16) * 8 + y;
+                    put(&mut image, PATTERN_TABLE_SIZE, px, py, rgb);
+                }
+            }
+        }
+        image
+    }
+
+    /// 512x480 image of the four logical nametables, with the visible
+    /// 256x240 area outlined
+    pub fn nametable_image(&self) -> Vec<u8> {
+        let mut image = vec![0u8; NAMETABLES_WIDTH * NAMETABLES_HEIGHT * 4];
+        let table = if self.ctrl & CTRL_BG_TABLE != 0 { 0x1000 } else { 0 };
+
+        for y in 0..NAMETABLES_HEIGHT {
+            for x in 0..NAMETABLES_WIDTH {
+                let (nametable_addr, attr_addr, shift) = Self::tile_addresses(x, y);
+                let tile_id = self.nametables[(nametable_addr - 0x2000) as usize] as usize;
+                let attribute = (self.nametables[(attr_addr - 0x2000) as usize] >> shift) & 0x03;
+                let pixel = self.tile_pixel(table + tile_id * 16, x % 8, y % 8);
+                put(&mut image, NAMETABLES_WIDTH, x, y, self.pixel_color(attribute as usize, pixel));
+            }
+        }
+
+        // Viewport, wrapping around the edges like the scroll does
+        let (left, top) = (self.scroll_x as usize, self.scroll_y as usize);
+        for dx in 0..256 {
+            for dy in [0, 239] {
+                put(&mut image, NAMETABLES_WIDTH, (left + dx) % NAMETABLES_WIDTH,
+                    (top + dy) % NAMETABLES_HEIGHT, VIEWPORT_COLOR);
+            }
+        }
+        for dy in 0..240 {
+            for dx in [0, 255] {
+                put(&mut image, NAMETABLES_WIDTH, (left + dx) % NAMETABLES_WIDTH,
+                    (top + dy) % NAMETABLES_HEIGHT, VIEWPORT_COLOR);
+            }
+        }
+
+        image
+    }
+
+    /// 8x16 preview of OAM entry `index`; 8x8 sprites fill the top half
+    pub fn sprite_image(&self, index: usize) -> Vec<u8> {
+        let mut image = vec![0u8; 8 * 16 * 4];
+        let entry = &self.oam[(index & 0x3F) * 4..(index & 0x3F) * 4 + 4];
+        let attributes = entry[2];
+        let palette = 4 + (attributes & 0x03) as usize;
+        let height = if self.ctrl & CTRL_SPRITE_16 != 0 { 16 } else { 8 };
+
+        for y in 0..height {
+            let row = if attributes & 0x80 != 0 { height - 1 - y } else { y };
+            let pattern_addr = self.sprite_pattern_addr(entry[1], row);
+            for x in 0..8 {
+                let col = if attributes & 0x40 != 0 { 7 - x } else { x };
+                let pixel = self.tile_pixel(pattern_addr, col, row % 8);
+                if pixel != 0 {
+                    put(&mut image, 8, x, y, self.pixel_color(palette, pixel));
+                }
+            }
+        }
+        image
+    }
+
+    /// RGB of the 32 palette RAM entries
+    pub fn palette_colors(&self) -> [[u8; 3]; 32] {
+        std::array::from_fn(|i| self.colors[(self.palette[i] & 0x3F) as usize])
+    }
+
+    pub fn describe_pattern(&self, table: usize, tile: usize) -> String {
+        format!("Pattern table {} tile ${:02X}: ${:04X}", table & 0x01, tile & 0xFF,
+                (table & 0x01) * 0x1000 + (tile & 0xFF) * 16)
+    }
+
+    /// Addresses behind a pixel of the nametable image
+    pub fn describe_nametable(&self, x: usize, y: usize) -> String {
+        let (x, y) = (x % NAMETABLES_WIDTH, y % NAMETABLES_HEIGHT);
+        let (nametable_addr, attr_addr, shift) = Self::tile_addresses(x, y);
+        let tile_id = self.nametables[(nametable_addr - 0x2000) as usize];
+        let attribute = (self.nametables[(attr_addr - 0x2000) as usize] >> shift) & 0x03;
+        let table = if self.ctrl & CTRL_BG_TABLE != 0 { 0x1000 } else { 0 };
+        format!("Nametable ${:04X} ({:?}) tile ${:02X} at {},{}: pattern ${:04X}, attribute ${:04X}, palette {} (${:04X})",
+                nametable_addr, self.mirroring, tile_id, x / 8 % 32, y / 8 % 30,
+                table + tile_id as u16 * 16, attr_addr, attribute, 0x3F00 + attribute as u16 * 4)
+    }
+
+    pub fn describe_sprite(&self, index: usize) -> String {
+        let index = index & 0x3F;
+        let entry = &self.oam[index * 4..index * 4 + 4];
+        format!("Sprite {} (OAM ${:02X}): x={} y={} tile ${:02X} attr ${:02X}, pattern ${:04X}, palette ${:04X}",
+                index, index * 4, entry[3], entry[0], entry[1], entry[2],
+                self.sprite_pattern_addr(entry[1], 0), 0x3F10 + (entry[2] & 0x03) as u16 * 4)
+    }
+
+    pub fn describe_palette(&self, index: usize) -> String {
+        let index = index & 0x1F;
+        format!("Palette ${:04X}: color ${:02X}", 0x3F00 + index, self.palette[index])
+    }
+
+    fn sprite_pattern_addr(&self, tile: u8, row: usize) -> usize {
+        let tile = tile as usize;
+        if self.ctrl & CTRL_SPRITE_16 != 0 {
+            (tile & 0x01) * 0x1000 + ((tile & 0xFE) + row / 8) * 16
+        } else {
+            let table = if self.ctrl & CTRL_SPRITE_TABLE != 0 { 0x1000 } else { 0 };
+            table + tile * 16
+        }
+    }
+
+    /// Nametable byte, attribute byte and attribute shift for a pixel of
+    /// the 512x480 nametable image
+    fn tile_addresses(x: usize, y: usize) -> (u16, u16, u8) {
+        let table = (y / 240) * 2 + x / 256;
+        let (coarse_x, coarse_y) = ((x % 256) / 8, (y % 240) / 8);
+        let base = 0x2000 + table as u16 * 0x400;
+        let nametable_addr = base + (coarse_y * 32 + coarse_x) as u16;
+        let attr_addr = base + 0x3C0 + ((coarse_y / 4) * 8 + coarse_x / 4) as u16;
+        let shift = (((coarse_y & 0x02) << 1) | (coarse_x & 0x02)) as u8;
+        (nametable_addr, attr_addr, shift)
+    }
+}
+
+fn put(image: &mut [u8], width: usize, x: usize, y: usize, rgb: [u8; 3]) {
+    let i = (y * width + x) * 4;
+    image[i..i + 4].copy_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+}
@@ -0,0 +1,190 @@
+/// Nintendo Vs. System (Vs. UniSystem)
+///
+/// Arcade board built around the NES chipset:
+/// - RGB PPUs (2C03/2C04/2C05) instead of the composite 2C02
+/// - Coin slots, service button and 8 DIP switches on $4016/$4017
+/// - Joystick ports wired the other way round from the NES
+
+use serde::{Deserialize, Serialize};
+use crate::cartridge::RomHeader;
+use crate::ppu::PpuModel;
+
+/// PPU fitted to the board (NES 2.0 byte 13, low nibble)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VsPpuType {
+    Rp2C03B,
+    Rp2C03G,
+    Rp2C04_0001,
+    Rp2C04_0002,
+    Rp2C04_0003,
+    Rp2C04_0004,
+    Rc2C03B,
+    Rc2C03C,
+    Rc2C05_01,
+    Rc2C05_02,
+    Rc2C05_03,
+    Rc2C05_04,
+    Rc2C05_05,
+}
+
+impl VsPpuType {
+    pub fn from_nes2(value: u8) -> Option<Self> {
+        Some(match value {
+            0x0 => Self::Rp2C03B,
+            0x1 => Self::Rp2C03G,
+            0x2 => Self::Rp2C04_0001,
+            0x3 => Self::Rp2C04_0002,
+            0x4 => Self::Rp2C04_0003,
+            0x5 => Self::Rp2C04_0004,
+            0x6 => Self::Rc2C03B,
+            0x7 => Self::Rc2C03C,
+            0x8 => Self::Rc2C05_01,
+            0x9 => Self::Rc2C05_02,
+            0xA => Self::Rc2C05_03,
+            0xB => Self::Rc2C05_04,
+            0xC => Self::Rc2C05_05,
+            _ => return None,
+        })
+    }
+
+    pub fn ppu_model(self) -> PpuModel {
+        match self {
+            Self::Rp2C03B | Self::Rp2C03G | Self::Rc2C03B | Self::Rc2C03C => PpuModel::Rp2C03,
+            Self::Rp2C04_0001 => PpuModel::Rp2C04(1),
+            Self::Rp2C04_0002 => PpuModel::Rp2C04(2),
+            Self::Rp2C04_0003 => PpuModel::Rp2C04(3),
+            Self::Rp2C04_0004 => PpuModel::Rp2C04(4),
+            // $2002 signature in the low bits
+            Self::Rc2C05_01 => PpuModel::Rc2C05 { id: 0x1B, mask: 0x1F },
+            Self::Rc2C05_02 => PpuModel::Rc2C05 { id: 0x3D, mask: 0x3F },
+            Self::Rc2C05_03 => PpuModel::Rc2C05 { id: 0x1C, mask: 0x1F },
+            Self::Rc2C05_04 => PpuModel::Rc2C05 { id: 0x1B, mask: 0x1F },
+            Self::Rc2C05_05 => PpuModel::Rc2C05 { id: 0x00, mask: 0x00 },
+        }
+    }
+}
+
+/// Board type (NES 2.0 byte 13, high nibble)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VsHardware {
+    UniSystem,
+    RbiBaseballProtection,
+    TkoBoxingProtection,
+    SuperXeviousProtection,
+    IceClimberProtection,
+    DualSystem,
+    RaidOnBungelingBayProtection,
+}
+
+impl VsHardware {
+    pub fn from_nes2(value: u8) -> Self {
+        match value {
+            1 => Self::RbiBaseballProtection,
+            2 => Self::TkoBoxingProtection,
+            3 => Self::SuperXeviousProtection,
+            4 => Self::IceClimberProtection,
+            5 => Self::DualSystem,
+            6 => Self::RaidOnBungelingBayProtection,
+            _ => Self::UniSystem,
+        }
+    }
+}
+
+/// Per-game Vs. settings; stored in the game library alongside the ROM path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VsConfig {
+    pub ppu: VsPpuType,
+    pub hardware: VsHardware,
+    /// DIP switches 1-8, switch 1 in bit 0
+    #[serde(default)]
+    pub dip_switches: u8,
+    /// Most games expect player 1 on $4017; a few were wired like an NES
+    #[serde(default = "default_swap_controllers")]
+    pub swap_controllers: bool,
+}
+
+fn default_swap_controllers() -> bool {
+    true
+}
+
+impl VsConfig {
+    /// Settings from a NES 2.0 header; iNES 1.0 dumps carry no PPU type
+    /// and need a game library entry to pick the right palette
+    pub fn from_header(header: &RomHeader) -> Self {
+        let ppu = if header.nes2 {
+            VsPpuType::from_nes2(header.vs_ppu_type).unwrap_or(VsPpuType::Rp2C03B)
+        } else {
+            log::warn!("iNES 1.0 Vs. System dump: PPU type unknown, assuming RP2C03");
+            VsPpuType::Rp2C03B
+        };
+
+        Self {
+            ppu,
+            hardware: VsHardware::from_nes2(header.vs_hardware_type),
+            dip_switches: 0,
+            swap_controllers: true,
+        }
+    }
+}
+
+/// Coin/service inputs and DIP switches of a running Vs. board
+pub struct VsSystem {
+    pub config: VsConfig,
+    pub coin1: bool,
+    pub coin2: bool,
+    pub service: bool,
+    coin_counter: u8,
+}
+
+impl VsSystem {
+    pub fn new(config: VsConfig) -> Self {
+        match config.hardware {
+            VsHardware::UniSystem => {}
+            VsHardware::DualSystem | VsHardware::RaidOnBungelingBayProtection => {
+                log::warn!("Vs. DualSystem games need two linked consoles; running the main CPU only");
+            }
+            protection => {
+                log::warn!("Vs. copy protection {:?} is not emulated", protection);
+            }
+        }
+
+        Self {
+            config,
+            coin1: false,
+            coin2: false,
+            service: false,
+            coin_counter: 0,
+        }
+    }
+
+    /// Upper bits of $4016: service, DIP 1-2, coin 1-2
+    pub fn read_4016(&self) -> u8 {
+        let dips = self.config.dip_switches;
+        ((self.service as u8) << 2)
+            | ((dips & 0x03) << 3)
+            | ((self.coin1 as u8) << 5)
+            | ((self.coin2 as u8) << 6)
+    }
+
+    /// Upper bits of $4017: DIP 3-8
+    pub fn read_4017(&self) -> u8 {
+        self.config.dip_switches & 0xFC
+    }
+
+    /// $4020: coin counter / lockout
+    pub fn write_4020(&mut self, value: u8) {
+        if value & 0x01 != 0 && self.coin_counter & 0x01 == 0 {
+            log::debug!("Vs. coin counter ticked");
+        }
+        self.coin_counter = value;
+    }
+
+    /// Map a logical player (0/1) to the physical port it is read through
+    pub fn port_for_player(&self, player: usize) -> usize {
+        if self.config.swap_controllers {
+            player ^ 1
+        } else {
+            player
+        }
+    }
+}
@@ -0,0 +1,234 @@
+/// SNES Audio Processing Unit (S-SMP)
+/// The SPC700 with its 64KB ARAM, the 64-byte IPL boot ROM, three timers,
+/// the S-DSP and the four ports shared with the 5A22. It runs on its own
+/// 1.024 MHz clock and is caught up with the master clock whenever the CPU
+/// touches a port, and once per scanline.
+
+use crate::dsp::{Dsp, CYCLES_PER_SAMPLE};
+use crate::spc700::{SpcBus, SPC700};
+
+pub const ARAM_SIZE: usize = 0x10000;
+
+/// SPC700 clock: 24.576 MHz crystal / 24
+pub const APU_CLOCK: i64 = 1_024_000;
+pub const NTSC_MASTER_CLOCK: i64 = 21_477_272;
+pub const PAL_MASTER_CLOCK: i64 = 21_281_370;
+
+/// Boot ROM mapped at $FFC0-$FFFF: clears the zero page, then runs the
+/// $2140-$2143 upload protocol
+#[rustfmt::skip]
+const IPL_ROM: [u8; 64] = [
+    0xCD, 0xEF, 0xBD, 0xE8, 0x00, 0xC6, 0x1D, 0xD0, 0xFC, 0x8F, 0xAA, 0xF4, 0x8F, 0xBB, 0xF5, 0x78,
+    0xCC, 0xF4, 0xD0, 0xFB, 0x2F, 0x19, 0xEB, 0xF4, 0xD0, 0xFC, 0x7E, 0xF4, 0xD0, 0x0B, 0xE4, 0xF5,
+    0xCB, 0xF4, 0xD7, 0x00, 0xFC, 0xD0, 0xF3, 0xAB, 0x01, 0x10, 0xEF, 0x7E, 0xF4, 0x10, 0xEB, 0xBA,
+    0xF6, 0xDA, 0x00, 0xBA, 0xF4, 0xC4, 0xF4, 0xDD, 0x5D, 0xD0, 0xDB, 0x1F, 0x00, 0x00, 0xC0, 0xFF,
+];
+
+/// SPC700 cycles per timer tick: 8 kHz for timers 0-1, 64 kHz for timer 2
+const TIMER_PERIODS: [u32; 3] = [128, 128, 16];
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Timer {
+    enabled: bool,
+    /// $FA-$FC: ticks per counter increment, 0 means 256
+    target: u8,
+    /// Ticks since the last increment
+    stage: u8,
+    /// $FD-$FF: 4-bit up counter, cleared when read
+    counter: u8,
+    /// SPC700 cycles toward the next tick
+    clock: u32,
+}
+
+pub struct ApuBus {
+    ram: Vec<u8>,
+    /// CONTROL bit 7: IPL ROM readable at $FFC0
+    ipl_enabled: bool,
+    timers: [Timer; 3],
+    /// DSPADDR ($F2); DSPDATA ($F3) reaches the register it selects
+    dsp_addr: u8,
+    pub dsp: Dsp,
+    /// SPC700 cycles toward the next DSP sample
+    dsp_clock: u32,
+    /// $F8/$F9: two bytes of general purpose latches
+    aux: [u8; 2],
+
+    /// Ports written by the 5A22 ($2140-$2143), read at $F4-$F7
+    pub cpu_to_apu: [u8; 4],
+    /// Ports written at $F4-$F7, read by the 5A22
+    pub apu_to_cpu: [u8; 4],
+}
+
+impl ApuBus {
+    pub fn new() -> Self {
+        Self {
+            ram: vec![0; ARAM_SIZE],
+            ipl_enabled: true,
+            timers: [Timer::default(); 3],
+            dsp_addr: 0,
+            dsp: Dsp::new(),
+            dsp_clock: 0,
+            aux: [0; 2],
+            cpu_to_apu: [0; 4],
+            apu_to_cpu: [0; 4],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.ipl_enabled = true;
+        self.timers = [Timer::default(); 3];
+        self.dsp_addr = 0;
+        self.dsp.reset();
+        self.dsp_clock = 0;
+        self.cpu_to_apu = [0; 4];
+        self.apu_to_cpu = [0; 4];
+    }
+
+    /// ARAM as the sound driver sees it, for the DSP and save states
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Restore ARAM and the DSP from a snapshot; the I/O registers take the
+    /// values left in ARAM at $F0-$FF
+    pub fn load_snapshot(&mut self, ram: &[u8], dsp_regs: &[u8; 128], extra_ram: &[u8; 64]) {
+        self.reset();
+        self.ram.copy_from_slice(&ram[..ARAM_SIZE]);
+        self.write_control(self.ram[0x00F1] & 0x87);
+        // The dump holds the IPL ROM where the RAM behind it was hidden
+        if self.ipl_enabled {
+            self.ram[0xFFC0..].copy_from_slice(extra_ram);
+        }
+        self.dsp_addr = self.ram[0x00F2];
+        self.cpu_to_apu.copy_from_slice(&self.ram[0x00F4..0x00F8]);
+        self.aux.copy_from_slice(&self.ram[0x00F8..0x00FA]);
+        for (i, timer) in self.timers.iter_mut().enumerate() {
+            timer.target = self.ram[0x00FA + i];
+            timer.counter = self.ram[0x00FD + i] & 0x0F;
+        }
+        self.dsp.load_registers(dsp_regs);
+    }
+
+    /// Advance the timers and the DSP by `cycles` SPC700 cycles
+    pub fn tick(&mut self, cycles: u32) {
+        self.dsp_clock += cycles;
+        while self.dsp_clock >= CYCLES_PER_SAMPLE {
+            self.dsp_clock -= CYCLES_PER_SAMPLE;
+            self.dsp.run_sample(&mut self.ram);
+        }
+
+        for (timer, &period) in self.timers.iter_mut().zip(TIMER_PERIODS.iter()) {
+            timer.clock += cycles;
+            while timer.clock >= period {
+                timer.clock -= period;
+                if timer.enabled {
+                    timer.stage = timer.stage.wrapping_add(1);
+                    if timer.stage == timer.target {
+                        timer.stage = 0;
+                        timer.counter = (timer.counter + 1) & 0x0F;
+                    }
+                }
+            }
+        }
+    }
+
+    fn write_control(&mut self, value: u8) {
+        for (i, timer) in self.timers.iter_mut().enumerate() {
+            let enable = value & (1 << i) != 0;
+            // Starting a timer restarts its count
+            if enable && !timer.enabled {
+                timer.stage = 0;
+                timer.counter = 0;
+            }
+            timer.enabled = enable;
+        }
+        if value & 0x10 != 0 {
+            self.cpu_to_apu[0] = 0;
+            self.cpu_to_apu[1] = 0;
+        }
+        if value & 0x20 != 0 {
+            self.cpu_to_apu[2] = 0;
+            self.cpu_to_apu[3] = 0;
+        }
+        self.ipl_enabled = value & 0x80 != 0;
+    }
+}
+
+impl SpcBus for ApuBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x00F2 => self.dsp_addr,
+            0x00F3 => self.dsp.read(self.dsp_addr),
+            0x00F4..=0x00F7 => self.cpu_to_apu[(addr - 0x00F4) as usize],
+            0x00F8..=0x00F9 => self.aux[(addr - 0x00F8) as usize],
+            0x00FD..=0x00FF => {
+                let timer = &mut self.timers[(addr - 0x00FD) as usize];
+                std::mem::take(&mut timer.counter)
+            }
+            // TEST, CONTROL and the timer targets are write-only
+            0x00F0 | 0x00F1 | 0x00FA..=0x00FC => 0,
+            0xFFC0..=0xFFFF if self.ipl_enabled => IPL_ROM[(addr - 0xFFC0) as usize],
+            _ => self.ram[addr as usize],
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x00F1 => self.write_control(value),
+            0x00F2 => self.dsp_addr = value,
+            0x00F3 => self.dsp.write(self.dsp_addr, value),
+            0x00F4..=0x00F7 => self.apu_to_cpu[(addr - 0x00F4) as usize] = value,
+            0x00F8..=0x00F9 => self.aux[(addr - 0x00F8) as usize] = value,
+            0x00FA..=0x00FC => self.timers[(addr - 0x00FA) as usize].target = value,
+            _ => {}
+        }
+        // Writes to the I/O page and under the IPL ROM still reach ARAM
+        self.ram[addr as usize] = value;
+    }
+}
+
+impl Default for ApuBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Apu {
+    pub spc: SPC700,
+    pub bus: ApuBus,
+    /// SPC700 time owed to the master clock, in master cycles x APU_CLOCK
+    pending: i64,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self {
+            spc: SPC700::new(),
+            bus: ApuBus::new(),
+            pending: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.bus.reset();
+        self.spc.reset(&mut self.bus);
+        self.pending = 0;
+    }
+
+    /// Run the SPC700 for as long as `master_cycles` of 5A22 time
+    pub fn run(&mut self, master_cycles: u64, pal: bool) {
+        let master_clock = if pal { PAL_MASTER_CLOCK } else { NTSC_MASTER_CLOCK };
+        self.pending += master_cycles as i64 * APU_CLOCK;
+        while self.pending > 0 {
+            let cycles = self.spc.step(&mut self.bus);
+            self.bus.tick(cycles);
+            self.pending -= cycles as i64 * master_clock;
+        }
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
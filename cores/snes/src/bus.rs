@@ -0,0 +1,611 @@
+/// SNES System Bus
+/// Decodes the 24-bit A-bus (WRAM, MMIO, cartridge) and keeps the master
+/// clock: every CPU access costs 6, 8 or 12 master cycles, and DMA/HDMA
+/// halt the CPU while they run
+
+use crate::apu::Apu;
+use crate::cartridge::SnesCartridge;
+use crate::cpu::CpuBus;
+use crate::cpu_io::CpuIo;
+use crate::dma::DmaChannel;
+use crate::necdsp::DspInterface;
+use crate::ppu::PPU;
+use crate::sa1::Sa1;
+use crate::sdd1::Sdd1;
+use crate::spc7110::Spc7110;
+use crate::superfx::{self, SuperFx};
+use crate::hg51b::Cx4Interface;
+use crate::msu1::Msu1;
+
+pub const WRAM_SIZE: usize = 0x20000;
+
+/// Master cycles per scanline (341 dots of 4 cycles)
+pub const CYCLES_PER_LINE: u32 = 1364;
+pub const NTSC_LINES: u16 = 262;
+pub const PAL_LINES: u16 = 312;
+
+/// HDMA transfers happen near the end of each visible line
+const HDMA_POSITION: u32 = 1104;
+
+/// DRAM refresh pauses the CPU for 40 master cycles once per line
+const REFRESH_POSITION: u32 = 538;
+const REFRESH_CYCLES: u32 = 40;
+
+// Access speeds in master cycles
+const FAST: u32 = 6;
+const SLOW: u32 = 8;
+const XSLOW: u32 = 12;
+
+pub struct Bus {
+    wram: Vec<u8>,
+    /// WMADD ($2181-$2183), 17 bits
+    wram_addr: u32,
+
+    pub cartridge: Option<SnesCartridge>,
+    pub ppu: PPU,
+
+    pub apu: Apu,
+    /// Master cycle the APU has been run up to
+    apu_synced: u64,
+
+    /// DSP-n coprocessor on the cartridge, emulated or reimplemented
+    pub dsp: Option<Box<dyn DspInterface>>,
+    /// Master cycle the DSP has been run up to
+    pub(crate) dsp_synced: u64,
+
+    /// Super FX on the cartridge
+    pub superfx: Option<SuperFx>,
+    /// Master cycle the GSU has been run up to
+    superfx_synced: u64,
+    /// Cx4 on the HG51B core or its HLE stand-in
+    pub cx4: Option<Box<dyn Cx4Interface>>,
+    pub cx4_synced: u64,
+    /// MSU-1 media, present when a `.msu` file sits next to the ROM
+    pub msu1: Option<Msu1>,
+
+    /// SA-1 on the cartridge; it takes over the cartridge memory map
+    pub sa1: Option<Sa1>,
+    /// Master cycle the SA-1 has been run up to
+    sa1_synced: u64,
+
+    /// S-DD1 on the cartridge; it decompresses DMA reads from $C0-$FF
+    pub sdd1: Option<Sdd1>,
+
+    /// SPC7110 on the cartridge, with the RTC-4513 where fitted
+    pub spc7110: Option<Spc7110>,
+    /// Master cycle the SPC7110's RTC has been run up to
+    spc7110_synced: u64,
+
+    /// NMI/IRQ timers, multiplier/divider and joypads
+    pub io: CpuIo,
+
+    /// DMA channels ($4300-$437F)
+    pub dma: [DmaChannel; 8],
+    /// MDMAEN channels waiting to run
+    pub(crate) dma_pending: u8,
+    /// HDMAEN
+    pub(crate) hdma_enable: u8,
+    pub(crate) hdma_init_pending: bool,
+    pub(crate) hdma_run_pending: bool,
+    hdma_line_done: bool,
+
+    /// MEMSEL bit 0: banks $80-$FF at $8000+ run at 6 cycles
+    fast_rom: bool,
+
+    /// Last value driven on the data bus
+    pub(crate) open_bus: u8,
+
+    // Master clock
+    pub master_cycles: u64,
+    pub scanline: u16,
+    /// Master cycles into the current scanline
+    pub line_cycle: u32,
+    pub frame: u64,
+    pub pal: bool,
+    refreshed: bool,
+    frame_complete: bool,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self {
+            wram: vec![0; WRAM_SIZE],
+            wram_addr: 0,
+            cartridge: None,
+            ppu: PPU::new(),
+            apu: Apu::new(),
+            apu_synced: 0,
+            dsp: None,
+            dsp_synced: 0,
+            superfx: None,
+            superfx_synced: 0,
+            cx4: None,
+            cx4_synced: 0,
+            msu1: None,
+            sa1: None,
+            sa1_synced: 0,
+            sdd1: None,
+            spc7110: None,
+            spc7110_synced: 0,
+            io: CpuIo::new(),
+            dma: [DmaChannel::new(); 8],
+            dma_pending: 0,
+            hdma_enable: 0,
+            hdma_init_pending: false,
+            hdma_run_pending: false,
+            hdma_line_done: false,
+            fast_rom: false,
+            open_bus: 0,
+            master_cycles: 0,
+            scanline: 0,
+            line_cycle: 0,
+            frame: 0,
+            pal: false,
+            refreshed: false,
+            frame_complete: false,
+        }
+    }
+
+    pub fn load_cartridge(&mut self, cartridge: SnesCartridge) {
+        self.pal = cartridge.region.is_pal();
+        self.ppu.pal = self.pal;
+        self.cartridge = Some(cartridge);
+    }
+
+    pub fn reset(&mut self) {
+        self.wram_addr = 0;
+        self.fast_rom = false;
+        self.dma_pending = 0;
+        self.hdma_enable = 0;
+        self.hdma_init_pending = false;
+        self.hdma_run_pending = false;
+        self.hdma_line_done = false;
+        self.ppu.reset();
+        self.apu.reset();
+        self.io.reset();
+        if let Some(dsp) = &mut self.dsp {
+            dsp.reset();
+        }
+        if let Some(gsu) = &mut self.superfx {
+            gsu.reset();
+        }
+        self.master_cycles = 0;
+        self.apu_synced = 0;
+        self.dsp_synced = 0;
+        self.superfx_synced = 0;
+        if let Some(cx4) = &mut self.cx4 {
+            cx4.reset();
+        }
+        self.cx4_synced = 0;
+        if let Some(msu1) = &mut self.msu1 {
+            msu1.reset();
+        }
+        if let Some(sa1) = &mut self.sa1 {
+            sa1.reset();
+        }
+        self.sa1_synced = 0;
+        if let Some(sdd1) = &mut self.sdd1 {
+            sdd1.reset();
+        }
+        if let Some(spc7110) = &mut self.spc7110 {
+            spc7110.reset();
+        }
+        self.spc7110_synced = 0;
+        self.scanline = 0;
+        self.line_cycle = 0;
+        self.refreshed = false;
+        self.frame_complete = false;
+    }
+
+    pub fn lines_per_frame(&self) -> u16 {
+        if self.pal { PAL_LINES } else { NTSC_LINES }
+    }
+
+    /// True once per frame, when vblank starts
+    pub fn take_frame_complete(&mut self) -> bool {
+        std::mem::take(&mut self.frame_complete)
+    }
+
+    /// Master cycles a CPU access to `addr` takes
+    pub fn access_cycles(&self, addr: u32) -> u32 {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+
+        if bank & 0x40 == 0 {
+            // $00-$3F, $80-$BF: system area below $8000
+            match offset {
+                0x0000..=0x1FFF => SLOW,
+                0x2000..=0x3FFF => FAST,
+                0x4000..=0x41FF => XSLOW,
+                0x4200..=0x5FFF => FAST,
+                0x6000..=0x7FFF => SLOW,
+                _ if bank & 0x80 != 0 && self.fast_rom => FAST,
+                _ => SLOW,
+            }
+        } else if bank & 0x80 != 0 && self.fast_rom {
+            FAST
+        } else {
+            SLOW
+        }
+    }
+
+    /// Advance the master clock, inserting the DRAM refresh and rolling
+    /// over scanlines and frames
+    pub fn add_cycles(&mut self, cycles: u32) {
+        let from = self.line_cycle;
+        self.master_cycles += cycles as u64;
+        self.line_cycle += cycles;
+
+        if !self.refreshed && self.line_cycle >= REFRESH_POSITION {
+            self.refreshed = true;
+            self.master_cycles += REFRESH_CYCLES as u64;
+            self.line_cycle += REFRESH_CYCLES;
+        }
+
+        if !self.hdma_line_done && self.line_cycle >= HDMA_POSITION {
+            self.hdma_line_done = true;
+            if self.scanline < self.ppu.vblank_line() {
+                self.hdma_run_pending = true;
+            }
+        }
+
+        self.io_poll_irq(from, self.line_cycle);
+
+        while self.line_cycle >= CYCLES_PER_LINE {
+            self.line_cycle -= CYCLES_PER_LINE;
+            self.refreshed = false;
+            self.hdma_line_done = false;
+            self.scanline += 1;
+            if self.scanline == self.lines_per_frame() {
+                self.scanline = 0;
+                self.frame += 1;
+            }
+            self.start_scanline();
+        }
+    }
+
+    /// Run the APU up to the current master cycle
+    pub fn sync_apu(&mut self) {
+        let first = self.apu.bus.dsp.samples().len();
+        self.apu.run(self.master_cycles - self.apu_synced, self.pal);
+        self.apu_synced = self.master_cycles;
+        if let Some(msu1) = &mut self.msu1 {
+            msu1.mix(&mut self.apu.bus.dsp.samples_mut()[first..]);
+        }
+    }
+
+    /// Run the DSP up to the current master cycle
+    fn sync_dsp(&mut self) {
+        if let Some(dsp) = &mut self.dsp {
+            dsp.run(self.master_cycles - self.dsp_synced, self.pal);
+        }
+        self.dsp_synced = self.master_cycles;
+    }
+
+    /// Run the GSU up to the current master cycle
+    fn sync_superfx(&mut self) {
+        if let (Some(gsu), Some(cart)) = (&mut self.superfx, &mut self.cartridge) {
+            gsu.run(self.master_cycles - self.superfx_synced, cart);
+        }
+        self.superfx_synced = self.master_cycles;
+    }
+
+    /// Run the Cx4 up to the current master cycle
+    fn sync_cx4(&mut self) {
+        if let (Some(cx4), Some(cart)) = (&mut self.cx4, &mut self.cartridge) {
+            cx4.run(self.master_cycles - self.cx4_synced, self.pal, cart);
+        }
+        self.cx4_synced = self.master_cycles;
+    }
+
+    /// Run the SA-1 up to the current master cycle
+    fn sync_sa1(&mut self) {
+        if let (Some(sa1), Some(cart)) = (&mut self.sa1, &mut self.cartridge) {
+            sa1.run(self.master_cycles - self.sa1_synced, cart);
+        }
+        self.sa1_synced = self.master_cycles;
+    }
+
+    /// Run the SPC7110's RTC up to the current master cycle
+    fn sync_spc7110(&mut self) {
+        if let Some(spc7110) = &mut self.spc7110 {
+            spc7110.run(self.master_cycles - self.spc7110_synced, self.pal);
+        }
+        self.spc7110_synced = self.master_cycles;
+    }
+
+    fn start_scanline(&mut self) {
+        self.sync_apu();
+        self.sync_dsp();
+        self.sync_superfx();
+        self.sync_cx4();
+        self.sync_sa1();
+        self.sync_spc7110();
+        self.ppu.start_scanline(self.scanline);
+        if self.scanline == 0 {
+            self.hdma_init_pending = true;
+        }
+        if self.scanline == self.ppu.vblank_line() {
+            self.frame_complete = true;
+        }
+        self.io_start_scanline();
+    }
+
+    /// Read without advancing the clock
+    pub fn read_byte(&mut self, addr: u32) -> u8 {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+
+        let value = match (bank, offset) {
+            (0x7E..=0x7F, _) => Some(self.wram[(addr as usize) & (WRAM_SIZE - 1)]),
+            (0x00..=0x3F | 0x80..=0xBF, 0x0000..=0x1FFF) => Some(self.wram[offset as usize]),
+            (0x00..=0x3F | 0x80..=0xBF, 0x2000..=0x5FFF) => self.read_io(offset),
+            _ => self.read_cartridge(addr),
+        };
+
+        // Unmapped reads return the last value on the bus
+        let value = value.unwrap_or(self.open_bus);
+        self.open_bus = value;
+        value
+    }
+
+    /// Write without advancing the clock
+    pub fn write_byte(&mut self, addr: u32, value: u8) {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        self.open_bus = value;
+
+        match (bank, offset) {
+            (0x7E..=0x7F, _) => self.wram[(addr as usize) & (WRAM_SIZE - 1)] = value,
+            (0x00..=0x3F | 0x80..=0xBF, 0x0000..=0x1FFF) => self.wram[offset as usize] = value,
+            (0x00..=0x3F | 0x80..=0xBF, 0x2000..=0x5FFF) => self.write_io(offset, value),
+            _ => self.write_cartridge(addr, value),
+        }
+    }
+
+    fn read_cartridge(&mut self, addr: u32) -> Option<u8> {
+        if self.sa1.is_some() {
+            self.sync_sa1();
+            let (sa1, cart) = (self.sa1.as_mut()?, self.cartridge.as_ref()?);
+            return sa1.read(cart, addr);
+        }
+        if let (Some(sdd1), Some(cart)) = (&mut self.sdd1, &self.cartridge) {
+            return sdd1.read(cart, addr);
+        }
+        if let (Some(spc7110), Some(cart)) = (&mut self.spc7110, &self.cartridge) {
+            return spc7110.read(cart, addr);
+        }
+        if self.superfx.is_some() {
+            self.sync_superfx();
+        }
+        let cart = self.cartridge.as_ref()?;
+        if let Some(gsu) = &self.superfx {
+            if gsu.owns_rom() && cart.rom_offset(addr).is_some() {
+                return Some(superfx::ROM_VECTORS[addr as usize & 0x0F]);
+            }
+            if gsu.owns_ram() && cart.sram_offset(addr).is_some() {
+                return None;
+            }
+        }
+        if self.cx4.is_some() {
+            self.sync_cx4();
+            if let (Some(cx4), Some(cart)) = (&mut self.cx4, &self.cartridge) {
+                if is_cx4_window(addr) {
+                    return cx4.read_io(addr as u16);
+                }
+                if cx4.owns_rom() && cart.rom_offset(addr).is_some() {
+                    return cx4.vector(addr);
+                }
+            }
+        }
+        let cart = self.cartridge.as_ref()?;
+        if let Some(offset) = cart.dsp_ram_offset(addr) {
+            if self.dsp.is_some() {
+                self.sync_dsp();
+                return self.dsp.as_mut().map(|dsp| dsp.read_ram(offset));
+            }
+        }
+        match cart.dsp_port(addr) {
+            Some(status) if self.dsp.is_some() => {
+                self.sync_dsp();
+                let dsp = self.dsp.as_mut()?;
+                Some(if status { dsp.read_status() } else { dsp.read_data() })
+            }
+            _ => cart.read(addr),
+        }
+    }
+
+    fn write_cartridge(&mut self, addr: u32, value: u8) {
+        if self.sa1.is_some() {
+            self.sync_sa1();
+            if let (Some(sa1), Some(cart)) = (&mut self.sa1, &mut self.cartridge) {
+                sa1.write(cart, addr, value);
+            }
+            return;
+        }
+        if let (Some(sdd1), Some(cart)) = (&mut self.sdd1, &mut self.cartridge) {
+            sdd1.write(cart, addr, value);
+            return;
+        }
+        if let (Some(spc7110), Some(cart)) = (&mut self.spc7110, &mut self.cartridge) {
+            spc7110.write(cart, addr, value);
+            return;
+        }
+        if self.superfx.is_some() {
+            self.sync_superfx();
+        }
+        let Some(cart) = &mut self.cartridge else {
+            return;
+        };
+        if self.superfx.as_ref().is_some_and(|gsu| gsu.owns_ram()) && cart.sram_offset(addr).is_some() {
+            return;
+        }
+        if self.cx4.is_some() && is_cx4_window(addr) {
+            self.sync_cx4();
+            if let (Some(cx4), Some(cart)) = (&mut self.cx4, &self.cartridge) {
+                cx4.write_io(cart, addr as u16, value);
+            }
+            return;
+        }
+        let Some(cart) = &mut self.cartridge else {
+            return;
+        };
+        if let Some(offset) = cart.dsp_ram_offset(addr) {
+            if self.dsp.is_some() {
+                self.sync_dsp();
+                if let Some(dsp) = &mut self.dsp {
+                    dsp.write_ram(offset, value);
+                }
+                return;
+            }
+        }
+        match cart.dsp_port(addr) {
+            // SR is read-only
+            Some(true) if self.dsp.is_some() => {}
+            Some(false) if self.dsp.is_some() => {
+                self.sync_dsp();
+                if let Some(dsp) = &mut self.dsp {
+                    dsp.write_data(value);
+                }
+            }
+            _ => cart.write(addr, value),
+        }
+    }
+
+    pub(crate) fn read_io(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0x2200..=0x23FF | 0x3000..=0x37FF if self.sa1.is_some() => {
+                self.sync_sa1();
+                let (sa1, cart) = (self.sa1.as_mut()?, self.cartridge.as_mut()?);
+                sa1.read_io(cart, addr)
+            }
+            0x3000..=0x32FF if self.superfx.is_some() => {
+                self.sync_superfx();
+                self.superfx.as_mut().map(|gsu| gsu.read_io(addr))
+            }
+            0x4800..=0x480F if self.sdd1.is_some() => self.sdd1.as_ref()?.read_io(addr),
+            0x4800..=0x4842 if self.spc7110.is_some() => {
+                self.sync_spc7110();
+                let (spc7110, cart) = (self.spc7110.as_mut()?, self.cartridge.as_ref()?);
+                spc7110.read_io(cart, addr)
+            }
+            0x2000..=0x2007 if self.msu1.is_some() => {
+                // Audio state changes with the samples already mixed
+                self.sync_apu();
+                self.msu1.as_mut().map(|msu1| msu1.read(addr))
+            }
+            0x2137 | 0x4016 | 0x4017 | 0x4200..=0x421F => self.read_cpu_io(addr),
+            0x2100..=0x213F => Some(self.ppu.read_register(addr, self.open_bus)),
+            0x2140..=0x217F => {
+                self.sync_apu();
+                Some(self.apu.bus.apu_to_cpu[(addr & 0x03) as usize])
+            }
+            0x2180 => {
+                // WMDATA
+                let value = self.wram[self.wram_addr as usize];
+                self.wram_addr = (self.wram_addr + 1) & (WRAM_SIZE as u32 - 1);
+                Some(value)
+            }
+            0x4300..=0x437F => self.dma[((addr >> 4) & 0x07) as usize].read(addr),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn write_io(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x2200..=0x23FF | 0x3000..=0x37FF if self.sa1.is_some() => {
+                self.sync_sa1();
+                if let (Some(sa1), Some(cart)) = (&mut self.sa1, &mut self.cartridge) {
+                    sa1.write_io(cart, addr, value);
+                }
+            }
+            0x3000..=0x32FF if self.superfx.is_some() => {
+                self.sync_superfx();
+                if let Some(gsu) = &mut self.superfx {
+                    gsu.write_io(addr, value);
+                }
+            }
+            0x4800..=0x480F if self.sdd1.is_some() => {
+                if let Some(sdd1) = &mut self.sdd1 {
+                    sdd1.write_io(addr, value);
+                }
+            }
+            0x4800..=0x4842 if self.spc7110.is_some() => {
+                self.sync_spc7110();
+                if let (Some(spc7110), Some(cart)) = (&mut self.spc7110, &self.cartridge) {
+                    spc7110.write_io(cart, addr, value);
+                }
+            }
+            0x2000..=0x2007 if self.msu1.is_some() => {
+                self.sync_apu();
+                if let Some(msu1) = &mut self.msu1 {
+                    msu1.write(addr, value);
+                }
+            }
+            0x2100..=0x213F => self.ppu.write_register(addr, value),
+            0x2140..=0x217F => {
+                self.sync_apu();
+                self.apu.bus.cpu_to_apu[(addr & 0x03) as usize] = value;
+            }
+            0x2180 => {
+                self.wram[self.wram_addr as usize] = value;
+                self.wram_addr = (self.wram_addr + 1) & (WRAM_SIZE as u32 - 1);
+            }
+            0x2181 => self.wram_addr = (self.wram_addr & 0x1FF00) | value as u32,
+            0x2182 => self.wram_addr = (self.wram_addr & 0x100FF) | ((value as u32) << 8),
+            0x2183 => self.wram_addr = (self.wram_addr & 0x0FFFF) | (((value & 0x01) as u32) << 16),
+            0x4016 | 0x4200..=0x420A => self.write_cpu_io(addr, value),
+            0x420B => self.dma_pending = value,
+            0x420C => self.hdma_enable = value,
+            0x420D => self.fast_rom = value & 0x01 != 0,
+            0x4300..=0x437F => {
+                self.dma[((addr >> 4) & 0x07) as usize].write(addr, value);
+                if let Some(sdd1) = &mut self.sdd1 {
+                    sdd1.snoop_dma(addr, value);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Bus {
+    /// Let DMA and due HDMA run before the CPU touches the bus again
+    fn halt_for_dma(&mut self) {
+        self.service_hdma();
+        self.run_dma();
+    }
+}
+
+impl CpuBus for Bus {
+    fn read(&mut self, addr: u32) -> u8 {
+        self.halt_for_dma();
+        self.io.alu_step();
+        self.add_cycles(self.access_cycles(addr));
+        self.read_byte(addr)
+    }
+
+    fn write(&mut self, addr: u32, value: u8) {
+        self.halt_for_dma();
+        self.io.alu_step();
+        self.add_cycles(self.access_cycles(addr));
+        self.write_byte(addr, value);
+    }
+
+    fn idle(&mut self) {
+        self.halt_for_dma();
+        self.io.alu_step();
+        self.add_cycles(FAST);
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The Cx4 answers at $6000-$7FFF of banks $00-$3F/$80-$BF
+fn is_cx4_window(addr: u32) -> bool {
+    addr & 0x40E000 == 0x006000
+}
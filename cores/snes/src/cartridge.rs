@@ -0,0 +1,551 @@
+/// SNES Cartridge
+/// Internal header detection, copier header / interleave cleanup and the
+/// LoROM/HiROM/ExHiROM memory maps
+
+use anyhow::Result;
+
+use crate::necdsp::{NecDspModel, UPD77C25_FREQUENCY};
+
+/// Size of the SMC/SWC copier header some dumps start with
+pub const COPIER_HEADER_SIZE: usize = 512;
+
+/// Internal header locations in the ROM image
+const LOROM_HEADER: usize = 0x7FC0;
+const HIROM_HEADER: usize = 0xFFC0;
+const EXHIROM_HEADER: usize = 0x40FFC0;
+
+/// Address decoding used by the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapMode {
+    /// 32KB banks at $8000-$FFFF
+    LoRom,
+    /// 64KB banks at $C0-$FF (mirrored to $8000-$FFFF of $00-$3F)
+    HiRom,
+    /// LoROM over 4MB; banks $00-$7F hold the upper half
+    ExLoRom,
+    /// HiROM over 4MB; banks $40-$7D and $00-$3F hold the upper half
+    ExHiRom,
+}
+
+/// Extra chip on the board, from the chipset byte ($FFD6)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coprocessor {
+    None,
+    /// DSP-1/2/3/4 (uPD77C25)
+    Dsp,
+    /// Super FX (GSU-1/2)
+    SuperFx,
+    Obc1,
+    Sa1,
+    Sdd1,
+    SRtc,
+    Spc7110,
+    /// ST010/ST011 (uPD96050)
+    St01x,
+    St018,
+    Cx4,
+    /// Super Game Boy, Satellaview and anything else not emulated
+    Other(u8),
+}
+
+/// Program in a NEC DSP board's chip; the header only says "DSP" or "ST01x"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DspModel {
+    Dsp1,
+    Dsp1B,
+    Dsp2,
+    Dsp3,
+    Dsp4,
+    /// uPD96050 boards
+    St010,
+    St011,
+}
+
+impl DspModel {
+    /// Told apart by title. Pilotwings needs the original DSP-1; every
+    /// other DSP-1 game runs on the 1B revision.
+    fn detect(title: &str) -> Self {
+        if title.starts_with("DUNGEON MASTER") {
+            DspModel::Dsp2
+        } else if title.starts_with("SD") && title.ends_with("GX") {
+            DspModel::Dsp3
+        } else if title.contains("TOP GEAR 3000") || title.contains("TG3000") {
+            DspModel::Dsp4
+        } else if title.starts_with("PILOTWINGS") {
+            DspModel::Dsp1
+        } else {
+            DspModel::Dsp1B
+        }
+    }
+
+    /// Shogi is the only ST011 game; everything else is ST010 (F1 ROC II)
+    fn detect_st01x(title: &str) -> Self {
+        if title.starts_with("HAYAZASHI") {
+            DspModel::St011
+        } else {
+            DspModel::St010
+        }
+    }
+
+    /// File name of the firmware dump (program ROM + data ROM)
+    pub fn firmware_name(self) -> &'static str {
+        match self {
+            DspModel::Dsp1 => "dsp1.rom",
+            DspModel::Dsp1B => "dsp1b.rom",
+            DspModel::Dsp2 => "dsp2.rom",
+            DspModel::Dsp3 => "dsp3.rom",
+            DspModel::Dsp4 => "dsp4.rom",
+            DspModel::St010 => "st010.rom",
+            DspModel::St011 => "st011.rom",
+        }
+    }
+
+    pub fn chip(self) -> NecDspModel {
+        match self {
+            DspModel::St010 | DspModel::St011 => NecDspModel::Upd96050,
+            _ => NecDspModel::Upd77c25,
+        }
+    }
+
+    /// Instruction clock of the chip on this board
+    pub fn frequency(self) -> i64 {
+        match self {
+            DspModel::St010 => 11_000_000,
+            DspModel::St011 => 15_000_000,
+            _ => UPD77C25_FREQUENCY,
+        }
+    }
+}
+
+/// Destination code ($FFD9)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Japan,
+    NorthAmerica,
+    Europe,
+    Scandinavia,
+    France,
+    Netherlands,
+    Spain,
+    Germany,
+    Italy,
+    China,
+    Korea,
+    Canada,
+    Brazil,
+    Australia,
+    Other(u8),
+}
+
+impl Region {
+    fn from_code(code: u8) -> Self {
+        match code {
+            0x00 => Region::Japan,
+            0x01 => Region::NorthAmerica,
+            0x02 => Region::Europe,
+            0x03 => Region::Scandinavia,
+            0x06 => Region::France,
+            0x07 => Region::Netherlands,
+            0x08 => Region::Spain,
+            0x09 => Region::Germany,
+            0x0A => Region::Italy,
+            0x0B => Region::China,
+            0x0D => Region::Korea,
+            0x0F => Region::Canada,
+            0x10 => Region::Brazil,
+            0x11 => Region::Australia,
+            _ => Region::Other(code),
+        }
+    }
+
+    /// 50Hz consoles: Europe, Asia outside Japan/Korea, Australia
+    pub fn is_pal(&self) -> bool {
+        match self {
+            Region::Japan | Region::NorthAmerica | Region::Korea | Region::Canada | Region::Brazil => false,
+            Region::Other(code) => (0x02..=0x0C).contains(code),
+            _ => true,
+        }
+    }
+}
+
+pub struct SnesCartridge {
+    pub rom: Vec<u8>,
+    pub sram: Vec<u8>,
+    pub title: String,
+    pub map_mode: MapMode,
+    /// Raw map mode byte ($FFD5)
+    pub map_mode_byte: u8,
+    pub coprocessor: Coprocessor,
+    /// Raw chipset byte ($FFD6)
+    pub chipset: u8,
+    /// Set for `Coprocessor::Dsp` and `Coprocessor::St01x` boards
+    pub dsp_model: Option<DspModel>,
+    pub region: Region,
+    pub version: u8,
+    /// SRAM is battery backed and worth saving
+    pub battery: bool,
+    /// FastROM (map mode bit 4); fast access still needs MEMSEL
+    pub fast_rom: bool,
+    /// Offset of the internal header that was picked
+    pub header_offset: usize,
+}
+
+impl SnesCartridge {
+    /// Detect the header and build the cartridge from a .sfc/.smc image
+    pub fn load(rom_data: &[u8]) -> Result<Self> {
+        let mut rom = strip_copier_header(rom_data).to_vec();
+        if rom.len() < 0x8000 {
+            anyhow::bail!("SNES ROM too small ({} bytes)", rom.len());
+        }
+
+        let mut header_offset = find_header(&rom);
+        if header_offset == LOROM_HEADER && is_interleaved(&rom) {
+            log::info!("Interleaved ROM dump, de-interleaving");
+            rom = deinterleave(&rom);
+            header_offset = find_header(&rom);
+        }
+
+        let header = &rom[header_offset..header_offset + 0x40];
+        let map_mode_byte = header[0x15];
+        let chipset = header[0x16];
+        let sram_code = header[0x18];
+
+        let title = header[..21]
+            .iter()
+            .map(|&c| if (0x20..0x7F).contains(&c) { c as char } else { ' ' })
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+
+        let map_mode = match header_offset {
+            EXHIROM_HEADER => MapMode::ExHiRom,
+            HIROM_HEADER => MapMode::HiRom,
+            _ if rom.len() > 0x400000 => MapMode::ExLoRom,
+            _ => MapMode::LoRom,
+        };
+
+        let coprocessor = if chipset & 0x0F < 0x03 {
+            Coprocessor::None
+        } else {
+            match chipset >> 4 {
+                0x0 => Coprocessor::Dsp,
+                0x1 => Coprocessor::SuperFx,
+                0x2 => Coprocessor::Obc1,
+                0x3 => Coprocessor::Sa1,
+                0x4 => Coprocessor::Sdd1,
+                0x5 => Coprocessor::SRtc,
+                0xF => match rom[header_offset - 1] {
+                    0x00 => Coprocessor::Spc7110,
+                    0x01 => Coprocessor::St01x,
+                    0x02 => Coprocessor::St018,
+                    0x10 => Coprocessor::Cx4,
+                    other => Coprocessor::Other(other),
+                },
+                _ => Coprocessor::Other(chipset),
+            }
+        };
+
+        // Super FX boards give their RAM size in the extended header
+        let sram_code = if sram_code == 0 && header[0x1A] == 0x33 && coprocessor == Coprocessor::SuperFx {
+            rom[header_offset - 0x03]
+        } else {
+            sram_code
+        };
+        let sram_size = if sram_code == 0 || sram_code > 0x0C { 0 } else { 0x400 << sram_code };
+        // Early Super FX boards leave it out; give them the full 64KB the GSU addresses
+        let sram_size = if sram_size == 0 && coprocessor == Coprocessor::SuperFx { 0x10000 } else { sram_size };
+
+        let dsp_model = match coprocessor {
+            Coprocessor::Dsp => Some(DspModel::detect(&title)),
+            Coprocessor::St01x => Some(DspModel::detect_st01x(&title)),
+            _ => None,
+        };
+        let cartridge = Self {
+            sram: vec![0xFF; sram_size],
+            title,
+            map_mode,
+            map_mode_byte,
+            coprocessor,
+            chipset,
+            dsp_model,
+            region: Region::from_code(header[0x19]),
+            version: header[0x1B],
+            battery: matches!(chipset & 0x0F, 0x02 | 0x05 | 0x06 | 0x09 | 0x0A),
+            fast_rom: map_mode_byte & 0x10 != 0,
+            header_offset,
+            rom,
+        };
+
+        log::info!("SNES cartridge \"{}\": {:?}, {}KB ROM, {}KB SRAM, {:?}, coprocessor {:?}",
+                   cartridge.title, cartridge.map_mode, cartridge.rom_size() / 1024,
+                   cartridge.sram_size() / 1024, cartridge.region, cartridge.coprocessor);
+        Ok(cartridge)
+    }
+
+    pub fn rom_size(&self) -> usize {
+        self.rom.len()
+    }
+
+    pub fn sram_size(&self) -> usize {
+        self.sram.len()
+    }
+
+    /// DSP register behind a CPU address: `Some(true)` for SR, `Some(false)`
+    /// for DR. HiROM boards decode A12 at $00-$1F:$6000-$7FFF; LoROM boards
+    /// decode A14 in the upper half of $30-$3F ($20-$3F for DSP-2/3), or in
+    /// the lower half of $60-$6F when the ROM fills the upper banks. ST01x
+    /// boards decode A0 at $60-$67:$0000-$3FFF.
+    pub fn dsp_port(&self, addr: u32) -> Option<bool> {
+        let model = self.dsp_model?;
+        let bank = (addr >> 16) as u8 & 0x7F;
+        let offset = addr as u16;
+        if model.chip() == NecDspModel::Upd96050 {
+            return ((0x60..=0x67).contains(&bank) && offset < 0x4000).then_some(offset & 0x0001 != 0);
+        }
+        match self.map_mode {
+            MapMode::HiRom | MapMode::ExHiRom => {
+                (bank < 0x20 && (0x6000..0x8000).contains(&offset)).then_some(offset & 0x1000 != 0)
+            }
+            MapMode::LoRom | MapMode::ExLoRom => {
+                let (banks, lower_half) = match model {
+                    DspModel::Dsp2 | DspModel::Dsp3 => (0x20..=0x3F, false),
+                    _ if self.rom.len() > 0x100000 => (0x60..=0x6F, true),
+                    _ => (0x30..=0x3F, false),
+                };
+                (banks.contains(&bank) && (offset < 0x8000) == lower_half).then_some(offset & 0x4000 != 0)
+            }
+        }
+    }
+
+    /// Byte address in the uPD96050's data RAM, which ST01x boards map at
+    /// $68-$6F:$0000-$0FFF
+    pub fn dsp_ram_offset(&self, addr: u32) -> Option<u16> {
+        let model = self.dsp_model?;
+        let bank = (addr >> 16) as u8 & 0x7F;
+        let offset = addr as u16;
+        (model.chip() == NecDspModel::Upd96050 && (0x68..=0x6F).contains(&bank) && offset < 0x8000)
+            .then_some(offset & 0x0FFF)
+    }
+
+    /// Cartridge read; `None` where the cartridge does not drive the bus
+    pub fn read(&self, addr: u32) -> Option<u8> {
+        if let Some(offset) = self.sram_offset(addr) {
+            return Some(self.sram[offset]);
+        }
+        self.rom_offset(addr).map(|offset| self.rom[offset])
+    }
+
+    pub fn write(&mut self, addr: u32, value: u8) {
+        if let Some(offset) = self.sram_offset(addr) {
+            self.sram[offset] = value;
+        }
+    }
+
+    /// ROM offset behind a CPU address, with non-power-of-two sizes mirrored
+    pub fn rom_offset(&self, addr: u32) -> Option<usize> {
+        let bank = (addr >> 16) as usize & 0xFF;
+        let offset = addr as usize & 0xFFFF;
+        if bank & 0xFE == 0x7E {
+            return None; // WRAM
+        }
+        if self.coprocessor == Coprocessor::SuperFx {
+            // $00-$3F:$8000-$FFFF LoROM, $40-$5F linear
+            let linear = match bank & 0x7F {
+                0x00..=0x3F if offset >= 0x8000 => (bank & 0x3F) * 0x8000 + (offset & 0x7FFF),
+                0x40..=0x5F => (bank & 0x1F) * 0x10000 + offset,
+                _ => return None,
+            };
+            return Some(mirror(linear, self.rom.len()));
+        }
+
+        let linear = match self.map_mode {
+            MapMode::LoRom | MapMode::ExLoRom => {
+                // Banks $40-$6F/$C0-$EF also mirror ROM below $8000
+                let lower_ok = matches!(bank & 0x7F, 0x40..=0x6F);
+                if offset < 0x8000 && !lower_ok {
+                    return None;
+                }
+                let linear = (bank & 0x7F) * 0x8000 + (offset & 0x7FFF);
+                if self.map_mode == MapMode::ExLoRom && bank & 0x80 == 0 {
+                    linear + 0x400000
+                } else {
+                    linear
+                }
+            }
+            MapMode::HiRom | MapMode::ExHiRom => {
+                if bank & 0x40 == 0 && offset < 0x8000 {
+                    return None;
+                }
+                let linear = (bank & 0x3F) * 0x10000 + offset;
+                if self.map_mode == MapMode::ExHiRom && bank & 0x80 == 0 {
+                    linear + 0x400000
+                } else {
+                    linear
+                }
+            }
+        };
+        Some(mirror(linear, self.rom.len()))
+    }
+
+    /// SRAM offset behind a CPU address
+    pub fn sram_offset(&self, addr: u32) -> Option<usize> {
+        if self.sram.is_empty() {
+            return None;
+        }
+        let bank = (addr >> 16) as usize & 0xFF;
+        let offset = addr as usize & 0xFFFF;
+
+        if self.coprocessor == Coprocessor::SuperFx {
+            // $70-$71 in full, the first 8KB at $00-$3F:$6000-$7FFF
+            let linear = match bank & 0x7F {
+                0x70..=0x71 => ((bank & 1) << 16) | offset,
+                0x00..=0x3F if (0x6000..0x8000).contains(&offset) => offset - 0x6000,
+                _ => return None,
+            };
+            return Some(linear % self.sram.len());
+        }
+
+        let linear = match self.map_mode {
+            MapMode::LoRom | MapMode::ExLoRom => {
+                // $70-$7D/$F0-$FF:$0000-$7FFF
+                if !((0x70..=0x7D).contains(&bank) || bank >= 0xF0) || offset >= 0x8000 {
+                    return None;
+                }
+                ((bank & 0x0F) * 0x8000) + offset
+            }
+            MapMode::HiRom | MapMode::ExHiRom => {
+                // $20-$3F/$A0-$BF:$6000-$7FFF in 8KB pages
+                if !(0x20..=0x3F).contains(&(bank & 0x7F)) || !(0x6000..0x8000).contains(&offset) {
+                    return None;
+                }
+                ((bank & 0x1F) * 0x2000) + (offset - 0x6000)
+            }
+        };
+        Some(linear % self.sram.len())
+    }
+}
+
+/// Drop a 512-byte copier header, detected from the image size
+pub fn strip_copier_header(rom_data: &[u8]) -> &[u8] {
+    if rom_data.len() % 0x400 == COPIER_HEADER_SIZE {
+        &rom_data[COPIER_HEADER_SIZE..]
+    } else {
+        rom_data
+    }
+}
+
+/// Map a linear address onto a ROM whose size is not a power of two: the
+/// image is treated as a power-of-two part followed by repeated remainders
+pub fn mirror(mut addr: usize, mut size: usize) -> usize {
+    if size == 0 {
+        return 0;
+    }
+    let mut base = 0;
+    let mut mask = 1 << 23;
+    while addr >= size {
+        while addr & mask == 0 {
+            mask >>= 1;
+        }
+        addr -= mask;
+        if size > mask {
+            size -= mask;
+            base += mask;
+        }
+        mask >>= 1;
+    }
+    base + addr
+}
+
+/// Header candidate with the best score
+fn find_header(rom: &[u8]) -> usize {
+    let checksum = checksum_of(rom);
+    [LOROM_HEADER, HIROM_HEADER, EXHIROM_HEADER]
+        .into_iter()
+        .filter(|&offset| offset + 0x40 <= rom.len())
+        .map(|offset| (score_header(rom, offset, checksum), offset))
+        // Ties keep the earlier (LoROM) candidate
+        .fold((i32::MIN, LOROM_HEADER), |best, candidate| if candidate.0 > best.0 { candidate } else { best })
+        .1
+}
+
+/// How plausible the header at `offset` is
+fn score_header(rom: &[u8], offset: usize, rom_checksum: u16) -> i32 {
+    let header = &rom[offset..offset + 0x40];
+    let mut score = 0;
+
+    let complement = u16::from_le_bytes([header[0x1C], header[0x1D]]);
+    let checksum = u16::from_le_bytes([header[0x1E], header[0x1F]]);
+    if checksum ^ complement == 0xFFFF {
+        score += 4;
+        if checksum == rom_checksum {
+            score += 4;
+        }
+    }
+
+    let map_mode = header[0x15];
+    let expected = match offset {
+        LOROM_HEADER => matches!(map_mode & 0x0F, 0x00 | 0x02 | 0x03),
+        HIROM_HEADER => matches!(map_mode & 0x0F, 0x01 | 0x0A),
+        _ => map_mode & 0x0F == 0x05,
+    };
+    if map_mode & 0xE0 == 0x20 && expected {
+        score += 2;
+    }
+
+    // The reset vector must point at ROM, ideally at a typical first opcode
+    let reset = u16::from_le_bytes([header[0x3C], header[0x3D]]) as usize;
+    if reset < 0x8000 {
+        score -= 4;
+    } else {
+        let bank_mask = if offset == LOROM_HEADER { 0x7FFF } else { 0xFFFF };
+        let entry = (offset & !0xFFFF) + (reset & bank_mask);
+        match rom.get(entry) {
+            // SEI, CLC, SEC, SEP, REP, LDX/LDA #, JMP, JML
+            Some(0x78 | 0x18 | 0x38 | 0xE2 | 0xC2 | 0xA2 | 0xA9 | 0x4C | 0x5C) => score += 8,
+            // BRK, COP, STP, WDM and erased flash
+            Some(0x00 | 0x02 | 0xDB | 0x42 | 0xFF) | None => score -= 8,
+            _ => {}
+        }
+    }
+
+    if header[..21].iter().all(|c| (0x20..0x7F).contains(c)) {
+        score += 2;
+    }
+    if (0x08..=0x0D).contains(&header[0x17]) {
+        score += 1;
+    }
+    if header[0x18] <= 0x08 {
+        score += 1;
+    }
+    if header[0x19] <= 0x14 {
+        score += 1;
+    }
+    score
+}
+
+/// Sum of all ROM bytes, with the part past the largest power of two
+/// repeated to fill it out
+fn checksum_of(rom: &[u8]) -> u16 {
+    let size = rom.len().next_power_of_two();
+    (0..size).fold(0u16, |sum, addr| sum.wrapping_add(rom[mirror(addr, rom.len())] as u16))
+}
+
+/// Interleaved HiROM dumps put the upper 32KB half of bank 0 first, so
+/// their header shows up at $7FC0 claiming a HiROM map
+fn is_interleaved(rom: &[u8]) -> bool {
+    rom.len() >= 0x10000 && rom.len().is_multiple_of(0x10000) && matches!(rom[LOROM_HEADER + 0x15] & 0x0F, 0x01 | 0x05)
+}
+
+/// Undo the 32KB block swap of interleaved dumps: the second half of the
+/// file holds the low halves of each bank, the first half the high halves
+fn deinterleave(rom: &[u8]) -> Vec<u8> {
+    let banks = rom.len() / 0x10000;
+    let mut output = vec![0; rom.len()];
+    for bank in 0..banks {
+        let low = (banks + bank) * 0x8000;
+        let high = bank * 0x8000;
+        output[bank * 0x10000..bank * 0x10000 + 0x8000].copy_from_slice(&rom[low..low + 0x8000]);
+        output[bank * 0x10000 + 0x8000..(bank + 1) * 0x10000].copy_from_slice(&rom[high..high + 0x8000]);
+    }
+    output
+}
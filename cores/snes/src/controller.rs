@@ -0,0 +1,72 @@
+/// SNES Standard Controller
+/// 12 buttons shifted out MSB first through $4016/$4017, or read in one go
+/// by the auto-read into $4218-$421F
+
+use bitflags::bitflags;
+
+bitflags! {
+    /// Button bits as laid out in the 16-bit JOYn registers
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct Buttons: u16 {
+        const R      = 0x0010;
+        const L      = 0x0020;
+        const X      = 0x0040;
+        const A      = 0x0080;
+        const RIGHT  = 0x0100;
+        const LEFT   = 0x0200;
+        const DOWN   = 0x0400;
+        const UP     = 0x0800;
+        const START  = 0x1000;
+        const SELECT = 0x2000;
+        const Y      = 0x4000;
+        const B      = 0x8000;
+    }
+}
+
+#[derive(Default)]
+pub struct Controller {
+    buttons: Buttons,
+    shift: u16,
+    latch: bool,
+}
+
+impl Controller {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        self.buttons
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = buttons;
+        if self.latch {
+            self.shift = buttons.bits();
+        }
+    }
+
+    pub fn write_latch(&mut self, value: u8) {
+        self.latch = value & 0x01 != 0;
+        if self.latch {
+            self.shift = self.buttons.bits();
+        }
+    }
+
+    /// Next serial bit; official controllers return 1 once all 16 are out
+    pub fn read(&mut self) -> u8 {
+        if self.latch {
+            return (self.buttons.bits() >> 15) as u8;
+        }
+        let bit = (self.shift >> 15) as u8;
+        self.shift = (self.shift << 1) | 0x0001;
+        bit
+    }
+
+    /// Strobe and shift all 16 bits out at once, as the auto-read does
+    pub fn read_all(&mut self) -> u16 {
+        let value = self.buttons.bits();
+        self.shift = if self.latch { value } else { 0xFFFF };
+        value
+    }
+}
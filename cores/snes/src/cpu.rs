@@ -0,0 +1,1095 @@
+/// Ricoh 5A22 CPU Core (WDC 65C816)
+///
+/// 16-bit extension of the 6502 with a 24-bit address space:
+/// - Emulation mode (E=1) behaves like a 65C02 with 8-bit registers
+/// - Native mode: M/X flags select 8/16-bit accumulator and index registers
+/// - Direct page register, data bank and program bank registers
+///
+/// Every bus access and internal operation goes through `CpuBus`, so the
+/// system bus can charge the right number of master cycles for each one.
+
+use bitflags::bitflags;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct StatusFlags: u8 {
+        const CARRY     = 0b0000_0001;  // C
+        const ZERO      = 0b0000_0010;  // Z
+        const INTERRUPT = 0b0000_0100;  // I (Interrupt Disable)
+        const DECIMAL   = 0b0000_1000;  // D
+        const INDEX_8   = 0b0001_0000;  // X (native), B when pushed in emulation mode
+        const MEMORY_8  = 0b0010_0000;  // M (native), always 1 in emulation mode
+        const OVERFLOW  = 0b0100_0000;  // V
+        const NEGATIVE  = 0b1000_0000;  // N
+    }
+}
+
+/// Memory interface seen by the CPU; addresses are 24-bit (bank:offset)
+pub trait CpuBus {
+    fn read(&mut self, addr: u32) -> u8;
+    fn write(&mut self, addr: u32, value: u8);
+
+    /// Internal operation cycle with no bus access
+    fn idle(&mut self) {}
+}
+
+// Native mode vectors (bank 0)
+const NATIVE_COP_VECTOR: u16 = 0xFFE4;
+const NATIVE_BRK_VECTOR: u16 = 0xFFE6;
+const NATIVE_ABORT_VECTOR: u16 = 0xFFE8;
+const NATIVE_NMI_VECTOR: u16 = 0xFFEA;
+const NATIVE_IRQ_VECTOR: u16 = 0xFFEE;
+
+// Emulation mode vectors (bank 0)
+const EMULATION_COP_VECTOR: u16 = 0xFFF4;
+const EMULATION_ABORT_VECTOR: u16 = 0xFFF8;
+const EMULATION_NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const EMULATION_IRQ_VECTOR: u16 = 0xFFFE; // Shared with BRK
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Imm,
+    Abs,
+    AbsX,
+    AbsY,
+    Long,
+    LongX,
+    Dp,
+    DpX,
+    DpY,
+    DpInd,
+    DpIndX,
+    DpIndY,
+    DpIndLong,
+    DpIndLongY,
+    Sr,
+    SrIndY,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interrupt {
+    Brk,
+    Cop,
+    Abort,
+    Nmi,
+    Irq,
+}
+
+pub struct CPU65816 {
+    // Registers
+    pub a: u16,         // Accumulator (C); B is the high byte
+    pub x: u16,         // X index register
+    pub y: u16,         // Y index register
+    pub sp: u16,        // Stack pointer
+    pub dp: u16,        // Direct page register (D)
+    pub dbr: u8,        // Data bank register
+    pub pbr: u8,        // Program bank register
+    pub pc: u16,        // Program counter
+    pub status: StatusFlags,
+    pub emulation: bool,
+
+    // State
+    pub cycles: u64,
+    /// Set by STP; only reset recovers
+    pub stopped: bool,
+    /// Set by WAI until the next interrupt
+    pub waiting: bool,
+
+    step_cycles: u32,
+}
+
+impl CPU65816 {
+    pub fn new() -> Self {
+        Self {
+            a: 0,
+            x: 0,
+            y: 0,
+            sp: 0x01FF,
+            dp: 0,
+            dbr: 0,
+            pbr: 0,
+            pc: 0,
+            status: StatusFlags::MEMORY_8 | StatusFlags::INDEX_8 | StatusFlags::INTERRUPT,
+            emulation: true,
+            cycles: 0,
+            stopped: false,
+            waiting: false,
+            step_cycles: 0,
+        }
+    }
+
+    pub fn reset<B: CpuBus>(&mut self, bus: &mut B) {
+        self.step_cycles = 0;
+        self.emulation = true;
+        self.status.insert(StatusFlags::MEMORY_8 | StatusFlags::INDEX_8 | StatusFlags::INTERRUPT);
+        self.status.remove(StatusFlags::DECIMAL);
+        self.x &= 0x00FF;
+        self.y &= 0x00FF;
+        self.sp = 0x0100 | (self.sp & 0x00FF);
+        self.dp = 0;
+        self.dbr = 0;
+        self.pbr = 0;
+        self.stopped = false;
+        self.waiting = false;
+
+        // Reset takes two internal cycles and three dummy stack reads
+        for _ in 0..5 {
+            self.idle(bus);
+        }
+        self.pc = self.read_word_bank0(bus, RESET_VECTOR);
+        self.cycles += self.step_cycles as u64;
+    }
+
+    /// Execute one instruction; returns CPU cycles (bus accesses + internal)
+    pub fn step<B: CpuBus>(&mut self, bus: &mut B) -> u32 {
+        self.step_cycles = 0;
+
+        if self.stopped || self.waiting {
+            self.idle(bus);
+        } else {
+            let opcode = self.fetch(bus);
+            self.execute(opcode, bus);
+        }
+
+        self.cycles += self.step_cycles as u64;
+        self.step_cycles
+    }
+
+    /// Non-maskable interrupt (edge already detected by the caller)
+    pub fn nmi<B: CpuBus>(&mut self, bus: &mut B) -> u32 {
+        self.hardware_interrupt(bus, Interrupt::Nmi)
+    }
+
+    /// Maskable interrupt; returns 0 if the I flag blocked it
+    pub fn irq<B: CpuBus>(&mut self, bus: &mut B) -> u32 {
+        if self.status.contains(StatusFlags::INTERRUPT) {
+            // WAI resumes even when the IRQ itself is masked
+            self.waiting = false;
+            return 0;
+        }
+        self.hardware_interrupt(bus, Interrupt::Irq)
+    }
+
+    /// ABORT input; the 5A22 never drives it, but coprocessor boards may
+    pub fn abort<B: CpuBus>(&mut self, bus: &mut B) -> u32 {
+        self.hardware_interrupt(bus, Interrupt::Abort)
+    }
+
+    fn hardware_interrupt<B: CpuBus>(&mut self, bus: &mut B, kind: Interrupt) -> u32 {
+        if self.stopped {
+            return 0;
+        }
+        self.step_cycles = 0;
+        self.waiting = false;
+        // Opcode fetch and operand cycles are replaced by internal operations
+        self.idle(bus);
+        self.idle(bus);
+        self.interrupt(bus, kind);
+        self.cycles += self.step_cycles as u64;
+        self.step_cycles
+    }
+
+    fn interrupt<B: CpuBus>(&mut self, bus: &mut B, kind: Interrupt) {
+        if !self.emulation {
+            self.push8(bus, self.pbr);
+        }
+        self.push16(bus, self.pc);
+        let mut status = self.status.bits();
+        if self.emulation && !matches!(kind, Interrupt::Brk | Interrupt::Cop) {
+            // B is only set in the copy pushed by BRK
+            status &= !StatusFlags::INDEX_8.bits();
+        }
+        self.push8(bus, status);
+
+        self.status.insert(StatusFlags::INTERRUPT);
+        self.status.remove(StatusFlags::DECIMAL);
+        self.pbr = 0;
+
+        let vector = match (kind, self.emulation) {
+            (Interrupt::Brk, false) => NATIVE_BRK_VECTOR,
+            (Interrupt::Cop, false) => NATIVE_COP_VECTOR,
+            (Interrupt::Abort, false) => NATIVE_ABORT_VECTOR,
+            (Interrupt::Nmi, false) => NATIVE_NMI_VECTOR,
+            (Interrupt::Irq, false) => NATIVE_IRQ_VECTOR,
+            (Interrupt::Cop, true) => EMULATION_COP_VECTOR,
+            (Interrupt::Abort, true) => EMULATION_ABORT_VECTOR,
+            (Interrupt::Nmi, true) => EMULATION_NMI_VECTOR,
+            (Interrupt::Brk | Interrupt::Irq, true) => EMULATION_IRQ_VECTOR,
+        };
+        self.pc = self.read_word_bank0(bus, vector);
+    }
+
+    fn execute<B: CpuBus>(&mut self, opcode: u8, bus: &mut B) {
+        // ORA/AND/EOR/ADC/STA/LDA/CMP/SBC share one addressing mode layout
+        if let Some(mode) = Self::alu_mode(opcode) {
+            let wide = !self.m8();
+            match opcode >> 5 {
+                0 => { let v = self.operand(bus, mode, wide); self.set_a(self.a | v, wide); }
+                1 => { let v = self.operand(bus, mode, wide); self.set_a(self.a & (v | if wide { 0 } else { 0xFF00 }), wide); }
+                2 => { let v = self.operand(bus, mode, wide); self.set_a(self.a ^ v, wide); }
+                3 => { let v = self.operand(bus, mode, wide); self.adc(v, wide); }
+                4 => self.store(bus, mode, self.a, wide),
+                5 => { let v = self.operand(bus, mode, wide); self.set_a(v, wide); }
+                6 => { let v = self.operand(bus, mode, wide); self.compare(self.a, v, wide); }
+                _ => { let v = self.operand(bus, mode, wide); self.sbc(v, wide); }
+            }
+            return;
+        }
+
+        match opcode {
+            // Shifts and rotates
+            0x0A => self.modify_a(bus, Self::asl),
+            0x06 => self.modify(bus, Mode::Dp, Self::asl),
+            0x0E => self.modify(bus, Mode::Abs, Self::asl),
+            0x16 => self.modify(bus, Mode::DpX, Self::asl),
+            0x1E => self.modify(bus, Mode::AbsX, Self::asl),
+            0x2A => self.modify_a(bus, Self::rol),
+            0x26 => self.modify(bus, Mode::Dp, Self::rol),
+            0x2E => self.modify(bus, Mode::Abs, Self::rol),
+            0x36 => self.modify(bus, Mode::DpX, Self::rol),
+            0x3E => self.modify(bus, Mode::AbsX, Self::rol),
+            0x4A => self.modify_a(bus, Self::lsr),
+            0x46 => self.modify(bus, Mode::Dp, Self::lsr),
+            0x4E => self.modify(bus, Mode::Abs, Self::lsr),
+            0x56 => self.modify(bus, Mode::DpX, Self::lsr),
+            0x5E => self.modify(bus, Mode::AbsX, Self::lsr),
+            0x6A => self.modify_a(bus, Self::ror),
+            0x66 => self.modify(bus, Mode::Dp, Self::ror),
+            0x6E => self.modify(bus, Mode::Abs, Self::ror),
+            0x76 => self.modify(bus, Mode::DpX, Self::ror),
+            0x7E => self.modify(bus, Mode::AbsX, Self::ror),
+
+            // INC/DEC memory and accumulator
+            0x1A => self.modify_a(bus, Self::inc),
+            0xE6 => self.modify(bus, Mode::Dp, Self::inc),
+            0xEE => self.modify(bus, Mode::Abs, Self::inc),
+            0xF6 => self.modify(bus, Mode::DpX, Self::inc),
+            0xFE => self.modify(bus, Mode::AbsX, Self::inc),
+            0x3A => self.modify_a(bus, Self::dec),
+            0xC6 => self.modify(bus, Mode::Dp, Self::dec),
+            0xCE => self.modify(bus, Mode::Abs, Self::dec),
+            0xD6 => self.modify(bus, Mode::DpX, Self::dec),
+            0xDE => self.modify(bus, Mode::AbsX, Self::dec),
+
+            // TSB/TRB
+            0x04 => self.modify(bus, Mode::Dp, Self::tsb),
+            0x0C => self.modify(bus, Mode::Abs, Self::tsb),
+            0x14 => self.modify(bus, Mode::Dp, Self::trb),
+            0x1C => self.modify(bus, Mode::Abs, Self::trb),
+
+            // BIT
+            0x89 => {
+                // Immediate BIT only touches Z
+                let wide = !self.m8();
+                let value = self.operand(bus, Mode::Imm, wide);
+                self.status.set(StatusFlags::ZERO, self.a & value & Self::mask(wide) == 0);
+            }
+            0x24 => self.bit(bus, Mode::Dp),
+            0x2C => self.bit(bus, Mode::Abs),
+            0x34 => self.bit(bus, Mode::DpX),
+            0x3C => self.bit(bus, Mode::AbsX),
+
+            // Stores
+            0x64 => self.store(bus, Mode::Dp, 0, !self.m8()),
+            0x74 => self.store(bus, Mode::DpX, 0, !self.m8()),
+            0x9C => self.store(bus, Mode::Abs, 0, !self.m8()),
+            0x9E => self.store(bus, Mode::AbsX, 0, !self.m8()),
+            0x84 => self.store(bus, Mode::Dp, self.y, !self.x8()),
+            0x8C => self.store(bus, Mode::Abs, self.y, !self.x8()),
+            0x94 => self.store(bus, Mode::DpX, self.y, !self.x8()),
+            0x86 => self.store(bus, Mode::Dp, self.x, !self.x8()),
+            0x8E => self.store(bus, Mode::Abs, self.x, !self.x8()),
+            0x96 => self.store(bus, Mode::DpY, self.x, !self.x8()),
+
+            // Index loads and compares
+            0xA0 => self.ldy(bus, Mode::Imm),
+            0xA4 => self.ldy(bus, Mode::Dp),
+            0xAC => self.ldy(bus, Mode::Abs),
+            0xB4 => self.ldy(bus, Mode::DpX),
+            0xBC => self.ldy(bus, Mode::AbsX),
+            0xA2 => self.ldx(bus, Mode::Imm),
+            0xA6 => self.ldx(bus, Mode::Dp),
+            0xAE => self.ldx(bus, Mode::Abs),
+            0xB6 => self.ldx(bus, Mode::DpY),
+            0xBE => self.ldx(bus, Mode::AbsY),
+            0xC0 => self.compare_index(bus, Mode::Imm, self.y),
+            0xC4 => self.compare_index(bus, Mode::Dp, self.y),
+            0xCC => self.compare_index(bus, Mode::Abs, self.y),
+            0xE0 => self.compare_index(bus, Mode::Imm, self.x),
+            0xE4 => self.compare_index(bus, Mode::Dp, self.x),
+            0xEC => self.compare_index(bus, Mode::Abs, self.x),
+
+            // Branches
+            0x10 => self.branch(bus, !self.status.contains(StatusFlags::NEGATIVE)),
+            0x30 => self.branch(bus, self.status.contains(StatusFlags::NEGATIVE)),
+            0x50 => self.branch(bus, !self.status.contains(StatusFlags::OVERFLOW)),
+            0x70 => self.branch(bus, self.status.contains(StatusFlags::OVERFLOW)),
+            0x90 => self.branch(bus, !self.status.contains(StatusFlags::CARRY)),
+            0xB0 => self.branch(bus, self.status.contains(StatusFlags::CARRY)),
+            0xD0 => self.branch(bus, !self.status.contains(StatusFlags::ZERO)),
+            0xF0 => self.branch(bus, self.status.contains(StatusFlags::ZERO)),
+            0x80 => self.branch(bus, true),
+            0x82 => { // BRL
+                let offset = self.fetch16(bus);
+                self.idle(bus);
+                self.pc = self.pc.wrapping_add(offset);
+            }
+
+            // Jumps
+            0x4C => self.pc = self.fetch16(bus),
+            0x5C => { // JML long
+                let target = self.fetch24(bus);
+                self.pc = target as u16;
+                self.pbr = (target >> 16) as u8;
+            }
+            0x6C => { // JMP (abs)
+                let ptr = self.fetch16(bus);
+                self.pc = self.read_word_bank0(bus, ptr);
+            }
+            0x7C => { // JMP (abs,X)
+                let ptr = self.fetch16(bus).wrapping_add(self.x);
+                self.idle(bus);
+                self.pc = self.read_word_program(bus, ptr);
+            }
+            0xDC => { // JML [abs]
+                let ptr = self.fetch16(bus);
+                let lo = self.read(bus, ptr as u32) as u16;
+                let hi = self.read(bus, ptr.wrapping_add(1) as u32) as u16;
+                self.pbr = self.read(bus, ptr.wrapping_add(2) as u32);
+                self.pc = (hi << 8) | lo;
+            }
+
+            // Subroutines
+            0x20 => { // JSR abs
+                let target = self.fetch16(bus);
+                self.idle(bus);
+                self.push16(bus, self.pc.wrapping_sub(1));
+                self.pc = target;
+            }
+            0x22 => { // JSL long
+                let target = self.fetch16(bus);
+                self.push8(bus, self.pbr);
+                self.idle(bus);
+                let bank = self.fetch(bus);
+                self.push16(bus, self.pc.wrapping_sub(1));
+                self.pbr = bank;
+                self.pc = target;
+            }
+            0xFC => { // JSR (abs,X)
+                let lo = self.fetch(bus) as u16;
+                self.push16(bus, self.pc);
+                let hi = self.fetch(bus) as u16;
+                self.idle(bus);
+                let ptr = ((hi << 8) | lo).wrapping_add(self.x);
+                self.pc = self.read_word_program(bus, ptr);
+            }
+            0x60 => { // RTS
+                self.idle(bus);
+                self.idle(bus);
+                self.pc = self.pull16(bus).wrapping_add(1);
+                self.idle(bus);
+            }
+            0x6B => { // RTL
+                self.idle(bus);
+                self.idle(bus);
+                self.pc = self.pull16(bus).wrapping_add(1);
+                self.pbr = self.pull8(bus);
+            }
+            0x40 => { // RTI
+                self.idle(bus);
+                self.idle(bus);
+                let status = self.pull8(bus);
+                self.set_status(status);
+                self.pc = self.pull16(bus);
+                if !self.emulation {
+                    self.pbr = self.pull8(bus);
+                }
+            }
+
+            // Software interrupts and halts
+            0x00 => { // BRK (signature byte skipped)
+                self.fetch(bus);
+                self.interrupt(bus, Interrupt::Brk);
+            }
+            0x02 => { // COP
+                self.fetch(bus);
+                self.interrupt(bus, Interrupt::Cop);
+            }
+            0xCB => { // WAI
+                self.idle(bus);
+                self.idle(bus);
+                self.waiting = true;
+            }
+            0xDB => { // STP
+                self.idle(bus);
+                self.idle(bus);
+                self.stopped = true;
+            }
+            0xEA => self.idle(bus), // NOP
+            0x42 => { self.fetch(bus); } // WDM (reserved, 2-byte NOP)
+
+            // Stack
+            0x48 => { self.idle(bus); self.push_width(bus, self.a, !self.m8()); }
+            0xDA => { self.idle(bus); self.push_width(bus, self.x, !self.x8()); }
+            0x5A => { self.idle(bus); self.push_width(bus, self.y, !self.x8()); }
+            0x08 => { self.idle(bus); self.push8(bus, self.status.bits()); }
+            0x8B => { self.idle(bus); self.push8(bus, self.dbr); }
+            0x4B => { self.idle(bus); self.push8(bus, self.pbr); }
+            0x0B => { self.idle(bus); self.push16(bus, self.dp); }
+            0x68 => { // PLA
+                self.idle(bus);
+                self.idle(bus);
+                let wide = !self.m8();
+                let value = self.pull_width(bus, wide);
+                self.set_a(value, wide);
+            }
+            0xFA => { // PLX
+                self.idle(bus);
+                self.idle(bus);
+                let value = self.pull_width(bus, !self.x8());
+                self.x = value;
+                self.set_nz(value, !self.x8());
+            }
+            0x7A => { // PLY
+                self.idle(bus);
+                self.idle(bus);
+                let value = self.pull_width(bus, !self.x8());
+                self.y = value;
+                self.set_nz(value, !self.x8());
+            }
+            0x28 => { // PLP
+                self.idle(bus);
+                self.idle(bus);
+                let status = self.pull8(bus);
+                self.set_status(status);
+            }
+            0xAB => { // PLB
+                self.idle(bus);
+                self.idle(bus);
+                self.dbr = self.pull8(bus);
+                self.set_nz(self.dbr as u16, false);
+            }
+            0x2B => { // PLD
+                self.idle(bus);
+                self.idle(bus);
+                self.dp = self.pull16(bus);
+                self.set_nz(self.dp, true);
+            }
+            0xF4 => { // PEA
+                let value = self.fetch16(bus);
+                self.push16(bus, value);
+            }
+            0xD4 => { // PEI
+                let offset = self.fetch(bus);
+                self.direct_page_penalty(bus);
+                let addr = self.direct(offset, 0);
+                let value = self.read_word_direct(bus, addr);
+                self.push16(bus, value);
+            }
+            0x62 => { // PER
+                let offset = self.fetch16(bus);
+                self.idle(bus);
+                self.push16(bus, self.pc.wrapping_add(offset));
+            }
+
+            // Flags
+            0x18 => { self.idle(bus); self.status.remove(StatusFlags::CARRY); }
+            0x38 => { self.idle(bus); self.status.insert(StatusFlags::CARRY); }
+            0x58 => { self.idle(bus); self.status.remove(StatusFlags::INTERRUPT); }
+            0x78 => { self.idle(bus); self.status.insert(StatusFlags::INTERRUPT); }
+            0xB8 => { self.idle(bus); self.status.remove(StatusFlags::OVERFLOW); }
+            0xD8 => { self.idle(bus); self.status.remove(StatusFlags::DECIMAL); }
+            0xF8 => { self.idle(bus); self.status.insert(StatusFlags::DECIMAL); }
+            0xC2 => { // REP
+                let mask = self.fetch(bus);
+                self.idle(bus);
+                self.set_status(self.status.bits() & !mask);
+            }
+            0xE2 => { // SEP
+                let mask = self.fetch(bus);
+                self.idle(bus);
+                self.set_status(self.status.bits() | mask);
+            }
+            0xFB => { // XCE
+                self.idle(bus);
+                let carry = self.status.contains(StatusFlags::CARRY);
+                self.status.set(StatusFlags::CARRY, self.emulation);
+                self.emulation = carry;
+                self.set_status(self.status.bits());
+            }
+
+            // Transfers
+            0xAA => { self.idle(bus); self.x = self.a; self.set_index_result_x(); }
+            0xA8 => { self.idle(bus); self.y = self.a; self.set_index_result_y(); }
+            0xBA => { self.idle(bus); self.x = self.sp; self.set_index_result_x(); }
+            0x9B => { self.idle(bus); self.y = self.x; self.set_index_result_y(); }
+            0xBB => { self.idle(bus); self.x = self.y; self.set_index_result_x(); }
+            0x8A => { self.idle(bus); self.set_a(self.x, !self.m8()); }
+            0x98 => { self.idle(bus); self.set_a(self.y, !self.m8()); }
+            0x9A => { // TXS
+                self.idle(bus);
+                self.sp = if self.emulation { 0x0100 | (self.x & 0x00FF) } else { self.x };
+            }
+            0x5B => { self.idle(bus); self.dp = self.a; self.set_nz(self.dp, true); }
+            0x7B => { self.idle(bus); self.a = self.dp; self.set_nz(self.a, true); }
+            0x1B => { // TCS
+                self.idle(bus);
+                self.sp = if self.emulation { 0x0100 | (self.a & 0x00FF) } else { self.a };
+            }
+            0x3B => { self.idle(bus); self.a = self.sp; self.set_nz(self.a, true); }
+            0xEB => { // XBA
+                self.idle(bus);
+                self.idle(bus);
+                self.a = self.a.rotate_left(8);
+                self.set_nz(self.a & 0x00FF, false);
+            }
+
+            // Index increments
+            0xE8 => { self.idle(bus); self.x = self.x.wrapping_add(1); self.set_index_result_x(); }
+            0xC8 => { self.idle(bus); self.y = self.y.wrapping_add(1); self.set_index_result_y(); }
+            0xCA => { self.idle(bus); self.x = self.x.wrapping_sub(1); self.set_index_result_x(); }
+            0x88 => { self.idle(bus); self.y = self.y.wrapping_sub(1); self.set_index_result_y(); }
+
+            // Block moves, one byte per execution until A wraps to $FFFF
+            0x54 => self.block_move(bus, 1),
+            0x44 => self.block_move(bus, -1),
+
+            _ => unreachable!("65C816 opcode ${:02X} not decoded", opcode),
+        }
+    }
+
+    /// Addressing mode of the ORA/AND/EOR/ADC/STA/LDA/CMP/SBC column layout
+    fn alu_mode(opcode: u8) -> Option<Mode> {
+        if opcode == 0x89 {
+            return None; // BIT #imm sits where STA #imm would be
+        }
+        Some(match opcode & 0x1F {
+            0x01 => Mode::DpIndX,
+            0x03 => Mode::Sr,
+            0x05 => Mode::Dp,
+            0x07 => Mode::DpIndLong,
+            0x09 => Mode::Imm,
+            0x0D => Mode::Abs,
+            0x0F => Mode::Long,
+            0x11 => Mode::DpIndY,
+            0x12 => Mode::DpInd,
+            0x13 => Mode::SrIndY,
+            0x15 => Mode::DpX,
+            0x17 => Mode::DpIndLongY,
+            0x19 => Mode::AbsY,
+            0x1D => Mode::AbsX,
+            0x1F => Mode::LongX,
+            _ => return None,
+        })
+    }
+
+    // Bus access, counting one CPU cycle per access
+
+    fn read<B: CpuBus>(&mut self, bus: &mut B, addr: u32) -> u8 {
+        self.step_cycles += 1;
+        bus.read(addr & 0xFF_FFFF)
+    }
+
+    fn write<B: CpuBus>(&mut self, bus: &mut B, addr: u32, value: u8) {
+        self.step_cycles += 1;
+        bus.write(addr & 0xFF_FFFF, value);
+    }
+
+    fn idle<B: CpuBus>(&mut self, bus: &mut B) {
+        self.step_cycles += 1;
+        bus.idle();
+    }
+
+    fn fetch<B: CpuBus>(&mut self, bus: &mut B) -> u8 {
+        let value = self.read(bus, ((self.pbr as u32) << 16) | self.pc as u32);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch16<B: CpuBus>(&mut self, bus: &mut B) -> u16 {
+        let lo = self.fetch(bus) as u16;
+        let hi = self.fetch(bus) as u16;
+        (hi << 8) | lo
+    }
+
+    fn fetch24<B: CpuBus>(&mut self, bus: &mut B) -> u32 {
+        let lo = self.fetch16(bus) as u32;
+        let bank = self.fetch(bus) as u32;
+        (bank << 16) | lo
+    }
+
+    fn read_word_bank0<B: CpuBus>(&mut self, bus: &mut B, addr: u16) -> u16 {
+        let lo = self.read(bus, addr as u32) as u16;
+        let hi = self.read(bus, addr.wrapping_add(1) as u32) as u16;
+        (hi << 8) | lo
+    }
+
+    /// Pointer read from the program bank, for JMP/JSR (abs,X)
+    fn read_word_program<B: CpuBus>(&mut self, bus: &mut B, addr: u16) -> u16 {
+        let bank = (self.pbr as u32) << 16;
+        let lo = self.read(bus, bank | addr as u32) as u16;
+        let hi = self.read(bus, bank | addr.wrapping_add(1) as u32) as u16;
+        (hi << 8) | lo
+    }
+
+    /// Pointer read from the direct page; the emulation-mode page wrap
+    /// applies when DL is zero
+    fn read_word_direct<B: CpuBus>(&mut self, bus: &mut B, addr: u16) -> u16 {
+        let lo = self.read(bus, addr as u32) as u16;
+        let hi = self.read(bus, self.direct_next(addr) as u32) as u16;
+        (hi << 8) | lo
+    }
+
+    fn direct_next(&self, addr: u16) -> u16 {
+        if self.emulation && self.dp & 0x00FF == 0 {
+            (addr & 0xFF00) | (addr.wrapping_add(1) & 0x00FF)
+        } else {
+            addr.wrapping_add(1)
+        }
+    }
+
+    // Stack
+
+    fn push8<B: CpuBus>(&mut self, bus: &mut B, value: u8) {
+        self.write(bus, self.sp as u32, value);
+        self.sp = self.sp.wrapping_sub(1);
+        if self.emulation {
+            self.sp = 0x0100 | (self.sp & 0x00FF);
+        }
+    }
+
+    fn pull8<B: CpuBus>(&mut self, bus: &mut B) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        if self.emulation {
+            self.sp = 0x0100 | (self.sp & 0x00FF);
+        }
+        self.read(bus, self.sp as u32)
+    }
+
+    fn push16<B: CpuBus>(&mut self, bus: &mut B, value: u16) {
+        self.push8(bus, (value >> 8) as u8);
+        self.push8(bus, value as u8);
+    }
+
+    fn pull16<B: CpuBus>(&mut self, bus: &mut B) -> u16 {
+        let lo = self.pull8(bus) as u16;
+        let hi = self.pull8(bus) as u16;
+        (hi << 8) | lo
+    }
+
+    fn push_width<B: CpuBus>(&mut self, bus: &mut B, value: u16, wide: bool) {
+        if wide {
+            self.push16(bus, value);
+        } else {
+            self.push8(bus, value as u8);
+        }
+    }
+
+    fn pull_width<B: CpuBus>(&mut self, bus: &mut B, wide: bool) -> u16 {
+        if wide { self.pull16(bus) } else { self.pull8(bus) as u16 }
+    }
+
+    // Addressing
+
+    fn m8(&self) -> bool {
+        self.status.contains(StatusFlags::MEMORY_8)
+    }
+
+    fn x8(&self) -> bool {
+        self.status.contains(StatusFlags::INDEX_8)
+    }
+
+    fn mask(wide: bool) -> u16 {
+        if wide { 0xFFFF } else { 0x00FF }
+    }
+
+    /// Extra cycle for direct page modes when DL is not zero
+    fn direct_page_penalty<B: CpuBus>(&mut self, bus: &mut B) {
+        if self.dp & 0x00FF != 0 {
+            self.idle(bus);
+        }
+    }
+
+    /// Direct page address (bank 0); indexing stays inside the page in
+    /// emulation mode when DL is zero
+    fn direct(&self, offset: u8, index: u16) -> u16 {
+        if self.emulation && self.dp & 0x00FF == 0 {
+            self.dp | (offset.wrapping_add(index as u8) as u16)
+        } else {
+            self.dp.wrapping_add(offset as u16).wrapping_add(index)
+        }
+    }
+
+    /// Indexed absolute addressing: the extra cycle is taken for 16-bit
+    /// indexes, page crossings and writes
+    fn indexed<B: CpuBus>(&mut self, bus: &mut B, base: u32, index: u16, write: bool) -> u32 {
+        let addr = (base + index as u32) & 0xFF_FFFF;
+        if write || !self.x8() || (base & 0xFF_FF00) != (addr & 0xFF_FF00) {
+            self.idle(bus);
+        }
+        addr
+    }
+
+    /// Effective address; the flag is true for bank 0 wrapping accesses
+    /// (direct page and stack)
+    fn address<B: CpuBus>(&mut self, bus: &mut B, mode: Mode, write: bool) -> (u32, bool) {
+        let data_bank = (self.dbr as u32) << 16;
+        match mode {
+            Mode::Imm => unreachable!("immediate operands have no address"),
+            Mode::Abs => (data_bank | self.fetch16(bus) as u32, false),
+            Mode::AbsX => {
+                let base = data_bank | self.fetch16(bus) as u32;
+                (self.indexed(bus, base, self.x, write), false)
+            }
+            Mode::AbsY => {
+                let base = data_bank | self.fetch16(bus) as u32;
+                (self.indexed(bus, base, self.y, write), false)
+            }
+            Mode::Long => (self.fetch24(bus), false),
+            Mode::LongX => ((self.fetch24(bus) + self.x as u32) & 0xFF_FFFF, false),
+            Mode::Dp => {
+                let offset = self.fetch(bus);
+                self.direct_page_penalty(bus);
+                (self.direct(offset, 0) as u32, true)
+            }
+            Mode::DpX | Mode::DpY => {
+                let offset = self.fetch(bus);
+                self.direct_page_penalty(bus);
+                self.idle(bus);
+                let index = if mode == Mode::DpX { self.x } else { self.y };
+                (self.direct(offset, index) as u32, true)
+            }
+            Mode::DpInd => {
+                let offset = self.fetch(bus);
+                self.direct_page_penalty(bus);
+                let ptr = self.read_word_direct(bus, self.direct(offset, 0));
+                (data_bank | ptr as u32, false)
+            }
+            Mode::DpIndX => {
+                let offset = self.fetch(bus);
+                self.direct_page_penalty(bus);
+                self.idle(bus);
+                let ptr = self.read_word_direct(bus, self.direct(offset, self.x));
+                (data_bank | ptr as u32, false)
+            }
+            Mode::DpIndY => {
+                let offset = self.fetch(bus);
+                self.direct_page_penalty(bus);
+                let ptr = self.read_word_direct(bus, self.direct(offset, 0));
+                (self.indexed(bus, data_bank | ptr as u32, self.y, write), false)
+            }
+            Mode::DpIndLong | Mode::DpIndLongY => {
+                let offset = self.fetch(bus);
+                self.direct_page_penalty(bus);
+                let addr = self.direct(offset, 0);
+                let lo = self.read_word_direct(bus, addr) as u32;
+                let bank = self.read(bus, addr.wrapping_add(2) as u32) as u32;
+                let ptr = (bank << 16) | lo;
+                let index = if mode == Mode::DpIndLongY { self.y as u32 } else { 0 };
+                ((ptr + index) & 0xFF_FFFF, false)
+            }
+            Mode::Sr => {
+                let offset = self.fetch(bus);
+                self.idle(bus);
+                (self.sp.wrapping_add(offset as u16) as u32, true)
+            }
+            Mode::SrIndY => {
+                let offset = self.fetch(bus);
+                self.idle(bus);
+                let ptr = self.read_word_bank0(bus, self.sp.wrapping_add(offset as u16));
+                self.idle(bus);
+                (((data_bank | ptr as u32) + self.y as u32) & 0xFF_FFFF, false)
+            }
+        }
+    }
+
+    fn next_addr(addr: u32, bank0: bool) -> u32 {
+        if bank0 { (addr + 1) & 0xFFFF } else { (addr + 1) & 0xFF_FFFF }
+    }
+
+    fn read_data<B: CpuBus>(&mut self, bus: &mut B, addr: u32, bank0: bool, wide: bool) -> u16 {
+        let lo = self.read(bus, addr) as u16;
+        if !wide {
+            return lo;
+        }
+        let hi = self.read(bus, Self::next_addr(addr, bank0)) as u16;
+        (hi << 8) | lo
+    }
+
+    fn write_data<B: CpuBus>(&mut self, bus: &mut B, addr: u32, bank0: bool, value: u16, wide: bool) {
+        self.write(bus, addr, value as u8);
+        if wide {
+            self.write(bus, Self::next_addr(addr, bank0), (value >> 8) as u8);
+        }
+    }
+
+    /// 8 or 16-bit operand
+    fn operand<B: CpuBus>(&mut self, bus: &mut B, mode: Mode, wide: bool) -> u16 {
+        if mode == Mode::Imm {
+            return if wide { self.fetch16(bus) } else { self.fetch(bus) as u16 };
+        }
+        let (addr, bank0) = self.address(bus, mode, false);
+        self.read_data(bus, addr, bank0, wide)
+    }
+
+    fn store<B: CpuBus>(&mut self, bus: &mut B, mode: Mode, value: u16, wide: bool) {
+        let (addr, bank0) = self.address(bus, mode, true);
+        self.write_data(bus, addr, bank0, value, wide);
+    }
+
+    /// Read-modify-write at M width; the high byte is written first
+    fn modify<B: CpuBus>(&mut self, bus: &mut B, mode: Mode, op: fn(&mut Self, u16, bool) -> u16) {
+        let wide = !self.m8();
+        let (addr, bank0) = self.address(bus, mode, true);
+        let value = self.read_data(bus, addr, bank0, wide);
+        self.idle(bus);
+        let result = op(self, value, wide);
+        if wide {
+            self.write(bus, Self::next_addr(addr, bank0), (result >> 8) as u8);
+        }
+        self.write(bus, addr, result as u8);
+    }
+
+    fn modify_a<B: CpuBus>(&mut self, bus: &mut B, op: fn(&mut Self, u16, bool) -> u16) {
+        self.idle(bus);
+        let wide = !self.m8();
+        let result = op(self, self.a & Self::mask(wide), wide);
+        self.a = if wide { result } else { (self.a & 0xFF00) | (result & 0x00FF) };
+    }
+
+    // Register and flag helpers
+
+    fn set_nz(&mut self, value: u16, wide: bool) {
+        let (value, sign) = if wide { (value, 0x8000) } else { (value & 0x00FF, 0x0080) };
+        self.status.set(StatusFlags::ZERO, value == 0);
+        self.status.set(StatusFlags::NEGATIVE, value & sign != 0);
+    }
+
+    /// Write A at M width (8-bit writes keep B) and update N/Z
+    fn set_a(&mut self, value: u16, wide: bool) {
+        self.a = if wide { value } else { (self.a & 0xFF00) | (value & 0x00FF) };
+        self.set_nz(value, wide);
+    }
+
+    fn set_index_result_x(&mut self) {
+        if self.x8() {
+            self.x &= 0x00FF;
+        }
+        self.set_nz(self.x, !self.x8());
+    }
+
+    fn set_index_result_y(&mut self) {
+        if self.x8() {
+            self.y &= 0x00FF;
+        }
+        self.set_nz(self.y, !self.x8());
+    }
+
+    /// Load P, applying the emulation-mode and 8-bit index side effects
+    fn set_status(&mut self, value: u8) {
+        self.status = StatusFlags::from_bits_retain(value);
+        if self.emulation {
+            self.status.insert(StatusFlags::MEMORY_8 | StatusFlags::INDEX_8);
+            self.sp = 0x0100 | (self.sp & 0x00FF);
+        }
+        if self.x8() {
+            self.x &= 0x00FF;
+            self.y &= 0x00FF;
+        }
+    }
+
+    // Instructions
+
+    fn ldx<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        self.x = self.operand(bus, mode, !self.x8());
+        self.set_nz(self.x, !self.x8());
+    }
+
+    fn ldy<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        self.y = self.operand(bus, mode, !self.x8());
+        self.set_nz(self.y, !self.x8());
+    }
+
+    fn compare(&mut self, register: u16, value: u16, wide: bool) {
+        let mask = Self::mask(wide);
+        let (register, value) = (register & mask, value & mask);
+        self.status.set(StatusFlags::CARRY, register >= value);
+        self.set_nz(register.wrapping_sub(value), wide);
+    }
+
+    fn compare_index<B: CpuBus>(&mut self, bus: &mut B, mode: Mode, register: u16) {
+        let wide = !self.x8();
+        let value = self.operand(bus, mode, wide);
+        self.compare(register, value, wide);
+    }
+
+    fn bit<B: CpuBus>(&mut self, bus: &mut B, mode: Mode) {
+        let wide = !self.m8();
+        let value = self.operand(bus, mode, wide);
+        let sign = if wide { 0x8000 } else { 0x0080 };
+        self.status.set(StatusFlags::ZERO, self.a & value & Self::mask(wide) == 0);
+        self.status.set(StatusFlags::NEGATIVE, value & sign != 0);
+        self.status.set(StatusFlags::OVERFLOW, value & (sign >> 1) != 0);
+    }
+
+    fn branch<B: CpuBus>(&mut self, bus: &mut B, condition: bool) {
+        let offset = self.fetch(bus) as i8;
+        if condition {
+            self.idle(bus);
+            let target = self.pc.wrapping_add(offset as u16);
+            // Page crossings only cost a cycle in emulation mode
+            if self.emulation && (target & 0xFF00) != (self.pc & 0xFF00) {
+                self.idle(bus);
+            }
+            self.pc = target;
+        }
+    }
+
+    fn block_move<B: CpuBus>(&mut self, bus: &mut B, step: i16) {
+        let dest_bank = self.fetch(bus);
+        let src_bank = self.fetch(bus);
+        self.dbr = dest_bank;
+
+        let value = self.read(bus, ((src_bank as u32) << 16) | self.x as u32);
+        self.write(bus, ((dest_bank as u32) << 16) | self.y as u32, value);
+        self.idle(bus);
+        self.idle(bus);
+
+        self.x = self.x.wrapping_add(step as u16);
+        self.y = self.y.wrapping_add(step as u16);
+        if self.x8() {
+            self.x &= 0x00FF;
+            self.y &= 0x00FF;
+        }
+        self.a = self.a.wrapping_sub(1);
+        if self.a != 0xFFFF {
+            // Re-execute the same instruction for the next byte
+            self.pc = self.pc.wrapping_sub(3);
+        }
+    }
+
+    fn asl(&mut self, value: u16, wide: bool) -> u16 {
+        let sign = if wide { 0x8000 } else { 0x0080 };
+        self.status.set(StatusFlags::CARRY, value & sign != 0);
+        let result = (value << 1) & Self::mask(wide);
+        self.set_nz(result, wide);
+        result
+    }
+
+    fn lsr(&mut self, value: u16, wide: bool) -> u16 {
+        self.status.set(StatusFlags::CARRY, value & 0x0001 != 0);
+        let result = (value & Self::mask(wide)) >> 1;
+        self.set_nz(result, wide);
+        result
+    }
+
+    fn rol(&mut self, value: u16, wide: bool) -> u16 {
+        let sign = if wide { 0x8000 } else { 0x0080 };
+        let carry_in = self.status.contains(StatusFlags::CARRY) as u16;
+        self.status.set(StatusFlags::CARRY, value & sign != 0);
+        let result = ((value << 1) | carry_in) & Self::mask(wide);
+        self.set_nz(result, wide);
+        result
+    }
+
+    fn ror(&mut self, value: u16, wide: bool) -> u16 {
+        let sign = if wide { 0x8000 } else { 0x0080 };
+        let carry_in = if self.status.contains(StatusFlags::CARRY) { sign } else { 0 };
+        self.status.set(StatusFlags::CARRY, value & 0x0001 != 0);
+        let result = ((value & Self::mask(wide)) >> 1) | carry_in;
+        self.set_nz(result, wide);
+        result
+    }
+
+    fn inc(&mut self, value: u16, wide: bool) -> u16 {
+        let result = value.wrapping_add(1) & Self::mask(wide);
+        self.set_nz(result, wide);
+        result
+    }
+
+    fn dec(&mut self, value: u16, wide: bool) -> u16 {
+        let result = value.wrapping_sub(1) & Self::mask(wide);
+        self.set_nz(result, wide);
+        result
+    }
+
+    fn tsb(&mut self, value: u16, wide: bool) -> u16 {
+        let a = self.a & Self::mask(wide);
+        self.status.set(StatusFlags::ZERO, value & a == 0);
+        value | a
+    }
+
+    fn trb(&mut self, value: u16, wide: bool) -> u16 {
+        let a = self.a & Self::mask(wide);
+        self.status.set(StatusFlags::ZERO, value & a == 0);
+        value & !a
+    }
+
+    fn adc(&mut self, value: u16, wide: bool) {
+        self.add(value, wide, false);
+    }
+
+    fn sbc(&mut self, value: u16, wide: bool) {
+        self.add(!value, wide, true);
+    }
+
+    /// ADC, and SBC as ADC of the complement. Decimal mode adjusts one
+    /// nibble at a time; V comes from the value before the final adjust.
+    fn add(&mut self, value: u16, wide: bool, subtract: bool) {
+        let mask = Self::mask(wide) as i32;
+        let a = self.a as i32 & mask;
+        let value = value as i32 & mask;
+        let mut carry = self.status.contains(StatusFlags::CARRY) as i32;
+        let nibbles = if wide { 4 } else { 2 };
+
+        let mut result;
+        if !self.status.contains(StatusFlags::DECIMAL) {
+            result = a + value + carry;
+        } else {
+            result = 0;
+            for nibble in 0..nibbles {
+                let shift = nibble * 4;
+                let digit_mask = 0x0F << shift;
+                let low_mask = (1 << shift) - 1;
+                result = (a & digit_mask) + (value & digit_mask) + (carry << shift) + (result & low_mask);
+                if nibble == nibbles - 1 {
+                    break;
+                }
+                if subtract {
+                    if result <= (0x0F << shift) | low_mask {
+                        result -= 0x06 << shift;
+                    }
+                } else if result > (0x09 << shift) | low_mask {
+                    result += 0x06 << shift;
+                }
+                carry = (result > ((0x10 << shift) - 1)) as i32;
+            }
+        }
+
+        let sign = if wide { 0x8000 } else { 0x0080 };
+        let overflow = !(a ^ value) & (a ^ result) & sign != 0;
+
+        if self.status.contains(StatusFlags::DECIMAL) {
+            let top = (nibbles - 1) * 4;
+            let low_mask = (1 << top) - 1;
+            if subtract {
+                if result <= mask {
+                    result -= 0x06 << top;
+                }
+            } else if result > (0x09 << top) | low_mask {
+                result += 0x06 << top;
+            }
+        }
+
+        self.status.set(StatusFlags::OVERFLOW, overflow);
+        self.status.set(StatusFlags::CARRY, result > mask);
+        self.set_a(result as u16 & mask as u16, wide);
+    }
+}
+
+impl Default for CPU65816 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,305 @@
+/// 5A22 On-Chip Peripherals
+/// NMI and H/V IRQ timers, the multiplier and divider, the joypad ports and
+/// the auto-read ($4016/$4017, $4200-$421F). The ALU steps once per CPU
+/// cycle, so its results are only complete 8 or 16 cycles after the write.
+
+use crate::bus::Bus;
+use crate::controller::{Buttons, Controller};
+
+/// Master cycles the auto-read keeps HVBJOY bit 0 set
+const AUTO_READ_CYCLES: u64 = 4224;
+
+/// H counter dot where hblank starts
+const HBLANK_START_DOT: u32 = 274;
+
+// NMITIMEN bits
+const NMITIMEN_AUTO_READ: u8 = 0x01;
+const NMITIMEN_HIRQ: u8 = 0x10;
+const NMITIMEN_VIRQ: u8 = 0x20;
+const NMITIMEN_NMI: u8 = 0x80;
+
+pub struct CpuIo {
+    /// NMITIMEN ($4200)
+    nmitimen: u8,
+    /// WRIO ($4201); bit 7 is wired to the PPU counter latch
+    wrio: u8,
+    /// HTIME/VTIME ($4207-$420A), 9 bits each
+    htime: u16,
+    vtime: u16,
+
+    /// RDNMI bit 7: set when vblank starts, cleared by reading or at vblank end
+    nmi_flag: bool,
+    /// TIMEUP bit 7: set by the H/V timer, cleared by reading or disabling it
+    irq_flag: bool,
+    /// NMI edge waiting for the CPU
+    nmi_pending: bool,
+
+    // Multiplier/divider: WRMPYA, WRDIVL/H and the RDDIV/RDMPY results
+    wrmpya: u8,
+    wrdiv: u16,
+    rddiv: u16,
+    rdmpy: u16,
+    /// Multiplicand or divisor, shifted one bit per step
+    alu_shift: u32,
+    mpy_steps: u8,
+    div_steps: u8,
+
+    pub controllers: [Controller; 2],
+    /// JOY1-JOY4 ($4218-$421F); 3 and 4 need a multitap
+    joy: [u16; 4],
+    /// Master cycle the auto-read finishes
+    auto_read_until: u64,
+}
+
+impl CpuIo {
+    pub fn new() -> Self {
+        Self {
+            nmitimen: 0,
+            wrio: 0xFF,
+            htime: 0x1FF,
+            vtime: 0x1FF,
+            nmi_flag: false,
+            irq_flag: false,
+            nmi_pending: false,
+            wrmpya: 0xFF,
+            wrdiv: 0xFFFF,
+            rddiv: 0,
+            rdmpy: 0,
+            alu_shift: 0,
+            mpy_steps: 0,
+            div_steps: 0,
+            controllers: [Controller::new(), Controller::new()],
+            joy: [0; 4],
+            auto_read_until: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.nmitimen = 0;
+        self.wrio = 0xFF;
+        self.htime = 0x1FF;
+        self.vtime = 0x1FF;
+        self.nmi_flag = false;
+        self.irq_flag = false;
+        self.nmi_pending = false;
+        self.mpy_steps = 0;
+        self.div_steps = 0;
+        self.joy = [0; 4];
+        self.auto_read_until = 0;
+    }
+
+    /// One CPU cycle of the multiplier (8 steps) or divider (16 steps)
+    pub fn alu_step(&mut self) {
+        if self.mpy_steps > 0 {
+            self.mpy_steps -= 1;
+            if self.rddiv & 0x01 != 0 {
+                self.rdmpy = self.rdmpy.wrapping_add(self.alu_shift as u16);
+            }
+            self.rddiv >>= 1;
+            self.alu_shift <<= 1;
+        }
+        if self.div_steps > 0 {
+            self.div_steps -= 1;
+            self.rddiv <<= 1;
+            self.alu_shift >>= 1;
+            // Dividing by zero leaves the dividend as the remainder and
+            // $FFFF as the quotient
+            if self.rdmpy as u32 >= self.alu_shift {
+                self.rdmpy -= self.alu_shift as u16;
+                self.rddiv |= 0x0001;
+            }
+        }
+    }
+
+    fn alu_busy(&self) -> bool {
+        self.mpy_steps > 0 || self.div_steps > 0
+    }
+
+    /// Dot on the current line where the H/V timer fires, if enabled
+    fn irq_dot(&self) -> Option<u32> {
+        match self.nmitimen & (NMITIMEN_HIRQ | NMITIMEN_VIRQ) {
+            0 => None,
+            NMITIMEN_VIRQ => Some(0),
+            _ => Some(self.htime as u32),
+        }
+    }
+
+    /// Whether the H/V timer matches `scanline`
+    fn irq_line_matches(&self, scanline: u16) -> bool {
+        self.nmitimen & NMITIMEN_VIRQ == 0 || scanline == self.vtime
+    }
+}
+
+impl Default for CpuIo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    /// Set the buttons held on controller port 1 (0) or 2 (1)
+    pub fn set_buttons(&mut self, player: usize, buttons: Buttons) {
+        if let Some(controller) = self.io.controllers.get_mut(player) {
+            controller.set_buttons(buttons);
+        }
+    }
+
+    /// True once for each NMI edge
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.io.nmi_pending)
+    }
+
+    /// IRQ line level: the H/V timer until TIMEUP is read, or a
+    /// coprocessor until it is acknowledged
+    pub fn irq_line(&self) -> bool {
+        self.io.irq_flag
+            || self.superfx.as_ref().is_some_and(|gsu| gsu.irq())
+            || self.sa1.as_ref().is_some_and(|sa1| sa1.irq())
+            || self.cx4.as_ref().is_some_and(|cx4| cx4.irq())
+    }
+
+    /// Vblank and timer events at the start of a scanline
+    pub(crate) fn io_start_scanline(&mut self) {
+        if self.scanline == 0 {
+            self.io.nmi_flag = false;
+        }
+        if self.scanline == self.ppu.vblank_line() {
+            self.io.nmi_flag = true;
+            if self.io.nmitimen & NMITIMEN_NMI != 0 {
+                self.io.nmi_pending = true;
+            }
+            if self.io.nmitimen & NMITIMEN_AUTO_READ != 0 {
+                self.auto_read_joypads();
+            }
+        }
+        // A timer on dot 0 fires as the line begins
+        if let Some(dot) = self.io.irq_dot() {
+            if dot * 4 <= self.line_cycle && self.io.irq_line_matches(self.scanline) {
+                self.io.irq_flag = true;
+            }
+        }
+    }
+
+    /// Fire the H/V timer if its dot lies in (`from`, `to`] on this line
+    pub(crate) fn io_poll_irq(&mut self, from: u32, to: u32) {
+        if let Some(dot) = self.io.irq_dot() {
+            let position = dot * 4;
+            if from < position && position <= to && self.io.irq_line_matches(self.scanline) {
+                self.io.irq_flag = true;
+            }
+        }
+    }
+
+    fn auto_read_joypads(&mut self) {
+        for (joy, controller) in self.io.joy.iter_mut().zip(self.io.controllers.iter_mut()) {
+            *joy = controller.read_all();
+        }
+        self.io.auto_read_until = self.master_cycles + AUTO_READ_CYCLES;
+    }
+
+    fn latch_counters(&mut self) {
+        self.ppu.latch_counters((self.line_cycle / 4) as u16, self.scanline);
+    }
+
+    pub(crate) fn read_cpu_io(&mut self, addr: u16) -> Option<u8> {
+        let open_bus = self.open_bus;
+        let value = match addr {
+            0x2137 => {
+                // SLHV: latch the counters if WRIO allows it
+                if self.io.wrio & 0x80 != 0 {
+                    self.latch_counters();
+                }
+                open_bus
+            }
+            0x4016 => self.io.controllers[0].read() | (open_bus & 0xFC),
+            0x4017 => self.io.controllers[1].read() | 0x1C | (open_bus & 0xE0),
+            0x4210 => {
+                // RDNMI: NMI flag and 5A22 version 2
+                let value = ((self.io.nmi_flag as u8) << 7) | (open_bus & 0x70) | 0x02;
+                self.io.nmi_flag = false;
+                value
+            }
+            0x4211 => {
+                let value = ((self.io.irq_flag as u8) << 7) | (open_bus & 0x7F);
+                self.io.irq_flag = false;
+                value
+            }
+            0x4212 => {
+                // HVBJOY: vblank, hblank, auto-read busy
+                let vblank = self.scanline >= self.ppu.vblank_line();
+                let dot = self.line_cycle / 4;
+                let hblank = dot == 0 || dot >= HBLANK_START_DOT;
+                let busy = self.master_cycles < self.io.auto_read_until;
+                ((vblank as u8) << 7) | ((hblank as u8) << 6) | (open_bus & 0x3E) | busy as u8
+            }
+            0x4213 => self.io.wrio,
+            0x4214 => self.io.rddiv as u8,
+            0x4215 => (self.io.rddiv >> 8) as u8,
+            0x4216 => self.io.rdmpy as u8,
+            0x4217 => (self.io.rdmpy >> 8) as u8,
+            0x4218..=0x421F => {
+                let joy = self.io.joy[((addr - 0x4218) >> 1) as usize];
+                if addr & 0x01 == 0 { joy as u8 } else { (joy >> 8) as u8 }
+            }
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    pub(crate) fn write_cpu_io(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4016 => {
+                for controller in &mut self.io.controllers {
+                    controller.write_latch(value);
+                }
+            }
+            0x4200 => {
+                let io = &mut self.io;
+                // Enabling NMI during vblank fires it straight away
+                if value & NMITIMEN_NMI != 0 && io.nmitimen & NMITIMEN_NMI == 0 && io.nmi_flag {
+                    io.nmi_pending = true;
+                }
+                io.nmitimen = value;
+                if value & (NMITIMEN_HIRQ | NMITIMEN_VIRQ) == 0 {
+                    io.irq_flag = false;
+                }
+            }
+            0x4201 => {
+                // WRIO: a falling bit 7 latches the counters
+                if self.io.wrio & 0x80 != 0 && value & 0x80 == 0 {
+                    self.latch_counters();
+                }
+                self.io.wrio = value;
+            }
+            0x4202 => self.io.wrmpya = value,
+            0x4203 => {
+                // WRMPYB: start an 8-cycle multiply
+                let io = &mut self.io;
+                io.rdmpy = 0;
+                if io.alu_busy() {
+                    return;
+                }
+                io.rddiv = ((value as u16) << 8) | io.wrmpya as u16;
+                io.alu_shift = value as u32;
+                io.mpy_steps = 8;
+            }
+            0x4204 => self.io.wrdiv = (self.io.wrdiv & 0xFF00) | value as u16,
+            0x4205 => self.io.wrdiv = (self.io.wrdiv & 0x00FF) | ((value as u16) << 8),
+            0x4206 => {
+                // WRDIVB: start a 16-cycle divide
+                let io = &mut self.io;
+                io.rdmpy = io.wrdiv;
+                if io.alu_busy() {
+                    return;
+                }
+                io.alu_shift = (value as u32) << 16;
+                io.div_steps = 16;
+            }
+            0x4207 => self.io.htime = (self.io.htime & 0x100) | value as u16,
+            0x4208 => self.io.htime = (self.io.htime & 0x0FF) | (((value & 0x01) as u16) << 8),
+            0x4209 => self.io.vtime = (self.io.vtime & 0x100) | value as u16,
+            0x420A => self.io.vtime = (self.io.vtime & 0x0FF) | (((value & 0x01) as u16) << 8),
+            _ => {}
+        }
+    }
+}
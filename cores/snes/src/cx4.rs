@@ -0,0 +1,441 @@
+/// Cx4 HLE
+/// Stand-in for the HG51B when no data ROM dump is available. Commands are
+/// written to $7F4F and finish at once, with $7F5E never reporting busy.
+/// Covers the math and trig commands and the wireframe renderer used in the
+/// Mega Man X2/X3 intros. Sprite scaling, rotation and OAM building are not
+/// reimplemented; those need the LLE core.
+
+use std::f64::consts::PI;
+
+use crate::cartridge::SnesCartridge;
+use crate::hg51b::Cx4Interface;
+
+/// What the test command at $7F4F = $5C loads into $7F80 (the HG51B constants)
+const CONSTANTS: [u32; 16] = [
+    0x000000, 0xFFFFFF, 0x00FF00, 0xFF0000, 0x00FFFF, 0xFFFF00, 0x800000, 0x7FFFFF,
+    0x008000, 0x007FFF, 0xFF7FFF, 0xFFFF7F, 0x010000, 0xFEFFFF, 0x000100, 0x00FEFF,
+];
+
+/// Offset of the 2bpp wireframe bitmap in RAM and its size
+const BITMAP: usize = 0x300;
+const BITMAP_SIZE: usize = 16 * 12 * 3 * 4;
+
+/// Angles are 512 steps per turn
+fn sin(angle: u16) -> i32 {
+    (((angle & 0x1FF) as f64 * PI / 256.0).sin() * 32767.0).round() as i32
+}
+
+fn cos(angle: u16) -> i32 {
+    sin(angle.wrapping_add(128))
+}
+
+/// Float to int16 the way the C conversion truncates and wraps
+fn to_i16(value: f64) -> i16 {
+    value as i32 as i16
+}
+
+/// Point being transformed for the wireframe commands
+#[derive(Default)]
+struct Wireframe {
+    x: i16,
+    y: i16,
+    z: i16,
+    /// Rotation angles (128 steps per turn) before `calc_line`; line end
+    /// point after
+    x2: i16,
+    y2: i16,
+    dist: i16,
+    scale: i16,
+}
+
+impl Wireframe {
+    /// Rotate around X, Y and Z by `x2`, `y2` and `dist`
+    fn rotate(&self, z_offset: f64) -> (f64, f64, f64) {
+        let (x, y, z) = (self.x as f64, self.y as f64, self.z as f64 - z_offset);
+
+        let angle = -(self.x2 as f64) * PI * 2.0 / 128.0;
+        let y2 = y * angle.cos() - z * angle.sin();
+        let z2 = y * angle.sin() + z * angle.cos();
+
+        let angle = -(self.y2 as f64) * PI * 2.0 / 128.0;
+        let x2 = x * angle.cos() + z2 * angle.sin();
+        let z = x * -angle.sin() + z2 * angle.cos();
+
+        let angle = -(self.dist as f64) * PI * 2.0 / 128.0;
+        let x = x2 * angle.cos() - y2 * angle.sin();
+        let y = x2 * angle.sin() + y2 * angle.cos();
+        (x, y, z)
+    }
+
+    /// Rotate and project in perspective
+    fn transform(&mut self) {
+        let (x, y, z) = self.rotate(0x95 as f64);
+        let factor = self.scale as f64 / (0x90 as f64 * (z + 0x95 as f64)) * 0x95 as f64;
+        self.x = to_i16(x * factor);
+        self.y = to_i16(y * factor);
+    }
+
+    /// Rotate and scale without perspective
+    fn transform_flat(&mut self) {
+        let (x, y, _) = self.rotate(0.0);
+        self.x = to_i16(x * self.scale as f64 / 256.0);
+        self.y = to_i16(y * self.scale as f64 / 256.0);
+    }
+
+    /// Step (8.8) and length of a line from (x, y) to (x2, y2)
+    fn calc_line(&mut self) {
+        self.x = self.x2.wrapping_sub(self.x);
+        self.y = self.y2.wrapping_sub(self.y);
+        let (dx, dy) = ((self.x as i32).abs(), (self.y as i32).abs());
+        if dx > dy {
+            self.dist = (dx + 1) as i16;
+            self.y = to_i16(256.0 * self.y as f64 / dx as f64);
+            self.x = if self.x < 0 { -256 } else { 256 };
+        } else if dy != 0 {
+            self.dist = (dy + 1) as i16;
+            self.x = to_i16(256.0 * self.x as f64 / dy as f64);
+            self.y = if self.y < 0 { -256 } else { 256 };
+        } else {
+            self.dist = 0;
+        }
+    }
+}
+
+pub struct Cx4 {
+    /// $6000-$7FFF
+    ram: Vec<u8>,
+}
+
+impl Cx4 {
+    pub fn new() -> Self {
+        Self { ram: vec![0; 0x2000] }
+    }
+
+    fn word(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.ram[offset], self.ram[offset + 1]])
+    }
+
+    fn set_word(&mut self, offset: usize, value: u16) {
+        self.ram[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn long(&self, offset: usize) -> u32 {
+        u32::from_le_bytes([self.ram[offset], self.ram[offset + 1], self.ram[offset + 2], 0])
+    }
+
+    fn set_long(&mut self, offset: usize, value: u32) {
+        self.ram[offset..offset + 3].copy_from_slice(&value.to_le_bytes()[..3]);
+    }
+
+    /// A 24-bit address as the chip sees it: its own RAM or the cartridge
+    fn read_bus(&self, cart: &SnesCartridge, addr: u32) -> u8 {
+        if addr & 0x40E000 == 0x006000 {
+            return self.ram[addr as usize & 0x1FFF];
+        }
+        cart.read(addr & 0xFFFFFF).unwrap_or(0)
+    }
+
+    fn command(&mut self, cart: &SnesCartridge, command: u8) {
+        // Test command: echo the index
+        if self.ram[0x1F4D] == 0x0E && command < 0x40 && command & 0x03 == 0 {
+            self.ram[0x1F80] = command >> 2;
+            return;
+        }
+        match command {
+            0x00 => match self.ram[0x1F4D] {
+                0x05 => self.transform_lines(),
+                0x08 => self.draw_wireframe(cart),
+                sub => log::debug!("Cx4 sprite command {:02X} needs the data ROM", sub),
+            },
+            0x01 => {
+                self.ram[BITMAP..BITMAP + BITMAP_SIZE].fill(0);
+                self.draw_wireframe(cart);
+            }
+            // Propulsion
+            0x05 => {
+                let divisor = self.word(0x1F83) as i32;
+                let mut result = 0x10000;
+                if divisor != 0 {
+                    result = (result / divisor * self.word(0x1F81) as i32) >> 8;
+                }
+                self.set_word(0x1F80, result as u16);
+            }
+            // Set vector length
+            0x0D => {
+                let x = self.word(0x1F80) as i16 as f64;
+                let y = self.word(0x1F83) as i16 as f64;
+                let length = self.word(0x1F86) as i16 as f64;
+                let factor = length / (x * x + y * y).sqrt();
+                self.set_word(0x1F89, to_i16(x * factor * 0.98) as u16);
+                self.set_word(0x1F8C, to_i16(y * factor * 0.99) as u16);
+            }
+            // Polar to rectangular
+            0x10 => {
+                let radius = self.word(0x1F83) as i16 as i32;
+                let angle = self.word(0x1F80);
+                let x = radius.wrapping_mul(cos(angle)).wrapping_mul(2) >> 16;
+                self.set_long(0x1F86, x as u32);
+                let y = radius.wrapping_mul(sin(angle)).wrapping_mul(2) >> 16;
+                self.set_long(0x1F89, (y - (y >> 6)) as u32);
+            }
+            0x13 => {
+                let radius = self.word(0x1F83) as i32;
+                let angle = self.word(0x1F80);
+                let x = radius.wrapping_mul(cos(angle)).wrapping_mul(2) >> 8;
+                self.set_long(0x1F86, x as u32);
+                let y = radius.wrapping_mul(sin(angle)).wrapping_mul(2) >> 8;
+                self.set_long(0x1F89, y as u32);
+            }
+            // Pythagorean
+            0x15 => {
+                let x = self.word(0x1F80) as i16 as f64;
+                let y = self.word(0x1F83) as i16 as f64;
+                self.set_word(0x1F80, to_i16((x * x + y * y).sqrt()) as u16);
+            }
+            // Arctangent
+            0x1F => {
+                let x = self.word(0x1F80) as i16;
+                let y = self.word(0x1F83) as i16;
+                let angle = if x == 0 {
+                    if y > 0 { 0x80 } else { 0x180 }
+                } else {
+                    let angle = to_i16((y as f64 / x as f64).atan() / (PI * 2.0) * 512.0);
+                    (if x < 0 { angle.wrapping_add(0x100) } else { angle }) & 0x1FF
+                };
+                self.set_word(0x1F86, angle as u16);
+            }
+            0x22 => self.trapezoid(),
+            // Multiply
+            0x25 => {
+                let product = (self.long(0x1F80) as i32).wrapping_mul(self.long(0x1F83) as i32);
+                self.set_long(0x1F80, product as u32);
+            }
+            // Transform coordinates
+            0x2D => {
+                let mut point = Wireframe {
+                    x: self.word(0x1F81) as i16,
+                    y: self.word(0x1F84) as i16,
+                    z: self.word(0x1F87) as i16,
+                    x2: self.ram[0x1F89] as i16,
+                    y2: self.ram[0x1F8A] as i16,
+                    dist: self.ram[0x1F8B] as i16,
+                    scale: self.word(0x1F90) as i16,
+                };
+                point.transform_flat();
+                self.set_word(0x1F80, point.x as u16);
+                self.set_word(0x1F83, point.y as u16);
+            }
+            // Checksum of the first 2KB
+            0x40 => {
+                let sum = self.ram[..0x800].iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+                self.set_word(0x1F80, sum);
+            }
+            // Square
+            0x54 => {
+                let value = ((self.long(0x1F80) << 8) as i32 >> 8) as i64;
+                let square = value * value;
+                self.set_long(0x1F83, square as u32);
+                self.set_long(0x1F86, (square >> 24) as u32);
+            }
+            0x5C => {
+                for (index, &constant) in CONSTANTS.iter().enumerate() {
+                    self.set_long(0x1F80 + index * 3, constant);
+                }
+            }
+            // First data ROM word
+            0x89 => self.ram[0x1F80..0x1F83].copy_from_slice(&[0x36, 0x43, 0x05]),
+            _ => log::debug!("Cx4 command {:02X} needs the data ROM", command),
+        }
+    }
+
+    /// Left and right edges of a trapezoid for 225 scanlines (HDMA windows)
+    fn trapezoid(&mut self) {
+        let tangent = |angle: u16| {
+            let cos = cos(angle);
+            if cos != 0 { sin(angle).wrapping_shl(16) / cos } else { i32::MIN }
+        };
+        let left_tan = tangent(self.word(0x1F8C));
+        let right_tan = tangent(self.word(0x1F8F));
+        let origin = self.word(0x1F86) as i32 - self.word(0x1F80) as i32;
+        let width = self.word(0x1F93) as i32;
+        let mut y = self.word(0x1F83).wrapping_sub(self.word(0x1F89)) as i16;
+
+        for line in 0..225 {
+            let (left, right) = if y >= 0 {
+                let left = ((left_tan.wrapping_mul(y as i32) >> 16) + origin) as i16;
+                let right = ((right_tan.wrapping_mul(y as i32) >> 16) + origin + width) as i16;
+                let (left, right) = match (left < 0, right < 0) {
+                    (true, true) => (1, 0),
+                    (true, false) => (0, right),
+                    (false, true) => (left, 0),
+                    _ => (left, right),
+                };
+                match (left > 255, right > 255) {
+                    (true, true) => (255, 254),
+                    (true, false) => (255, right),
+                    (false, true) => (left, 255),
+                    _ => (left, right),
+                }
+            } else {
+                (1, 0)
+            };
+            self.ram[0x800 + line] = left as u8;
+            self.ram[0x900 + line] = right as u8;
+            y = y.wrapping_add(1);
+        }
+    }
+
+    /// Project the vertex list at $6000 (16 bytes each) and turn the edge
+    /// list at $6B00 into line steps at $6600
+    fn transform_lines(&mut self) {
+        let mut point = Wireframe {
+            x2: self.ram[0x1F83] as i16,
+            y2: self.ram[0x1F86] as i16,
+            dist: self.ram[0x1F89] as i16,
+            scale: self.ram[0x1F8C] as i16,
+            ..Default::default()
+        };
+        let vertices = self.word(0x1F80) as usize;
+        for vertex in (0..vertices).map(|index| index * 0x10).take_while(|&offset| offset + 10 < 0x2000) {
+            point.x = self.word(vertex + 1) as i16;
+            point.y = self.word(vertex + 5) as i16;
+            point.z = self.word(vertex + 9) as i16;
+            point.transform();
+            self.set_word(vertex + 1, point.x.wrapping_add(0x80) as u16);
+            self.set_word(vertex + 5, point.y.wrapping_add(0x50) as u16);
+        }
+
+        for offset in [0x600, 0x608] {
+            self.set_word(offset, 23);
+            self.set_word(offset + 2, 0x60);
+            self.set_word(offset + 5, 0x40);
+        }
+
+        let edges = self.word(0xB00) as usize;
+        for edge in 0..edges {
+            let from = (self.ram[0xB02 + edge * 2] as usize) << 4;
+            let to = (self.ram[0xB03 + edge * 2] as usize) << 4;
+            point.x = self.word(from + 1) as i16;
+            point.y = self.word(from + 5) as i16;
+            point.x2 = self.word(to + 1) as i16;
+            point.y2 = self.word(to + 5) as i16;
+            point.calc_line();
+            let out = 0x600 + edge * 8;
+            if out + 7 > 0x2000 {
+                break;
+            }
+            self.set_word(out, if point.dist != 0 { point.dist as u16 } else { 1 });
+            self.set_word(out + 2, point.x as u16);
+            self.set_word(out + 5, point.y as u16);
+        }
+    }
+
+    /// Draw the line list in ROM at $7F80 (5 bytes per line: two vertex
+    /// pointers and a color) into the 2bpp bitmap at $6300
+    fn draw_wireframe(&mut self, cart: &SnesCartridge) {
+        let bank = (self.ram[0x1F82] as u32) << 16;
+        let pointer = |this: &Self, addr: u32| {
+            bank | (this.read_bus(cart, addr) as u32) << 8 | this.read_bus(cart, addr + 1) as u32
+        };
+        let vertex = |this: &Self, addr: u32| {
+            let coordinate = |index: u32| {
+                i16::from_be_bytes([this.read_bus(cart, addr + index * 2), this.read_bus(cart, addr + index * 2 + 1)])
+            };
+            (coordinate(0), coordinate(1), coordinate(2))
+        };
+
+        let mut line = self.long(0x1F80);
+        for _ in 0..self.ram[0x295] {
+            // $FFFF continues from the end point of the last real line
+            let from = if self.read_bus(cart, line) == 0xFF && self.read_bus(cart, line + 1) == 0xFF {
+                let mut previous = line.wrapping_sub(5);
+                while self.read_bus(cart, previous + 2) == 0xFF && self.read_bus(cart, previous + 3) == 0xFF {
+                    previous = previous.wrapping_sub(5);
+                }
+                pointer(self, previous + 2)
+            } else {
+                pointer(self, line)
+            };
+            let to = pointer(self, line + 2);
+            let color = self.read_bus(cart, line + 4);
+            self.draw_line(vertex(self, from), vertex(self, to), color);
+            line = line.wrapping_add(5);
+        }
+    }
+
+    fn draw_line(&mut self, from: (i16, i16, i16), to: (i16, i16, i16), color: u8) {
+        let mut point = Wireframe {
+            scale: self.ram[0x1F90] as i16,
+            x2: self.ram[0x1F86] as i16,
+            y2: self.ram[0x1F87] as i16,
+            dist: self.ram[0x1F88] as i16,
+            ..Default::default()
+        };
+        let mut project = |(x, y, z): (i16, i16, i16)| {
+            (point.x, point.y, point.z) = (x, y, z);
+            point.transform_flat();
+            ((point.x as i32 + 48) << 8, (point.y as i32 + 48) << 8)
+        };
+        let (mut x, mut y) = project(from);
+        let (x2, y2) = project(to);
+
+        let mut line = Wireframe {
+            x: (x >> 8) as i16,
+            y: (y >> 8) as i16,
+            x2: (x2 >> 8) as i16,
+            y2: (y2 >> 8) as i16,
+            ..Default::default()
+        };
+        line.calc_line();
+
+        for _ in 0..line.dist.max(1) {
+            if x > 0xFF && y > 0xFF && x < 0x6000 && y < 0x6000 {
+                let (column, row) = ((x >> 8) as usize, (y >> 8) as usize);
+                let offset = BITMAP + (row >> 3) * 0xC0 + (column >> 3) * 0x10 + (row & 7) * 2;
+                let bit = 0x80 >> (column & 7);
+                self.ram[offset] = (self.ram[offset] & !bit) | if color & 1 != 0 { bit } else { 0 };
+                self.ram[offset + 1] = (self.ram[offset + 1] & !bit) | if color & 2 != 0 { bit } else { 0 };
+            }
+            x += line.x as i32;
+            y += line.y as i32;
+        }
+    }
+}
+
+impl Default for Cx4 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cx4Interface for Cx4 {
+    fn read_io(&mut self, addr: u16) -> Option<u8> {
+        // Commands finish at once, so the chip is never busy
+        if addr == 0x7F5E {
+            return Some(0);
+        }
+        Some(self.ram[addr as usize & 0x1FFF])
+    }
+
+    fn write_io(&mut self, cart: &SnesCartridge, addr: u16, value: u8) {
+        self.ram[addr as usize & 0x1FFF] = value;
+        match addr {
+            // DMA into RAM
+            0x7F47 => {
+                let source = self.long(0x1F40);
+                let length = self.word(0x1F43) as u32;
+                let target = self.word(0x1F45) as usize & 0x1FFF;
+                let data: Vec<u8> = (0..length).map(|offset| self.read_bus(cart, source + offset)).collect();
+                for (offset, byte) in data.into_iter().enumerate() {
+                    self.ram[(target + offset) & 0x1FFF] = byte;
+                }
+            }
+            0x7F4F => self.command(cart, value),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.ram.fill(0);
+    }
+}
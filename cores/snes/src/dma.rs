@@ -0,0 +1,318 @@
+/// SNES DMA / HDMA Controller
+/// Eight channels moving data between the A-bus (24-bit CPU space) and the
+/// B-bus ($2100-$21FF). General purpose DMA halts the CPU until it is done;
+/// HDMA transfers a few bytes per scanline from a table.
+
+use crate::bus::Bus;
+
+/// Master cycles per transferred byte
+const BYTE_CYCLES: u32 = 8;
+/// Setup cost of a DMA run, and per channel
+const DMA_OVERHEAD: u32 = 8;
+const DMA_CHANNEL_OVERHEAD: u32 = 8;
+/// Setup cost of HDMA on a line where any channel is active
+const HDMA_OVERHEAD: u32 = 18;
+
+// DMAPx bits
+const CONTROL_B_TO_A: u8 = 0x80;
+const CONTROL_INDIRECT: u8 = 0x40;
+const CONTROL_DECREMENT: u8 = 0x10;
+const CONTROL_FIXED: u8 = 0x08;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DmaChannel {
+    /// DMAPx: direction, HDMA indirect, A-bus step and transfer mode
+    pub control: u8,
+    /// BBADx: B-bus address low byte
+    pub b_addr: u8,
+    /// A1Tx/A1Bx: A-bus address (HDMA table start)
+    pub a_addr: u16,
+    pub a_bank: u8,
+    /// DASx: byte count for DMA, indirect address for HDMA
+    pub size: u16,
+    /// DASBx: HDMA indirect bank
+    pub indirect_bank: u8,
+    /// A2Ax: HDMA table position
+    pub table_addr: u16,
+    /// NLTRx: HDMA line counter, bit 7 = repeat
+    pub line_counter: u8,
+    /// $43xB/$43xF: unused but readable and writable
+    pub unused: u8,
+
+    hdma_do_transfer: bool,
+    hdma_completed: bool,
+}
+
+impl DmaChannel {
+    pub fn new() -> Self {
+        Self {
+            control: 0xFF,
+            b_addr: 0xFF,
+            a_addr: 0xFFFF,
+            a_bank: 0xFF,
+            size: 0xFFFF,
+            indirect_bank: 0xFF,
+            table_addr: 0xFFFF,
+            line_counter: 0xFF,
+            unused: 0xFF,
+            hdma_do_transfer: false,
+            hdma_completed: true,
+        }
+    }
+
+    /// Read of $43xR; `None` for the unmapped $43xC-$43xE
+    pub fn read(&self, reg: u16) -> Option<u8> {
+        Some(match reg & 0x0F {
+            0x0 => self.control,
+            0x1 => self.b_addr,
+            0x2 => self.a_addr as u8,
+            0x3 => (self.a_addr >> 8) as u8,
+            0x4 => self.a_bank,
+            0x5 => self.size as u8,
+            0x6 => (self.size >> 8) as u8,
+            0x7 => self.indirect_bank,
+            0x8 => self.table_addr as u8,
+            0x9 => (self.table_addr >> 8) as u8,
+            0xA => self.line_counter,
+            0xB | 0xF => self.unused,
+            _ => return None,
+        })
+    }
+
+    pub fn write(&mut self, reg: u16, value: u8) {
+        match reg & 0x0F {
+            0x0 => self.control = value,
+            0x1 => self.b_addr = value,
+            0x2 => self.a_addr = (self.a_addr & 0xFF00) | value as u16,
+            0x3 => self.a_addr = (self.a_addr & 0x00FF) | ((value as u16) << 8),
+            0x4 => self.a_bank = value,
+            0x5 => self.size = (self.size & 0xFF00) | value as u16,
+            0x6 => self.size = (self.size & 0x00FF) | ((value as u16) << 8),
+            0x7 => self.indirect_bank = value,
+            0x8 => self.table_addr = (self.table_addr & 0xFF00) | value as u16,
+            0x9 => self.table_addr = (self.table_addr & 0x00FF) | ((value as u16) << 8),
+            0xA => self.line_counter = value,
+            0xB | 0xF => self.unused = value,
+            _ => {}
+        }
+    }
+
+    /// B-bus address offsets of one transfer unit
+    fn pattern(&self) -> &'static [u8] {
+        match self.control & 0x07 {
+            0 => &[0],
+            1 => &[0, 1],
+            2 | 6 => &[0, 0],
+            3 | 7 => &[0, 0, 1, 1],
+            4 => &[0, 1, 2, 3],
+            _ => &[0, 1, 0, 1],
+        }
+    }
+
+    fn indirect(&self) -> bool {
+        self.control & CONTROL_INDIRECT != 0
+    }
+
+    /// Step the DMA A-bus address (the bank never changes)
+    fn step_a_addr(&mut self) {
+        if self.control & CONTROL_FIXED == 0 {
+            self.a_addr = if self.control & CONTROL_DECREMENT != 0 {
+                self.a_addr.wrapping_sub(1)
+            } else {
+                self.a_addr.wrapping_add(1)
+            };
+        }
+    }
+}
+
+impl Default for DmaChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    /// Run DMA started through MDMAEN, lowest channel first. The CPU is
+    /// halted for the whole transfer.
+    pub(crate) fn run_dma(&mut self) {
+        if self.dma_pending == 0 {
+            return;
+        }
+        self.add_cycles(DMA_OVERHEAD);
+
+        for index in 0..8 {
+            let bit = 1 << index;
+            if self.dma_pending & bit == 0 {
+                continue;
+            }
+            self.add_cycles(DMA_CHANNEL_OVERHEAD);
+
+            let pattern = self.dma[index].pattern();
+            let mut unit = 0;
+            loop {
+                let channel = self.dma[index];
+                let a_addr = ((channel.a_bank as u32) << 16) | channel.a_addr as u32;
+                let b_addr = channel.b_addr.wrapping_add(pattern[unit % pattern.len()]);
+                self.add_cycles(BYTE_CYCLES);
+                self.transfer(a_addr, b_addr, channel.control & CONTROL_B_TO_A != 0);
+
+                let channel = &mut self.dma[index];
+                channel.step_a_addr();
+                channel.size = channel.size.wrapping_sub(1);
+                unit += 1;
+                if channel.size == 0 {
+                    break;
+                }
+
+                // HDMA on the same channel cuts the transfer short
+                if self.service_hdma() & bit != 0 {
+                    break;
+                }
+            }
+            self.dma_pending &= !bit;
+        }
+        self.dma_pending = 0;
+    }
+
+    /// Run the HDMA events that came due; returns the channels that
+    /// did an HDMA transfer
+    pub(crate) fn service_hdma(&mut self) -> u8 {
+        let mut transferred = 0;
+        while self.hdma_init_pending || self.hdma_run_pending {
+            if std::mem::take(&mut self.hdma_init_pending) {
+                self.hdma_init();
+            }
+            if std::mem::take(&mut self.hdma_run_pending) {
+                transferred |= self.hdma_run();
+            }
+        }
+        transferred
+    }
+
+    /// Start of frame: load the first entry of every enabled table
+    fn hdma_init(&mut self) {
+        for channel in &mut self.dma {
+            channel.hdma_do_transfer = false;
+            channel.hdma_completed = true;
+        }
+        if self.hdma_enable == 0 {
+            return;
+        }
+        self.add_cycles(HDMA_OVERHEAD);
+
+        for index in 0..8 {
+            if self.hdma_enable & (1 << index) == 0 {
+                continue;
+            }
+            // HDMA takes the channel from a DMA that has not started yet
+            self.dma_pending &= !(1 << index);
+            let channel = &mut self.dma[index];
+            channel.table_addr = channel.a_addr;
+            channel.line_counter = 0;
+            self.hdma_reload(index);
+        }
+    }
+
+    /// One scanline of HDMA for every active channel
+    fn hdma_run(&mut self) -> u8 {
+        let active = (0..8)
+            .filter(|&i| self.hdma_enable & (1 << i) != 0 && !self.dma[i].hdma_completed)
+            .fold(0u8, |mask, i| mask | (1 << i));
+        if active == 0 {
+            return 0;
+        }
+        self.add_cycles(HDMA_OVERHEAD);
+
+        let mut transferred = 0;
+        for index in 0..8 {
+            if active & (1 << index) == 0 {
+                continue;
+            }
+            self.add_cycles(DMA_CHANNEL_OVERHEAD);
+
+            if self.dma[index].hdma_do_transfer {
+                transferred |= 1 << index;
+                let pattern = self.dma[index].pattern();
+                for &offset in pattern {
+                    let channel = &mut self.dma[index];
+                    let a_addr = if channel.indirect() {
+                        let addr = ((channel.indirect_bank as u32) << 16) | channel.size as u32;
+                        channel.size = channel.size.wrapping_add(1);
+                        addr
+                    } else {
+                        let addr = ((channel.a_bank as u32) << 16) | channel.table_addr as u32;
+                        channel.table_addr = channel.table_addr.wrapping_add(1);
+                        addr
+                    };
+                    let b_addr = channel.b_addr.wrapping_add(offset);
+                    let b_to_a = channel.control & CONTROL_B_TO_A != 0;
+                    self.add_cycles(BYTE_CYCLES);
+                    self.transfer(a_addr, b_addr, b_to_a);
+                }
+            }
+
+            let channel = &mut self.dma[index];
+            channel.line_counter = channel.line_counter.wrapping_sub(1);
+            channel.hdma_do_transfer = channel.line_counter & 0x80 != 0;
+            if channel.line_counter & 0x7F == 0 {
+                self.hdma_reload(index);
+            }
+        }
+        transferred
+    }
+
+    /// Read the next table entry: line counter, then the indirect address
+    fn hdma_reload(&mut self, index: usize) {
+        let line_counter = self.hdma_table_read(index);
+        self.dma[index].line_counter = line_counter;
+
+        if self.dma[index].indirect() {
+            let lo = self.hdma_table_read(index) as u16;
+            let hi = self.hdma_table_read(index) as u16;
+            self.dma[index].size = (hi << 8) | lo;
+        }
+
+        let channel = &mut self.dma[index];
+        channel.hdma_completed = line_counter == 0;
+        channel.hdma_do_transfer = true;
+    }
+
+    fn hdma_table_read(&mut self, index: usize) -> u8 {
+        let channel = &mut self.dma[index];
+        let addr = ((channel.a_bank as u32) << 16) | channel.table_addr as u32;
+        channel.table_addr = channel.table_addr.wrapping_add(1);
+        self.add_cycles(BYTE_CYCLES);
+        self.a_bus_read(addr)
+    }
+
+    /// Move one byte between the A-bus and $21xx
+    fn transfer(&mut self, a_addr: u32, b_addr: u8, b_to_a: bool) {
+        let b_addr = 0x2100 | b_addr as u16;
+        if b_to_a {
+            let value = self.read_io(b_addr).unwrap_or(self.open_bus);
+            self.open_bus = value;
+            if Self::a_bus_valid(a_addr) {
+                self.write_byte(a_addr, value);
+            }
+        } else {
+            let value = self.a_bus_read(a_addr);
+            self.open_bus = value;
+            self.write_io(b_addr, value);
+        }
+    }
+
+    fn a_bus_read(&mut self, addr: u32) -> u8 {
+        if Self::a_bus_valid(addr) {
+            self.read_byte(addr)
+        } else {
+            self.open_bus
+        }
+    }
+
+    /// The A-bus side of a transfer cannot reach the MMIO registers
+    fn a_bus_valid(addr: u32) -> bool {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        bank & 0x40 != 0 || !matches!(offset, 0x2100..=0x21FF | 0x4000..=0x41FF | 0x4200..=0x421F | 0x4300..=0x437F)
+    }
+}
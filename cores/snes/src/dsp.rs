@@ -0,0 +1,584 @@
+/// Sony S-DSP
+/// Eight voices playing BRR-compressed samples out of ARAM, each with
+/// Gaussian interpolation, an ADSR or GAIN envelope, pitch modulation and
+/// noise, mixed with an 8-tap FIR echo whose ring buffer also lives in
+/// ARAM. Produces one stereo sample every 32 SPC700 cycles (32 kHz).
+
+pub const SAMPLE_RATE: u32 = 32_000;
+
+/// SPC700 cycles per output sample
+pub const CYCLES_PER_SAMPLE: u32 = 32;
+
+// Voice registers, at voice * $10 + offset
+const V_VOLL: usize = 0x0;
+const V_PITCHL: usize = 0x2;
+const V_PITCHH: usize = 0x3;
+const V_SRCN: usize = 0x4;
+const V_ADSR1: usize = 0x5;
+const V_ADSR2: usize = 0x6;
+const V_GAIN: usize = 0x7;
+const V_ENVX: usize = 0x8;
+const V_OUTX: usize = 0x9;
+
+// Global registers
+const R_MVOLL: usize = 0x0C;
+const R_EVOLL: usize = 0x2C;
+const R_KON: usize = 0x4C;
+const R_KOFF: usize = 0x5C;
+const R_FLG: usize = 0x6C;
+const R_ENDX: usize = 0x7C;
+const R_EFB: usize = 0x0D;
+const R_PMON: usize = 0x2D;
+const R_NON: usize = 0x3D;
+const R_EON: usize = 0x4D;
+const R_DIR: usize = 0x5D;
+const R_ESA: usize = 0x6D;
+const R_EDL: usize = 0x7D;
+/// FIR coefficients at $0F, $1F, ... $7F
+const R_FIR: usize = 0x0F;
+
+// FLG bits
+const FLG_SOFT_RESET: u8 = 0x80;
+const FLG_MUTE: u8 = 0x40;
+const FLG_ECHO_DISABLE: u8 = 0x20;
+
+/// Decoded samples kept per voice: three BRR groups of four
+const BRR_BUFFER: usize = 12;
+const BRR_BLOCK: u16 = 9;
+
+/// The envelope and noise clocks run off one counter that divides
+/// evenly by every rate
+const COUNTER_RANGE: i32 = 2048 * 5 * 3;
+
+/// Samples between events for each of the 32 rates; rate 0 never fires
+const COUNTER_RATES: [i32; 32] = [
+    COUNTER_RANGE + 1, 2048, 1536, 1280, 1024, 768, 640, 512, 384, 320, 256, 192, 160, 128, 96, 80,
+    64, 48, 40, 32, 24, 20, 16, 12, 10, 8, 6, 5, 4, 3, 2, 1,
+];
+
+const COUNTER_OFFSETS: [i32; 32] = [
+    1, 0, 1040, 536, 0, 1040, 536, 0, 1040, 536, 0, 1040, 536, 0, 1040, 536,
+    0, 1040, 536, 0, 1040, 536, 0, 1040, 536, 0, 1040, 536, 0, 1040, 0, 0,
+];
+
+/// Gaussian interpolation kernel, as in the S-DSP's ROM
+#[rustfmt::skip]
+const GAUSS: [i32; 512] = [
+       0,    0,    0,    0,    0,    0,    0,    0,    0,    0,    0,    0,    0,    0,    0,    0,
+       1,    1,    1,    1,    1,    1,    1,    1,    1,    1,    1,    2,    2,    2,    2,    2,
+       2,    2,    3,    3,    3,    3,    3,    4,    4,    4,    4,    4,    5,    5,    5,    5,
+       6,    6,    6,    6,    7,    7,    7,    8,    8,    8,    9,    9,    9,   10,   10,   10,
+      11,   11,   11,   12,   12,   13,   13,   14,   14,   15,   15,   15,   16,   16,   17,   17,
+      18,   19,   19,   20,   20,   21,   21,   22,   23,   23,   24,   24,   25,   26,   27,   27,
+      28,   29,   29,   30,   31,   32,   32,   33,   34,   35,   36,   36,   37,   38,   39,   40,
+      41,   42,   43,   44,   45,   46,   47,   48,   49,   50,   51,   52,   53,   54,   55,   56,
+      58,   59,   60,   61,   62,   64,   65,   66,   67,   69,   70,   71,   73,   74,   76,   77,
+      78,   80,   81,   83,   84,   86,   87,   89,   90,   92,   94,   95,   97,   99,  100,  102,
+     104,  106,  107,  109,  111,  113,  115,  117,  118,  120,  122,  124,  126,  128,  130,  132,
+     134,  137,  139,  141,  143,  145,  147,  150,  152,  154,  156,  159,  161,  163,  166,  168,
+     171,  173,  175,  178,  180,  183,  186,  188,  191,  193,  196,  199,  201,  204,  207,  210,
+     212,  215,  218,  221,  224,  227,  230,  233,  236,  239,  242,  245,  248,  251,  254,  257,
+     260,  263,  267,  270,  273,  276,  280,  283,  286,  290,  293,  297,  300,  304,  307,  311,
+     314,  318,  321,  325,  328,  332,  336,  339,  343,  347,  351,  354,  358,  362,  366,  370,
+     374,  378,  381,  385,  389,  393,  397,  401,  405,  410,  414,  418,  422,  426,  430,  434,
+     439,  443,  447,  451,  456,  460,  464,  469,  473,  477,  482,  486,  491,  495,  499,  504,
+     508,  513,  517,  522,  527,  531,  536,  540,  545,  550,  554,  559,  563,  568,  573,  577,
+     582,  587,  592,  596,  601,  606,  611,  615,  620,  625,  630,  635,  640,  644,  649,  654,
+     659,  664,  669,  674,  678,  683,  688,  693,  698,  703,  708,  713,  718,  723,  728,  732,
+     737,  742,  747,  752,  757,  762,  767,  772,  777,  782,  787,  792,  797,  802,  806,  811,
+     816,  821,  826,  831,  836,  841,  846,  851,  855,  860,  865,  870,  875,  880,  884,  889,
+     894,  899,  904,  908,  913,  918,  923,  927,  932,  937,  941,  946,  951,  955,  960,  965,
+     969,  974,  978,  983,  988,  992,  997, 1001, 1005, 1010, 1014, 1019, 1023, 1027, 1032, 1036,
+    1040, 1045, 1049, 1053, 1057, 1061, 1066, 1070, 1074, 1078, 1082, 1086, 1090, 1094, 1098, 1102,
+    1106, 1109, 1113, 1117, 1121, 1125, 1128, 1132, 1136, 1139, 1143, 1146, 1150, 1153, 1157, 1160,
+    1164, 1167, 1170, 1174, 1177, 1180, 1183, 1186, 1190, 1193, 1196, 1199, 1202, 1205, 1207, 1210,
+    1213, 1216, 1219, 1221, 1224, 1227, 1229, 1232, 1234, 1237, 1239, 1241, 1244, 1246, 1248, 1251,
+    1253, 1255, 1257, 1259, 1261, 1263, 1265, 1267, 1269, 1270, 1272, 1274, 1275, 1277, 1279, 1280,
+    1282, 1283, 1284, 1286, 1287, 1288, 1290, 1291, 1292, 1293, 1294, 1295, 1296, 1297, 1297, 1298,
+    1299, 1300, 1300, 1301, 1302, 1302, 1303, 1303, 1303, 1304, 1304, 1304, 1304, 1304, 1305, 1305,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EnvelopeMode {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    /// Decoded samples, a ring of three groups of four
+    buffer: [i32; BRR_BUFFER],
+    buffer_pos: usize,
+    /// Pitch counter: source sample in bits 12+, fraction below
+    interp_pos: i32,
+    /// Current BRR block and the byte being decoded within it
+    brr_addr: u16,
+    brr_offset: u16,
+    /// Samples left before a keyed-on voice starts playing
+    kon_delay: u8,
+    env_mode: EnvelopeMode,
+    /// 11-bit envelope level
+    env: i32,
+    /// Level before clamping, used by the bent-line GAIN mode
+    hidden_env: i32,
+}
+
+impl Voice {
+    fn new() -> Self {
+        Self {
+            buffer: [0; BRR_BUFFER],
+            buffer_pos: 0,
+            interp_pos: 0,
+            brr_addr: 0,
+            brr_offset: 1,
+            kon_delay: 0,
+            env_mode: EnvelopeMode::Release,
+            env: 0,
+            hidden_env: 0,
+        }
+    }
+}
+
+pub struct Dsp {
+    regs: [u8; 128],
+    voices: [Voice; 8],
+
+    /// Envelope and noise clock, counting down through COUNTER_RANGE
+    counter: i32,
+    /// 15-bit LFSR
+    noise: i32,
+    /// KON and KOFF are only polled every other sample
+    every_other_sample: bool,
+    kon: u8,
+    new_kon: u8,
+
+    /// Last eight echo samples per channel, for the FIR filter
+    echo_hist: [[i32; 2]; 8],
+    echo_hist_pos: usize,
+    echo_offset: usize,
+    echo_length: usize,
+
+    /// Voices left out of the mix, one bit each; not a hardware register
+    voice_mute: u8,
+
+    /// Interleaved stereo output since the last `clear_samples`
+    samples: Vec<i16>,
+}
+
+impl Dsp {
+    pub fn new() -> Self {
+        let mut dsp = Self {
+            regs: [0; 128],
+            voices: [Voice::new(); 8],
+            counter: 0,
+            noise: 0x4000,
+            every_other_sample: true,
+            kon: 0,
+            new_kon: 0,
+            echo_hist: [[0; 2]; 8],
+            echo_hist_pos: 0,
+            echo_offset: 0,
+            echo_length: 0,
+            voice_mute: 0,
+            samples: Vec::new(),
+        };
+        dsp.reset();
+        dsp
+    }
+
+    pub fn reset(&mut self) {
+        self.regs = [0; 128];
+        // Soft reset, muted, echo writes off
+        self.regs[R_FLG] = FLG_SOFT_RESET | FLG_MUTE | FLG_ECHO_DISABLE;
+        self.voices = [Voice::new(); 8];
+        self.counter = 0;
+        self.noise = 0x4000;
+        self.every_other_sample = true;
+        self.kon = 0;
+        self.new_kon = 0;
+        self.echo_hist = [[0; 2]; 8];
+        self.echo_hist_pos = 0;
+        self.echo_offset = 0;
+        self.echo_length = 0;
+        self.samples.clear();
+    }
+
+    pub fn read(&self, addr: u8) -> u8 {
+        self.regs[(addr & 0x7F) as usize]
+    }
+
+    /// $80-$FF mirror the registers read-only
+    pub fn write(&mut self, addr: u8, value: u8) {
+        let addr = addr as usize;
+        if addr >= 0x80 {
+            return;
+        }
+        self.regs[addr] = value;
+        match addr {
+            R_KON => self.new_kon = value,
+            // Any write clears every end flag
+            R_ENDX => self.regs[R_ENDX] = 0,
+            _ => {}
+        }
+    }
+
+    /// Restore all registers from a snapshot. Voices start silent; the ones
+    /// set in KON are keyed on again.
+    pub fn load_registers(&mut self, regs: &[u8; 128]) {
+        let voice_mute = self.voice_mute;
+        self.reset();
+        self.regs = *regs;
+        self.new_kon = regs[R_KON];
+        self.voice_mute = voice_mute;
+    }
+
+    /// Keep voices out of the mix (bit n = voice n), for music players
+    pub fn set_voice_mute(&mut self, mask: u8) {
+        self.voice_mute = mask;
+    }
+
+    pub fn voice_mute(&self) -> u8 {
+        self.voice_mute
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    pub fn samples_mut(&mut self) -> &mut [i16] {
+        &mut self.samples
+    }
+
+    pub fn clear_samples(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Zero when an event at `rate` is due on this sample
+    fn read_counter(&self, rate: usize) -> i32 {
+        (self.counter + COUNTER_OFFSETS[rate]) % COUNTER_RATES[rate]
+    }
+
+    /// Produce one stereo sample
+    pub fn run_sample(&mut self, ram: &mut [u8]) {
+        self.counter -= 1;
+        if self.counter < 0 {
+            self.counter = COUNTER_RANGE - 1;
+        }
+
+        if self.read_counter((self.regs[R_FLG] & 0x1F) as usize) == 0 {
+            let feedback = (self.noise << 13) ^ (self.noise << 14);
+            self.noise = (feedback & 0x4000) ^ (self.noise >> 1);
+        }
+
+        self.every_other_sample = !self.every_other_sample;
+        if self.every_other_sample {
+            self.new_kon &= !self.kon;
+            self.kon = self.new_kon;
+        }
+
+        let mut main_out = [0i32; 2];
+        let mut echo_out = [0i32; 2];
+        let mut previous_output = 0;
+        for index in 0..8 {
+            let output = self.run_voice(index, ram, previous_output);
+            previous_output = output;
+            if self.voice_mute & (1 << index) != 0 {
+                continue;
+            }
+            let base = index << 4;
+            for ch in 0..2 {
+                let amp = (output * self.regs[base + V_VOLL + ch] as i8 as i32) >> 7;
+                main_out[ch] = clamp16(main_out[ch] + amp);
+                if self.regs[R_EON] & (1 << index) != 0 {
+                    echo_out[ch] = clamp16(echo_out[ch] + amp);
+                }
+            }
+        }
+
+        let echo_in = self.run_echo(ram, echo_out);
+
+        let mut out = [0i32; 2];
+        for ch in 0..2 {
+            let master = ((main_out[ch] * self.regs[R_MVOLL + ch * 0x10] as i8 as i32) >> 7) as i16 as i32;
+            let echo = ((echo_in[ch] * self.regs[R_EVOLL + ch * 0x10] as i8 as i32) >> 7) as i16 as i32;
+            out[ch] = clamp16(master + echo);
+        }
+        if self.regs[R_FLG] & FLG_MUTE != 0 {
+            out = [0, 0];
+        }
+        self.samples.push(out[0] as i16);
+        self.samples.push(out[1] as i16);
+    }
+
+    /// One sample of a voice; returns its output after the envelope
+    fn run_voice(&mut self, index: usize, ram: &mut [u8], previous_output: i32) -> i32 {
+        let base = index << 4;
+        let bit = 1 << index;
+
+        // Sample directory entry: start address, then loop address
+        let dir_entry = (self.regs[R_DIR] as usize * 0x100 + self.regs[base + V_SRCN] as usize * 4) & 0xFFFF;
+        let entry = if self.voices[index].kon_delay != 0 { dir_entry } else { dir_entry + 2 };
+        let next_addr = read16(ram, entry);
+
+        let mut pitch = ((self.regs[base + V_PITCHH] as i32 & 0x3F) << 8) | self.regs[base + V_PITCHL] as i32;
+        if index > 0 && self.regs[R_PMON] & bit != 0 {
+            pitch += ((previous_output >> 5) * pitch) >> 10;
+        }
+
+        let voice = &mut self.voices[index];
+        let mut header = ram[voice.brr_addr as usize];
+        if voice.kon_delay != 0 {
+            if voice.kon_delay == 5 {
+                voice.brr_addr = next_addr;
+                voice.brr_offset = 1;
+                voice.buffer_pos = 0;
+                // The header is ignored on this sample
+                header = 0;
+                self.regs[R_ENDX] &= !bit;
+            }
+            // The envelope holds at zero and the pitch counter only moves
+            // to decode the first three groups
+            voice.env = 0;
+            voice.hidden_env = 0;
+            voice.kon_delay -= 1;
+            voice.interp_pos = if voice.kon_delay & 3 != 0 { 0x4000 } else { 0 };
+            pitch = 0;
+        }
+
+        let mut output = if self.regs[R_NON] & bit != 0 {
+            (self.noise * 2) as i16 as i32
+        } else {
+            interpolate(voice)
+        };
+        output = ((output * voice.env) >> 11) & !1;
+        self.regs[base + V_ENVX] = (voice.env >> 4) as u8;
+        self.regs[base + V_OUTX] = (output >> 8) as u8;
+
+        // Soft reset, or an end block without the loop flag, cut the voice
+        if self.regs[R_FLG] & FLG_SOFT_RESET != 0 || header & 0x03 == 0x01 {
+            voice.env_mode = EnvelopeMode::Release;
+            voice.env = 0;
+        }
+
+        if self.every_other_sample {
+            if self.regs[R_KOFF] & bit != 0 {
+                voice.env_mode = EnvelopeMode::Release;
+            }
+            if self.kon & bit != 0 {
+                voice.kon_delay = 5;
+                voice.env_mode = EnvelopeMode::Attack;
+            }
+        }
+
+        if self.voices[index].kon_delay == 0 {
+            self.run_envelope(index);
+        }
+
+        let voice = &mut self.voices[index];
+        if voice.interp_pos >= 0x4000 {
+            decode_brr(voice, ram, header);
+            voice.brr_offset += 2;
+            if voice.brr_offset >= BRR_BLOCK {
+                voice.brr_addr = voice.brr_addr.wrapping_add(BRR_BLOCK);
+                if header & 0x01 != 0 {
+                    voice.brr_addr = next_addr;
+                    self.regs[R_ENDX] |= bit;
+                }
+                voice.brr_offset = 1;
+            }
+        }
+        voice.interp_pos = ((voice.interp_pos & 0x3FFF) + pitch).min(0x7FFF);
+
+        output
+    }
+
+    fn run_envelope(&mut self, index: usize) {
+        let base = index << 4;
+        let adsr1 = self.regs[base + V_ADSR1];
+        let voice = &mut self.voices[index];
+        let mut env = voice.env;
+
+        if voice.env_mode == EnvelopeMode::Release {
+            voice.env = (env - 0x08).max(0);
+            return;
+        }
+
+        let rate;
+        let env_data;
+        if adsr1 & 0x80 != 0 {
+            // ADSR; the sustain level and rate are in ADSR2
+            env_data = self.regs[base + V_ADSR2];
+            if voice.env_mode == EnvelopeMode::Attack {
+                rate = ((adsr1 & 0x0F) as usize) * 2 + 1;
+                env += if rate < 31 { 0x20 } else { 0x400 };
+            } else {
+                env -= 1;
+                env -= env >> 8;
+                rate = if voice.env_mode == EnvelopeMode::Decay {
+                    (((adsr1 >> 3) & 0x0E) + 0x10) as usize
+                } else {
+                    (env_data & 0x1F) as usize
+                };
+            }
+        } else {
+            // GAIN
+            env_data = self.regs[base + V_GAIN];
+            let mode = env_data >> 5;
+            if mode < 4 {
+                // Direct
+                env = env_data as i32 * 0x10;
+                rate = 31;
+            } else {
+                rate = (env_data & 0x1F) as usize;
+                match mode {
+                    4 => env -= 0x20,
+                    5 => {
+                        env -= 1;
+                        env -= env >> 8;
+                    }
+                    6 => env += 0x20,
+                    _ => {
+                        // Bent line: slower above 3/4
+                        env += if voice.hidden_env >= 0x600 { 0x08 } else { 0x20 };
+                    }
+                }
+            }
+        }
+
+        // Sustain level is compared against the top 3 bits; in GAIN mode
+        // this quirkily reads the GAIN register instead of ADSR2
+        if (env >> 8) == (env_data >> 5) as i32 && voice.env_mode == EnvelopeMode::Decay {
+            voice.env_mode = EnvelopeMode::Sustain;
+        }
+        voice.hidden_env = env;
+
+        if !(0..=0x7FF).contains(&env) {
+            env = env.clamp(0, 0x7FF);
+            if voice.env_mode == EnvelopeMode::Attack {
+                voice.env_mode = EnvelopeMode::Decay;
+            }
+        }
+
+        if self.read_counter(rate) == 0 {
+            self.voices[index].env = env;
+        }
+    }
+
+    /// Read the echo buffer, filter it, and write back the new echo input
+    /// with feedback. Returns the FIR output.
+    fn run_echo(&mut self, ram: &mut [u8], echo_out: [i32; 2]) -> [i32; 2] {
+        let ptr = (self.regs[R_ESA] as usize * 0x100 + self.echo_offset) & 0xFFFF;
+
+        self.echo_hist_pos = (self.echo_hist_pos + 1) % 8;
+        for ch in 0..2 {
+            self.echo_hist[self.echo_hist_pos][ch] = (read16(ram, ptr + ch * 2) as i16 as i32) >> 1;
+        }
+
+        let mut fir_out = [0i32; 2];
+        for (ch, out) in fir_out.iter_mut().enumerate() {
+            // Tap 0 is the oldest sample, tap 7 the one just read
+            let tap = |i: usize| {
+                let sample = self.echo_hist[(self.echo_hist_pos + 1 + i) % 8][ch];
+                (sample * self.regs[R_FIR + i * 0x10] as i8 as i32) >> 6
+            };
+            // The first seven taps wrap at 16 bits, only the last clamps
+            let partial = (0..7).map(tap).sum::<i32>() as i16 as i32;
+            *out = clamp16(partial + tap(7) as i16 as i32) & !1;
+        }
+
+        let efb = self.regs[R_EFB] as i8 as i32;
+        let flg = self.regs[R_FLG];
+        for ch in 0..2 {
+            let feedback = ((fir_out[ch] * efb) >> 7) as i16 as i32;
+            let value = clamp16(echo_out[ch] + feedback) & !1;
+            if flg & FLG_ECHO_DISABLE == 0 {
+                write16(ram, ptr + ch * 2, value as u16);
+            }
+        }
+
+        if self.echo_offset == 0 {
+            self.echo_length = (self.regs[R_EDL] & 0x0F) as usize * 0x800;
+        }
+        self.echo_offset += 4;
+        if self.echo_offset >= self.echo_length {
+            self.echo_offset = 0;
+        }
+
+        fir_out
+    }
+}
+
+impl Default for Dsp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 4-point Gaussian interpolation at the voice's pitch counter
+fn interpolate(voice: &Voice) -> i32 {
+    let offset = ((voice.interp_pos >> 4) & 0xFF) as usize;
+    let start = voice.buffer_pos + (voice.interp_pos >> 12) as usize;
+    let sample = |i: usize| voice.buffer[(start + i) % BRR_BUFFER];
+
+    let mut out = (GAUSS[255 - offset] * sample(0)) >> 11;
+    out += (GAUSS[511 - offset] * sample(1)) >> 11;
+    out += (GAUSS[256 + offset] * sample(2)) >> 11;
+    out = out as i16 as i32;
+    out += (GAUSS[offset] * sample(3)) >> 11;
+    clamp16(out) & !1
+}
+
+/// Decode the next four samples of a BRR block into the voice's buffer
+fn decode_brr(voice: &mut Voice, ram: &[u8], header: u8) {
+    let shift = (header >> 4) as i32;
+    let filter = (header >> 2) & 0x03;
+    let addr = voice.brr_addr.wrapping_add(voice.brr_offset) as usize;
+    let nybbles = ((ram[addr] as u16) << 8) | ram[(addr + 1) & 0xFFFF] as u16;
+
+    for i in 0..4 {
+        let nybble = ((nybbles << (i * 4)) as i16 >> 12) as i32;
+        let mut s = (nybble << shift) >> 1;
+        if shift >= 0x0D {
+            // Invalid shifts leave only the sign
+            s = if s < 0 { -0x800 } else { 0 };
+        }
+
+        // Previous samples are stored doubled
+        let p1 = voice.buffer[(voice.buffer_pos + BRR_BUFFER - 1) % BRR_BUFFER];
+        let p2 = voice.buffer[(voice.buffer_pos + BRR_BUFFER - 2) % BRR_BUFFER] >> 1;
+        match filter {
+            1 => {
+                s += p1 >> 1;
+                s += (-p1) >> 5;
+            }
+            2 => {
+                s += p1 - p2;
+                s += p2 >> 4;
+                s += (p1 * -3) >> 6;
+            }
+            3 => {
+                s += p1 - p2;
+                s += (p1 * -13) >> 7;
+                s += (p2 * 3) >> 4;
+            }
+            _ => {}
+        }
+        voice.buffer[voice.buffer_pos] = (clamp16(s) * 2) as i16 as i32;
+        voice.buffer_pos = (voice.buffer_pos + 1) % BRR_BUFFER;
+    }
+}
+
+fn clamp16(value: i32) -> i32 {
+    value.clamp(i16::MIN as i32, i16::MAX as i32)
+}
+
+fn read16(ram: &[u8], addr: usize) -> u16 {
+    ram[addr & 0xFFFF] as u16 | ((ram[(addr + 1) & 0xFFFF] as u16) << 8)
+}
+
+fn write16(ram: &mut [u8], addr: usize, value: u16) {
+    ram[addr & 0xFFFF] = value as u8;
+    ram[(addr + 1) & 0xFFFF] = (value >> 8) as u8;
+}
@@ -0,0 +1,619 @@
+/// DSP-1 High-Level Emulation
+/// Reimplements the DSP-1/1B command set for when no firmware dump is
+/// available. Sines and reciprocals are computed instead of read from the
+/// chip's data ROM, so results can be a bit off from hardware in the last
+/// place, and Parameter (02) clips the zenith angle without the ROM's
+/// correction polynomial for VOF.
+
+use std::f64::consts::PI;
+
+use crate::necdsp::DspInterface;
+
+/// Status register as read by the 5A22: RQM, always ready
+const STATUS_READY: u8 = 0x80;
+
+/// Largest zenith angle that keeps the horizon on screen, by exponent of
+/// the eye height
+const MAX_AZS_EXP: [i16; 16] = [
+    0x38B4, 0x38B7, 0x38BA, 0x38BE, 0x38C0, 0x38C4, 0x38C7, 0x38CA,
+    0x38CE, 0x38D0, 0x38D4, 0x38D7, 0x38DA, 0x38DD, 0x38E0, 0x38E4,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Multiply,
+    MultiplyRound,
+    Inverse,
+    Triangle,
+    Radius,
+    Range,
+    Distance,
+    Rotate,
+    Polar,
+    Parameter,
+    Raster,
+    Project,
+    Target,
+    Attitude(usize),
+    Objective(usize),
+    Subjective(usize),
+    Scalar(usize),
+    Gyrate,
+    MemoryTest,
+    MemoryDump,
+    MemorySize,
+}
+
+impl Command {
+    /// Command and its number of input words; several codes are aliases
+    fn decode(code: u8) -> Option<(Command, usize)> {
+        use Command::*;
+        Some(match code {
+            0x00 => (Multiply, 2),
+            0x20 => (MultiplyRound, 2),
+            0x10 | 0x30 => (Inverse, 2),
+            0x04 | 0x24 => (Triangle, 2),
+            0x08 => (Radius, 3),
+            0x18 | 0x38 => (Range, 4),
+            0x28 => (Distance, 3),
+            0x0C | 0x2C => (Rotate, 3),
+            0x1C | 0x3C => (Polar, 6),
+            0x02 | 0x12 | 0x22 | 0x32 => (Parameter, 7),
+            0x0A | 0x1A | 0x2A | 0x3A => (Raster, 1),
+            0x06 | 0x16 | 0x26 | 0x36 => (Project, 3),
+            0x0E | 0x1E | 0x2E | 0x3E => (Target, 2),
+            0x01 | 0x05 | 0x31 | 0x35 => (Attitude(0), 4),
+            0x11 | 0x15 => (Attitude(1), 4),
+            0x21 | 0x25 => (Attitude(2), 4),
+            0x09 | 0x0D | 0x39 | 0x3D => (Objective(0), 3),
+            0x19 | 0x1D => (Objective(1), 3),
+            0x29 | 0x2D => (Objective(2), 3),
+            0x03 | 0x33 => (Subjective(0), 3),
+            0x13 => (Subjective(1), 3),
+            0x23 => (Subjective(2), 3),
+            0x0B | 0x3B => (Scalar(0), 3),
+            0x1B => (Scalar(1), 3),
+            0x2B => (Scalar(2), 3),
+            0x14 | 0x34 => (Gyrate, 6),
+            0x07 | 0x0F => (MemoryTest, 1),
+            0x1F => (MemoryDump, 1),
+            0x17 | 0x27 | 0x2F | 0x37 | 0x3F => (MemorySize, 1),
+            _ => return None,
+        })
+    }
+}
+
+/// Q15 product, truncated like the chip's multiplier
+fn mul(a: i32, b: i32) -> i32 {
+    (a * b) >> 15
+}
+
+fn sin(angle: i16) -> i16 {
+    ((angle as f64 * PI / 32768.0).sin() * 32767.0).round() as i16
+}
+
+fn cos(angle: i16) -> i16 {
+    ((angle as f64 * PI / 32768.0).cos() * 32767.0).round() as i16
+}
+
+/// Sign bits below the top one
+fn redundant_bits(value: i32) -> u32 {
+    let magnitude = if value < 0 { !value } else { value };
+    magnitude.leading_zeros() - 1
+}
+
+/// Shift `m` up until bit 14 differs from the sign, lowering the exponent
+fn normalize(m: i16, exponent: &mut i16) -> i16 {
+    let e = (redundant_bits(m as i32) - 16).min(15);
+    *exponent -= e as i16;
+    (m as i32).wrapping_shl(e) as i16
+}
+
+/// Normalize a 31-bit product to a coefficient and the shift it took
+fn normalize_double(product: i32) -> (i16, i16) {
+    let e = redundant_bits(product).saturating_sub(1).min(30);
+    ((((product as i64) << e) >> 15) as i16, e as i16)
+}
+
+/// C x 2^E, saturated when E > 0
+fn truncate(c: i16, e: i16) -> i16 {
+    if e > 0 {
+        match c {
+            1.. => 32767,
+            0 => 0,
+            _ => -32767,
+        }
+    } else if e < -15 {
+        0
+    } else {
+        ((c as i32) >> -e) as i16
+    }
+}
+
+/// C / 2^E through the ROM's power table, which tops out at $7FFF
+fn shift_right(c: i16, e: i16) -> i16 {
+    if e == 0 {
+        mul(c as i32, 0x7FFF) as i16
+    } else if e > 15 {
+        0
+    } else {
+        ((c as i32) >> e) as i16
+    }
+}
+
+/// 1 / (C x 2^E) as a normalized coefficient and exponent
+fn inverse(coefficient: i16, exponent: i16) -> (i16, i16) {
+    if coefficient == 0 {
+        return (0x7FFF, 0x002F);
+    }
+    let negative = coefficient < 0;
+    let mut c = (coefficient as i32).abs().min(32767);
+    let mut e = exponent as i32;
+    while c < 0x4000 {
+        c <<= 1;
+        e -= 1;
+    }
+    let result = if c == 0x4000 {
+        if negative {
+            e -= 1;
+            -0x4000
+        } else {
+            0x7FFF
+        }
+    } else {
+        let i = (1 << 29) / c;
+        if negative { -i } else { i }
+    };
+    (result as i16, (1 - e) as i16)
+}
+
+pub struct Dsp1 {
+    command: Option<Command>,
+    input: Vec<u8>,
+    input_size: usize,
+    output: Vec<u8>,
+    output_pos: usize,
+
+    /// Attitude matrices A, B and C
+    matrix: [[[i16; 3]; 3]; 3],
+
+    // Projection set up by Parameter
+    sin_aas: i16,
+    cos_aas: i16,
+    sin_azs: i16,
+    cos_azs: i16,
+    nx: i16,
+    ny: i16,
+    nz: i16,
+    gx: i16,
+    gy: i16,
+    gz: i16,
+    centre_x: i16,
+    centre_y: i16,
+    c_les: i16,
+    e_les: i16,
+    g_les: i16,
+    vplane_c: i16,
+    vplane_e: i16,
+    sec_azs_c1: i16,
+    sec_azs_e1: i16,
+    sec_azs_c2: i16,
+    sec_azs_e2: i16,
+    voffset: i16,
+    /// Line Raster reports next; it keeps going until a new command
+    raster_line: i16,
+}
+
+impl Dsp1 {
+    pub fn new() -> Self {
+        Self {
+            command: None,
+            input: Vec::with_capacity(14),
+            input_size: 0,
+            output: Vec::new(),
+            output_pos: 0,
+            matrix: [[[0; 3]; 3]; 3],
+            sin_aas: 0,
+            cos_aas: 0,
+            sin_azs: 0,
+            cos_azs: 0,
+            nx: 0,
+            ny: 0,
+            nz: 0,
+            gx: 0,
+            gy: 0,
+            gz: 0,
+            centre_x: 0,
+            centre_y: 0,
+            c_les: 0,
+            e_les: 0,
+            g_les: 0,
+            vplane_c: 0,
+            vplane_e: 0,
+            sec_azs_c1: 0,
+            sec_azs_e1: 0,
+            sec_azs_c2: 0,
+            sec_azs_e2: 0,
+            voffset: 0,
+            raster_line: 0,
+        }
+    }
+
+    fn start_command(&mut self, code: u8) {
+        self.output.clear();
+        self.output_pos = 0;
+        self.input.clear();
+        self.command = None;
+        if let Some((command, inputs)) = Command::decode(code) {
+            self.command = Some(command);
+            self.input_size = inputs * 2;
+        }
+    }
+
+    fn execute(&mut self, command: Command) {
+        let w: Vec<i16> = self.input.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        let result: Vec<i16> = match command {
+            Command::Multiply => vec![mul(w[0] as i32, w[1] as i32) as i16],
+            Command::MultiplyRound => vec![(mul(w[0] as i32, w[1] as i32) + 1) as i16],
+            Command::Inverse => {
+                let (c, e) = inverse(w[0], w[1]);
+                vec![c, e]
+            }
+            Command::Triangle => vec![
+                mul(w[1] as i32, sin(w[0]) as i32) as i16,
+                mul(w[1] as i32, cos(w[0]) as i32) as i16,
+            ],
+            Command::Radius => {
+                let size = (sum_of_squares(&w[..3]) << 1) as i32;
+                vec![size as i16, (size >> 16) as i16]
+            }
+            Command::Range => {
+                let r = w[3] as i64;
+                vec![((sum_of_squares(&w[..3]) - r * r) >> 15) as i16]
+            }
+            Command::Distance => {
+                let distance = (sum_of_squares(&w[..3]) as f64).sqrt();
+                vec![distance.min(32767.0) as i16]
+            }
+            Command::Rotate => {
+                let (a, x, y) = (w[0], w[1] as i32, w[2] as i32);
+                vec![
+                    (mul(y, sin(a) as i32) + mul(x, cos(a) as i32)) as i16,
+                    (mul(y, cos(a) as i32) - mul(x, sin(a) as i32)) as i16,
+                ]
+            }
+            Command::Polar => self.polar(&w),
+            Command::Parameter => self.parameter(&w),
+            Command::Raster => {
+                self.raster_line = w[0];
+                self.raster()
+            }
+            Command::Project => self.project(w[0], w[1], w[2]),
+            Command::Target => self.target(w[0], w[1]),
+            Command::Attitude(m) => {
+                self.attitude(m, &w);
+                Vec::new()
+            }
+            Command::Objective(m) => self.objective(m, &w),
+            Command::Subjective(m) => self.subjective(m, &w),
+            Command::Scalar(m) => {
+                let row = self.matrix[m][0];
+                let s = (0..3).map(|i| w[i] as i32 * row[i] as i32).sum::<i32>() >> 15;
+                vec![s as i16]
+            }
+            Command::Gyrate => self.gyrate(&w),
+            Command::MemoryTest => vec![0x0000],
+            // The data ROM is not part of the reimplementation
+            Command::MemoryDump => vec![0; 1024],
+            Command::MemorySize => vec![0x0100],
+        };
+        self.set_output(&result);
+    }
+
+    fn set_output(&mut self, words: &[i16]) {
+        self.output.clear();
+        self.output.extend(words.iter().flat_map(|w| w.to_le_bytes()));
+        self.output_pos = 0;
+    }
+
+    fn polar(&self, w: &[i16]) -> Vec<i16> {
+        let (az, ay, ax) = (w[0], w[1], w[2]);
+        let (mut x, mut y, mut z) = (w[3] as i32, w[4] as i32, w[5] as i32);
+
+        let x0 = x;
+        x = (mul(y, sin(az) as i32) + mul(x, cos(az) as i32)) as i16 as i32;
+        y = (mul(y, cos(az) as i32) - mul(x0, sin(az) as i32)) as i16 as i32;
+
+        let z0 = z;
+        z = (mul(x, sin(ay) as i32) + mul(z, cos(ay) as i32)) as i16 as i32;
+        x = (mul(x, cos(ay) as i32) - mul(z0, sin(ay) as i32)) as i16 as i32;
+
+        let y0 = y;
+        y = (mul(z, sin(ax) as i32) + mul(y, cos(ax) as i32)) as i16 as i32;
+        z = (mul(z, cos(ax) as i32) - mul(y0, sin(ax) as i32)) as i16 as i32;
+
+        vec![x as i16, y as i16, z as i16]
+    }
+
+    fn attitude(&mut self, index: usize, w: &[i16]) {
+        let s = (w[0] >> 1) as i32;
+        let (sin_az, cos_az) = (sin(w[1]) as i32, cos(w[1]) as i32);
+        let (sin_ay, cos_ay) = (sin(w[2]) as i32, cos(w[2]) as i32);
+        let (sin_ax, cos_ax) = (sin(w[3]) as i32, cos(w[3]) as i32);
+
+        let m = &mut self.matrix[index];
+        m[0][0] = mul(mul(s, cos_az), cos_ay) as i16;
+        m[0][1] = -mul(mul(s, sin_az), cos_ay) as i16;
+        m[0][2] = mul(s, sin_ay) as i16;
+        m[1][0] = (mul(mul(s, sin_az), cos_ax) + mul(mul(mul(s, cos_az), sin_ax), sin_ay)) as i16;
+        m[1][1] = (mul(mul(s, cos_az), cos_ax) - mul(mul(mul(s, sin_az), sin_ax), sin_ay)) as i16;
+        m[1][2] = -mul(mul(s, sin_ax), cos_ay) as i16;
+        m[2][0] = (mul(mul(s, sin_az), sin_ax) - mul(mul(mul(s, cos_az), cos_ax), sin_ay)) as i16;
+        m[2][1] = (mul(mul(s, cos_az), sin_ax) + mul(mul(mul(s, sin_az), cos_ax), sin_ay)) as i16;
+        m[2][2] = mul(mul(s, cos_ax), cos_ay) as i16;
+    }
+
+    /// Global to object coordinates
+    fn objective(&self, index: usize, w: &[i16]) -> Vec<i16> {
+        let m = &self.matrix[index];
+        (0..3)
+            .map(|row| (0..3).map(|i| mul(w[i] as i32, m[row][i] as i32)).sum::<i32>() as i16)
+            .collect()
+    }
+
+    /// Object to global coordinates
+    fn subjective(&self, index: usize, w: &[i16]) -> Vec<i16> {
+        let m = &self.matrix[index];
+        (0..3)
+            .map(|col| (0..3).map(|i| mul(w[i] as i32, m[i][col] as i32)).sum::<i32>() as i16)
+            .collect()
+    }
+
+    fn gyrate(&self, w: &[i16]) -> Vec<i16> {
+        let (zr, xr, yr) = (w[0], w[1], w[2]);
+        let (u, f, l) = (w[3] as i32, w[4] as i32, w[5] as i32);
+        let (sin_y, cos_y) = (sin(yr) as i32, cos(yr) as i32);
+        let (csec, esec) = inverse(cos(xr), 0);
+
+        // Rotation around Z
+        let (c, e) = normalize_double(u * cos_y - f * sin_y);
+        let mut e = esec - e;
+        let c = normalize(mul(c as i32, csec as i32) as i16, &mut e);
+        let zrr = zr.wrapping_add(truncate(c, e));
+
+        // Rotation around X
+        let xrr = xr.wrapping_add((mul(u, sin_y) + mul(f, cos_y)) as i16);
+
+        // Rotation around Y
+        let (c, e) = normalize_double(u * cos_y + f * sin_y);
+        let mut e = esec - e;
+        let csin = normalize(sin(xr), &mut e);
+        let ctan = mul(csec as i32, csin as i32);
+        let c = normalize(-mul(c as i32, ctan) as i16, &mut e);
+        let yrr = yr.wrapping_add(truncate(c, e)).wrapping_add(l as i16);
+
+        vec![zrr, xrr, yrr]
+    }
+
+    fn parameter(&mut self, w: &[i16]) -> Vec<i16> {
+        let (fx, fy, fz) = (w[0] as i32, w[1] as i32, w[2] as i32);
+        let (lfe, les, aas, azs) = (w[3] as i32, w[4], w[5], w[6]);
+
+        self.sin_aas = sin(aas);
+        self.cos_aas = cos(aas);
+        self.sin_azs = sin(azs);
+        self.cos_azs = cos(azs);
+
+        // Unit normal of the screen plane
+        self.nx = mul(self.sin_azs as i32, -(self.sin_aas as i32)) as i16;
+        self.ny = mul(self.sin_azs as i32, self.cos_aas as i32) as i16;
+        self.nz = mul(self.cos_azs as i32, 0x7FFF) as i16;
+
+        // Centre of projection, then the eye behind it
+        let mut centre_x = (fx + mul(lfe, self.nx as i32)) as i16;
+        let mut centre_y = (fy + mul(lfe, self.ny as i32)) as i16;
+        let centre_z = (fz + mul(lfe, self.nz as i32)) as i16;
+        let les32 = les as i32;
+        self.gx = centre_x.wrapping_sub(mul(les32, self.nx as i32) as i16);
+        self.gy = centre_y.wrapping_sub(mul(les32, self.ny as i32) as i16);
+        self.gz = centre_z.wrapping_sub(mul(les32, self.nz as i32) as i16);
+
+        self.e_les = 0;
+        self.c_les = normalize(les, &mut self.e_les);
+        self.g_les = les;
+
+        let mut e = 0;
+        let mut c = normalize(centre_z, &mut e);
+        self.vplane_c = c;
+        self.vplane_e = e;
+
+        // Clip the zenith angle so the horizon stays on screen
+        let max_azs = MAX_AZS_EXP[(-e).clamp(0, 15) as usize];
+        let clipped_azs = if azs < 0 { azs.max(-max_azs + 1) } else { azs.min(max_azs) };
+        let sin_azs = sin(clipped_azs);
+        let cos_azs = cos(clipped_azs);
+
+        let (sec_c1, sec_e1) = inverse(cos_azs, 0);
+        self.sec_azs_c1 = sec_c1;
+        self.sec_azs_e1 = sec_e1;
+        c = normalize(mul(c as i32, sec_c1 as i32) as i16, &mut e);
+        e += sec_e1;
+        let c = mul(truncate(c, e) as i32, sin_azs as i32);
+        centre_x = centre_x.wrapping_add(mul(c, self.sin_aas as i32) as i16);
+        centre_y = centre_y.wrapping_sub(mul(c, self.cos_aas as i32) as i16);
+        self.centre_x = centre_x;
+        self.centre_y = centre_y;
+
+        let vof = 0;
+        self.voffset = mul(les32, cos_azs as i32) as i16;
+
+        let (csec, mut e) = inverse(sin_azs, 0);
+        let c = normalize(self.voffset, &mut e);
+        let mut c = normalize(mul(c as i32, csec as i32) as i16, &mut e);
+        if c == -32768 {
+            c >>= 1;
+            e += 1;
+        }
+        let vva = truncate(c.wrapping_neg(), e);
+
+        let (sec_c2, sec_e2) = inverse(cos_azs, 0);
+        self.sec_azs_c2 = sec_c2;
+        self.sec_azs_e2 = sec_e2;
+
+        vec![vof, vva, centre_x, centre_y]
+    }
+
+    /// Mode 7 matrix for one line of the ground plane
+    fn raster(&self) -> Vec<i16> {
+        let vs = self.raster_line as i32;
+        let (c, mut e) = inverse((mul(vs, self.sin_azs as i32) + self.voffset as i32) as i16, 7);
+        e += self.vplane_e;
+        let c1 = mul(c as i32, self.vplane_c as i32) as i16;
+        let mut e1 = e + self.sec_azs_e2;
+
+        let c = normalize(c1, &mut e);
+        let c = truncate(c, e) as i32;
+        let an = mul(c, self.cos_aas as i32) as i16;
+        let cn = mul(c, self.sin_aas as i32) as i16;
+
+        let c = normalize(mul(c1 as i32, self.sec_azs_c2 as i32) as i16, &mut e1);
+        let c = truncate(c, e1) as i32;
+        let bn = mul(c, -(self.sin_aas as i32)) as i16;
+        let dn = mul(c, self.cos_aas as i32) as i16;
+        vec![an, bn, cn, dn]
+    }
+
+    /// Screen position and scale of a point in the world
+    fn project(&self, x: i16, y: i16, z: i16) -> Vec<i16> {
+        let (px, mut e4) = normalize_double(x as i32 - self.gx as i32);
+        let (py, mut e) = normalize_double(y as i32 - self.gy as i32);
+        let (pz, mut e3) = normalize_double(z as i32 - self.gz as i32);
+        // Halve to keep the scalar products in range
+        let (px, py, pz) = (px >> 1, py >> 1, pz >> 1);
+        e4 -= 1;
+        e -= 1;
+        e3 -= 1;
+
+        let ref_e = e.min(e3).min(e4);
+        let px = shift_right(px, e4 - ref_e) as i32;
+        let py = shift_right(py, e - ref_e) as i32;
+        let pz = shift_right(pz, e3 - ref_e) as i32;
+
+        let c11 = -mul(px, self.nx as i32);
+        let c8 = -mul(py, self.ny as i32);
+        let c9 = -mul(pz, self.nz as i32);
+        let c12 = (c11 + c8 + c9) as i16;
+
+        // Back to 32-bit for the distance from the screen plane
+        let shift = 16 - ref_e;
+        let mut aux4 = c12 as i32;
+        aux4 = if shift >= 0 { aux4 << shift } else { aux4 >> -shift };
+        if aux4 == -1 {
+            aux4 = 0;
+        }
+        aux4 >>= 1;
+
+        let aux = self.g_les as u16 as i32 + aux4;
+        let (c10, e2) = normalize_double(aux);
+        let e2 = 15 - e2;
+
+        let (c4, mut e4) = inverse(c10, 0);
+        let c2 = mul(c4 as i32, self.c_les as i32);
+
+        // H: along the horizontal screen vector
+        let c16 = mul(px, mul(self.cos_aas as i32, 0x7FFF));
+        let c20 = mul(py, mul(self.sin_aas as i32, 0x7FFF));
+        let c18 = mul((c16 + c20) as i16 as i32, c2) as i16;
+        let mut e7 = 0;
+        let c19 = normalize(c18, &mut e7);
+        let h = truncate(c19, self.e_les - e2 + ref_e + e7);
+
+        // V: along the vertical screen vector
+        let c21 = mul(px, mul(self.cos_azs as i32, -(self.sin_aas as i32)));
+        let c22 = mul(py, mul(self.cos_azs as i32, self.cos_aas as i32));
+        let c23 = mul(pz, mul(-(self.sin_azs as i32), 0x7FFF));
+        let c26 = mul((c21 + c22 + c23) as i16 as i32, c2) as i16;
+        let mut e6 = 0;
+        let c25 = normalize(c26, &mut e6);
+        let v = truncate(c25, self.e_les - e2 + ref_e + e6);
+
+        // M: the scale factor over 2^7
+        let c6 = normalize(c2 as i16, &mut e4);
+        let m = truncate(c6, e4 + self.e_les - e2 - 7);
+
+        vec![h, v, m]
+    }
+
+    /// Ground position under a screen position
+    fn target(&self, h: i16, v: i16) -> Vec<i16> {
+        let (c, mut e) = inverse((mul(v as i32, self.sin_azs as i32) + self.voffset as i32) as i16, 8);
+        e += self.vplane_e;
+        let c1 = mul(c as i32, self.vplane_c as i32) as i16;
+        let mut e1 = e + self.sec_azs_e1;
+
+        let h = (h as i32) << 8;
+        let c = normalize(c1, &mut e);
+        let c = mul(truncate(c, e) as i32, h) as i16 as i32;
+        let mut x = self.centre_x.wrapping_add(mul(c, self.cos_aas as i32) as i16);
+        let mut y = self.centre_y.wrapping_sub(mul(c, self.sin_aas as i32) as i16);
+
+        let v = (v as i32) << 8;
+        let c = normalize(mul(c1 as i32, self.sec_azs_c1 as i32) as i16, &mut e1);
+        let c = mul(truncate(c, e1) as i32, v) as i16 as i32;
+        x = x.wrapping_add(mul(c, -(self.sin_aas as i32)) as i16);
+        y = y.wrapping_add(mul(c, self.cos_aas as i32) as i16);
+        vec![x, y]
+    }
+}
+
+fn sum_of_squares(w: &[i16]) -> i64 {
+    w.iter().map(|&v| v as i64 * v as i64).sum()
+}
+
+impl DspInterface for Dsp1 {
+    fn read_status(&mut self) -> u8 {
+        STATUS_READY
+    }
+
+    fn read_data(&mut self) -> u8 {
+        if self.output_pos >= self.output.len() {
+            return 0x80;
+        }
+        let value = self.output[self.output_pos];
+        self.output_pos += 1;
+        if self.output_pos == self.output.len() && self.command == Some(Command::Raster) {
+            // Raster moves on to the next line by itself
+            self.raster_line = self.raster_line.wrapping_add(1);
+            let words = self.raster();
+            self.set_output(&words);
+        }
+        value
+    }
+
+    fn write_data(&mut self, value: u8) {
+        match self.command {
+            Some(command) if command != Command::Raster || self.output.is_empty() => {
+                self.input.push(value);
+                if self.input.len() == self.input_size {
+                    self.input_size = 0;
+                    self.execute(command);
+                    self.input.clear();
+                    if command != Command::Raster {
+                        self.command = None;
+                    }
+                }
+            }
+            _ => self.start_command(value),
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for Dsp1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
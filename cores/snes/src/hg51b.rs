@@ -0,0 +1,808 @@
+/// Hitachi HG51B169 (Cx4)
+/// 24-bit DSP on the Mega Man X2/X3 boards. Code is fetched from ROM into
+/// two 256-word cache pages, constants come from a 1024-word data ROM inside
+/// the chip (user-supplied dump) and work is done in 3KB of data RAM that
+/// the 5A22 shares at $6000-$6BFF. Registers live at $7F40-$7FAF; while the
+/// chip fills its cache or runs DMA it holds the ROM bus and the 5A22 only
+/// sees the vectors at $7F60-$7F7F.
+
+use anyhow::{bail, Result};
+
+use crate::apu::{NTSC_MASTER_CLOCK, PAL_MASTER_CLOCK};
+use crate::cartridge::{mirror, SnesCartridge};
+
+pub const FIRMWARE_NAME: &str = "cx4.rom";
+
+const FREQUENCY: i64 = 20_000_000;
+const DATA_ROM_WORDS: usize = 1024;
+const DATA_RAM_SIZE: usize = 0xC00;
+
+/// A in ALU operations is shifted by one of these
+const SHIFTS: [u32; 4] = [0, 1, 8, 16];
+
+/// Cycles a SUSPEND write at $7F55-$7F5C lasts; zero waits for $7F5D
+const SUSPEND_DURATIONS: [u32; 8] = [0, 32, 64, 96, 128, 160, 192, 224];
+
+/// What the 5A22 sees of a Cx4, either the LLE core or the HLE one
+pub trait Cx4Interface {
+    /// $6000-$7FFF of banks $00-$3F/$80-$BF; `None` where nothing answers
+    fn read_io(&mut self, addr: u16) -> Option<u8>;
+    fn write_io(&mut self, cart: &SnesCartridge, addr: u16, value: u8);
+    fn reset(&mut self);
+
+    /// Catch up with `master_cycles` of 5A22 time
+    fn run(&mut self, _master_cycles: u64, _pal: bool, _cart: &mut SnesCartridge) {}
+
+    /// The 5A22 sees `vector` instead of ROM
+    fn owns_rom(&self) -> bool {
+        false
+    }
+
+    /// Replacement for ROM reads while `owns_rom`; open bus outside the vectors
+    fn vector(&self, _addr: u32) -> Option<u8> {
+        None
+    }
+
+    fn irq(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Default)]
+struct Dma {
+    enable: bool,
+    source: u32,
+    length: u16,
+    target: u32,
+}
+
+#[derive(Default)]
+struct Cache {
+    /// A $7F48 write asked for a page load
+    enable: bool,
+    page: usize,
+    lock: [bool; 2],
+    base: u32,
+    /// ROM address each page was loaded from
+    address: [Option<u32>; 2],
+    /// Start address for the next $7F4F
+    pb: u16,
+    pc: u8,
+}
+
+/// Access started through registers $2E/$2F, finished by WAIT or time
+#[derive(Default)]
+struct BusAccess {
+    reading: bool,
+    /// Cycles until it completes; 0 when idle
+    pending: u32,
+    address: u32,
+}
+
+pub struct Hg51b {
+    data_rom: Vec<u32>,
+    data_ram: Vec<u8>,
+    program_ram: [[u16; 256]; 2],
+
+    pb: u16,
+    pc: u8,
+    /// Page register, copied to PB by far jumps
+    p: u16,
+    a: u32,
+    mdr: u32,
+    rom: u32,
+    ram: u32,
+    mar: u32,
+    dpr: u32,
+    /// 48-bit product
+    mul: u64,
+    gpr: [u32; 16],
+    stack: [u32; 8],
+    n: bool,
+    z: bool,
+    c: bool,
+    v: bool,
+    /// IRQ to the 5A22, raised on halt unless masked by $7F51
+    i: bool,
+
+    halt: bool,
+    /// Stuck after an impossible DMA until $7F53
+    lock: bool,
+    suspend: bool,
+    suspend_duration: u32,
+    wait_rom: u32,
+    wait_ram: u32,
+    irq_disable: bool,
+    rom_select: u8,
+    dma: Dma,
+    cache: Cache,
+    bus: BusAccess,
+    vectors: [u8; 32],
+
+    /// Chip cycles spent by the current step
+    clocks: u32,
+    pending: i64,
+}
+
+impl Hg51b {
+    pub fn new(firmware: &[u8]) -> Result<Self> {
+        if firmware.len() != DATA_ROM_WORDS * 3 {
+            bail!("Cx4 data ROM must be {} bytes, got {}", DATA_ROM_WORDS * 3, firmware.len());
+        }
+        let data_rom = firmware
+            .chunks_exact(3)
+            .map(|word| word[0] as u32 | (word[1] as u32) << 8 | (word[2] as u32) << 16)
+            .collect();
+        let mut cx4 = Self {
+            data_rom,
+            data_ram: vec![0; DATA_RAM_SIZE],
+            program_ram: [[0; 256]; 2],
+            pb: 0,
+            pc: 0,
+            p: 0,
+            a: 0,
+            mdr: 0,
+            rom: 0,
+            ram: 0,
+            mar: 0,
+            dpr: 0,
+            mul: 0,
+            gpr: [0; 16],
+            stack: [0; 8],
+            n: false,
+            z: false,
+            c: false,
+            v: false,
+            i: false,
+            halt: true,
+            lock: false,
+            suspend: false,
+            suspend_duration: 0,
+            wait_rom: 3,
+            wait_ram: 3,
+            irq_disable: false,
+            rom_select: 0,
+            dma: Dma::default(),
+            cache: Cache::default(),
+            bus: BusAccess::default(),
+            vectors: [0; 32],
+            clocks: 0,
+            pending: 0,
+        };
+        cx4.reset();
+        Ok(cx4)
+    }
+
+    fn running(&self) -> bool {
+        self.busy() || !self.halt
+    }
+
+    fn busy(&self) -> bool {
+        self.cache.enable || self.dma.enable || self.bus.pending > 0
+    }
+
+    fn main(&mut self, cart: &mut SnesCartridge) {
+        if self.lock {
+            return self.step(1, cart);
+        }
+        if self.suspend {
+            return self.resume(cart);
+        }
+        if self.cache.enable {
+            self.load_cache(cart);
+            return;
+        }
+        if self.dma.enable {
+            return self.run_dma(cart);
+        }
+        if self.halt {
+            return self.step(1, cart);
+        }
+        self.execute(cart);
+    }
+
+    fn execute(&mut self, cart: &mut SnesCartridge) {
+        if !self.load_cache(cart) {
+            return self.stop();
+        }
+        let opcode = self.program_ram[self.cache.page][self.pc as usize];
+        self.advance(cart);
+        self.step(1, cart);
+        self.decode(opcode, cart);
+    }
+
+    /// Next instruction; running off a page continues in the second one
+    fn advance(&mut self, cart: &mut SnesCartridge) {
+        self.pc = self.pc.wrapping_add(1);
+        if self.pc != 0 {
+            return;
+        }
+        if self.cache.page == 1 || self.cache.lock[1] {
+            return self.stop();
+        }
+        self.cache.page = 1;
+        self.pb = self.p;
+        if !self.load_cache(cart) {
+            self.stop();
+        }
+    }
+
+    /// Make sure the page for PB is cached; false when both pages are locked
+    fn load_cache(&mut self, cart: &mut SnesCartridge) -> bool {
+        let address = self.cache.base.wrapping_add(self.pb as u32 * 512) & 0xFFFFFF;
+        self.cache.enable = false;
+        if self.cache.address[self.cache.page] == Some(address) {
+            return true;
+        }
+        self.cache.page ^= 1;
+        if self.cache.address[self.cache.page] == Some(address) {
+            return true;
+        }
+        if self.cache.lock[self.cache.page] {
+            self.cache.page ^= 1;
+        }
+        if self.cache.lock[self.cache.page] {
+            self.halt = true;
+            return false;
+        }
+
+        self.cache.address[self.cache.page] = Some(address);
+        for offset in 0..256 {
+            let address = address + offset as u32 * 2;
+            self.step(self.wait(address), cart);
+            let lo = self.read(cart, address) as u16;
+            let hi = self.read(cart, address + 1) as u16;
+            self.program_ram[self.cache.page][offset] = lo | hi << 8;
+        }
+        true
+    }
+
+    fn run_dma(&mut self, cart: &mut SnesCartridge) {
+        for offset in 0..self.dma.length as u32 {
+            let source = (self.dma.source + offset) & 0xFFFFFF;
+            let target = (self.dma.target + offset) & 0xFFFFFF;
+            if (is_rom(source) && is_rom(target)) || (is_ram(source) && is_ram(target)) {
+                self.dma.enable = false;
+                self.lock = true;
+                return;
+            }
+            self.step(self.wait(source), cart);
+            let value = self.read(cart, source);
+            self.step(self.wait(target), cart);
+            self.write(cart, target, value);
+        }
+        self.dma.enable = false;
+    }
+
+    fn resume(&mut self, cart: &mut SnesCartridge) {
+        if self.suspend_duration == 0 {
+            return self.step(1, cart);
+        }
+        self.step(self.suspend_duration, cart);
+        self.suspend_duration = 0;
+        self.suspend = false;
+    }
+
+    fn stop(&mut self) {
+        self.halt = true;
+        if !self.irq_disable {
+            self.i = true;
+        }
+    }
+
+    /// Spend chip cycles, finishing a register-started bus access on time
+    fn step(&mut self, clocks: u32, cart: &mut SnesCartridge) {
+        self.clocks += clocks;
+        if self.bus.pending == 0 {
+            return;
+        }
+        if self.bus.pending > clocks {
+            self.bus.pending -= clocks;
+            return;
+        }
+        self.bus.pending = 0;
+        if self.bus.reading {
+            self.mdr = self.read(cart, self.bus.address) as u32;
+        } else {
+            self.write(cart, self.bus.address, self.mdr as u8);
+        }
+    }
+
+    // --- Chip bus ---
+
+    fn wait(&self, addr: u32) -> u32 {
+        if is_rom(addr) {
+            1 + self.wait_rom
+        } else if is_ram(addr) {
+            1 + self.wait_ram
+        } else {
+            1
+        }
+    }
+
+    fn read(&self, cart: &SnesCartridge, addr: u32) -> u8 {
+        if is_rom(addr) {
+            if cart.rom.is_empty() {
+                return 0;
+            }
+            let offset = ((addr & 0x7F0000) >> 1 | (addr & 0x7FFF)) as usize;
+            return cart.rom[mirror(offset, cart.rom.len())];
+        }
+        if is_ram(addr) {
+            return cart.sram_offset(addr).map_or(0, |offset| cart.sram[offset]);
+        }
+        if addr & 0x40E000 == 0x006000 {
+            return self.data_ram.get(addr as usize & 0x0FFF).copied().unwrap_or(0);
+        }
+        0
+    }
+
+    fn write(&mut self, cart: &mut SnesCartridge, addr: u32, value: u8) {
+        if is_ram(addr) {
+            if let Some(offset) = cart.sram_offset(addr) {
+                cart.sram[offset] = value;
+            }
+        } else if addr & 0x40E000 == 0x006000 {
+            if let Some(byte) = self.data_ram.get_mut(addr as usize & 0x0FFF) {
+                *byte = value;
+            }
+        }
+    }
+
+    fn start_bus(&mut self, reading: bool, wait: u32) {
+        self.bus = BusAccess { reading, pending: 1 + wait, address: self.mar };
+    }
+
+    // --- Registers ---
+
+    fn read_register(&mut self, reg: u8) -> u32 {
+        match reg {
+            0x01 => (self.mul >> 24) as u32 & 0xFFFFFF,
+            0x02 => self.mul as u32 & 0xFFFFFF,
+            0x03 => self.mdr,
+            0x08 => self.rom,
+            0x0C => self.ram,
+            0x13 => self.mar,
+            0x1C => self.dpr,
+            0x20 => self.pc as u32,
+            0x28 => self.p as u32,
+            0x2E => {
+                self.start_bus(true, self.wait_rom);
+                0
+            }
+            0x2F => {
+                self.start_bus(true, self.wait_ram);
+                0
+            }
+            // Constants
+            0x50 => 0x000000,
+            0x51 => 0xFFFFFF,
+            0x52 => 0x00FF00,
+            0x53 => 0xFF0000,
+            0x54 => 0x00FFFF,
+            0x55 => 0xFFFF00,
+            0x56 => 0x800000,
+            0x57 => 0x7FFFFF,
+            0x58 => 0x008000,
+            0x59 => 0x007FFF,
+            0x5A => 0xFF7FFF,
+            0x5B => 0xFFFF7F,
+            0x5C => 0x010000,
+            0x5D => 0xFEFFFF,
+            0x5E => 0x000100,
+            0x5F => 0x00FEFF,
+            0x60..=0x7F => self.gpr[reg as usize & 0x0F],
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, reg: u8, value: u32) {
+        let value = value & 0xFFFFFF;
+        match reg {
+            0x01 => self.mul = (self.mul & 0xFFFFFF) | (value as u64) << 24,
+            0x02 => self.mul = (self.mul & !0xFFFFFF) | value as u64,
+            0x03 => self.mdr = value,
+            0x08 => self.rom = value,
+            0x0C => self.ram = value,
+            0x13 => self.mar = value,
+            0x1C => self.dpr = value,
+            0x20 => self.pc = value as u8,
+            0x28 => self.p = value as u16 & 0x7FFF,
+            0x2E => self.start_bus(false, self.wait_rom),
+            0x2F => self.start_bus(false, self.wait_ram),
+            0x60..=0x7F => self.gpr[reg as usize & 0x0F] = value,
+            _ => {}
+        }
+    }
+
+    // --- Instructions ---
+
+    fn decode(&mut self, opcode: u16, cart: &mut SnesCartridge) {
+        let reg = (opcode & 0x7F) as u8;
+        let imm = opcode as u8 as u32;
+        let sub = (opcode >> 8) as usize & 0x03;
+        let shift = SHIFTS[sub];
+        let far = opcode & 0x0200 != 0;
+        match opcode >> 10 {
+            0x02 => self.jump(imm, far, true, false, cart),
+            0x03 => self.jump(imm, far, self.z, false, cart),
+            0x04 => self.jump(imm, far, self.c, false, cart),
+            0x05 => self.jump(imm, far, self.n, false, cart),
+            0x06 => self.jump(imm, far, self.v, false, cart),
+            // WAIT
+            0x07 if self.bus.pending > 0 => self.step(self.bus.pending, cart),
+            // SKIP V/C/Z/N
+            0x09 => {
+                let flag = [self.v, self.c, self.z, self.n][sub];
+                if flag == (opcode & 0x01 != 0) {
+                    self.advance(cart);
+                    self.step(1, cart);
+                }
+            }
+            0x0A => self.jump(imm, far, true, true, cart),
+            0x0B => self.jump(imm, far, self.z, true, cart),
+            0x0C => self.jump(imm, far, self.c, true, cart),
+            0x0D => self.jump(imm, far, self.n, true, cart),
+            0x0E => self.jump(imm, far, self.v, true, cart),
+            // RTS
+            0x0F => {
+                let top = self.stack[0];
+                self.stack.copy_within(1.., 0);
+                self.stack[7] = 0;
+                self.pb = (top >> 8) as u16 & 0x7FFF;
+                self.pc = top as u8;
+                self.step(2, cart);
+            }
+            0x10 => self.mar = (self.mar + 1) & 0xFFFFFF,
+            // CMPR
+            0x12 => {
+                let value = self.read_register(reg);
+                self.sub(value, self.a << shift);
+            }
+            0x13 => {
+                self.sub(imm, self.a << shift);
+            }
+            // CMP
+            0x14 => {
+                let value = self.read_register(reg);
+                self.sub(self.a << shift, value);
+            }
+            0x15 => {
+                self.sub(self.a << shift, imm);
+            }
+            // SXB, SXW
+            0x16 if sub == 1 => self.a = self.logic(self.a as u8 as i8 as u32),
+            0x16 if sub == 2 => self.a = self.logic(self.a as u16 as i16 as u32),
+            0x18 => {
+                let value = self.read_register(reg);
+                self.load(sub, value);
+            }
+            0x19 => self.load(sub, imm),
+            // RDRAM byte,[A] / [DPR+imm]
+            0x1A => self.read_ram(sub, self.a),
+            0x1B => self.read_ram(sub, self.dpr + imm),
+            // RDROM [A] / [imm]
+            0x1C => self.rom = self.data_rom[self.a as usize & (DATA_ROM_WORDS - 1)],
+            0x1D => self.rom = self.data_rom[opcode as usize & (DATA_ROM_WORDS - 1)],
+            // LD P.L / P.H
+            0x1F if sub == 0 => self.p = (self.p & 0x7F00) | imm as u16,
+            0x1F if sub == 1 => self.p = (self.p & 0x00FF) | (imm as u16 & 0x7F) << 8,
+            0x20 => {
+                let value = self.read_register(reg);
+                self.a = self.add(self.a << shift, value);
+            }
+            0x21 => self.a = self.add(self.a << shift, imm),
+            // SUBR
+            0x22 => {
+                let value = self.read_register(reg);
+                self.a = self.sub(value, self.a << shift);
+            }
+            0x23 => self.a = self.sub(imm, self.a << shift),
+            0x24 => {
+                let value = self.read_register(reg);
+                self.a = self.sub(self.a << shift, value);
+            }
+            0x25 => self.a = self.sub(self.a << shift, imm),
+            0x26 => {
+                let value = self.read_register(reg);
+                self.multiply(value);
+            }
+            0x27 => self.multiply(imm),
+            0x28 => {
+                let value = self.read_register(reg);
+                self.a = self.logic(!(self.a << shift) ^ value);
+            }
+            0x29 => self.a = self.logic(!(self.a << shift) ^ imm),
+            0x2A => {
+                let value = self.read_register(reg);
+                self.a = self.logic((self.a << shift) ^ value);
+            }
+            0x2B => self.a = self.logic((self.a << shift) ^ imm),
+            0x2C => {
+                let value = self.read_register(reg);
+                self.a = self.logic((self.a << shift) & value);
+            }
+            0x2D => self.a = self.logic((self.a << shift) & imm),
+            0x2E => {
+                let value = self.read_register(reg);
+                self.a = self.logic((self.a << shift) | value);
+            }
+            0x2F => self.a = self.logic((self.a << shift) | imm),
+            0x30..=0x37 => {
+                let amount = if opcode & 0x0400 != 0 { imm & 0x1F } else { self.read_register(reg) };
+                // Shifts past 24 bits do nothing
+                let amount = if amount > 24 { 0 } else { amount };
+                let a = self.a;
+                let result = match (opcode >> 11) & 0x03 {
+                    0 => a >> amount,
+                    1 => ((((a << 8) as i32) >> 8) >> amount) as u32,
+                    2 => (a >> amount) | a.checked_shl(24 - amount).unwrap_or(0),
+                    _ => a << amount,
+                };
+                self.a = self.logic(result);
+            }
+            // ST reg,A / reg,MDR
+            0x38 if sub == 0 => self.write_register(reg, self.a),
+            0x38 if sub == 1 => self.write_register(reg, self.mdr),
+            // WRRAM byte,[A] / [DPR+imm]
+            0x3A => self.write_ram(sub, self.a),
+            0x3B => self.write_ram(sub, self.dpr + imm),
+            0x3C => std::mem::swap(&mut self.a, &mut self.gpr[opcode as usize & 0x0F]),
+            // CLEAR
+            0x3E => {
+                self.a = 0;
+                self.p = 0;
+                self.ram = 0;
+                self.dpr = 0;
+            }
+            0x3F => self.stop(),
+            _ => {}
+        }
+    }
+
+    /// JMP/JSR to `target` in the current page, or in page P when `far`
+    fn jump(&mut self, target: u32, far: bool, take: bool, call: bool, cart: &mut SnesCartridge) {
+        if !take {
+            return;
+        }
+        if call {
+            self.stack.copy_within(..7, 1);
+            self.stack[0] = (self.pb as u32) << 8 | self.pc as u32;
+        }
+        if far {
+            self.pb = self.p;
+        }
+        self.pc = target as u8;
+        self.step(2, cart);
+    }
+
+    fn load(&mut self, target: usize, value: u32) {
+        match target {
+            0 => self.a = value,
+            1 => self.mdr = value,
+            2 => self.mar = value,
+            _ => self.p = value as u16 & 0x7FFF,
+        }
+    }
+
+    /// Data RAM address; $C00-$FFF wraps back into the last kilobyte
+    fn ram_address(addr: u32) -> usize {
+        let addr = addr as usize & 0x0FFF;
+        if addr >= DATA_RAM_SIZE { addr - 0x400 } else { addr }
+    }
+
+    fn read_ram(&mut self, byte: usize, addr: u32) {
+        let value = self.data_ram[Self::ram_address(addr)] as u32;
+        let shift = byte * 8;
+        self.ram = ((self.ram & !(0xFF << shift)) | value << shift) & 0xFFFFFF;
+    }
+
+    fn write_ram(&mut self, byte: usize, addr: u32) {
+        self.data_ram[Self::ram_address(addr)] = (self.ram >> (byte * 8)) as u8;
+    }
+
+    fn add(&mut self, x: u32, y: u32) -> u32 {
+        let (x, y) = (x & 0xFFFFFF, y & 0xFFFFFF);
+        let result = x + y;
+        self.c = result > 0xFFFFFF;
+        self.v = !(x ^ y) & (x ^ result) & 0x800000 != 0;
+        self.logic(result)
+    }
+
+    fn sub(&mut self, x: u32, y: u32) -> u32 {
+        let (x, y) = (x & 0xFFFFFF, y & 0xFFFFFF);
+        let result = x.wrapping_sub(y);
+        self.c = x >= y;
+        self.v = (x ^ y) & (x ^ result) & 0x800000 != 0;
+        self.logic(result)
+    }
+
+    fn multiply(&mut self, value: u32) {
+        let x = ((self.a << 8) as i32 >> 8) as i64;
+        let y = ((value << 8) as i32 >> 8) as i64;
+        self.mul = (x * y) as u64 & 0xFFFF_FFFF_FFFF;
+    }
+
+    /// N and Z of a 24-bit result
+    fn logic(&mut self, result: u32) -> u32 {
+        let result = result & 0xFFFFFF;
+        self.n = result & 0x800000 != 0;
+        self.z = result == 0;
+        result
+    }
+}
+
+impl Cx4Interface for Hg51b {
+    fn read_io(&mut self, addr: u16) -> Option<u8> {
+        if addr < 0x7C00 {
+            let offset = addr as usize & 0x0FFF;
+            return self.data_ram.get(offset).copied();
+        }
+        let addr = 0x7C00 | (addr & 0x03FF);
+        let value = match addr {
+            0x7F40..=0x7F42 => (self.dma.source >> ((addr - 0x7F40) * 8)) as u8,
+            0x7F43..=0x7F44 => (self.dma.length >> ((addr - 0x7F43) * 8)) as u8,
+            0x7F45..=0x7F47 => (self.dma.target >> ((addr - 0x7F45) * 8)) as u8,
+            0x7F48 => self.cache.page as u8,
+            0x7F49..=0x7F4B => (self.cache.base >> ((addr - 0x7F49) * 8)) as u8,
+            0x7F4C => self.cache.lock[0] as u8 | (self.cache.lock[1] as u8) << 1,
+            0x7F4D..=0x7F4E => (self.cache.pb >> ((addr - 0x7F4D) * 8)) as u8,
+            0x7F4F => self.cache.pc,
+            0x7F50 => self.wait_ram as u8 | (self.wait_rom as u8) << 4,
+            0x7F51 => self.irq_disable as u8,
+            0x7F52 => self.rom_select,
+            0x7F53..=0x7F57 | 0x7F59 | 0x7F5B..=0x7F5F => {
+                self.suspend as u8
+                    | (self.i as u8) << 1
+                    | (self.running() as u8) << 6
+                    | (self.busy() as u8) << 7
+            }
+            0x7F60..=0x7F7F => self.vectors[addr as usize & 0x1F],
+            0x7F80..=0x7FAF => {
+                let index = (addr - 0x7F80) as usize;
+                (self.gpr[index / 3] >> ((index % 3) * 8)) as u8
+            }
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn write_io(&mut self, _cart: &SnesCartridge, addr: u16, value: u8) {
+        if addr < 0x7C00 {
+            if let Some(byte) = self.data_ram.get_mut(addr as usize & 0x0FFF) {
+                *byte = value;
+            }
+            return;
+        }
+        let addr = 0x7C00 | (addr & 0x03FF);
+        let set_byte = |word: &mut u32, shift: u16| {
+            *word = (*word & !(0xFF << shift)) | (value as u32) << shift;
+        };
+        match addr {
+            0x7F40..=0x7F42 => set_byte(&mut self.dma.source, (addr - 0x7F40) * 8),
+            0x7F43..=0x7F44 => {
+                let shift = (addr - 0x7F43) * 8;
+                self.dma.length = (self.dma.length & !(0xFF << shift)) | (value as u16) << shift;
+            }
+            0x7F45..=0x7F47 => {
+                set_byte(&mut self.dma.target, (addr - 0x7F45) * 8);
+                if addr == 0x7F47 && self.halt {
+                    self.dma.enable = true;
+                }
+            }
+            0x7F48 => {
+                self.cache.page = value as usize & 0x01;
+                if self.halt {
+                    self.cache.enable = true;
+                }
+            }
+            0x7F49..=0x7F4B => set_byte(&mut self.cache.base, (addr - 0x7F49) * 8),
+            0x7F4C => self.cache.lock = [value & 0x01 != 0, value & 0x02 != 0],
+            0x7F4D => self.cache.pb = (self.cache.pb & 0x7F00) | value as u16,
+            0x7F4E => self.cache.pb = (self.cache.pb & 0x00FF) | (value as u16 & 0x7F) << 8,
+            0x7F4F => {
+                self.cache.pc = value;
+                if self.halt {
+                    self.halt = false;
+                    self.pb = self.cache.pb;
+                    self.pc = self.cache.pc;
+                }
+            }
+            0x7F50 => {
+                self.wait_ram = value as u32 & 0x07;
+                self.wait_rom = (value as u32 >> 4) & 0x07;
+            }
+            0x7F51 => self.irq_disable = value & 0x01 != 0,
+            0x7F52 => self.rom_select = value & 0x01,
+            0x7F53 => {
+                self.lock = false;
+                self.halt = true;
+            }
+            0x7F55..=0x7F5C => {
+                self.suspend = true;
+                self.suspend_duration = SUSPEND_DURATIONS[(addr - 0x7F55) as usize];
+            }
+            0x7F5D => self.suspend = false,
+            0x7F5E => self.i = false,
+            0x7F60..=0x7F7F => self.vectors[addr as usize & 0x1F] = value,
+            0x7F80..=0x7FAF => {
+                let index = (addr - 0x7F80) as usize;
+                set_byte(&mut self.gpr[index / 3], (index % 3) as u16 * 8);
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.data_ram.fill(0);
+        self.pb = 0;
+        self.pc = 0;
+        self.p = 0;
+        self.a = 0;
+        self.mdr = 0;
+        self.rom = 0;
+        self.ram = 0;
+        self.mar = 0;
+        self.dpr = 0;
+        self.mul = 0;
+        self.gpr = [0; 16];
+        self.stack = [0; 8];
+        self.n = false;
+        self.z = false;
+        self.c = false;
+        self.v = false;
+        self.i = false;
+        self.halt = true;
+        self.lock = false;
+        self.suspend = false;
+        self.suspend_duration = 0;
+        self.wait_rom = 3;
+        self.wait_ram = 3;
+        self.irq_disable = false;
+        self.rom_select = 0;
+        self.dma = Dma::default();
+        self.cache = Cache::default();
+        self.bus = BusAccess::default();
+        self.vectors = [0; 32];
+        self.pending = 0;
+    }
+
+    fn run(&mut self, master_cycles: u64, pal: bool, cart: &mut SnesCartridge) {
+        if !self.running() {
+            self.pending = 0;
+            return;
+        }
+        let master_clock = if pal { PAL_MASTER_CLOCK } else { NTSC_MASTER_CLOCK };
+        self.pending += master_cycles as i64 * FREQUENCY;
+        while self.pending > 0 && self.running() {
+            self.clocks = 0;
+            self.main(cart);
+            self.pending -= self.clocks.max(1) as i64 * master_clock;
+        }
+        if !self.running() {
+            self.pending = 0;
+        }
+    }
+
+    fn owns_rom(&self) -> bool {
+        self.busy()
+    }
+
+    fn vector(&self, addr: u32) -> Option<u8> {
+        (addr & 0x40FFE0 == 0x00FFE0).then(|| self.vectors[addr as usize & 0x1F])
+    }
+
+    fn irq(&self) -> bool {
+        self.i
+    }
+}
+
+/// ROM as the Cx4 sees it: $00-$3F/$80-$BF:$8000-$FFFF and $C0-$FF
+fn is_rom(addr: u32) -> bool {
+    addr & 0x408000 == 0x008000 || addr & 0xC00000 == 0xC00000
+}
+
+/// Game pak RAM at $70-$77:$0000-$7FFF
+fn is_ram(addr: u32) -> bool {
+    addr & 0xF88000 == 0x700000
+}
@@ -6,65 +6,179 @@
 /// - Audio: SPC700 + S-DSP (8-channel)
 /// - Memory: 128KB RAM + 64KB VRAM
 
-use anyhow::Result;
+pub mod cpu;
+pub mod cpu_io;
+pub mod controller;
+pub mod cartridge;
+pub mod bus;
+pub mod dma;
+pub mod ppu;
+pub mod spc700;
+pub mod apu;
+pub mod dsp;
+pub mod spc_file;
+pub mod necdsp;
+pub mod dsp1;
+pub mod superfx;
+pub mod sa1;
+pub mod sdd1;
+pub mod spc7110;
+pub mod rtc4513;
+pub mod hg51b;
+pub mod cx4;
+pub mod msu1;
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use bus::Bus;
+use cartridge::{Coprocessor, DspModel, SnesCartridge};
+use controller::Buttons;
+use cpu::CPU65816;
 
 pub struct SNES {
-    cpu_cycles: u64,
-    framebuffer: Vec<u8>,
+    pub cpu: CPU65816,
+    pub bus: Bus,
 }
 
 impl SNES {
     pub fn new() -> Self {
         Self {
-            cpu_cycles: 0,
-            framebuffer: vec![0; 256 * 224 * 4], // 256x224 RGBA
+            cpu: CPU65816::new(),
+            bus: Bus::new(),
         }
     }
     
-    pub fn load_rom(&mut self, _rom_data: &[u8]) -> Result<()> {
-        log::info!("SNES ROM loaded");
+    pub fn load_rom(&mut self, rom_data: &[u8]) -> Result<()> {
+        let cartridge = SnesCartridge::load(rom_data)?;
+        // DSP-1 games run on the reimplementation until firmware is loaded
+        self.bus.dsp = match cartridge.dsp_model {
+            Some(DspModel::Dsp1 | DspModel::Dsp1B) => Some(Box::new(dsp1::Dsp1::new())),
+            Some(model) => {
+                log::warn!("{:?} needs its firmware dump ({})", model, model.firmware_name());
+                None
+            }
+            None => None,
+        };
+        self.bus.superfx = (cartridge.coprocessor == Coprocessor::SuperFx).then(superfx::SuperFx::new);
+        self.bus.sa1 = (cartridge.coprocessor == Coprocessor::Sa1).then(|| sa1::Sa1::new(cartridge.region.is_pal()));
+        // Cx4 games run on the HLE until the data ROM is loaded
+        self.bus.cx4 = (cartridge.coprocessor == Coprocessor::Cx4)
+            .then(|| Box::new(cx4::Cx4::new()) as Box<dyn hg51b::Cx4Interface>);
+        self.bus.sdd1 = (cartridge.coprocessor == Coprocessor::Sdd1).then(sdd1::Sdd1::new);
+        // Chipset $F9 boards add the RTC-4513
+        self.bus.spc7110 = (cartridge.coprocessor == Coprocessor::Spc7110)
+            .then(|| spc7110::Spc7110::new(cartridge.chipset == 0xF9));
+        self.bus.msu1 = None;
+        self.bus.load_cartridge(cartridge);
         self.reset();
         Ok(())
     }
+
+    /// Firmware dump the cartridge's coprocessor can run from, if any
+    pub fn firmware_name(&self) -> Option<&'static str> {
+        let cartridge = self.bus.cartridge.as_ref()?;
+        if cartridge.coprocessor == Coprocessor::Cx4 {
+            return Some(hg51b::FIRMWARE_NAME);
+        }
+        cartridge.dsp_model.map(DspModel::firmware_name)
+    }
+
+    /// Run the coprocessor from a firmware dump instead of reimplementing it
+    pub fn load_firmware(&mut self, firmware: &[u8]) -> Result<()> {
+        let Some(cartridge) = &self.bus.cartridge else {
+            bail!("The cartridge has no coprocessor firmware");
+        };
+        if cartridge.coprocessor == Coprocessor::Cx4 {
+            self.bus.cx4 = Some(Box::new(hg51b::Hg51b::new(firmware)?));
+            self.bus.cx4_synced = self.bus.master_cycles;
+            return Ok(());
+        }
+        let Some(model) = cartridge.dsp_model else {
+            bail!("The cartridge has no coprocessor firmware");
+        };
+        let dsp = necdsp::NecDsp::new(model.chip(), firmware, model.frequency())?;
+        self.bus.dsp = Some(Box::new(dsp));
+        self.bus.dsp_synced = self.bus.master_cycles;
+        Ok(())
+    }
     
+    /// Attach MSU-1 media: the `.msu` data file, with `-N.pcm` tracks
+    /// beside it
+    pub fn load_msu1(&mut self, data_path: &Path) -> Result<()> {
+        self.bus.msu1 = Some(msu1::Msu1::open(data_path)?);
+        Ok(())
+    }
+
     pub fn reset(&mut self) {
-        self.cpu_cycles = 0;
-        self.render_test_pattern();
+        self.bus.reset();
+        self.cpu.reset(&mut self.bus);
     }
-    
+
+    /// Execute one CPU instruction; returns the master cycles it took
+    pub fn step(&mut self) -> u64 {
+        let start = self.bus.master_cycles;
+        let interrupted = if self.bus.take_nmi() {
+            self.cpu.nmi(&mut self.bus) > 0
+        } else if self.bus.irq_line() {
+            self.cpu.irq(&mut self.bus) > 0
+        } else {
+            false
+        };
+        if !interrupted {
+            self.cpu.step(&mut self.bus);
+        }
+        self.bus.master_cycles - start
+    }
+
     pub fn run_frame(&mut self) {
-        self.cpu_cycles += 89341;
-        self.render_test_pattern();
+        self.bus.apu.bus.dsp.clear_samples();
+        // Run until vblank starts (~357,000 master cycles at 60 Hz)
+        while !self.bus.take_frame_complete() {
+            self.step();
+        }
     }
     
+    /// Run the Super FX `factor` times faster than the stock clock
+    pub fn set_superfx_overclock(&mut self, factor: u32) {
+        if let Some(gsu) = &mut self.bus.superfx {
+            gsu.overclock = factor.max(1);
+        }
+    }
+
+    /// Redraw the Mode 7 layer at `scale` (2-4) times the resolution, 1 to
+    /// turn it off; `perspective` interpolates the matrix between lines
+    pub fn set_mode7_hd(&mut self, scale: u32, perspective: bool) {
+        self.bus.ppu.set_mode7_hd(scale, perspective);
+    }
+
+    /// Set the buttons held on controller port 1 (0) or 2 (1)
+    pub fn set_buttons(&mut self, player: usize, buttons: Buttons) {
+        self.bus.set_buttons(player, buttons);
+    }
+
     pub fn get_framebuffer(&self) -> &[u8] {
-        &self.framebuffer
+        self.bus.ppu.get_framebuffer()
     }
-    
-    fn render_test_pattern(&mut self) {
-        // Mode 7-inspired rotating pattern with animation
-        let time = (self.cpu_cycles as f32) / 10000.0;
-        
-        for y in 0..224 {
-            for x in 0..256 {
-                let pixel_index = (y * 256 + x) * 4;
-                
-                let center_x = 128.0;
-                let center_y = 112.0;
-                let dx = x as f32 - center_x;
-                let dy = y as f32 - center_y;
-                let distance = (dx * dx + dy * dy).sqrt();
-                let angle = dy.atan2(dx);
-                
-                let r = ((angle + distance / 20.0 + time).sin() * 127.0 + 128.0) as u8;
-                let g = ((angle + distance / 30.0 + time + 2.0).sin() * 127.0 + 128.0) as u8;
-                let b = ((distance / 10.0 + time).sin() * 127.0 + 128.0) as u8;
-                
-                self.framebuffer[pixel_index] = r;
-                self.framebuffer[pixel_index + 1] = g;
-                self.framebuffer[pixel_index + 2] = b;
-                self.framebuffer[pixel_index + 3] = 255;
-            }
-        }
+
+    /// Interleaved stereo at `dsp::SAMPLE_RATE` from the last frame
+    pub fn get_audio_samples(&self) -> &[i16] {
+        self.bus.apu.bus.dsp.samples()
+    }
+
+    /// Width and height of `get_framebuffer`, which change with hi-res and
+    /// interlace
+    pub fn frame_size(&self) -> (u32, u32) {
+        self.bus.ppu.frame_size()
+    }
+
+    /// The frame with hi-res Mode 7; the native frame when that is off or
+    /// the frame was hi-res or interlaced
+    pub fn get_scaled_framebuffer(&self) -> &[u8] {
+        self.bus.ppu.get_scaled_framebuffer()
+    }
+
+    pub fn scaled_frame_size(&self) -> (u32, u32) {
+        self.bus.ppu.scaled_frame_size()
     }
 }
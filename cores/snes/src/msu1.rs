@@ -0,0 +1,260 @@
+/// MSU-1
+/// Streaming media chip from the SD2SNES, mostly used by ROM hacks. The
+/// data file (`game.msu`) is read a byte at a time through $2001 after
+/// seeking with $2000-$2003; audio tracks (`game-N.pcm`) are 44.1 kHz
+/// 16-bit stereo after an 8-byte "MSU1" + loop point header. Files are
+/// streamed from disk, and since seeks finish at once the busy flags never
+/// show.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::dsp::SAMPLE_RATE;
+
+const REVISION: u8 = 2;
+const IDENTIFIER: &[u8; 6] = b"S-MSU1";
+const PCM_RATE: u32 = 44100;
+/// Signature and loop point in front of the samples
+const PCM_HEADER: u64 = 8;
+
+// $2000 status bits
+const STATUS_ERROR: u8 = 0x08;
+const STATUS_PLAYING: u8 = 0x10;
+const STATUS_REPEAT: u8 = 0x20;
+
+// $2007 control bits
+const CONTROL_PLAY: u8 = 0x01;
+const CONTROL_REPEAT: u8 = 0x02;
+
+struct Track {
+    file: BufReader<File>,
+    size: u64,
+    /// Byte offset of the next sample frame
+    offset: u64,
+    loop_offset: u64,
+}
+
+impl Track {
+    fn open(path: &Path) -> Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let size = file.get_ref().metadata()?.len();
+        let mut header = [0u8; PCM_HEADER as usize];
+        file.read_exact(&mut header)?;
+        anyhow::ensure!(&header[..4] == b"MSU1", "{} is not an MSU-1 track", path.display());
+        let loop_point = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        let loop_offset = PCM_HEADER + loop_point * 4;
+        Ok(Self {
+            file,
+            size,
+            offset: PCM_HEADER,
+            loop_offset: if loop_offset < size { loop_offset } else { PCM_HEADER },
+        })
+    }
+
+    fn seek(&mut self, offset: u64) {
+        self.offset = offset;
+        if self.file.seek(SeekFrom::Start(offset)).is_err() {
+            self.offset = self.size;
+        }
+    }
+
+    /// Next sample frame; `None` at the end of the file
+    fn next_frame(&mut self) -> Option<[i16; 2]> {
+        if self.offset + 4 > self.size {
+            return None;
+        }
+        let mut frame = [0u8; 4];
+        self.file.read_exact(&mut frame).ok()?;
+        self.offset += 4;
+        Some([i16::from_le_bytes([frame[0], frame[1]]), i16::from_le_bytes([frame[2], frame[3]])])
+    }
+}
+
+pub struct Msu1 {
+    data: BufReader<File>,
+    data_size: u64,
+    /// `game` of `game.msu`, for finding `game-N.pcm`
+    track_base: PathBuf,
+
+    seek_offset: u32,
+    read_offset: u64,
+    track_number: u16,
+    track: Option<Track>,
+    volume: u8,
+    playing: bool,
+    repeat: bool,
+    error: bool,
+
+    /// Frames either side of the current 32 kHz output sample
+    frames: [[i16; 2]; 2],
+    /// Position between `frames`, in units of 1/SAMPLE_RATE of a PCM frame
+    phase: u32,
+}
+
+impl Msu1 {
+    /// Open `game.msu`; tracks are looked up next to it as they are selected
+    pub fn open(data_path: &Path) -> Result<Self> {
+        let file = File::open(data_path).with_context(|| format!("Opening {}", data_path.display()))?;
+        let data_size = file.metadata()?.len();
+        Ok(Self {
+            data: BufReader::new(file),
+            data_size,
+            track_base: data_path.with_extension(""),
+            seek_offset: 0,
+            read_offset: 0,
+            track_number: 0,
+            track: None,
+            volume: 0,
+            playing: false,
+            repeat: false,
+            error: false,
+            frames: [[0; 2]; 2],
+            phase: 0,
+        })
+    }
+
+    pub fn reset(&mut self) {
+        self.seek_offset = 0;
+        self.seek_data(0);
+        self.track_number = 0;
+        self.track = None;
+        self.volume = 0;
+        self.playing = false;
+        self.repeat = false;
+        self.error = false;
+        self.frames = [[0; 2]; 2];
+        self.phase = 0;
+    }
+
+    fn seek_data(&mut self, offset: u64) {
+        self.read_offset = offset;
+        if self.data.seek(SeekFrom::Start(offset)).is_err() {
+            self.read_offset = self.data_size;
+        }
+    }
+
+    /// $2000-$2007
+    pub fn read(&mut self, addr: u16) -> u8 {
+        match addr & 0x07 {
+            0 => {
+                let mut status = REVISION;
+                if self.error {
+                    status |= STATUS_ERROR;
+                }
+                if self.playing {
+                    status |= STATUS_PLAYING;
+                }
+                if self.repeat {
+                    status |= STATUS_REPEAT;
+                }
+                status
+            }
+            1 => {
+                if self.read_offset >= self.data_size {
+                    return 0;
+                }
+                let mut byte = [0u8];
+                if self.data.read_exact(&mut byte).is_err() {
+                    return 0;
+                }
+                self.read_offset += 1;
+                byte[0]
+            }
+            index => IDENTIFIER[index as usize - 2],
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr & 0x07 {
+            index @ 0..=3 => {
+                let shift = index * 8;
+                self.seek_offset = (self.seek_offset & !(0xFF << shift)) | (value as u32) << shift;
+                if index == 3 {
+                    self.seek_data(self.seek_offset as u64);
+                }
+            }
+            4 => self.track_number = (self.track_number & 0xFF00) | value as u16,
+            5 => {
+                self.track_number = (self.track_number & 0x00FF) | (value as u16) << 8;
+                self.select_track();
+            }
+            6 => self.volume = value,
+            _ => {
+                if self.error {
+                    return;
+                }
+                self.playing = value & CONTROL_PLAY != 0;
+                self.repeat = value & CONTROL_REPEAT != 0;
+            }
+        }
+    }
+
+    fn select_track(&mut self) {
+        self.playing = false;
+        self.repeat = false;
+        let mut path = self.track_base.clone().into_os_string();
+        path.push(format!("-{}.pcm", self.track_number));
+        match Track::open(Path::new(&path)) {
+            Ok(track) => {
+                self.track = Some(track);
+                self.error = false;
+            }
+            Err(err) => {
+                log::debug!("MSU-1 track {}: {}", self.track_number, err);
+                self.track = None;
+                self.error = true;
+            }
+        }
+        self.frames = [[0; 2]; 2];
+        self.phase = 0;
+    }
+
+    /// Next 44.1 kHz frame of the playing track, looping or stopping at the end
+    fn next_frame(&mut self) -> [i16; 2] {
+        if !self.playing {
+            return [0; 2];
+        }
+        let Some(track) = &mut self.track else {
+            self.playing = false;
+            return [0; 2];
+        };
+        if let Some(frame) = track.next_frame() {
+            return frame;
+        }
+        if self.repeat {
+            track.seek(track.loop_offset);
+            track.next_frame().unwrap_or([0; 2])
+        } else {
+            self.playing = false;
+            track.seek(PCM_HEADER);
+            [0; 2]
+        }
+    }
+
+    /// Add the track, resampled to 32 kHz, to interleaved S-DSP output
+    pub fn mix(&mut self, samples: &mut [i16]) {
+        if !self.playing && self.phase == 0 {
+            return;
+        }
+        for output in samples.chunks_exact_mut(2) {
+            self.phase += PCM_RATE;
+            while self.phase >= SAMPLE_RATE {
+                self.phase -= SAMPLE_RATE;
+                self.frames = [self.frames[1], self.next_frame()];
+            }
+            for (channel, sample) in output.iter_mut().enumerate() {
+                let [previous, next] = [self.frames[0][channel] as i32, self.frames[1][channel] as i32];
+                let value = previous + (next - previous) * self.phase as i32 / SAMPLE_RATE as i32;
+                let value = value * self.volume as i32 / 255;
+                *sample = (*sample as i32 + value).clamp(-32768, 32767) as i16;
+            }
+        }
+        if !self.playing {
+            self.frames = [[0; 2]; 2];
+            self.phase = 0;
+        }
+    }
+}
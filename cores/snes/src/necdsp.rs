@@ -0,0 +1,509 @@
+/// NEC uPD77C25 / uPD96050 Signal Processors
+/// Low-level core for the DSP-1/2/3/4 (uPD77C25) and ST010/ST011 (uPD96050)
+/// boards, running the chip's own firmware. Every instruction takes one
+/// cycle and does an ALU op, a register move and pointer updates at once;
+/// the 5A22 talks to it through the data (DR) and status (SR) registers.
+
+use anyhow::{bail, Result};
+
+/// Instruction clock of the uPD77C25 on DSP-n boards
+pub const UPD77C25_FREQUENCY: i64 = 7_600_000;
+
+/// Host side of a DSP chip as the 5A22 sees it. The high-level fallbacks
+/// implement it too.
+pub trait DspInterface {
+    fn read_status(&mut self) -> u8;
+    fn read_data(&mut self) -> u8;
+    fn write_data(&mut self, value: u8);
+    fn reset(&mut self);
+
+    /// Catch up with `master_cycles` of 5A22 time
+    fn run(&mut self, _master_cycles: u64, _pal: bool) {}
+
+    /// Data RAM by byte address, which ST01x boards put on the 5A22 bus
+    fn read_ram(&mut self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn write_ram(&mut self, _addr: u16, _value: u8) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NecDspModel {
+    /// 2K x 24-bit program ROM, 1K x 16-bit data ROM, 256 words of RAM
+    Upd77c25,
+    /// 16K program ROM, 2K data ROM, 2K words of RAM
+    Upd96050,
+}
+
+impl NecDspModel {
+    fn program_size(self) -> usize {
+        match self {
+            NecDspModel::Upd77c25 => 2048,
+            NecDspModel::Upd96050 => 16384,
+        }
+    }
+
+    fn data_rom_size(self) -> usize {
+        match self {
+            NecDspModel::Upd77c25 => 1024,
+            NecDspModel::Upd96050 => 2048,
+        }
+    }
+
+    fn data_ram_size(self) -> usize {
+        match self {
+            NecDspModel::Upd77c25 => 256,
+            NecDspModel::Upd96050 => 2048,
+        }
+    }
+
+    fn stack_size(self) -> usize {
+        match self {
+            NecDspModel::Upd77c25 => 4,
+            NecDspModel::Upd96050 => 16,
+        }
+    }
+
+    /// Size of a firmware dump: 24-bit program words, then 16-bit data
+    /// words, both little-endian
+    pub fn firmware_size(self) -> usize {
+        self.program_size() * 3 + self.data_rom_size() * 2
+    }
+}
+
+// Status register bits
+const SR_RQM: u16 = 0x8000;
+const SR_DRS: u16 = 0x1000;
+const SR_DRC: u16 = 0x0400;
+const SR_SIACK: u16 = 0x0020;
+const SR_SOACK: u16 = 0x0010;
+/// Bits the program cannot write through SR
+const SR_READ_ONLY: u16 = 0x907C;
+
+/// ALU flags, one set per accumulator
+#[derive(Debug, Clone, Copy, Default)]
+struct Flags {
+    ov0: bool,
+    ov1: bool,
+    z: bool,
+    c: bool,
+    s0: bool,
+    s1: bool,
+}
+
+pub struct NecDsp {
+    model: NecDspModel,
+    program_rom: Vec<u32>,
+    data_rom: Vec<u16>,
+    pub data_ram: Vec<u16>,
+
+    pc: u16,
+    stack: [u16; 16],
+    sp: usize,
+    /// Data ROM pointer
+    rp: u16,
+    /// Data RAM pointer
+    dp: u16,
+    a: u16,
+    b: u16,
+    flag_a: Flags,
+    flag_b: Flags,
+    /// Multiplier inputs and the product of the previous instruction
+    k: u16,
+    l: u16,
+    m: u16,
+    n: u16,
+    tr: u16,
+    trb: u16,
+    dr: u16,
+    sr: u16,
+    si: u16,
+    so: u16,
+
+    /// Instruction clock
+    frequency: i64,
+    /// DSP time owed to the master clock, in master cycles x frequency
+    pending: i64,
+}
+
+impl NecDsp {
+    pub fn new(model: NecDspModel, firmware: &[u8], frequency: i64) -> Result<Self> {
+        if firmware.len() != model.firmware_size() {
+            bail!("{:?} firmware must be {} bytes, got {}", model, model.firmware_size(), firmware.len());
+        }
+        let (program, data) = firmware.split_at(model.program_size() * 3);
+        let program_rom = program
+            .chunks_exact(3)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], 0]))
+            .collect();
+        let data_rom = data.chunks_exact(2).map(|w| u16::from_le_bytes([w[0], w[1]])).collect();
+
+        let mut dsp = Self {
+            model,
+            program_rom,
+            data_rom,
+            data_ram: vec![0; model.data_ram_size()],
+            pc: 0,
+            stack: [0; 16],
+            sp: 0,
+            rp: 0,
+            dp: 0,
+            a: 0,
+            b: 0,
+            flag_a: Flags::default(),
+            flag_b: Flags::default(),
+            k: 0,
+            l: 0,
+            m: 0,
+            n: 0,
+            tr: 0,
+            trb: 0,
+            dr: 0,
+            sr: 0,
+            si: 0,
+            so: 0,
+            frequency,
+            pending: 0,
+        };
+        dsp.reset();
+        Ok(dsp)
+    }
+
+    fn data_rom_at(&self, addr: u16) -> u16 {
+        self.data_rom[addr as usize & (self.data_rom.len() - 1)]
+    }
+
+    fn ram_index(&self, addr: u16) -> usize {
+        addr as usize & (self.data_ram.len() - 1)
+    }
+
+    /// Execute one instruction
+    pub fn step(&mut self) {
+        let opcode = self.program_rom[self.pc as usize];
+        self.pc = (self.pc + 1) & (self.program_rom.len() - 1) as u16;
+        match opcode >> 22 {
+            0 => self.exec_op(opcode),
+            1 => self.exec_rt(opcode),
+            2 => self.exec_jp(opcode),
+            _ => self.exec_ld(opcode),
+        }
+
+        // The multiplier runs every cycle: sign + 30 bits across M and N
+        let product = (self.k as i16 as i32) * (self.l as i16 as i32);
+        self.m = (product >> 15) as u16;
+        self.n = (product << 1) as u16;
+    }
+
+    fn exec_op(&mut self, opcode: u32) {
+        let pselect = (opcode >> 20) & 0x03;
+        let alu = (opcode >> 16) & 0x0F;
+        let asl = (opcode >> 15) & 0x01;
+        let dpl = (opcode >> 13) & 0x03;
+        let dphm = ((opcode >> 9) & 0x0F) as u16;
+        let rpdcr = (opcode >> 8) & 0x01 != 0;
+        let src = (opcode >> 4) & 0x0F;
+        let dst = opcode & 0x0F;
+
+        let idb = match src {
+            0 => self.trb,
+            1 => self.a,
+            2 => self.b,
+            3 => self.tr,
+            4 => self.dp,
+            5 => self.rp,
+            6 => self.data_rom_at(self.rp),
+            // SGN: saturation value for the sign of the last A overflow
+            7 => 0x8000u16.wrapping_sub(self.flag_a.s1 as u16),
+            8 => {
+                self.sr |= SR_RQM;
+                self.dr
+            }
+            9 => self.dr,
+            10 => self.sr,
+            11 | 12 => self.si,
+            13 => self.k,
+            14 => self.l,
+            _ => self.data_ram[self.ram_index(self.dp)],
+        };
+
+        if alu != 0 {
+            self.exec_alu(pselect, alu, asl != 0, idb);
+        }
+
+        self.exec_ld(((idb as u32) << 6) | dst);
+
+        let dp_low = match dpl {
+            1 => (self.dp + 1) & 0x0F,
+            2 => self.dp.wrapping_sub(1) & 0x0F,
+            3 => 0,
+            _ => self.dp & 0x0F,
+        };
+        self.dp = ((self.dp & !0x0F) | dp_low) ^ (dphm << 4);
+
+        if rpdcr {
+            self.rp = self.rp.wrapping_sub(1) & (self.data_rom.len() - 1) as u16;
+        }
+    }
+
+    fn exec_alu(&mut self, pselect: u32, alu: u32, use_b: bool, idb: u16) {
+        let mut p = match pselect {
+            0 => self.data_ram[self.ram_index(self.dp)],
+            1 => idb,
+            2 => self.m,
+            _ => self.n,
+        };
+        let (q, mut flag, carry) = if use_b {
+            (self.b, self.flag_b, self.flag_a.c as u16)
+        } else {
+            (self.a, self.flag_a, self.flag_b.c as u16)
+        };
+
+        let r = match alu {
+            1 => q | p,
+            2 => q & p,
+            3 => q ^ p,
+            4 => q.wrapping_sub(p),
+            5 => q.wrapping_add(p),
+            6 => q.wrapping_sub(p).wrapping_sub(carry),
+            7 => q.wrapping_add(p).wrapping_add(carry),
+            8 => {
+                p = 1;
+                q.wrapping_sub(1)
+            }
+            9 => {
+                p = 1;
+                q.wrapping_add(1)
+            }
+            10 => !q,
+            11 => (q >> 1) | (q & 0x8000),
+            12 => (q << 1) | carry,
+            13 => (q << 2) | 0x03,
+            14 => (q << 4) | 0x0F,
+            _ => q.rotate_left(8),
+        };
+
+        flag.s0 = r & 0x8000 != 0;
+        flag.z = r == 0;
+        match alu {
+            4..=9 => {
+                if alu & 0x01 != 0 {
+                    flag.ov0 = (q ^ r) & (p ^ r) & 0x8000 != 0;
+                    flag.c = r < q;
+                } else {
+                    flag.ov0 = (q ^ r) & (q ^ p) & 0x8000 != 0;
+                    flag.c = r > q;
+                }
+                if flag.ov0 {
+                    flag.s1 = flag.ov1 ^ (r & 0x8000 == 0);
+                    flag.ov1 = !flag.ov1;
+                }
+            }
+            _ => {
+                flag.c = match alu {
+                    11 => q & 0x01 != 0,
+                    12 => q & 0x8000 != 0,
+                    _ => false,
+                };
+                flag.ov0 = false;
+                flag.ov1 = false;
+            }
+        }
+
+        if use_b {
+            self.b = r;
+            self.flag_b = flag;
+        } else {
+            self.a = r;
+            self.flag_a = flag;
+        }
+    }
+
+    fn exec_rt(&mut self, opcode: u32) {
+        self.exec_op(opcode);
+        self.sp = self.sp.wrapping_sub(1) & (self.model.stack_size() - 1);
+        self.pc = self.stack[self.sp];
+    }
+
+    fn exec_jp(&mut self, opcode: u32) {
+        let brch = (opcode >> 13) & 0x1FF;
+        let na = ((opcode >> 2) & 0x7FF) as u16;
+        let bank = (opcode & 0x03) as u16;
+        let mask = (self.program_rom.len() - 1) as u16;
+        let jp = ((self.pc & 0x2000) | (bank << 11) | na) & mask;
+        let (fa, fb) = (self.flag_a, self.flag_b);
+        let dp_low = self.dp & 0x0F;
+
+        let taken = match brch {
+            0x000 => {
+                // JMPSO
+                self.pc = self.so & mask;
+                return;
+            }
+            0x080 => !fa.c,
+            0x082 => fa.c,
+            0x084 => !fb.c,
+            0x086 => fb.c,
+            0x088 => !fa.z,
+            0x08A => fa.z,
+            0x08C => !fb.z,
+            0x08E => fb.z,
+            0x090 => !fa.ov0,
+            0x092 => fa.ov0,
+            0x094 => !fb.ov0,
+            0x096 => fb.ov0,
+            0x098 => !fa.ov1,
+            0x09A => fa.ov1,
+            0x09C => !fb.ov1,
+            0x09E => fb.ov1,
+            0x0A0 => !fa.s0,
+            0x0A2 => fa.s0,
+            0x0A4 => !fb.s0,
+            0x0A6 => fb.s0,
+            0x0A8 => !fa.s1,
+            0x0AA => fa.s1,
+            0x0AC => !fb.s1,
+            0x0AE => fb.s1,
+            0x0B0 => dp_low == 0x00,
+            0x0B1 => dp_low != 0x00,
+            0x0B2 => dp_low == 0x0F,
+            0x0B3 => dp_low != 0x0F,
+            // The serial port is not connected on SNES boards
+            0x0B4 => self.sr & SR_SIACK == 0,
+            0x0B6 => self.sr & SR_SIACK != 0,
+            0x0B8 => self.sr & SR_SOACK == 0,
+            0x0BA => self.sr & SR_SOACK != 0,
+            0x0BC => self.sr & SR_RQM == 0,
+            0x0BE => self.sr & SR_RQM != 0,
+            0x100 => {
+                self.pc = jp & !0x2000;
+                return;
+            }
+            0x101 => {
+                self.pc = (jp | 0x2000) & mask;
+                return;
+            }
+            0x140 | 0x141 => {
+                self.stack[self.sp] = self.pc;
+                self.sp = (self.sp + 1) & (self.model.stack_size() - 1);
+                self.pc = if brch & 0x01 != 0 { (jp | 0x2000) & mask } else { jp & !0x2000 };
+                return;
+            }
+            _ => false,
+        };
+        if taken {
+            self.pc = jp;
+        }
+    }
+
+    fn exec_ld(&mut self, opcode: u32) {
+        let id = (opcode >> 6) as u16;
+        match opcode & 0x0F {
+            0 => {}
+            1 => self.a = id,
+            2 => self.b = id,
+            3 => self.tr = id,
+            4 => self.dp = id & (self.data_ram.len() - 1) as u16,
+            5 => self.rp = id & (self.data_rom.len() - 1) as u16,
+            6 => {
+                self.dr = id;
+                self.sr |= SR_RQM;
+            }
+            7 => self.sr = (self.sr & SR_READ_ONLY) | (id & !SR_READ_ONLY),
+            8 => self.so = id.reverse_bits(),
+            9 => self.so = id,
+            10 => self.k = id,
+            11 => {
+                self.k = id;
+                self.l = self.data_rom_at(self.rp);
+            }
+            12 => {
+                self.l = id;
+                self.k = self.data_ram[self.ram_index(self.dp | 0x40)];
+            }
+            13 => self.l = id,
+            14 => self.trb = id,
+            _ => {
+                let index = self.ram_index(self.dp);
+                self.data_ram[index] = id;
+            }
+        }
+    }
+}
+
+impl DspInterface for NecDsp {
+    fn read_status(&mut self) -> u8 {
+        (self.sr >> 8) as u8
+    }
+
+    /// DR is 16 bits wide unless SR.DRC selects 8-bit transfers; DRS tracks
+    /// which half comes next and RQM drops once the transfer is complete
+    fn read_data(&mut self) -> u8 {
+        if self.sr & SR_DRC != 0 {
+            self.sr &= !SR_RQM;
+            return self.dr as u8;
+        }
+        if self.sr & SR_DRS == 0 {
+            self.sr |= SR_DRS;
+            self.dr as u8
+        } else {
+            self.sr &= !(SR_RQM | SR_DRS);
+            (self.dr >> 8) as u8
+        }
+    }
+
+    fn write_data(&mut self, value: u8) {
+        if self.sr & SR_DRC != 0 {
+            self.sr &= !SR_RQM;
+            self.dr = (self.dr & 0xFF00) | value as u16;
+            return;
+        }
+        if self.sr & SR_DRS == 0 {
+            self.sr |= SR_DRS;
+            self.dr = (self.dr & 0xFF00) | value as u16;
+        } else {
+            self.sr &= !(SR_RQM | SR_DRS);
+            self.dr = ((value as u16) << 8) | (self.dr & 0x00FF);
+        }
+    }
+
+    fn read_ram(&mut self, addr: u16) -> u8 {
+        let word = self.data_ram[self.ram_index(addr >> 1)];
+        if addr & 0x01 != 0 { (word >> 8) as u8 } else { word as u8 }
+    }
+
+    fn write_ram(&mut self, addr: u16, value: u8) {
+        let index = self.ram_index(addr >> 1);
+        let word = &mut self.data_ram[index];
+        *word = if addr & 0x01 != 0 {
+            (*word & 0x00FF) | ((value as u16) << 8)
+        } else {
+            (*word & 0xFF00) | value as u16
+        };
+    }
+
+    fn reset(&mut self) {
+        self.pc = 0;
+        self.stack = [0; 16];
+        self.sp = 0;
+        self.rp = (self.model.data_rom_size() - 1) as u16;
+        self.dp = 0;
+        self.a = 0;
+        self.b = 0;
+        self.flag_a = Flags::default();
+        self.flag_b = Flags::default();
+        self.sr = 0;
+        self.si = 0;
+        self.so = 0;
+        self.pending = 0;
+    }
+
+    fn run(&mut self, master_cycles: u64, pal: bool) {
+        let master_clock = if pal { crate::apu::PAL_MASTER_CLOCK } else { crate::apu::NTSC_MASTER_CLOCK };
+        self.pending += master_cycles as i64 * self.frequency;
+        while self.pending > 0 {
+            self.step();
+            self.pending -= master_clock;
+        }
+    }
+}
@@ -0,0 +1,1514 @@
+/// S-PPU (5C77 + 5C78)
+/// 64KB VRAM, 512-byte CGRAM and the register interface at $2100-$213F.
+/// Renders one scanline at a time: background modes 0-6, the Mode 7
+/// affine layer and up to 32 sprites go through main/sub screen priority, windows and color
+/// math into a buffer that handles hi-res (512 wide) and interlace
+/// (448/478 lines). Optionally the Mode 7 layer is also redrawn at up to
+/// 4x resolution into a separate, scaled framebuffer.
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 224;
+
+/// Largest output: hi-res, interlaced, overscan
+pub const MAX_WIDTH: usize = 512;
+pub const MAX_HEIGHT: usize = 478;
+
+const VRAM_WORDS: usize = 0x8000;
+
+// INIDISP
+const INIDISP_FORCED_BLANK: u8 = 0x80;
+
+// SETINI
+const SETINI_INTERLACE: u8 = 0x01;
+const SETINI_OBJ_INTERLACE: u8 = 0x02;
+const SETINI_OVERSCAN: u8 = 0x04;
+const SETINI_PSEUDO_HIRES: u8 = 0x08;
+const SETINI_EXTBG: u8 = 0x40;
+
+/// Rank of the backdrop; every opaque layer pixel is in front of it
+const BACKDROP_RANK: u8 = 0xFF;
+
+const BACKDROP: LinePixel = LinePixel { color: 0, rank: BACKDROP_RANK, layer: Layer::Backdrop, math: true };
+
+/// Screen layers, in the bit order of TM/TS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Bg1,
+    Bg2,
+    Bg3,
+    Bg4,
+    Obj,
+    Backdrop,
+}
+
+impl Layer {
+    /// Bit in TM/TS/TMW/TSW/CGADSUB
+    fn bit(self) -> u8 {
+        match self {
+            Layer::Bg1 => 0x01,
+            Layer::Bg2 => 0x02,
+            Layer::Bg3 => 0x04,
+            Layer::Bg4 => 0x08,
+            Layer::Obj => 0x10,
+            Layer::Backdrop => 0x20,
+        }
+    }
+}
+
+const BG_LAYERS: [Layer; 4] = [Layer::Bg1, Layer::Bg2, Layer::Bg3, Layer::Bg4];
+
+/// Per-background registers
+#[derive(Debug, Clone, Copy, Default)]
+struct Background {
+    /// BGnSC: tilemap word address and 64-tile width/height
+    tilemap_addr: u16,
+    tilemap_wide: bool,
+    tilemap_tall: bool,
+    /// BGnNBA: character data word address
+    char_addr: u16,
+    hofs: u16,
+    vofs: u16,
+    /// BGMODE: 16x16 tiles
+    large_tiles: bool,
+}
+
+/// OAM: 128 four-byte entries, then a 32-byte table of X bit 8 and size bits
+const OAM_SIZE: usize = 0x220;
+
+/// OBSEL small and large sprite sizes (width, height)
+const OBJ_SIZES: [((u16, u16), (u16, u16)); 8] = [
+    ((8, 8), (16, 16)),
+    ((8, 8), (32, 32)),
+    ((8, 8), (64, 64)),
+    ((16, 16), (32, 32)),
+    ((16, 16), (64, 64)),
+    ((32, 32), (64, 64)),
+    ((16, 32), (32, 64)),
+    ((16, 32), (32, 32)),
+];
+
+/// Sprites one line can hold, and 8-pixel slivers the PPU can fetch
+const OBJ_RANGE_LIMIT: usize = 32;
+const OBJ_TIME_LIMIT: usize = 34;
+
+/// One decoded OAM entry
+#[derive(Debug, Clone, Copy)]
+struct Sprite {
+    /// 9 bits, 256-511 are off the left edge
+    x: u16,
+    y: u8,
+    /// 9 bits, bit 8 selects the second name table
+    tile: u16,
+    /// vflip, hflip, priority (2 bits), palette (3 bits), name table
+    attr: u8,
+    width: u16,
+    height: u16,
+}
+
+impl Sprite {
+    fn hflip(&self) -> bool {
+        self.attr & 0x40 != 0
+    }
+
+    /// Row after a vertical flip; rectangular sprites flip each square half
+    fn vflip_row(&self, row: u16) -> u16 {
+        if self.width == self.height {
+            self.height - 1 - row
+        } else if row < self.width {
+            self.width - 1 - row
+        } else {
+            self.width + (self.width - 1 - (row - self.width))
+        }
+    }
+}
+
+/// Mode 7 registers; the matrix also drives the $2134-$2136 multiplier
+#[derive(Debug, Clone, Copy, Default)]
+struct Mode7 {
+    /// M7SEL: screen over (bits 6-7), V flip (bit 1), H flip (bit 0)
+    sel: u8,
+    a: i16,
+    b: i16,
+    c: i16,
+    d: i16,
+    /// Center, 13-bit signed
+    x: i16,
+    y: i16,
+    /// M7HOFS/M7VOFS, 13-bit signed
+    hofs: i16,
+    vofs: i16,
+    /// Shared latch of the write-twice Mode 7 registers
+    latch: u8,
+}
+
+impl Mode7 {
+    /// Next write-twice value: new byte on top of the latched one
+    fn word(&mut self, value: u8) -> i16 {
+        let word = ((value as u16) << 8) | self.latch as u16;
+        self.latch = value;
+        word as i16
+    }
+}
+
+impl Mode7 {
+    /// Texture position of screen pixel (x, y), given in 1/scale pixels, in
+    /// units of 1/(256 * scale) texels. At scale 1 this is the hardware's
+    /// math, rounding included; finer steps skip the rounding of the Y terms.
+    fn position(&self, x: i64, y: i64, scale: i64) -> (i64, i64) {
+        let (a, b, c, d) = (self.a as i64, self.b as i64, self.c as i64, self.d as i64);
+        let center_x = sign_extend_13(self.x) as i64;
+        let center_y = sign_extend_13(self.y) as i64;
+
+        // Scroll minus center, clipped to 10 bits plus sign
+        let clip = |n: i64| if n & 0x2000 != 0 { n | !0x03FF } else { n & 0x03FF };
+        let dx = clip(sign_extend_13(self.hofs) as i64 - center_x);
+        let dy = clip(sign_extend_13(self.vofs) as i64 - center_y);
+
+        let x = if self.sel & 0x01 != 0 { 256 * scale - 1 - x } else { x };
+        let y = if self.sel & 0x02 != 0 { 256 * scale - 1 - y } else { y };
+        let round = |n: i64| if scale == 1 { n & !63 } else { n };
+        let origin_x = ((a * dx) & !63) + ((b * dy) & !63) + (center_x << 8);
+        let origin_y = ((c * dx) & !63) + ((d * dy) & !63) + (center_y << 8);
+        (origin_x * scale + round(b * y) + a * x, origin_y * scale + round(d * y) + c * x)
+    }
+}
+
+/// Sign-extend a 13-bit value
+fn sign_extend_13(value: i16) -> i32 {
+    ((value << 3) >> 3) as i32
+}
+
+/// Opaque pixel of a single layer
+#[derive(Debug, Clone, Copy)]
+struct LayerPixel {
+    /// 15-bit BGR
+    color: u16,
+    /// Tilemap priority bit (0-1), or OBJ priority (0-3)
+    priority: u8,
+    /// Color math may apply (OBJ palettes 0-3 never blend)
+    math: bool,
+}
+
+type LayerLine = [Option<LayerPixel>; SCREEN_WIDTH];
+
+/// One pixel of the main or sub screen being composited
+#[derive(Debug, Clone, Copy)]
+struct LinePixel {
+    color: u16,
+    /// Position in the mode's priority order, lower is in front
+    rank: u8,
+    layer: Layer,
+    math: bool,
+}
+
+/// CGWSEL, CGADSUB and COLDATA as set for a line
+#[derive(Debug, Clone, Copy)]
+struct ColorMath {
+    cgwsel: u8,
+    cgadsub: u8,
+    fixed_color: u16,
+}
+
+impl ColorMath {
+    /// Final color of a main screen pixel after clipping and color math
+    fn apply(&self, main: LinePixel, sub: LinePixel, in_window: bool) -> u16 {
+        let region = |mode: u8| match mode & 0x03 {
+            0 => false,
+            1 => !in_window,
+            2 => in_window,
+            _ => true,
+        };
+        let clip = region(self.cgwsel >> 6);
+        let prevent = region(self.cgwsel >> 4);
+
+        let mut color = if clip { 0 } else { main.color };
+        if !prevent && main.math && self.cgadsub & main.layer.bit() != 0 {
+            let use_sub = self.cgwsel & 0x02 != 0;
+            let operand = if use_sub { sub.color } else { self.fixed_color };
+            // No halving against the sub screen backdrop or a clipped pixel
+            let half = self.cgadsub & 0x40 != 0 && !clip && !(use_sub && sub.layer == Layer::Backdrop);
+            color = blend(color, operand, self.cgadsub & 0x80 != 0, half);
+        }
+        color
+    }
+}
+
+/// A composited line held back for the hi-res Mode 7 pass until the next
+/// line's matrix is known
+struct Mode7Line {
+    y: u16,
+    /// Mode 7 registers, `None` on lines drawn at native resolution (other
+    /// modes, mosaic)
+    mode7: Option<Mode7>,
+    main: [LinePixel; SCREEN_WIDTH],
+    sub: [LinePixel; SCREEN_WIDTH],
+    color_window: [bool; SCREEN_WIDTH],
+    math: ColorMath,
+    brightness: u32,
+}
+
+pub struct PPU {
+    vram: Vec<u16>,
+    cgram: [u16; 256],
+
+    /// INIDISP: forced blank (bit 7) and master brightness
+    pub inidisp: u8,
+    /// BGMODE: mode, BG3 priority, tile sizes
+    bgmode: u8,
+    bg: [Background; 4],
+    /// Shared latches of the write-twice scroll registers
+    bgofs_latch: u8,
+    bghofs_latch: u8,
+    mode7: Mode7,
+
+    // VRAM port
+    vmain: u8,
+    vram_addr: u16,
+    vram_read_buffer: u16,
+
+    // CGRAM port
+    cgram_addr: u8,
+    cgram_high: bool,
+    cgram_latch: u8,
+
+    // Objects
+    oam: [u8; OAM_SIZE],
+    /// OBSEL: size select, name select gap, name base
+    obsel: u8,
+    /// OAMADD word address, reloaded into the byte address every vblank
+    oam_addr_reload: u16,
+    oam_addr: u16,
+    /// OAMADDH bit 7: evaluation starts at the sprite OAMADD points to
+    oam_priority_rotation: bool,
+    /// First byte of a low table word, written with the second one
+    oam_latch: u8,
+    /// STAT77 flags: more than 32 sprites / 34 slivers on a line
+    range_over: bool,
+    time_over: bool,
+
+    /// MOSAIC: size (bits 4-7) and enabled backgrounds
+    mosaic: u8,
+
+    // Windows
+    /// W12SEL/W34SEL/WOBJSEL: invert/enable bits, 4 per layer
+    window_sel: [u8; 3],
+    /// WH0-WH3: window 1 and 2 left/right edges
+    window1: (u8, u8),
+    window2: (u8, u8),
+    /// WBGLOG/WOBJLOG: OR/AND/XOR/XNOR per layer
+    window_bg_logic: u8,
+    window_obj_logic: u8,
+    /// TMW/TSW: layers masked by their window on the main/sub screen
+    main_window: u8,
+    sub_window: u8,
+
+    /// TM/TS
+    main_screen: u8,
+    sub_screen: u8,
+    /// CGWSEL: clip to black, prevent math, add sub screen, direct color
+    cgwsel: u8,
+    /// CGADSUB: subtract, half, enabled layers
+    cgadsub: u8,
+    /// COLDATA fixed color, 15-bit BGR
+    fixed_color: u16,
+    /// SETINI: EXTBG, pseudo hi-res, overscan, OBJ/screen interlace
+    setini: u8,
+
+    /// OPHCT/OPVCT: H/V counters latched through $2137 or WRIO
+    hcounter: u16,
+    vcounter: u16,
+    /// Second read of OPHCT/OPVCT returns the high bit
+    hcounter_high: bool,
+    vcounter_high: bool,
+    /// STAT78 bit 6, cleared when STAT78 is read
+    counter_latched: bool,
+
+    ppu1_open_bus: u8,
+    ppu2_open_bus: u8,
+
+    pub pal: bool,
+    pub scanline: u16,
+    /// Interlace field, toggled every frame while interlacing
+    pub field: bool,
+
+    main_line: [LinePixel; SCREEN_WIDTH],
+    sub_line: [LinePixel; SCREEN_WIDTH],
+    /// Lines as drawn, always 512 wide with room for interlace
+    screen: Vec<u8>,
+    /// Frame shape, decided by the lines drawn since the last vblank
+    frame_hires: bool,
+    frame_interlace: bool,
+    frame_overscan: bool,
+    /// Finished frame at its own size
+    framebuffer: Vec<u8>,
+    frame_size: (u32, u32),
+
+    /// Hi-res Mode 7: internal scale (1 = off) and matrix interpolation
+    /// between lines
+    mode7_scale: usize,
+    mode7_perspective: bool,
+    mode7_pending: Option<Box<Mode7Line>>,
+    /// Scaled lines as drawn, 256 * scale wide, and the last scaled frame
+    scaled_screen: Vec<u8>,
+    scaled_framebuffer: Vec<u8>,
+    scaled_frame_size: (u32, u32),
+}
+
+impl PPU {
+    pub fn new() -> Self {
+        Self {
+            vram: vec![0; VRAM_WORDS],
+            cgram: [0; 256],
+            inidisp: INIDISP_FORCED_BLANK,
+            bgmode: 0,
+            bg: [Background::default(); 4],
+            bgofs_latch: 0,
+            bghofs_latch: 0,
+            mode7: Mode7::default(),
+            vmain: 0,
+            vram_addr: 0,
+            vram_read_buffer: 0,
+            cgram_addr: 0,
+            cgram_high: false,
+            cgram_latch: 0,
+            oam: [0; OAM_SIZE],
+            obsel: 0,
+            oam_addr_reload: 0,
+            oam_addr: 0,
+            oam_priority_rotation: false,
+            oam_latch: 0,
+            range_over: false,
+            time_over: false,
+            mosaic: 0,
+            window_sel: [0; 3],
+            window1: (0, 0),
+            window2: (0, 0),
+            window_bg_logic: 0,
+            window_obj_logic: 0,
+            main_window: 0,
+            sub_window: 0,
+            main_screen: 0,
+            sub_screen: 0,
+            cgwsel: 0,
+            cgadsub: 0,
+            fixed_color: 0,
+            setini: 0,
+            hcounter: 0,
+            vcounter: 0,
+            hcounter_high: false,
+            vcounter_high: false,
+            counter_latched: false,
+            ppu1_open_bus: 0,
+            ppu2_open_bus: 0,
+            pal: false,
+            scanline: 0,
+            field: false,
+            main_line: [BACKDROP; SCREEN_WIDTH],
+            sub_line: [BACKDROP; SCREEN_WIDTH],
+            screen: vec![0; MAX_WIDTH * MAX_HEIGHT * 4],
+            frame_hires: false,
+            frame_interlace: false,
+            frame_overscan: false,
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            frame_size: (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32),
+            mode7_scale: 1,
+            mode7_perspective: false,
+            mode7_pending: None,
+            scaled_screen: Vec::new(),
+            scaled_framebuffer: Vec::new(),
+            scaled_frame_size: (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.inidisp = INIDISP_FORCED_BLANK;
+        self.bgmode = 0;
+        self.obsel = 0;
+        self.oam_addr_reload = 0;
+        self.oam_addr = 0;
+        self.oam_priority_rotation = false;
+        self.range_over = false;
+        self.time_over = false;
+        self.mosaic = 0;
+        self.window_sel = [0; 3];
+        self.main_window = 0;
+        self.sub_window = 0;
+        self.main_screen = 0;
+        self.sub_screen = 0;
+        self.cgwsel = 0;
+        self.cgadsub = 0;
+        self.fixed_color = 0;
+        self.setini = 0;
+        self.hcounter_high = false;
+        self.vcounter_high = false;
+        self.counter_latched = false;
+        self.vmain = 0;
+        self.cgram_high = false;
+        self.scanline = 0;
+        self.field = false;
+        self.screen.fill(0);
+        self.framebuffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+        self.frame_size = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        self.mode7_pending = None;
+        self.scaled_screen.fill(0);
+        self.scaled_framebuffer.clear();
+        self.scaled_frame_size = self.frame_size;
+    }
+
+    pub fn get_framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /// Size of the last finished frame: 256 or 512 wide, 224/239 lines or
+    /// 448/478 when interlaced
+    pub fn frame_size(&self) -> (u32, u32) {
+        self.frame_size
+    }
+
+    /// Draw the Mode 7 layer at `scale` (1-4) times the native resolution
+    /// into the scaled framebuffer, other layers are enlarged as they are.
+    /// `perspective` interpolates the matrix between one line and the next,
+    /// smoothing the per-line HDMA changes of 3D floors.
+    pub fn set_mode7_hd(&mut self, scale: u32, perspective: bool) {
+        self.mode7_scale = scale.clamp(1, 4) as usize;
+        self.mode7_perspective = perspective;
+        self.mode7_pending = None;
+        let width = SCREEN_WIDTH * self.mode7_scale;
+        self.scaled_screen = if self.mode7_scale > 1 { vec![0; width * 239 * self.mode7_scale * 4] } else { Vec::new() };
+        self.scaled_framebuffer.clear();
+    }
+
+    /// The frame with hi-res Mode 7, or the native one when that is off.
+    /// Hi-res and interlaced frames are always native.
+    pub fn get_scaled_framebuffer(&self) -> &[u8] {
+        if self.scaled_framebuffer.is_empty() { &self.framebuffer } else { &self.scaled_framebuffer }
+    }
+
+    pub fn scaled_frame_size(&self) -> (u32, u32) {
+        if self.scaled_framebuffer.is_empty() { self.frame_size } else { self.scaled_frame_size }
+    }
+
+    /// First vblank line: 225, or 240 with overscan
+    pub fn vblank_line(&self) -> u16 {
+        if self.overscan() { 240 } else { 225 }
+    }
+
+    fn overscan(&self) -> bool {
+        self.setini & SETINI_OVERSCAN != 0
+    }
+
+    fn interlace(&self) -> bool {
+        self.setini & SETINI_INTERLACE != 0
+    }
+
+    fn forced_blank(&self) -> bool {
+        self.inidisp & INIDISP_FORCED_BLANK != 0
+    }
+
+    /// Called by the bus at the start of every scanline
+    pub fn start_scanline(&mut self, scanline: u16) {
+        self.scanline = scanline;
+        if scanline == 0 {
+            self.field = self.interlace() && !self.field;
+            self.frame_hires = false;
+            self.frame_interlace = self.interlace();
+            self.frame_overscan = self.overscan();
+            if !self.forced_blank() {
+                self.range_over = false;
+                self.time_over = false;
+            }
+        } else if scanline < self.vblank_line() {
+            self.render_line(scanline);
+        } else if scanline == self.vblank_line() {
+            if !self.forced_blank() {
+                self.oam_addr = self.oam_addr_reload << 1;
+            }
+            self.finish_frame();
+        }
+    }
+
+    // Registers
+
+    /// Latch the H/V counters for OPHCT/OPVCT, on a $2137 read or when WRIO
+    /// bit 7 falls
+    pub fn latch_counters(&mut self, hcounter: u16, vcounter: u16) {
+        self.hcounter = hcounter;
+        self.vcounter = vcounter;
+        self.counter_latched = true;
+    }
+
+    /// CPU read of $21xx; `open_bus` is the last value on the data bus
+    pub fn read_register(&mut self, addr: u16, open_bus: u8) -> u8 {
+        match addr & 0x3F {
+            // Write-only registers on PPU1 return its latch
+            0x04..=0x06 | 0x08..=0x0A | 0x14..=0x16 | 0x18..=0x1A | 0x24..=0x26 | 0x28..=0x2A => {
+                self.ppu1_open_bus
+            }
+            0x34..=0x36 => {
+                // MPYL/MPYM/MPYH: M7A times the last byte written to M7B
+                let product = self.mode7.a as i32 * (self.mode7.b >> 8) as i32;
+                let value = (product >> (((addr & 0x3F) - 0x34) * 8)) as u8;
+                self.ppu1_open_bus = value;
+                value
+            }
+            0x39 => {
+                // VMDATALREAD
+                let value = self.vram_read_buffer as u8;
+                if self.vmain & 0x80 == 0 {
+                    self.prefetch_and_increment();
+                }
+                self.ppu1_open_bus = value;
+                value
+            }
+            0x3A => {
+                // VMDATAHREAD
+                let value = (self.vram_read_buffer >> 8) as u8;
+                if self.vmain & 0x80 != 0 {
+                    self.prefetch_and_increment();
+                }
+                self.ppu1_open_bus = value;
+                value
+            }
+            0x3B => {
+                // CGDATAREAD
+                let color = self.cgram[self.cgram_addr as usize];
+                let value = if self.cgram_high {
+                    self.cgram_addr = self.cgram_addr.wrapping_add(1);
+                    ((color >> 8) as u8 & 0x7F) | (self.ppu2_open_bus & 0x80)
+                } else {
+                    color as u8
+                };
+                self.cgram_high = !self.cgram_high;
+                self.ppu2_open_bus = value;
+                value
+            }
+            0x38 => {
+                // OAMDATAREAD
+                let value = self.oam[self.oam_index()];
+                self.oam_addr = (self.oam_addr + 1) & 0x3FF;
+                self.ppu1_open_bus = value;
+                value
+            }
+            0x3C => {
+                // OPHCT: low byte, then bit 8
+                let value = if self.hcounter_high {
+                    ((self.hcounter >> 8) as u8 & 0x01) | (self.ppu2_open_bus & 0xFE)
+                } else {
+                    self.hcounter as u8
+                };
+                self.hcounter_high = !self.hcounter_high;
+                self.ppu2_open_bus = value;
+                value
+            }
+            0x3D => {
+                // OPVCT
+                let value = if self.vcounter_high {
+                    ((self.vcounter >> 8) as u8 & 0x01) | (self.ppu2_open_bus & 0xFE)
+                } else {
+                    self.vcounter as u8
+                };
+                self.vcounter_high = !self.vcounter_high;
+                self.ppu2_open_bus = value;
+                value
+            }
+            0x3E => {
+                // STAT77: time over, range over, PPU1 version 1
+                let value = ((self.time_over as u8) << 7) | ((self.range_over as u8) << 6)
+                    | (self.ppu1_open_bus & 0x10) | 0x01;
+                self.ppu1_open_bus = value;
+                value
+            }
+            0x3F => {
+                // STAT78: field, counter latch, PPU2 version 3; resets the
+                // OPHCT/OPVCT flip-flops
+                let value = ((self.field as u8) << 7) | ((self.counter_latched as u8) << 6)
+                    | (self.ppu2_open_bus & 0x20) | ((self.pal as u8) << 4) | 0x03;
+                self.counter_latched = false;
+                self.hcounter_high = false;
+                self.vcounter_high = false;
+                self.ppu2_open_bus = value;
+                value
+            }
+            _ => open_bus,
+        }
+    }
+
+    /// CPU write of $21xx
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        match addr & 0x3F {
+            0x00 => {
+                // Leaving forced blank on the vblank line reloads OAMADD
+                if self.forced_blank() && value & INIDISP_FORCED_BLANK == 0
+                    && self.scanline == self.vblank_line()
+                {
+                    self.oam_addr = self.oam_addr_reload << 1;
+                }
+                self.inidisp = value;
+            }
+            0x01 => self.obsel = value,
+            0x02 => {
+                self.oam_addr_reload = (self.oam_addr_reload & 0x100) | value as u16;
+                self.oam_addr = self.oam_addr_reload << 1;
+            }
+            0x03 => {
+                self.oam_addr_reload = (self.oam_addr_reload & 0xFF) | (((value & 0x01) as u16) << 8);
+                self.oam_priority_rotation = value & 0x80 != 0;
+                self.oam_addr = self.oam_addr_reload << 1;
+            }
+            0x04 => self.write_oam(value),
+            0x06 => self.mosaic = value,
+            0x05 => {
+                self.bgmode = value;
+                for (i, bg) in self.bg.iter_mut().enumerate() {
+                    bg.large_tiles = value & (0x10 << i) != 0;
+                }
+            }
+            0x07..=0x0A => {
+                let bg = &mut self.bg[(addr & 0x3F) as usize - 0x07];
+                bg.tilemap_addr = ((value & 0xFC) as u16) << 8;
+                bg.tilemap_wide = value & 0x01 != 0;
+                bg.tilemap_tall = value & 0x02 != 0;
+            }
+            0x0B => {
+                self.bg[0].char_addr = ((value & 0x0F) as u16) << 12;
+                self.bg[1].char_addr = ((value >> 4) as u16) << 12;
+            }
+            0x0C => {
+                self.bg[2].char_addr = ((value & 0x0F) as u16) << 12;
+                self.bg[3].char_addr = ((value >> 4) as u16) << 12;
+            }
+            0x0D..=0x14 => {
+                let index = ((addr & 0x3F) as usize - 0x0D) / 2;
+                if (addr & 0x3F) % 2 == 1 {
+                    // BGnHOFS: fine bits come from the horizontal latch
+                    let bg = &mut self.bg[index];
+                    bg.hofs = (((value as u16) << 8) | (self.bgofs_latch & !0x07) as u16
+                        | (self.bghofs_latch & 0x07) as u16) & 0x03FF;
+                    self.bghofs_latch = value;
+                    if index == 0 {
+                        self.mode7.hofs = self.mode7.word(value);
+                    }
+                } else {
+                    if index == 0 {
+                        self.mode7.vofs = self.mode7.word(value);
+                    }
+                    self.bg[index].vofs = (((value as u16) << 8) | self.bgofs_latch as u16) & 0x03FF;
+                }
+                self.bgofs_latch = value;
+            }
+            0x15 => self.vmain = value,
+            0x16 => {
+                self.vram_addr = (self.vram_addr & 0xFF00) | value as u16;
+                self.vram_read_buffer = self.vram[self.vram_word_addr()];
+            }
+            0x17 => {
+                self.vram_addr = (self.vram_addr & 0x00FF) | ((value as u16) << 8);
+                self.vram_read_buffer = self.vram[self.vram_word_addr()];
+            }
+            0x18 => {
+                self.write_vram(value, false);
+                if self.vmain & 0x80 == 0 {
+                    self.vram_addr = self.vram_addr.wrapping_add(self.vram_step());
+                }
+            }
+            0x19 => {
+                self.write_vram(value, true);
+                if self.vmain & 0x80 != 0 {
+                    self.vram_addr = self.vram_addr.wrapping_add(self.vram_step());
+                }
+            }
+            0x21 => {
+                self.cgram_addr = value;
+                self.cgram_high = false;
+            }
+            0x22 => {
+                if self.cgram_high {
+                    self.cgram[self.cgram_addr as usize] =
+                        (((value & 0x7F) as u16) << 8) | self.cgram_latch as u16;
+                    self.cgram_addr = self.cgram_addr.wrapping_add(1);
+                } else {
+                    self.cgram_latch = value;
+                }
+                self.cgram_high = !self.cgram_high;
+            }
+            0x1A => self.mode7.sel = value,
+            0x1B => self.mode7.a = self.mode7.word(value),
+            0x1C => self.mode7.b = self.mode7.word(value),
+            0x1D => self.mode7.c = self.mode7.word(value),
+            0x1E => self.mode7.d = self.mode7.word(value),
+            0x1F => self.mode7.x = self.mode7.word(value),
+            0x20 => self.mode7.y = self.mode7.word(value),
+            0x23..=0x25 => self.window_sel[(addr & 0x3F) as usize - 0x23] = value,
+            0x26 => self.window1.0 = value,
+            0x27 => self.window1.1 = value,
+            0x28 => self.window2.0 = value,
+            0x29 => self.window2.1 = value,
+            0x2A => self.window_bg_logic = value,
+            0x2B => self.window_obj_logic = value,
+            0x2C => self.main_screen = value,
+            0x2D => self.sub_screen = value,
+            0x2E => self.main_window = value,
+            0x2F => self.sub_window = value,
+            0x30 => self.cgwsel = value,
+            0x31 => self.cgadsub = value,
+            0x32 => {
+                // COLDATA: intensity into each selected channel
+                let intensity = (value & 0x1F) as u16;
+                if value & 0x20 != 0 {
+                    self.fixed_color = (self.fixed_color & !0x001F) | intensity;
+                }
+                if value & 0x40 != 0 {
+                    self.fixed_color = (self.fixed_color & !0x03E0) | (intensity << 5);
+                }
+                if value & 0x80 != 0 {
+                    self.fixed_color = (self.fixed_color & !0x7C00) | (intensity << 10);
+                }
+            }
+            0x33 => self.setini = value,
+            _ => {}
+        }
+    }
+
+    /// OAM byte the address points to; the high table repeats above $220
+    fn oam_index(&self) -> usize {
+        if self.oam_addr < 0x200 { self.oam_addr as usize } else { 0x200 | (self.oam_addr as usize & 0x1F) }
+    }
+
+    /// OAMDATA: the low table is written a word at a time, the second
+    /// byte together with the latched first one; the high table directly
+    fn write_oam(&mut self, value: u8) {
+        if self.oam_addr >= 0x200 {
+            self.oam[self.oam_index()] = value;
+        } else if self.oam_addr & 0x01 == 0 {
+            self.oam_latch = value;
+        } else {
+            let addr = self.oam_addr as usize;
+            self.oam[addr - 1] = self.oam_latch;
+            self.oam[addr] = value;
+        }
+        self.oam_addr = (self.oam_addr + 1) & 0x3FF;
+    }
+
+    /// VMAIN step: 1, 32 or 128 words
+    fn vram_step(&self) -> u16 {
+        match self.vmain & 0x03 {
+            0 => 1,
+            1 => 32,
+            _ => 128,
+        }
+    }
+
+    /// VMADD after the VMAIN address translation, which turns bitmap
+    /// style writes into 2/4/8bpp tile rows
+    fn vram_word_addr(&self) -> usize {
+        let addr = self.vram_addr;
+        let remapped = match (self.vmain >> 2) & 0x03 {
+            0 => addr,
+            1 => (addr & 0xFF00) | ((addr & 0x001F) << 3) | ((addr >> 5) & 0x07),
+            2 => (addr & 0xFE00) | ((addr & 0x003F) << 3) | ((addr >> 6) & 0x07),
+            _ => (addr & 0xFC00) | ((addr & 0x007F) << 3) | ((addr >> 7) & 0x07),
+        };
+        remapped as usize & (VRAM_WORDS - 1)
+    }
+
+    fn write_vram(&mut self, value: u8, high: bool) {
+        // VRAM is only reachable during vblank or forced blank
+        if !self.forced_blank() && (1..self.vblank_line()).contains(&self.scanline) {
+            return;
+        }
+        let addr = self.vram_word_addr();
+        let word = &mut self.vram[addr];
+        *word = if high {
+            (*word & 0x00FF) | ((value as u16) << 8)
+        } else {
+            (*word & 0xFF00) | value as u16
+        };
+    }
+
+    fn prefetch_and_increment(&mut self) {
+        self.vram_read_buffer = self.vram[self.vram_word_addr()];
+        self.vram_addr = self.vram_addr.wrapping_add(self.vram_step());
+    }
+
+    // Rendering
+
+    fn mode(&self) -> u8 {
+        self.bgmode & 0x07
+    }
+
+    /// Bits per pixel of each background in the current mode
+    fn bg_bpp(&self, index: usize) -> Option<u8> {
+        let bpp: &[u8] = match self.mode() {
+            0 => &[2, 2, 2, 2],
+            1 => &[4, 4, 2],
+            2 => &[4, 4],
+            3 => &[8, 4],
+            4 => &[8, 2],
+            5 => &[4, 2],
+            6 => &[4],
+            _ => &[],
+        };
+        bpp.get(index).copied()
+    }
+
+    fn hires(&self) -> bool {
+        matches!(self.mode(), 5 | 6)
+    }
+
+    fn offset_per_tile(&self) -> bool {
+        matches!(self.mode(), 2 | 4 | 6)
+    }
+
+    /// Front-to-back order of (layer, priority bit) for the current mode.
+    /// OBJ entries are priorities 3 down to 0.
+    fn priority_order(&self) -> &'static [(Layer, u8)] {
+        use Layer::*;
+        match self.mode() {
+            0 => &[(Obj, 3), (Bg1, 1), (Bg2, 1), (Obj, 2), (Bg1, 0), (Bg2, 0),
+                   (Obj, 1), (Bg3, 1), (Bg4, 1), (Obj, 0), (Bg3, 0), (Bg4, 0)],
+            1 if self.bgmode & 0x08 != 0 => &[(Bg3, 1), (Obj, 3), (Bg1, 1), (Bg2, 1), (Obj, 2),
+                   (Bg1, 0), (Bg2, 0), (Obj, 1), (Obj, 0), (Bg3, 0)],
+            1 => &[(Obj, 3), (Bg1, 1), (Bg2, 1), (Obj, 2), (Bg1, 0), (Bg2, 0),
+                   (Obj, 1), (Bg3, 1), (Obj, 0), (Bg3, 0)],
+            6 => &[(Obj, 3), (Bg1, 1), (Obj, 2), (Obj, 1), (Bg1, 0), (Obj, 0)],
+            // BG1 has no priority bit; EXTBG BG2 takes it from pixel bit 7
+            7 if self.extbg() => &[(Obj, 3), (Obj, 2), (Bg2, 1), (Obj, 1), (Bg1, 0), (Obj, 0), (Bg2, 0)],
+            7 => &[(Obj, 3), (Obj, 2), (Obj, 1), (Bg1, 0), (Obj, 0)],
+            _ => &[(Obj, 3), (Bg1, 1), (Obj, 2), (Bg2, 1), (Obj, 1), (Bg1, 0), (Obj, 0), (Bg2, 0)],
+        }
+    }
+
+    fn rank(&self, layer: Layer, priority: u8) -> u8 {
+        self.priority_order()
+            .iter()
+            .position(|&entry| entry == (layer, priority))
+            .map_or(BACKDROP_RANK, |rank| rank as u8)
+    }
+
+    /// Draw a visible scanline (1-224, or 1-239 with overscan)
+    fn render_line(&mut self, y: u16) {
+        let row = if self.frame_interlace { (y as usize - 1) * 2 + self.field as usize } else { y as usize - 1 };
+        if row >= MAX_HEIGHT {
+            return;
+        }
+        if self.forced_blank() {
+            for pixel in self.screen[row * MAX_WIDTH * 4..(row + 1) * MAX_WIDTH * 4].chunks_mut(4) {
+                pixel.copy_from_slice(&[0, 0, 0, 255]);
+            }
+            if self.mode7_scale > 1 {
+                self.flush_mode7_hd(None);
+                let width = SCREEN_WIDTH * self.mode7_scale * 4;
+                let first = (y as usize - 1) * self.mode7_scale;
+                let rows = &mut self.scaled_screen[first * width..(first + self.mode7_scale) * width];
+                for pixel in rows.chunks_mut(4) {
+                    pixel.copy_from_slice(&[0, 0, 0, 255]);
+                }
+            }
+            return;
+        }
+
+        self.main_line = [LinePixel { color: self.cgram[0], ..BACKDROP }; SCREEN_WIDTH];
+        self.sub_line = [LinePixel { color: self.fixed_color, ..BACKDROP }; SCREEN_WIDTH];
+
+        if self.mode() == 7 {
+            self.render_mode7(y);
+        } else {
+            for (index, &layer) in BG_LAYERS.iter().enumerate() {
+                if (self.main_screen | self.sub_screen) & layer.bit() == 0 {
+                    continue;
+                }
+                let Some(bpp) = self.bg_bpp(index) else { continue };
+                let (main, sub) = self.bg_line(index, bpp, y);
+                self.add_layer(layer, &main, &sub);
+            }
+        }
+
+        let obj = self.obj_line(y);
+        if (self.main_screen | self.sub_screen) & Layer::Obj.bit() != 0 {
+            self.add_layer(Layer::Obj, &obj, &obj);
+        }
+
+        self.output_line(row);
+        if self.mode7_scale > 1 {
+            self.queue_mode7_hd(y);
+        }
+    }
+
+    fn sprite(&self, index: usize) -> Sprite {
+        let entry = &self.oam[index * 4..index * 4 + 4];
+        let high = self.oam[0x200 + index / 4] >> ((index % 4) * 2);
+        let (small, large) = OBJ_SIZES[(self.obsel >> 5) as usize];
+        let (width, height) = if high & 0x02 != 0 { large } else { small };
+        Sprite {
+            x: entry[0] as u16 | (((high & 0x01) as u16) << 8),
+            y: entry[1],
+            tile: entry[2] as u16 | (((entry[3] & 0x01) as u16) << 8),
+            attr: entry[3],
+            width,
+            height,
+        }
+    }
+
+    /// Sprite row shown on line `y`, if the sprite covers it. OBJ interlace
+    /// shows every other row, alternating with the field.
+    fn sprite_row(&self, sprite: &Sprite, y: u16) -> Option<u16> {
+        let interlace = self.setini & SETINI_OBJ_INTERLACE != 0;
+        let height = if interlace { sprite.height / 2 } else { sprite.height };
+        let row = ((y - 1) as u8).wrapping_sub(sprite.y) as u16;
+        if row >= height {
+            return None;
+        }
+        Some(if interlace { row * 2 + self.field as u16 } else { row })
+    }
+
+    /// Evaluate and draw the sprites of line `y`. The first 32 sprites on
+    /// the line are in range; their slivers are then fetched last to first
+    /// until 34 are loaded, so the lowest-numbered sprites lose out.
+    fn obj_line(&mut self, y: u16) -> LayerLine {
+        let first = if self.oam_priority_rotation { (self.oam_addr_reload >> 1) as usize & 0x7F } else { 0 };
+        let mut range = Vec::with_capacity(OBJ_RANGE_LIMIT);
+        for n in 0..128 {
+            let sprite = self.sprite((first + n) & 0x7F);
+            let Some(row) = self.sprite_row(&sprite, y) else { continue };
+            // Fully off the left edge, except X=-256 which still counts
+            if sprite.x > 256 && sprite.x + sprite.width - 1 < 512 {
+                continue;
+            }
+            if range.len() == OBJ_RANGE_LIMIT {
+                self.range_over = true;
+                break;
+            }
+            range.push((sprite, row));
+        }
+
+        let mut line = [None; SCREEN_WIDTH];
+        let mut slivers = 0;
+        'fetch: for &(sprite, row) in range.iter().rev() {
+            let row = if sprite.attr & 0x80 != 0 { sprite.vflip_row(row) } else { row };
+            for column in 0..sprite.width / 8 {
+                let x = (sprite.x + column * 8) & 0x1FF;
+                if x > 256 && x + 7 < 512 {
+                    continue;
+                }
+                if slivers == OBJ_TIME_LIMIT {
+                    self.time_over = true;
+                    break 'fetch;
+                }
+                slivers += 1;
+                self.draw_sliver(&mut line, &sprite, x, column, row);
+            }
+        }
+        line
+    }
+
+    /// Draw 8 pixels of a sprite row at screen X `x` (9 bits); lower
+    /// numbered sprites are drawn later and cover higher ones
+    fn draw_sliver(&self, line: &mut LayerLine, sprite: &Sprite, x: u16, column: u16, row: u16) {
+        let column = if sprite.hflip() { sprite.width / 8 - 1 - column } else { column };
+        // Characters of a large sprite are laid out in a 16x16 grid
+        let name = sprite.tile & 0xFF;
+        let tile = ((name + column) & 0x0F) | ((name + ((row / 8) << 4)) & 0xF0);
+        let mut char_addr = ((self.obsel & 0x07) as u16) << 13;
+        if sprite.tile & 0x100 != 0 {
+            char_addr = char_addr.wrapping_add((((self.obsel >> 3) & 0x03) as u16 + 1) << 12);
+        }
+
+        let palette = (sprite.attr >> 1) & 0x07;
+        for offset in 0..8 {
+            let sx = (x + offset) & 0x1FF;
+            if sx >= SCREEN_WIDTH as u16 {
+                continue;
+            }
+            let tx = if sprite.hflip() { 7 - offset } else { offset };
+            let pixel = self.tile_pixel(char_addr, tile, 4, tx, row & 0x07);
+            if pixel != 0 {
+                line[sx as usize] = Some(LayerPixel {
+                    color: self.cgram[0x80 + palette as usize * 16 + pixel as usize],
+                    priority: (sprite.attr >> 4) & 0x03,
+                    // Only palettes 4-7 take part in color math
+                    math: palette >= 4,
+                });
+            }
+        }
+    }
+
+    fn extbg(&self) -> bool {
+        self.setini & SETINI_EXTBG != 0
+    }
+
+    /// Mosaic block size for a background, 1 when off
+    fn mosaic_size(&self, index: usize) -> usize {
+        if self.mosaic & (1 << index) != 0 { (self.mosaic >> 4) as usize + 1 } else { 1 }
+    }
+
+    /// Line whose pixels a mosaic block repeats
+    fn mosaic_y(y: u16, size: usize) -> u16 {
+        y - (y - 1) % size as u16
+    }
+
+    /// One background line for the main and sub screen. They only differ
+    /// in hi-res modes, where the sub screen gets the even 512-wide pixels.
+    fn bg_line(&self, index: usize, bpp: u8, y: u16) -> (LayerLine, LayerLine) {
+        let size = self.mosaic_size(index);
+        let mut y = Self::mosaic_y(y, size);
+        if self.hires() && self.interlace() {
+            y = y * 2 + self.field as u16;
+        }
+
+        let mut main = [None; SCREEN_WIDTH];
+        let mut sub = [None; SCREEN_WIDTH];
+        for x in 0..SCREEN_WIDTH {
+            let mx = (x - x % size) as u16;
+            if self.hires() {
+                main[x] = self.bg_pixel(index, bpp, mx * 2 + 1, y);
+                sub[x] = self.bg_pixel(index, bpp, mx * 2, y);
+            } else {
+                main[x] = self.bg_pixel(index, bpp, mx, y);
+                sub[x] = main[x];
+            }
+        }
+        (main, sub)
+    }
+
+    /// Mode 7 BG1, and BG2 when EXTBG is on
+    fn render_mode7(&mut self, y: u16) {
+        let size = self.mosaic_size(0);
+        let pixels = self.mode7_line(Self::mosaic_y(y, size));
+
+        let mut bg1 = [None; SCREEN_WIDTH];
+        let mut bg2 = [None; SCREEN_WIDTH];
+        for x in 0..SCREEN_WIDTH {
+            let Some(pixel) = pixels[x - x % size] else { continue };
+            let color = if self.cgwsel & 0x01 != 0 { direct_color(pixel, 0) } else { self.cgram[pixel as usize] };
+            bg1[x] = Some(LayerPixel { color, priority: 0, math: true });
+            // EXTBG: 7-bit color, bit 7 is the priority
+            if pixel & 0x7F != 0 {
+                let color = self.cgram[(pixel & 0x7F) as usize];
+                bg2[x] = Some(LayerPixel { color, priority: pixel >> 7, math: true });
+            }
+        }
+
+        self.add_layer(Layer::Bg1, &bg1, &bg1);
+        if self.extbg() {
+            self.add_layer(Layer::Bg2, &bg2, &bg2);
+        }
+    }
+
+    /// Put a layer onto the main and sub screens where it is enabled, not
+    /// masked by its window and in front of what is already there
+    fn add_layer(&mut self, layer: Layer, main: &LayerLine, sub: &LayerLine) {
+        let ranks: [u8; 4] = std::array::from_fn(|priority| self.rank(layer, priority as u8));
+        let bit = layer.bit();
+        let window = self.layer_window(layer);
+        let on_main = self.main_screen & bit != 0;
+        let on_sub = self.sub_screen & bit != 0;
+
+        for x in 0..SCREEN_WIDTH {
+            if on_main && !(self.main_window & bit != 0 && window[x]) {
+                if let Some(pixel) = main[x] {
+                    let rank = ranks[pixel.priority as usize & 0x03];
+                    if rank < self.main_line[x].rank {
+                        self.main_line[x] = LinePixel { color: pixel.color, rank, layer, math: pixel.math };
+                    }
+                }
+            }
+            if on_sub && !(self.sub_window & bit != 0 && window[x]) {
+                if let Some(pixel) = sub[x] {
+                    let rank = ranks[pixel.priority as usize & 0x03];
+                    if rank < self.sub_line[x].rank {
+                        self.sub_line[x] = LinePixel { color: pixel.color, rank, layer, math: pixel.math };
+                    }
+                }
+            }
+        }
+    }
+
+    /// Window area of a layer, combined with its logic op
+    fn layer_window(&self, layer: Layer) -> [bool; SCREEN_WIDTH] {
+        let (sel, logic) = match layer {
+            Layer::Bg1 => (self.window_sel[0], self.window_bg_logic),
+            Layer::Bg2 => (self.window_sel[0] >> 4, self.window_bg_logic >> 2),
+            Layer::Bg3 => (self.window_sel[1], self.window_bg_logic >> 4),
+            Layer::Bg4 => (self.window_sel[1] >> 4, self.window_bg_logic >> 6),
+            Layer::Obj => (self.window_sel[2], self.window_obj_logic),
+            // The color window
+            Layer::Backdrop => (self.window_sel[2] >> 4, self.window_obj_logic >> 2),
+        };
+        self.window_mask(sel & 0x0F, logic & 0x03)
+    }
+
+    /// `sel`: window 1 invert/enable (bits 0-1), window 2 (bits 2-3)
+    fn window_mask(&self, sel: u8, logic: u8) -> [bool; SCREEN_WIDTH] {
+        let mut mask = [false; SCREEN_WIDTH];
+        let (enable1, enable2) = (sel & 0x02 != 0, sel & 0x08 != 0);
+        if !enable1 && !enable2 {
+            return mask;
+        }
+
+        for (x, masked) in mask.iter_mut().enumerate() {
+            let x = x as u8;
+            let in1 = (self.window1.0 <= x && x <= self.window1.1) != (sel & 0x01 != 0);
+            let in2 = (self.window2.0 <= x && x <= self.window2.1) != (sel & 0x04 != 0);
+            *masked = match (enable1, enable2) {
+                (true, false) => in1,
+                (false, true) => in2,
+                _ => match logic {
+                    0 => in1 || in2,
+                    1 => in1 && in2,
+                    2 => in1 != in2,
+                    _ => in1 == in2,
+                },
+            };
+        }
+        mask
+    }
+
+    /// Color indexes of one Mode 7 line (`None` = transparent), with the
+    /// hardware's fixed-point rounding
+    fn mode7_line(&self, y: u16) -> [Option<u8>; SCREEN_WIDTH] {
+        let mut pixels = [None; SCREEN_WIDTH];
+        for (x, out) in pixels.iter_mut().enumerate() {
+            let (px, py) = self.mode7.position(x as i64, y as i64, 1);
+            *out = self.mode7_texel(self.mode7.sel, (px >> 8) as i32, (py >> 8) as i32);
+        }
+        pixels
+    }
+
+    /// Color index at a texel of the 1024x1024 plane, or what lies outside
+    /// it per the screen over setting
+    fn mode7_texel(&self, sel: u8, px: i32, py: i32) -> Option<u8> {
+        let outside = !(0..1024).contains(&px) || !(0..1024).contains(&py);
+        let tile = match sel >> 6 {
+            2 if outside => return None,
+            3 if outside => 0,
+            _ => {
+                let map_addr = (((py & 0x3FF) >> 3) << 7) | ((px & 0x3FF) >> 3);
+                self.vram[map_addr as usize] & 0x00FF
+            }
+        };
+        let char_addr = (tile << 6) as i32 | ((py & 0x07) << 3) | (px & 0x07);
+        let pixel = (self.vram[char_addr as usize] >> 8) as u8;
+        (pixel != 0).then_some(pixel)
+    }
+
+    /// Hold the line just drawn for the hi-res Mode 7 pass and draw the
+    /// previous one, now that this line's matrix is known
+    fn queue_mode7_hd(&mut self, y: u16) {
+        let line = Box::new(Mode7Line {
+            y,
+            mode7: (self.mode() == 7 && self.mosaic_size(0) == 1).then_some(self.mode7),
+            main: self.main_line,
+            sub: self.sub_line,
+            color_window: self.layer_window(Layer::Backdrop),
+            math: self.color_math(),
+            brightness: (self.inidisp & 0x0F) as u32,
+        });
+        self.flush_mode7_hd(Some(&line));
+        self.mode7_pending = Some(line);
+    }
+
+    /// Draw the held line's scaled rows; `next` is the line below it
+    fn flush_mode7_hd(&mut self, next: Option<&Mode7Line>) {
+        let Some(line) = self.mode7_pending.take() else { return };
+        let next = next.filter(|next| next.y == line.y + 1).and_then(|next| next.mode7.as_ref());
+        let scale = self.mode7_scale;
+        let width = SCREEN_WIDTH * scale;
+        for j in 0..scale {
+            let row = (line.y as usize - 1) * scale + j;
+            for x in 0..SCREEN_WIDTH {
+                let main = line.main[x];
+                let native = line.math.apply(main, line.sub[x], line.color_window[x]);
+                for i in 0..scale {
+                    let color = match self.mode7_hd_color(&line, next, main.layer, x * scale + i, j) {
+                        Some(color) => line.math.apply(LinePixel { color, ..main }, line.sub[x], line.color_window[x]),
+                        None => native,
+                    };
+                    let [r, g, b] = bgr555_to_rgb(color, line.brightness);
+                    let offset = (row * width + x * scale + i) * 4;
+                    self.scaled_screen[offset..offset + 4].copy_from_slice(&[r, g, b, 255]);
+                }
+            }
+        }
+    }
+
+    /// Mode 7 color at sub-pixel column `sx` and sub-row `j` of a held line,
+    /// for the layer shown there; `None` keeps the native pixel
+    fn mode7_hd_color(&self, line: &Mode7Line, next: Option<&Mode7>, layer: Layer, sx: usize, j: usize) -> Option<u16> {
+        let m7 = line.mode7.as_ref()?;
+        if !matches!(layer, Layer::Bg1 | Layer::Bg2) {
+            return None;
+        }
+        let scale = self.mode7_scale as i64;
+        let (sx, j, y) = (sx as i64, j as i64, line.y as i64);
+        let (px, py) = match next {
+            Some(next) if self.mode7_perspective => {
+                let (x0, y0) = m7.position(sx, y * scale, scale);
+                let (x1, y1) = next.position(sx, (y + 1) * scale, scale);
+                ((x0 * (scale - j) + x1 * j).div_euclid(scale), (y0 * (scale - j) + y1 * j).div_euclid(scale))
+            }
+            _ => m7.position(sx, y * scale + j, scale),
+        };
+        let texel = 256 * scale;
+        let pixel = self.mode7_texel(m7.sel, px.div_euclid(texel) as i32, py.div_euclid(texel) as i32)?;
+        match layer {
+            Layer::Bg1 if line.math.cgwsel & 0x01 != 0 => Some(direct_color(pixel, 0)),
+            Layer::Bg1 => Some(self.cgram[pixel as usize]),
+            _ => (pixel & 0x7F != 0).then(|| self.cgram[(pixel & 0x7F) as usize]),
+        }
+    }
+
+    /// Scroll of a background at a screen column, after offset-per-tile
+    fn scroll_at(&self, index: usize, x: u16) -> (u16, u16) {
+        let bg = &self.bg[index];
+        let (mut hofs, mut vofs) = (bg.hofs, bg.vofs);
+        if !self.offset_per_tile() || index > 1 {
+            return (hofs, vofs);
+        }
+
+        let fine = if self.hires() { (hofs << 1) & 0x0F } else { hofs & 0x07 };
+        let tile_width = if self.hires() { 16 } else { 8 };
+        let column = (x + fine) / tile_width;
+        if column == 0 {
+            return (hofs, vofs);
+        }
+
+        // BG3's tilemap row holds one offset entry per column
+        let bg3 = &self.bg[2];
+        let lookup_x = (column - 1) * 8 + (bg3.hofs & !0x07);
+        let valid = 0x2000 << index;
+        let hval = self.tilemap_entry(2, lookup_x, bg3.vofs, 8, 8);
+        if self.mode() == 4 {
+            if hval & valid != 0 {
+                if hval & 0x8000 != 0 {
+                    vofs = hval & 0x03FF;
+                } else {
+                    hofs = (hval & 0x03F8) | (hofs & 0x07);
+                }
+            }
+        } else {
+            let vval = self.tilemap_entry(2, lookup_x, bg3.vofs.wrapping_add(8), 8, 8);
+            if hval & valid != 0 {
+                hofs = (hval & 0x03F8) | (hofs & 0x07);
+            }
+            if vval & valid != 0 {
+                vofs = vval & 0x03FF;
+            }
+        }
+        (hofs, vofs)
+    }
+
+    /// Tilemap entry covering a background pixel
+    fn tilemap_entry(&self, index: usize, px: u16, py: u16, tile_width: u16, tile_height: u16) -> u16 {
+        let bg = &self.bg[index];
+        let tx = px / tile_width;
+        let ty = py / tile_height;
+        let mut addr = bg.tilemap_addr + ((ty & 0x1F) << 5) + (tx & 0x1F);
+        if bg.tilemap_wide && tx & 0x20 != 0 {
+            addr += 0x400;
+        }
+        if bg.tilemap_tall && ty & 0x20 != 0 {
+            addr += if bg.tilemap_wide { 0x800 } else { 0x400 };
+        }
+        self.vram[addr as usize & (VRAM_WORDS - 1)]
+    }
+
+    /// Color and priority bit of an opaque background pixel
+    fn bg_pixel(&self, index: usize, bpp: u8, x: u16, y: u16) -> Option<LayerPixel> {
+        let bg = &self.bg[index];
+        let (hofs, vofs) = self.scroll_at(index, x);
+        let tile_width = if bg.large_tiles || self.hires() { 16 } else { 8 };
+        let tile_height = if bg.large_tiles { 16 } else { 8 };
+
+        // Hi-res modes scroll in 512-wide pixels
+        let hofs = if self.hires() { hofs << 1 } else { hofs };
+        let px = x.wrapping_add(hofs);
+        let py = y.wrapping_add(vofs);
+
+        let entry = self.tilemap_entry(index, px, py, tile_width, tile_height);
+        let mut fx = px % tile_width;
+        let mut fy = py % tile_height;
+        if entry & 0x4000 != 0 {
+            fx = tile_width - 1 - fx;
+        }
+        if entry & 0x8000 != 0 {
+            fy = tile_height - 1 - fy;
+        }
+
+        let tile = (entry & 0x03FF) + (fx >> 3) + ((fy >> 3) << 4);
+        let pixel = self.tile_pixel(bg.char_addr, tile & 0x03FF, bpp, fx & 0x07, fy & 0x07);
+        if pixel == 0 {
+            return None;
+        }
+
+        let palette = (entry >> 10) & 0x07;
+        let priority = ((entry >> 13) & 0x01) as u8;
+        let color = match bpp {
+            2 if self.mode() == 0 => self.cgram[(index as u16 * 32 + palette * 4 + pixel as u16) as usize],
+            2 => self.cgram[(palette * 4 + pixel as u16) as usize],
+            4 => self.cgram[(palette * 16 + pixel as u16) as usize],
+            _ if self.cgwsel & 0x01 != 0 => direct_color(pixel, palette as u8),
+            _ => self.cgram[pixel as usize],
+        };
+        Some(LayerPixel { color, priority, math: true })
+    }
+
+    /// Color index of one pixel of a 2/4/8bpp character
+    fn tile_pixel(&self, char_addr: u16, tile: u16, bpp: u8, x: u16, y: u16) -> u8 {
+        let base = char_addr.wrapping_add(tile.wrapping_mul(bpp as u16 * 4));
+        let shift = 7 - x;
+        let mut pixel = 0;
+        for pair in 0..(bpp as u16 / 2) {
+            let word = self.vram[(base.wrapping_add(pair * 8 + y)) as usize & (VRAM_WORDS - 1)];
+            pixel |= (((word >> shift) & 0x01) as u8) << (pair * 2);
+            pixel |= (((word >> (8 + shift)) & 0x01) as u8) << (pair * 2 + 1);
+        }
+        pixel
+    }
+
+    /// Apply clipping and color math, then write the line 512 wide: hi-res
+    /// and pseudo hi-res lines alternate sub and main screen pixels
+    fn output_line(&mut self, row: usize) {
+        let brightness = (self.inidisp & 0x0F) as u32;
+        let color_window = self.layer_window(Layer::Backdrop);
+        let hires = self.hires() || self.setini & SETINI_PSEUDO_HIRES != 0;
+        self.frame_hires |= hires;
+
+        let math = self.color_math();
+        for (x, &in_window) in color_window.iter().enumerate() {
+            let sub = self.sub_line[x];
+            let color = math.apply(self.main_line[x], sub, in_window);
+
+            let even = if hires { sub.color } else { color };
+            let i = (row * MAX_WIDTH + x * 2) * 4;
+            let left = bgr555_to_rgb(even, brightness);
+            let right = bgr555_to_rgb(color, brightness);
+            self.screen[i..i + 8].copy_from_slice(&[left[0], left[1], left[2], 255, right[0], right[1], right[2], 255]);
+        }
+    }
+
+    fn color_math(&self) -> ColorMath {
+        ColorMath { cgwsel: self.cgwsel, cgadsub: self.cgadsub, fixed_color: self.fixed_color }
+    }
+
+    /// Copy the drawn lines into a framebuffer of this frame's size
+    fn finish_frame(&mut self) {
+        let width = if self.frame_hires { MAX_WIDTH } else { SCREEN_WIDTH };
+        let lines = if self.frame_overscan { 239 } else { SCREEN_HEIGHT };
+        let height = if self.frame_interlace { lines * 2 } else { lines };
+
+        self.framebuffer.resize(width * height * 4, 0);
+        for row in 0..height {
+            let src = &self.screen[row * MAX_WIDTH * 4..(row + 1) * MAX_WIDTH * 4];
+            let dst = &mut self.framebuffer[row * width * 4..(row + 1) * width * 4];
+            if self.frame_hires {
+                dst.copy_from_slice(src);
+            } else {
+                for (out, pair) in dst.chunks_mut(4).zip(src.chunks(8)) {
+                    out.copy_from_slice(&pair[4..8]);
+                }
+            }
+        }
+        self.frame_size = (width as u32, height as u32);
+
+        self.flush_mode7_hd(None);
+        self.scaled_framebuffer.clear();
+        if self.mode7_scale > 1 && !self.frame_hires && !self.frame_interlace {
+            let size = SCREEN_WIDTH * self.mode7_scale * lines * self.mode7_scale * 4;
+            self.scaled_framebuffer.extend_from_slice(&self.scaled_screen[..size]);
+            self.scaled_frame_size = ((SCREEN_WIDTH * self.mode7_scale) as u32, (lines * self.mode7_scale) as u32);
+        }
+    }
+}
+
+/// Color math on two 15-bit colors, per channel with clamping
+fn blend(a: u16, b: u16, subtract: bool, half: bool) -> u16 {
+    [0, 5, 10].into_iter().fold(0, |out, shift| {
+        let x = ((a >> shift) & 0x1F) as i32;
+        let y = ((b >> shift) & 0x1F) as i32;
+        let mut value = if subtract { x - y } else { x + y };
+        if half {
+            value >>= 1;
+        }
+        out | ((value.clamp(0, 31) as u16) << shift)
+    })
+}
+
+/// 8bpp pixel shown as BBGGGRRR plus the palette bits as low color bits
+fn direct_color(pixel: u8, palette: u8) -> u16 {
+    let r = ((pixel & 0x07) << 2) | ((palette & 0x01) << 1);
+    let g = (((pixel >> 3) & 0x07) << 2) | (palette & 0x02);
+    let b = (((pixel >> 6) & 0x03) << 3) | (palette & 0x04);
+    (r as u16) | ((g as u16) << 5) | ((b as u16) << 10)
+}
+
+/// 15-bit BGR to 8-bit RGB, scaled by the INIDISP brightness (0-15)
+fn bgr555_to_rgb(color: u16, brightness: u32) -> [u8; 3] {
+    let channel = |shift: u16| {
+        let c5 = ((color >> shift) & 0x1F) as u32;
+        let c8 = (c5 << 3) | (c5 >> 2);
+        (c8 * brightness / 15) as u8
+    };
+    [channel(0), channel(5), channel(10)]
+}
+
+impl Default for PPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unscaled matrix with the given M7SEL and scroll
+    fn mode7(sel: u8, hofs: i16, vofs: i16) -> Mode7 {
+        Mode7 { sel, a: 0x100, d: 0x100, hofs, vofs, ..Default::default() }
+    }
+
+    /// Texel a position from `Mode7::position` falls in
+    fn texel((x, y): (i64, i64), scale: i64) -> (i64, i64) {
+        ((x >> 8).div_euclid(scale), (y >> 8).div_euclid(scale))
+    }
+
+    #[test]
+    fn native_flips() {
+        let m7 = mode7(0x00, 0, 0);
+        assert_eq!(m7.position(0, 0, 1), (0, 0));
+        assert_eq!(m7.position(10, 20, 1), (10 << 8, 20 << 8));
+
+        let m7 = mode7(0x03, 0, 0);
+        assert_eq!(m7.position(0, 0, 1), (255 << 8, 255 << 8));
+        assert_eq!(m7.position(255, 255, 1), (0, 0));
+    }
+
+    #[test]
+    fn scaled_sub_pixels_stay_in_the_native_texel() {
+        for sel in 0..4 {
+            for (hofs, vofs) in [(0, 0), (37, -5)] {
+                let m7 = mode7(sel, hofs, vofs);
+                for scale in 2..=4 {
+                    for x in [0, 1, 127, 128, 254, 255] {
+                        for j in 0..scale {
+                            let native = texel(m7.position(x, x, 1), 1);
+                            let scaled = texel(m7.position(x * scale + j, x * scale + j, scale), scale);
+                            assert_eq!(scaled, native, "sel {} scale {} x {} sub-pixel {}", sel, scale, x, j);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scaled_flip_mirrors_sub_pixels() {
+        let flipped = mode7(0x03, 0, 0);
+        let plain = mode7(0x00, 0, 0);
+        for scale in 2..=4 {
+            let last = 256 * scale - 1;
+            for x in [0, 1, scale - 1, 100, last] {
+                assert_eq!(flipped.position(x, x, scale), plain.position(last - x, last - x, scale));
+            }
+        }
+    }
+}
@@ -0,0 +1,295 @@
+/// Epson RTC-4513
+/// Real-time clock on the Tengai Makyou Zero board, reached through the
+/// SPC7110 at $4840-$4842. The CPU talks to it serially: after chip select,
+/// a command nibble (3 = write, C = read), a register index, then data
+/// nibbles that step through sixteen 4-bit registers holding the time in BCD.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::apu::{NTSC_MASTER_CLOCK, PAL_MASTER_CLOCK};
+
+// Register indices
+const SECOND_LO: usize = 0x0;
+const MINUTE_LO: usize = 0x2;
+const HOUR_LO: usize = 0x4;
+const HOUR_HI: usize = 0x5;
+const DAY_LO: usize = 0x6;
+const MONTH_LO: usize = 0x8;
+const YEAR_LO: usize = 0xA;
+const WEEKDAY: usize = 0xC;
+const CONTROL_D: usize = 0xD;
+const CONTROL_F: usize = 0xF;
+
+/// Hour tens bit 2 in 12-hour mode
+const HOUR_PM: u8 = 0x04;
+
+// Register D
+const D_HOLD: u8 = 0x01;
+const D_CALENDAR: u8 = 0x02;
+const D_IRQ_FLAG: u8 = 0x04;
+
+// Register F
+const F_PAUSE: u8 = 0x01;
+const F_STOP: u8 = 0x02;
+const F_24_HOUR: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Command,
+    Index,
+    Read,
+    Write,
+}
+
+pub struct Rtc4513 {
+    regs: [u8; 16],
+    chip_select: u8,
+    state: State,
+    command: u8,
+    index: u8,
+    /// Last nibble on the serial line
+    mdr: u8,
+    /// A second went by while HOLD was set
+    held_tick: bool,
+    /// Master cycles into the current second
+    cycles: i64,
+}
+
+impl Rtc4513 {
+    /// Starts out on the host's clock (UTC), in 24-hour mode
+    pub fn new() -> Self {
+        let mut rtc = Self {
+            regs: [0; 16],
+            chip_select: 0,
+            state: State::Command,
+            command: 0,
+            index: 0,
+            mdr: 0,
+            held_tick: false,
+            cycles: 0,
+        };
+        rtc.regs[CONTROL_D] = D_CALENDAR;
+        rtc.regs[CONTROL_F] = F_24_HOUR;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        rtc.set_time(now);
+        rtc
+    }
+
+    /// Load the registers from seconds since 1970
+    fn set_time(&mut self, timestamp: u64) {
+        let days = (timestamp / 86400) as i64;
+        let seconds = timestamp % 86400;
+        let (year, month, day) = civil_from_days(days);
+
+        self.set_bcd(SECOND_LO, (seconds % 60) as u8, 0x07);
+        self.set_bcd(MINUTE_LO, (seconds / 60 % 60) as u8, 0x07);
+        self.set_bcd(HOUR_LO, (seconds / 3600) as u8, 0x03);
+        self.set_bcd(DAY_LO, day, 0x03);
+        self.set_bcd(MONTH_LO, month, 0x01);
+        self.set_bcd(YEAR_LO, (year % 100) as u8, 0x0F);
+        // 1970-01-01 was a Thursday
+        self.regs[WEEKDAY] = ((days + 4).rem_euclid(7)) as u8;
+    }
+
+    /// Chip select dropped: back to waiting for a command
+    fn reset_serial(&mut self) {
+        self.state = State::Command;
+        self.index = 0;
+    }
+
+    /// Count the clock along with the master clock
+    pub fn run(&mut self, master_cycles: u64, pal: bool) {
+        let second = if pal { PAL_MASTER_CLOCK } else { NTSC_MASTER_CLOCK };
+        self.cycles += master_cycles as i64;
+        while self.cycles >= second {
+            self.cycles -= second;
+            if self.regs[CONTROL_F] & (F_PAUSE | F_STOP) == 0 {
+                self.tick_second();
+            }
+        }
+    }
+
+    /// $4840-$4842
+    pub fn read(&mut self, addr: u16) -> u8 {
+        match addr & 0x03 {
+            0 => self.chip_select,
+            1 => {
+                if self.chip_select != 1 {
+                    return 0;
+                }
+                match self.state {
+                    State::Write => self.mdr,
+                    State::Read => {
+                        let value = self.read_register(self.index as usize);
+                        self.index = (self.index + 1) & 0x0F;
+                        value
+                    }
+                    _ => 0,
+                }
+            }
+            // Always ready for the next nibble
+            2 => 0x80,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        let value = value & 0x0F;
+        match addr & 0x03 {
+            0 => {
+                self.chip_select = value;
+                if value != 1 {
+                    self.reset_serial();
+                }
+            }
+            1 if self.chip_select == 1 => {
+                match self.state {
+                    State::Command => {
+                        if value != 0x03 && value != 0x0C {
+                            return;
+                        }
+                        self.command = value;
+                        self.state = State::Index;
+                    }
+                    State::Index => {
+                        self.state = if self.command == 0x03 { State::Write } else { State::Read };
+                        self.index = value;
+                    }
+                    State::Write => {
+                        self.write_register(self.index as usize, value);
+                        self.index = (self.index + 1) & 0x0F;
+                    }
+                    State::Read => {}
+                }
+                self.mdr = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn read_register(&mut self, index: usize) -> u8 {
+        let value = self.regs[index];
+        if index == CONTROL_D {
+            // The interrupt flag clears on read
+            self.regs[CONTROL_D] &= !D_IRQ_FLAG;
+        }
+        value
+    }
+
+    fn write_register(&mut self, index: usize, value: u8) {
+        match index {
+            HOUR_HI => {
+                self.regs[HOUR_HI] = value;
+                self.fix_hour_mode();
+            }
+            CONTROL_D => {
+                let held = self.regs[CONTROL_D] & D_HOLD != 0;
+                self.regs[CONTROL_D] = (value & !D_IRQ_FLAG) | (self.regs[CONTROL_D] & D_IRQ_FLAG);
+                // The second that passed during HOLD counts once it is released
+                if held && value & D_HOLD == 0 && std::mem::take(&mut self.held_tick) {
+                    self.tick_second();
+                }
+            }
+            CONTROL_F => {
+                self.regs[CONTROL_F] = value;
+                self.fix_hour_mode();
+                if value & F_PAUSE != 0 {
+                    self.regs[SECOND_LO] = 0;
+                    self.regs[SECOND_LO + 1] &= !0x07;
+                }
+            }
+            _ => self.regs[index] = value,
+        }
+    }
+
+    /// The PM bit only exists in 12-hour mode, where the tens digit is 0-1
+    fn fix_hour_mode(&mut self) {
+        if self.regs[CONTROL_F] & F_24_HOUR != 0 {
+            self.regs[HOUR_HI] &= !HOUR_PM;
+        } else {
+            self.regs[HOUR_HI] &= HOUR_PM | 0x01;
+        }
+    }
+
+    /// BCD value of a lo/hi register pair; `mask` covers the tens digit
+    fn bcd(&self, lo: usize, mask: u8) -> u8 {
+        (self.regs[lo + 1] & mask) * 10 + self.regs[lo]
+    }
+
+    /// Store a BCD value, keeping the flag bits above the tens digit
+    fn set_bcd(&mut self, lo: usize, value: u8, mask: u8) {
+        self.regs[lo] = value % 10;
+        self.regs[lo + 1] = (self.regs[lo + 1] & !mask) | (value / 10);
+    }
+
+    /// Count a BCD pair up; true when it reaches `limit` and wraps to `first`
+    fn carry(&mut self, lo: usize, mask: u8, first: u8, limit: u8) -> bool {
+        let value = self.bcd(lo, mask) + 1;
+        let wrapped = value >= limit;
+        self.set_bcd(lo, if wrapped { first } else { value }, mask);
+        wrapped
+    }
+
+    fn tick_second(&mut self) {
+        if self.regs[CONTROL_D] & D_HOLD != 0 {
+            self.held_tick = true;
+            return;
+        }
+        if !self.carry(SECOND_LO, 0x07, 0, 60) || !self.carry(MINUTE_LO, 0x07, 0, 60) {
+            return;
+        }
+        if self.regs[CONTROL_F] & F_24_HOUR != 0 {
+            if !self.carry(HOUR_LO, 0x03, 0, 24) {
+                return;
+            }
+        } else {
+            if !self.carry(HOUR_LO, 0x01, 0, 12) {
+                return;
+            }
+            self.regs[HOUR_HI] ^= HOUR_PM;
+            if self.regs[HOUR_HI] & HOUR_PM != 0 {
+                return;
+            }
+        }
+
+        if self.regs[CONTROL_D] & D_CALENDAR == 0 {
+            return;
+        }
+        self.regs[WEEKDAY] = (self.regs[WEEKDAY] + 1) % 7;
+        let year = self.bcd(YEAR_LO, 0x0F);
+        let month = self.bcd(MONTH_LO, 0x01);
+        if self.carry(DAY_LO, 0x03, 1, days_in_month(month, year) + 1) && self.carry(MONTH_LO, 0x01, 1, 13) {
+            self.carry(YEAR_LO, 0x0F, 0, 100);
+        }
+    }
+}
+
+impl Default for Rtc4513 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The chip only knows two-digit years and takes every fourth as a leap year
+fn days_in_month(month: u8, year: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Year, month and day of a day count since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u8;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
@@ -0,0 +1,863 @@
+/// SA-1
+/// A second 65C816 at 10.74 MHz on the cartridge. It shares 2KB of I-RAM
+/// and the BW-RAM (the cartridge SRAM) with the 5A22 and adds:
+/// - the Super MMC: each quarter of the ROM map switches between 1MB banks,
+///   and each CPU picks the 8KB BW-RAM block it sees at $6000-$7FFF
+/// - a bitmap view of BW-RAM at $60-$6F with 2 or 4 bits per byte
+/// - a multiply/divide/multiply-accumulate unit
+/// - a variable-length bit reader over ROM/BW-RAM/I-RAM
+/// - a DMA unit that can convert bitmaps to SNES tiles on the way
+/// - message registers and interrupts in both directions, plus an H/V timer
+
+use crate::bus::{CYCLES_PER_LINE, NTSC_LINES, PAL_LINES};
+use crate::cartridge::{mirror, SnesCartridge};
+use crate::cpu::{CpuBus, CPU65816};
+
+pub const IRAM_SIZE: usize = 0x800;
+
+/// Master cycles per SA-1 cycle (10.74 MHz); BW-RAM takes two
+const CYCLE: u32 = 2;
+const BWRAM_CYCLE: u32 = 4;
+
+// CCNT ($2200)
+const CCNT_IRQ: u8 = 0x80;
+const CCNT_RDYB: u8 = 0x40;
+const CCNT_RESB: u8 = 0x20;
+const CCNT_NMI: u8 = 0x10;
+
+// SCNT ($2209)
+const SCNT_IRQ: u8 = 0x80;
+const SCNT_IVSW: u8 = 0x40;
+const SCNT_NVSW: u8 = 0x10;
+
+// 5A22 interrupt sources, as laid out in SIE/SIC/SFR
+const CPU_IRQ: u8 = 0x80;
+const CPU_CHDMA: u8 = 0x20;
+
+// SA-1 interrupt sources, as laid out in CIE/CIC/CFR
+const SA1_IRQ: u8 = 0x80;
+const SA1_TIMER: u8 = 0x40;
+const SA1_DMA: u8 = 0x20;
+const SA1_NMI: u8 = 0x10;
+
+// TMC ($2210)
+const TMC_HEN: u8 = 0x01;
+const TMC_VEN: u8 = 0x02;
+const TMC_LINEAR: u8 = 0x80;
+
+// DCNT ($2230)
+const DCNT_ENABLE: u8 = 0x80;
+const DCNT_CHAR_CONVERSION: u8 = 0x20;
+/// Character conversion type 1 (bitmap in BW-RAM) rather than type 2
+const DCNT_CC1: u8 = 0x10;
+const DCNT_DEST_BWRAM: u8 = 0x04;
+
+// CDMA ($2231)
+const CDMA_END: u8 = 0x80;
+
+// MCNT ($2250)
+const MCNT_DIVIDE: u8 = 0x01;
+const MCNT_ACCUMULATE: u8 = 0x02;
+
+/// VBD ($2258) bit 7: advance the bit reader on every VDPH read
+const VBD_AUTO_INCREMENT: u8 = 0x80;
+
+/// Version code in VC ($230E)
+const VERSION: u8 = 0x23;
+
+// Vectors the SA-1 takes from registers instead of ROM
+const NATIVE_NMI_VECTOR: u32 = 0x00FFEA;
+const NATIVE_IRQ_VECTOR: u32 = 0x00FFEE;
+const EMULATION_NMI_VECTOR: u32 = 0x00FFFA;
+const RESET_VECTOR: u32 = 0x00FFFC;
+const EMULATION_IRQ_VECTOR: u32 = 0x00FFFE;
+
+/// Registers, I-RAM and the units both CPUs talk to
+struct Sa1Io {
+    iram: Vec<u8>,
+    pal: bool,
+
+    ccnt: u8,
+    scnt: u8,
+    /// Messages: SMEG from the 5A22, CMEG from the SA-1
+    smeg: u8,
+    cmeg: u8,
+    /// Pending interrupts and enables towards the 5A22
+    cpu_flags: u8,
+    cpu_enable: u8,
+    /// Pending interrupts and enables towards the SA-1
+    sa1_flags: u8,
+    sa1_enable: u8,
+    nmi_pending: bool,
+    /// Set when CCNT releases the SA-1 from reset
+    reset_pending: bool,
+
+    crv: u16,
+    cnv: u16,
+    civ: u16,
+    snv: u16,
+    siv: u16,
+
+    /// CXB/DXB/EXB/FXB: 1MB bank per ROM quarter; bit 7 also remaps LoROM
+    rom_banks: [u8; 4],
+    /// BW-RAM block at $6000-$7FFF for the 5A22 (BMAPS)
+    sbm: u8,
+    /// Same for the SA-1 (BMAP); bit 7 selects the bitmap view
+    bmap: u8,
+    sbwe: bool,
+    cbwe: bool,
+    /// Write-protected BW-RAM area: 256 << BWPA bytes
+    bwpa: u8,
+    /// I-RAM write enables per 256-byte page
+    siwp: u8,
+    ciwp: u8,
+    /// BBF: 2 bits per bitmap pixel instead of 4
+    bitmap_2bpp: bool,
+
+    dcnt: u8,
+    cdma: u8,
+    sda: u32,
+    dda: u32,
+    dtc: u16,
+    /// Bitmap register file for type 2 character conversion
+    brf: [u8; 16],
+    cc2_line: u8,
+    /// Type 1 character conversion is active: 5A22 BW-RAM reads go
+    /// through the converter
+    cc1_active: bool,
+
+    mcnt: u8,
+    ma: u16,
+    mb: u16,
+    /// 40-bit result
+    mr: u64,
+    overflow: bool,
+
+    vbd: u8,
+    va: u32,
+    vbit: u32,
+
+    tmc: u8,
+    hcnt: u16,
+    vcnt: u16,
+    /// Master cycles into the line (H/V mode) or low counter bits
+    hcounter: u32,
+    vcounter: u32,
+    hcr: u16,
+    vcr: u16,
+}
+
+pub struct Sa1 {
+    pub cpu: CPU65816,
+    io: Sa1Io,
+    /// SA-1 time owed to the master clock
+    budget: i64,
+}
+
+impl Sa1 {
+    pub fn new(pal: bool) -> Self {
+        let mut sa1 = Self {
+            cpu: CPU65816::new(),
+            io: Sa1Io {
+                iram: vec![0; IRAM_SIZE],
+                pal,
+                ccnt: 0,
+                scnt: 0,
+                smeg: 0,
+                cmeg: 0,
+                cpu_flags: 0,
+                cpu_enable: 0,
+                sa1_flags: 0,
+                sa1_enable: 0,
+                nmi_pending: false,
+                reset_pending: false,
+                crv: 0,
+                cnv: 0,
+                civ: 0,
+                snv: 0,
+                siv: 0,
+                rom_banks: [0, 1, 2, 3],
+                sbm: 0,
+                bmap: 0,
+                sbwe: false,
+                cbwe: false,
+                bwpa: 0,
+                siwp: 0,
+                ciwp: 0,
+                bitmap_2bpp: false,
+                dcnt: 0,
+                cdma: 0,
+                sda: 0,
+                dda: 0,
+                dtc: 0,
+                brf: [0; 16],
+                cc2_line: 0,
+                cc1_active: false,
+                mcnt: 0,
+                ma: 0,
+                mb: 0,
+                mr: 0,
+                overflow: false,
+                vbd: 0,
+                va: 0,
+                vbit: 0,
+                tmc: 0,
+                hcnt: 0,
+                vcnt: 0,
+                hcounter: 0,
+                vcounter: 0,
+                hcr: 0,
+                vcr: 0,
+            },
+            budget: 0,
+        };
+        sa1.reset();
+        sa1
+    }
+
+    /// The SA-1 powers up held in reset until the 5A22 releases it
+    pub fn reset(&mut self) {
+        let io = &mut self.io;
+        io.ccnt = CCNT_RESB;
+        io.scnt = 0;
+        io.smeg = 0;
+        io.cmeg = 0;
+        io.cpu_flags = 0;
+        io.cpu_enable = 0;
+        io.sa1_flags = 0;
+        io.sa1_enable = 0;
+        io.nmi_pending = false;
+        io.reset_pending = false;
+        io.rom_banks = [0, 1, 2, 3];
+        io.sbm = 0;
+        io.bmap = 0;
+        io.sbwe = false;
+        io.cbwe = false;
+        io.bwpa = 0x0F;
+        io.siwp = 0;
+        io.ciwp = 0;
+        io.dcnt = 0;
+        io.cdma = 0;
+        io.cc1_active = false;
+        io.mcnt = 0;
+        io.mr = 0;
+        io.overflow = false;
+        io.tmc = 0;
+        io.hcounter = 0;
+        io.vcounter = 0;
+        self.budget = 0;
+    }
+
+    /// IRQ line to the 5A22
+    pub fn irq(&self) -> bool {
+        self.io.cpu_flags & self.io.cpu_enable & (CPU_IRQ | CPU_CHDMA) != 0
+    }
+
+    /// Catch up with `master_cycles` of 5A22 time
+    pub fn run(&mut self, master_cycles: u64, cart: &mut SnesCartridge) {
+        self.budget += master_cycles as i64;
+        while self.budget > 0 {
+            if self.io.ccnt & (CCNT_RDYB | CCNT_RESB) != 0 {
+                // Held in reset or waiting; the timer keeps counting
+                self.io.tick_timer(self.budget as u32);
+                self.budget = 0;
+                break;
+            }
+
+            let mut bus = Sa1Bus { io: &mut self.io, cart: &mut *cart, cycles: 0 };
+            if std::mem::take(&mut bus.io.reset_pending) {
+                self.cpu.reset(&mut bus);
+            } else {
+                let interrupted = if std::mem::take(&mut bus.io.nmi_pending) {
+                    self.cpu.nmi(&mut bus) > 0
+                } else if bus.io.irq_line() {
+                    self.cpu.irq(&mut bus) > 0
+                } else {
+                    false
+                };
+                if !interrupted {
+                    self.cpu.step(&mut bus);
+                }
+            }
+
+            let cycles = bus.cycles;
+            self.io.tick_timer(cycles);
+            self.budget -= cycles as i64;
+        }
+    }
+
+    // --- 5A22 side ---
+
+    /// $2200-$23FF registers and $3000-$37FF I-RAM
+    pub fn read_io(&mut self, cart: &mut SnesCartridge, addr: u16) -> Option<u8> {
+        match addr {
+            0x3000..=0x37FF => Some(self.io.iram[addr as usize & (IRAM_SIZE - 1)]),
+            _ => self.io.read_register(cart, addr),
+        }
+    }
+
+    pub fn write_io(&mut self, cart: &mut SnesCartridge, addr: u16, value: u8) {
+        match addr {
+            0x3000..=0x37FF => {
+                if self.io.siwp & (1 << ((addr >> 8) & 7)) != 0 {
+                    self.io.iram[addr as usize & (IRAM_SIZE - 1)] = value;
+                }
+            }
+            _ => self.io.write_register(cart, addr, value),
+        }
+    }
+
+    /// Cartridge area: BW-RAM block, BW-RAM at $40-$4F and ROM
+    pub fn read(&mut self, cart: &SnesCartridge, addr: u32) -> Option<u8> {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        let io = &mut self.io;
+
+        let bwram_addr = match (bank, offset) {
+            (0x00..=0x3F | 0x80..=0xBF, 0x6000..=0x7FFF) => Some((io.sbm as u32 & 0x1F) * 0x2000 + (offset as u32 & 0x1FFF)),
+            (0x40..=0x4F, _) => Some(addr & 0x0FFFFF),
+            _ => None,
+        };
+        if let Some(bwram_addr) = bwram_addr {
+            if io.cc1_active {
+                return Some(io.cc1_read(cart, bwram_addr));
+            }
+            return Some(io.bwram_read(cart, bwram_addr));
+        }
+
+        // Vector overrides from SCNT
+        match addr {
+            0x00FFEA if io.scnt & SCNT_NVSW != 0 => return Some(io.snv as u8),
+            0x00FFEB if io.scnt & SCNT_NVSW != 0 => return Some((io.snv >> 8) as u8),
+            0x00FFEE if io.scnt & SCNT_IVSW != 0 => return Some(io.siv as u8),
+            0x00FFEF if io.scnt & SCNT_IVSW != 0 => return Some((io.siv >> 8) as u8),
+            _ => {}
+        }
+        io.rom_read(cart, addr)
+    }
+
+    pub fn write(&mut self, cart: &mut SnesCartridge, addr: u32, value: u8) {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        let io = &mut self.io;
+
+        let bwram_addr = match (bank, offset) {
+            (0x00..=0x3F | 0x80..=0xBF, 0x6000..=0x7FFF) => (io.sbm as u32 & 0x1F) * 0x2000 + (offset as u32 & 0x1FFF),
+            (0x40..=0x4F, _) => addr & 0x0FFFFF,
+            _ => return,
+        };
+        if io.bwram_writable(bwram_addr, io.sbwe) {
+            io.bwram_write(cart, bwram_addr, value);
+        }
+    }
+}
+
+/// The SA-1 CPU's view of the cartridge
+struct Sa1Bus<'a> {
+    io: &'a mut Sa1Io,
+    cart: &'a mut SnesCartridge,
+    /// Master cycles used so far by the current instruction
+    cycles: u32,
+}
+
+impl CpuBus for Sa1Bus<'_> {
+    fn read(&mut self, addr: u32) -> u8 {
+        self.cycles += Sa1Io::access_cycles(addr);
+        self.io.sa1_read(self.cart, addr)
+    }
+
+    fn write(&mut self, addr: u32, value: u8) {
+        self.cycles += Sa1Io::access_cycles(addr);
+        self.io.sa1_write(self.cart, addr, value);
+    }
+
+    fn idle(&mut self) {
+        self.cycles += CYCLE;
+    }
+}
+
+impl Sa1Io {
+    fn access_cycles(addr: u32) -> u32 {
+        let bank = (addr >> 16) as u8;
+        match (bank, addr as u16) {
+            (0x00..=0x3F | 0x80..=0xBF, 0x6000..=0x7FFF) | (0x40..=0x4F | 0x60..=0x6F, _) => BWRAM_CYCLE,
+            _ => CYCLE,
+        }
+    }
+
+    fn irq_line(&self) -> bool {
+        self.sa1_flags & self.sa1_enable & (SA1_IRQ | SA1_TIMER | SA1_DMA) != 0
+    }
+
+    // --- SA-1 memory map ---
+
+    fn sa1_read(&mut self, cart: &mut SnesCartridge, addr: u32) -> u8 {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        match (bank, offset) {
+            (0x00..=0x3F | 0x80..=0xBF, 0x0000..=0x07FF | 0x3000..=0x37FF) => self.iram[offset as usize & (IRAM_SIZE - 1)],
+            (0x00..=0x3F | 0x80..=0xBF, 0x2200..=0x23FF) => self.read_register(cart, offset).unwrap_or(0),
+            (0x00..=0x3F | 0x80..=0xBF, 0x6000..=0x7FFF) => {
+                let block = (self.bmap as u32 & 0x7F) * 0x2000 + (offset as u32 & 0x1FFF);
+                if self.bmap & 0x80 != 0 {
+                    self.bitmap_read(cart, block)
+                } else {
+                    self.bwram_read(cart, block & 0x3FFFF)
+                }
+            }
+            (0x40..=0x4F, _) => self.bwram_read(cart, addr & 0x0FFFFF),
+            (0x60..=0x6F, _) => self.bitmap_read(cart, addr & 0x0FFFFF),
+            _ => match addr {
+                NATIVE_NMI_VECTOR | EMULATION_NMI_VECTOR => self.cnv as u8,
+                0x00FFEB | 0x00FFFB => (self.cnv >> 8) as u8,
+                NATIVE_IRQ_VECTOR | EMULATION_IRQ_VECTOR => self.civ as u8,
+                0x00FFEF | 0x00FFFF => (self.civ >> 8) as u8,
+                RESET_VECTOR => self.crv as u8,
+                0x00FFFD => (self.crv >> 8) as u8,
+                _ => self.rom_read(cart, addr).unwrap_or(0),
+            },
+        }
+    }
+
+    fn sa1_write(&mut self, cart: &mut SnesCartridge, addr: u32, value: u8) {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        match (bank, offset) {
+            // CIWP enables writes per 256-byte page
+            (0x00..=0x3F | 0x80..=0xBF, 0x0000..=0x07FF | 0x3000..=0x37FF)
+                if self.ciwp & (1 << ((offset >> 8) & 7)) != 0 =>
+            {
+                self.iram[offset as usize & (IRAM_SIZE - 1)] = value;
+            }
+            (0x00..=0x3F | 0x80..=0xBF, 0x2200..=0x23FF) => self.write_register(cart, offset, value),
+            (0x00..=0x3F | 0x80..=0xBF, 0x6000..=0x7FFF) => {
+                let block = (self.bmap as u32 & 0x7F) * 0x2000 + (offset as u32 & 0x1FFF);
+                if self.bmap & 0x80 != 0 {
+                    self.bitmap_write(cart, block, value);
+                } else if self.bwram_writable(block, self.cbwe) {
+                    self.bwram_write(cart, block & 0x3FFFF, value);
+                }
+            }
+            (0x40..=0x4F, _) => {
+                let bwram_addr = addr & 0x0FFFFF;
+                if self.bwram_writable(bwram_addr, self.cbwe) {
+                    self.bwram_write(cart, bwram_addr, value);
+                }
+            }
+            (0x60..=0x6F, _) => self.bitmap_write(cart, addr & 0x0FFFFF, value),
+            _ => {}
+        }
+    }
+
+    /// Any SA-1 address as the DMA and bit reader see it, without timing
+    fn peek(&self, cart: &SnesCartridge, addr: u32) -> u8 {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        match (bank, offset) {
+            (0x00..=0x3F | 0x80..=0xBF, 0x0000..=0x07FF | 0x3000..=0x37FF) => self.iram[offset as usize & (IRAM_SIZE - 1)],
+            (0x00..=0x3F | 0x80..=0xBF, 0x6000..=0x7FFF) | (0x40..=0x4F, _) => self.bwram_read(cart, addr),
+            _ => self.rom_read(cart, addr).unwrap_or(0),
+        }
+    }
+
+    /// ROM through the Super MMC. The LoROM map at $00-$3F/$80-$BF and the
+    /// HiROM map at $C0-$FF are each split in four 1MB quarters.
+    fn rom_read(&self, cart: &SnesCartridge, addr: u32) -> Option<u8> {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        let (linear, lorom) = if bank & 0x40 == 0 && offset >= 0x8000 {
+            ((addr & 0x800000) >> 2 | (addr & 0x3F0000) >> 1 | (addr & 0x7FFF), true)
+        } else if bank >= 0xC0 {
+            (addr & 0x3FFFFF, false)
+        } else {
+            return None;
+        };
+        let select = self.rom_banks[linear as usize >> 20];
+        let linear = if lorom && select & 0x80 == 0 {
+            linear
+        } else {
+            ((select as u32 & 0x07) << 20) | (linear & 0x0FFFFF)
+        };
+        Some(cart.rom[mirror(linear as usize, cart.rom.len())])
+    }
+
+    fn bwram_read(&self, cart: &SnesCartridge, addr: u32) -> u8 {
+        if cart.sram.is_empty() {
+            return 0;
+        }
+        cart.sram[addr as usize & (cart.sram.len() - 1)]
+    }
+
+    fn bwram_write(&self, cart: &mut SnesCartridge, addr: u32, value: u8) {
+        if cart.sram.is_empty() {
+            return;
+        }
+        let mask = cart.sram.len() - 1;
+        cart.sram[addr as usize & mask] = value;
+    }
+
+    /// The first 256 << BWPA bytes only take writes with SBWE/CBWE set
+    fn bwram_writable(&self, addr: u32, enable: bool) -> bool {
+        enable || addr >= 0x100 << self.bwpa
+    }
+
+    /// Bitmap view: each byte of BW-RAM holds two 4-bit or four 2-bit pixels
+    fn bitmap_read(&self, cart: &SnesCartridge, addr: u32) -> u8 {
+        if self.bitmap_2bpp {
+            let shift = (addr & 3) * 2;
+            (self.bwram_read(cart, addr >> 2) >> shift) & 0x03
+        } else {
+            let shift = (addr & 1) * 4;
+            (self.bwram_read(cart, addr >> 1) >> shift) & 0x0F
+        }
+    }
+
+    fn bitmap_write(&self, cart: &mut SnesCartridge, addr: u32, value: u8) {
+        let (byte_addr, shift, mask) = if self.bitmap_2bpp {
+            (addr >> 2, (addr & 3) * 2, 0x03)
+        } else {
+            (addr >> 1, (addr & 1) * 4, 0x0F)
+        };
+        let byte = self.bwram_read(cart, byte_addr);
+        self.bwram_write(cart, byte_addr, (byte & !(mask << shift)) | ((value & mask) << shift));
+    }
+
+    // --- Registers ---
+
+    fn read_register(&mut self, cart: &SnesCartridge, addr: u16) -> Option<u8> {
+        match addr {
+            // SFR: what the 5A22 sees
+            0x2300 => Some(self.cpu_flags | (self.scnt & (SCNT_IVSW | SCNT_NVSW)) | self.cmeg),
+            // CFR: what the SA-1 sees
+            0x2301 => Some(self.sa1_flags | self.smeg),
+            0x2302 => {
+                // Reading HCR latches both counters
+                self.hcr = (self.hcounter >> 2) as u16;
+                self.vcr = self.vcounter as u16;
+                Some(self.hcr as u8)
+            }
+            0x2303 => Some((self.hcr >> 8) as u8),
+            0x2304 => Some(self.vcr as u8),
+            0x2305 => Some((self.vcr >> 8) as u8),
+            0x2306..=0x230A => Some((self.mr >> ((addr - 0x2306) * 8)) as u8),
+            0x230B => Some((self.overflow as u8) << 7),
+            0x230C => Some(self.read_bits(cart) as u8),
+            0x230D => {
+                let value = (self.read_bits(cart) >> 8) as u8;
+                if self.vbd & VBD_AUTO_INCREMENT != 0 {
+                    self.advance_bits();
+                }
+                Some(value)
+            }
+            0x230E => Some(VERSION),
+            _ => None,
+        }
+    }
+
+    fn write_register(&mut self, cart: &mut SnesCartridge, addr: u16, value: u8) {
+        match addr {
+            0x2200 => {
+                // CCNT
+                if self.ccnt & CCNT_RESB != 0 && value & CCNT_RESB == 0 {
+                    self.reset_pending = true;
+                }
+                self.ccnt = value;
+                self.smeg = value & 0x0F;
+                if value & CCNT_IRQ != 0 {
+                    self.sa1_flags |= SA1_IRQ;
+                }
+                if value & CCNT_NMI != 0 {
+                    self.sa1_flags |= SA1_NMI;
+                    if self.sa1_enable & SA1_NMI != 0 {
+                        self.nmi_pending = true;
+                    }
+                }
+            }
+            0x2201 => self.cpu_enable = value & (CPU_IRQ | CPU_CHDMA),
+            0x2202 => self.cpu_flags &= !value,
+            0x2203 => self.crv = (self.crv & 0xFF00) | value as u16,
+            0x2204 => self.crv = ((value as u16) << 8) | (self.crv & 0x00FF),
+            0x2205 => self.cnv = (self.cnv & 0xFF00) | value as u16,
+            0x2206 => self.cnv = ((value as u16) << 8) | (self.cnv & 0x00FF),
+            0x2207 => self.civ = (self.civ & 0xFF00) | value as u16,
+            0x2208 => self.civ = ((value as u16) << 8) | (self.civ & 0x00FF),
+            0x2209 => {
+                // SCNT
+                self.scnt = value;
+                self.cmeg = value & 0x0F;
+                if value & SCNT_IRQ != 0 {
+                    self.cpu_flags |= CPU_IRQ;
+                }
+            }
+            0x220A => self.sa1_enable = value & (SA1_IRQ | SA1_TIMER | SA1_DMA | SA1_NMI),
+            0x220B => self.sa1_flags &= !value,
+            0x220C => self.snv = (self.snv & 0xFF00) | value as u16,
+            0x220D => self.snv = ((value as u16) << 8) | (self.snv & 0x00FF),
+            0x220E => self.siv = (self.siv & 0xFF00) | value as u16,
+            0x220F => self.siv = ((value as u16) << 8) | (self.siv & 0x00FF),
+            0x2210 => self.tmc = value,
+            0x2211 => {
+                self.hcounter = 0;
+                self.vcounter = 0;
+            }
+            0x2212 => self.hcnt = (self.hcnt & 0x0100) | value as u16,
+            0x2213 => self.hcnt = ((value as u16 & 0x01) << 8) | (self.hcnt & 0x00FF),
+            0x2214 => self.vcnt = (self.vcnt & 0x0100) | value as u16,
+            0x2215 => self.vcnt = ((value as u16 & 0x01) << 8) | (self.vcnt & 0x00FF),
+            0x2220..=0x2223 => self.rom_banks[addr as usize - 0x2220] = value,
+            0x2224 => self.sbm = value & 0x1F,
+            0x2225 => self.bmap = value,
+            0x2226 => self.sbwe = value & 0x80 != 0,
+            0x2227 => self.cbwe = value & 0x80 != 0,
+            0x2228 => self.bwpa = value & 0x0F,
+            0x2229 => self.siwp = value,
+            0x222A => self.ciwp = value,
+            0x2230 => {
+                if self.dcnt & DCNT_ENABLE == 0 && value & DCNT_ENABLE != 0 {
+                    self.cc2_line = 0;
+                }
+                self.dcnt = value;
+            }
+            0x2231 => {
+                self.cdma = value;
+                if value & CDMA_END != 0 {
+                    self.cc1_active = false;
+                }
+            }
+            0x2232 => self.sda = (self.sda & 0xFFFF00) | value as u32,
+            0x2233 => self.sda = (self.sda & 0xFF00FF) | (value as u32) << 8,
+            0x2234 => self.sda = (self.sda & 0x00FFFF) | (value as u32) << 16,
+            0x2235 => self.dda = (self.dda & 0xFFFF00) | value as u32,
+            0x2236 => {
+                self.dda = (self.dda & 0xFF00FF) | (value as u32) << 8;
+                if self.dcnt & DCNT_ENABLE != 0 {
+                    if self.dcnt & DCNT_CHAR_CONVERSION == 0 && self.dcnt & DCNT_DEST_BWRAM == 0 {
+                        self.dma_normal(cart);
+                    } else if self.dcnt & (DCNT_CHAR_CONVERSION | DCNT_CC1) == DCNT_CHAR_CONVERSION | DCNT_CC1 {
+                        self.dma_cc1();
+                    }
+                }
+            }
+            0x2237 => {
+                self.dda = (self.dda & 0x00FFFF) | (value as u32) << 16;
+                if self.dcnt & (DCNT_ENABLE | DCNT_CHAR_CONVERSION | DCNT_DEST_BWRAM) == DCNT_ENABLE | DCNT_DEST_BWRAM {
+                    self.dma_normal(cart);
+                }
+            }
+            0x2238 => self.dtc = (self.dtc & 0xFF00) | value as u16,
+            0x2239 => self.dtc = ((value as u16) << 8) | (self.dtc & 0x00FF),
+            0x223F => self.bitmap_2bpp = value & 0x80 != 0,
+            0x2240..=0x224F => {
+                self.brf[addr as usize & 0x0F] = value;
+                let cc2 = DCNT_ENABLE | DCNT_CHAR_CONVERSION;
+                if addr & 0x07 == 0x07 && self.dcnt & (cc2 | DCNT_CC1) == cc2 {
+                    self.dma_cc2();
+                }
+            }
+            0x2250 => {
+                self.mcnt = value & 0x03;
+                if value & MCNT_ACCUMULATE != 0 {
+                    self.mr = 0;
+                }
+            }
+            0x2251 => self.ma = (self.ma & 0xFF00) | value as u16,
+            0x2252 => self.ma = ((value as u16) << 8) | (self.ma & 0x00FF),
+            0x2253 => self.mb = (self.mb & 0xFF00) | value as u16,
+            0x2254 => {
+                self.mb = ((value as u16) << 8) | (self.mb & 0x00FF);
+                self.calculate();
+            }
+            0x2258 => {
+                self.vbd = value;
+                if value & VBD_AUTO_INCREMENT == 0 {
+                    self.advance_bits();
+                }
+            }
+            0x2259 => self.va = (self.va & 0xFFFF00) | value as u32,
+            0x225A => self.va = (self.va & 0xFF00FF) | (value as u32) << 8,
+            0x225B => {
+                self.va = (self.va & 0x00FFFF) | (value as u32) << 16;
+                self.vbit = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Writing MB's high byte starts the operation selected in MCNT
+    fn calculate(&mut self) {
+        if self.mcnt & MCNT_ACCUMULATE != 0 {
+            let product = (self.ma as i16 as i64) * (self.mb as i16 as i64);
+            let sum = self.mr as i64 + product;
+            self.overflow = (sum >> 40) & 1 != 0;
+            self.mr = sum as u64 & 0xFF_FFFF_FFFF;
+            self.mb = 0;
+        } else if self.mcnt & MCNT_DIVIDE != 0 {
+            // Signed dividend, unsigned divisor, remainder always positive
+            if self.mb == 0 {
+                self.mr = 0;
+            } else {
+                let dividend = self.ma as i16 as i32;
+                let divisor = self.mb as i32;
+                let remainder = dividend.rem_euclid(divisor);
+                let quotient = (dividend - remainder) / divisor;
+                self.mr = ((remainder as u16 as u64) << 16) | quotient as u16 as u64;
+            }
+            self.ma = 0;
+            self.mb = 0;
+        } else {
+            self.mr = ((self.ma as i16 as i32) * (self.mb as i16 as i32)) as u32 as u64;
+            self.mb = 0;
+        }
+    }
+
+    /// 16 bits from the bit reader's position
+    fn read_bits(&self, cart: &SnesCartridge) -> u16 {
+        let data = (0..3).fold(0u32, |data, n| data | (self.peek(cart, self.va.wrapping_add(n) & 0xFFFFFF) as u32) << (n * 8));
+        (data >> self.vbit) as u16
+    }
+
+    fn advance_bits(&mut self) {
+        let width = match self.vbd & 0x0F {
+            0 => 16,
+            n => n as u32,
+        };
+        self.vbit += width;
+        self.va = (self.va + (self.vbit >> 3)) & 0xFFFFFF;
+        self.vbit &= 7;
+    }
+
+    fn tick_timer(&mut self, cycles: u32) {
+        let lines = (if self.pal { PAL_LINES } else { NTSC_LINES }) as u32;
+        for _ in 0..cycles / 2 {
+            self.hcounter += 2;
+            if self.tmc & TMC_LINEAR != 0 {
+                self.vcounter = (self.vcounter + (self.hcounter >> 11)) & 0x1FF;
+                self.hcounter &= 0x7FF;
+            } else if self.hcounter >= CYCLES_PER_LINE {
+                self.hcounter = 0;
+                self.vcounter += 1;
+                if self.vcounter >= lines {
+                    self.vcounter = 0;
+                }
+            }
+
+            let h_match = self.hcounter == (self.hcnt as u32) << 2;
+            let v_match = self.vcounter == self.vcnt as u32;
+            let trigger = match self.tmc & (TMC_HEN | TMC_VEN) {
+                TMC_HEN => h_match,
+                TMC_VEN => v_match && self.hcounter == 0,
+                0 => false,
+                _ => v_match && h_match,
+            };
+            if trigger {
+                self.sa1_flags |= SA1_TIMER;
+            }
+        }
+    }
+
+    // --- DMA ---
+
+    /// Plain copy between ROM, BW-RAM and I-RAM; raises the SA-1 DMA IRQ
+    fn dma_normal(&mut self, cart: &mut SnesCartridge) {
+        let source = self.dcnt & 0x03;
+        let to_bwram = self.dcnt & DCNT_DEST_BWRAM != 0;
+        // BW-RAM to BW-RAM and I-RAM to I-RAM do nothing
+        let same = (source == 1 && to_bwram) || (source == 2 && !to_bwram);
+        for _ in 0..self.dtc {
+            let sda = self.sda;
+            let dda = self.dda;
+            self.sda = (self.sda + 1) & 0xFFFFFF;
+            self.dda = (self.dda + 1) & 0xFFFFFF;
+            if same {
+                continue;
+            }
+            let value = match source {
+                0 => self.rom_read(cart, sda).unwrap_or(0),
+                1 => self.bwram_read(cart, sda),
+                _ => self.iram[sda as usize & (IRAM_SIZE - 1)],
+            };
+            if to_bwram {
+                self.bwram_write(cart, dda, value);
+            } else {
+                self.iram[dda as usize & (IRAM_SIZE - 1)] = value;
+            }
+        }
+        self.dtc = 0;
+        self.sa1_flags |= SA1_DMA;
+    }
+
+    /// Color depth from CDMA: 0 = 8bpp, 1 = 4bpp, 2 = 2bpp
+    fn dma_color_bits(&self) -> u32 {
+        (self.cdma as u32 & 0x03).min(2)
+    }
+
+    /// Type 1: the 5A22 DMAs a BW-RAM bitmap out through the converter,
+    /// which fills I-RAM one tile at a time
+    fn dma_cc1(&mut self) {
+        self.cc1_active = true;
+        self.cpu_flags |= CPU_CHDMA;
+    }
+
+    fn cc1_read(&mut self, cart: &SnesCartridge, addr: u32) -> u8 {
+        let color_bits = self.dma_color_bits();
+        let tile_size_mask = (1u32 << (6 - color_bits)) - 1;
+
+        if addr & tile_size_mask == 0 && !cart.sram.is_empty() {
+            let bpp = 2u32 << (2 - color_bits);
+            // Tiles across the bitmap: 1 << size, bytes per bitmap line
+            let size = ((self.cdma as u32 >> 2) & 0x07).min(5);
+            let line_bytes = (8 << size) >> color_bits;
+            let bw_mask = cart.sram.len() as u32 - 1;
+            let tile = (addr.wrapping_sub(self.sda) & bw_mask) >> (6 - color_bits);
+            let tile_y = tile >> size;
+            let tile_x = tile & ((1 << size) - 1);
+            let mut source = self.sda + tile_y * 8 * line_bytes + tile_x * bpp;
+
+            for y in 0..8 {
+                let mut pixels = (0..bpp).fold(0u64, |data, byte| {
+                    data | (cart.sram[((source + byte) & bw_mask) as usize] as u64) << (byte * 8)
+                });
+                source += line_bytes;
+
+                let mut planes = [0u8; 8];
+                for x in 0..8 {
+                    for plane in planes.iter_mut().take(bpp as usize) {
+                        *plane |= ((pixels & 1) as u8) << (7 - x);
+                        pixels >>= 1;
+                    }
+                }
+                for (byte, &plane) in planes.iter().enumerate().take(bpp as usize) {
+                    let dest = self.dda as usize + (y << 1) + ((byte & 6) << 3) + (byte & 1);
+                    self.iram[dest & (IRAM_SIZE - 1)] = plane;
+                }
+            }
+        }
+
+        self.iram[(self.dda + (addr & tile_size_mask)) as usize & (IRAM_SIZE - 1)]
+    }
+
+    /// Type 2: the SA-1 writes 8 pixels of a row to BRF and the converter
+    /// stores them as tile bitplanes in I-RAM
+    fn dma_cc2(&mut self) {
+        let color_bits = self.dma_color_bits();
+        let bpp = 2usize << (2 - color_bits);
+        let line = self.cc2_line as usize;
+        let brf = &self.brf[(line & 1) * 8..][..8];
+
+        let mut addr = self.dda as usize & (IRAM_SIZE - 1);
+        addr &= !((1 << (7 - color_bits)) - 1);
+        addr += (line & 8) * bpp;
+        addr += (line & 7) * 2;
+
+        let mut planes = [0u8; 8];
+        for (byte, plane) in planes.iter_mut().enumerate().take(bpp) {
+            for (bit, &pixel) in brf.iter().enumerate() {
+                *plane |= ((pixel >> byte) & 1) << (7 - bit);
+            }
+        }
+        for (byte, &plane) in planes.iter().enumerate().take(bpp) {
+            self.iram[(addr + ((byte & 6) << 3) + (byte & 1)) & (IRAM_SIZE - 1)] = plane;
+        }
+        self.cc2_line = (self.cc2_line + 1) & 0x0F;
+    }
+}
@@ -0,0 +1,372 @@
+/// S-DD1
+/// Graphics decompression chip of Star Ocean and Street Fighter Alpha 2.
+/// It maps up to 8MB of ROM through four 1MB windows at $C0-$FF and, once
+/// a DMA channel is armed through $4800/$4801, answers that channel's reads
+/// with the output of its context-model arithmetic decoder instead of the
+/// compressed bytes.
+
+use crate::cartridge::{mirror, SnesCartridge};
+
+/// Probability estimation states: Golomb code order, next state at the end
+/// of an MPS run, next state after an LPS
+const EVOLUTION: [(u8, u8, u8); 33] = [
+    (0, 25, 25), (0, 2, 1), (0, 3, 1), (0, 4, 2), (0, 5, 3),
+    (1, 6, 4), (1, 7, 5), (1, 8, 6), (1, 9, 7),
+    (2, 10, 8), (2, 11, 9), (2, 12, 10), (2, 13, 11),
+    (3, 14, 12), (3, 15, 13), (3, 16, 14), (3, 17, 15),
+    (4, 18, 16), (4, 19, 17), (5, 20, 18), (5, 21, 19),
+    (6, 22, 20), (6, 23, 21), (7, 24, 22), (7, 24, 23),
+    (0, 26, 1), (1, 27, 2), (2, 28, 4), (3, 29, 8),
+    (4, 30, 12), (5, 31, 16), (6, 32, 18), (7, 24, 22),
+];
+
+/// One Golomb-code bit generator per code order
+#[derive(Debug, Clone, Copy, Default)]
+struct BitGenerator {
+    /// More probable symbols left in the current run
+    mps_count: u8,
+    /// The run ends with a less probable symbol
+    lps_index: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ContextInfo {
+    /// State in `EVOLUTION`
+    status: u8,
+    /// Which bit value is currently the more probable one
+    mps: u8,
+}
+
+/// Decoder pipeline: input manager -> Golomb decoder -> bit generators ->
+/// probability estimation -> context model -> output logic
+struct Decompressor {
+    // Input manager
+    offset: u32,
+    bit_count: u8,
+
+    generators: [BitGenerator; 8],
+    contexts: [ContextInfo; 32],
+
+    // Context model
+    bitplanes_info: u8,
+    context_bits_info: u8,
+    bit_number: u8,
+    current_bitplane: u8,
+    previous_bitplane_bits: [u16; 8],
+
+    // Output logic
+    r0: u8,
+    r1: u8,
+    r2: u8,
+}
+
+impl Decompressor {
+    fn new() -> Self {
+        Self {
+            offset: 0,
+            bit_count: 0,
+            generators: [BitGenerator::default(); 8],
+            contexts: [ContextInfo::default(); 32],
+            bitplanes_info: 0,
+            context_bits_info: 0,
+            bit_number: 0,
+            current_bitplane: 0,
+            previous_bitplane_bits: [0; 8],
+            r0: 0,
+            r1: 0,
+            r2: 0,
+        }
+    }
+
+    /// Start a stream; its first nibble selects the bitplane layout and
+    /// the context bits
+    fn init(&mut self, rom: &impl Fn(u32) -> u8, offset: u32) {
+        let header = rom(offset);
+        self.offset = offset;
+        self.bit_count = 4;
+        self.generators = [BitGenerator::default(); 8];
+        self.contexts = [ContextInfo::default(); 32];
+
+        self.bitplanes_info = header & 0xC0;
+        self.context_bits_info = header & 0x30;
+        self.bit_number = 0;
+        self.previous_bitplane_bits = [0; 8];
+        self.current_bitplane = match self.bitplanes_info {
+            0x00 => 1,
+            0x40 => 7,
+            0x80 => 3,
+            _ => 0,
+        };
+        self.r0 = 0x01;
+    }
+
+    /// Input manager: the next code word, MSB aligned. A leading 1 is
+    /// followed by `length` more bits.
+    fn code_word(&mut self, rom: &impl Fn(u32) -> u8, length: u8) -> u8 {
+        let mut word = rom(self.offset) << self.bit_count;
+        self.bit_count += 1;
+        if word & 0x80 != 0 {
+            word |= ((rom(self.offset.wrapping_add(1)) as u16) >> (9 - self.bit_count)) as u8;
+            self.bit_count += length;
+        }
+        if self.bit_count & 0x08 != 0 {
+            self.offset = self.offset.wrapping_add(1);
+            self.bit_count &= 0x07;
+        }
+        word
+    }
+
+    /// Golomb decoder: a 0 is a full run of 2^order MPS; a 1 is followed by
+    /// the (inverted, LSB first) length of a shorter run ending in an LPS
+    fn run_count(&mut self, rom: &impl Fn(u32) -> u8, order: u8) -> (u8, bool) {
+        let word = self.code_word(rom, order);
+        if word & 0x80 == 0 {
+            return (1 << order, false);
+        }
+        if order == 0 {
+            return (0, true);
+        }
+        let bits = (!word >> (7 - order)) & ((1 << order) - 1);
+        (bits.reverse_bits() >> (8 - order), true)
+    }
+
+    /// Returns the bit and whether it ended a run
+    fn generator_bit(&mut self, rom: &impl Fn(u32) -> u8, order: u8) -> (u8, bool) {
+        let mut generator = self.generators[order as usize];
+        if generator.mps_count == 0 && !generator.lps_index {
+            (generator.mps_count, generator.lps_index) = self.run_count(rom, order);
+        }
+        let bit = if generator.mps_count > 0 {
+            generator.mps_count -= 1;
+            0
+        } else {
+            generator.lps_index = false;
+            1
+        };
+        self.generators[order as usize] = generator;
+        (bit, generator.mps_count == 0 && !generator.lps_index)
+    }
+
+    /// Probability estimation: picks the bit generator for the context's
+    /// state and moves the state along at the end of each run
+    fn context_bit(&mut self, rom: &impl Fn(u32) -> u8, context: u8) -> u8 {
+        let info = self.contexts[context as usize];
+        let (order, next_if_mps, next_if_lps) = EVOLUTION[info.status as usize];
+        let (bit, end_of_run) = self.generator_bit(rom, order);
+
+        if end_of_run {
+            let info = &mut self.contexts[context as usize];
+            if bit != 0 {
+                if info.status & 0xFE == 0 {
+                    info.mps ^= 1;
+                }
+                info.status = next_if_lps;
+            } else {
+                info.status = next_if_mps;
+            }
+        }
+        bit ^ info.mps
+    }
+
+    /// Context model: the context comes from the plane's previous bits
+    fn model_bit(&mut self, rom: &impl Fn(u32) -> u8) -> u8 {
+        match self.bitplanes_info {
+            0x00 => self.current_bitplane ^= 1,
+            0x40 => {
+                self.current_bitplane ^= 1;
+                if self.bit_number & 0x7F == 0 {
+                    self.current_bitplane = (self.current_bitplane + 2) & 0x07;
+                }
+            }
+            0x80 => {
+                self.current_bitplane ^= 1;
+                if self.bit_number & 0x7F == 0 {
+                    self.current_bitplane ^= 2;
+                }
+            }
+            _ => self.current_bitplane = self.bit_number & 0x07,
+        }
+
+        let plane = self.current_bitplane as usize;
+        let bits = self.previous_bitplane_bits[plane];
+        let history = match self.context_bits_info {
+            0x00 => ((bits & 0x01C0) >> 5) | (bits & 0x0001),
+            0x10 => ((bits & 0x0180) >> 5) | (bits & 0x0001),
+            0x20 => ((bits & 0x00C0) >> 5) | (bits & 0x0001),
+            _ => ((bits & 0x0180) >> 5) | (bits & 0x0003),
+        };
+        let context = ((self.current_bitplane & 0x01) << 4) | history as u8;
+
+        let bit = self.context_bit(rom, context);
+        self.previous_bitplane_bits[plane] = (bits << 1) | bit as u16;
+        self.bit_number = self.bit_number.wrapping_add(1);
+        bit
+    }
+
+    /// Output logic: the next decompressed byte. Planar modes decode two
+    /// planes at once and hand out the second byte on the following read.
+    fn read(&mut self, rom: &impl Fn(u32) -> u8) -> u8 {
+        if self.bitplanes_info == 0xC0 {
+            self.r1 = 0;
+            for bit in 0..8 {
+                if self.model_bit(rom) != 0 {
+                    self.r1 |= 1 << bit;
+                }
+            }
+            return self.r1;
+        }
+
+        if self.r0 == 0 {
+            self.r0 = 0xFF;
+            return self.r2;
+        }
+        self.r1 = 0;
+        self.r2 = 0;
+        self.r0 = 0x80;
+        while self.r0 != 0 {
+            if self.model_bit(rom) != 0 {
+                self.r1 |= self.r0;
+            }
+            if self.model_bit(rom) != 0 {
+                self.r2 |= self.r0;
+            }
+            self.r0 >>= 1;
+        }
+        self.r1
+    }
+}
+
+pub struct Sdd1 {
+    /// $4800: channels that may decompress
+    dma_enable: u8,
+    /// $4801: channels armed for their next transfer
+    dma_ready: u8,
+    /// $4804-$4807: 1MB ROM bank behind $C0-$CF, $D0-$DF, $E0-$EF and
+    /// $F0-$FF. Bit 7 of $4805/$4807 folds $20-$3F/$A0-$BF onto $00-$1F.
+    banks: [u8; 4],
+    /// Source address and byte count of each DMA channel, snooped from
+    /// the writes to $43x2-$43x6
+    dma_addr: [u32; 8],
+    dma_size: [u16; 8],
+    /// The decompressor holds the stream of the transfer in progress
+    streaming: bool,
+    decompressor: Decompressor,
+}
+
+impl Sdd1 {
+    pub fn new() -> Self {
+        Self {
+            dma_enable: 0,
+            dma_ready: 0,
+            banks: [0, 1, 2, 3],
+            dma_addr: [0; 8],
+            dma_size: [0; 8],
+            streaming: false,
+            decompressor: Decompressor::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.dma_enable = 0;
+        self.dma_ready = 0;
+        self.banks = [0, 1, 2, 3];
+        self.streaming = false;
+    }
+
+    /// $4800-$480F; `None` for the unused registers
+    pub fn read_io(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800 => Some(self.dma_enable),
+            0x4801 => Some(self.dma_ready),
+            0x4804..=0x4807 => Some(self.banks[(addr & 0x03) as usize]),
+            _ => None,
+        }
+    }
+
+    pub fn write_io(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4800 => self.dma_enable = value,
+            0x4801 => self.dma_ready = value,
+            0x4804..=0x4807 => self.banks[(addr & 0x03) as usize] = value & 0x8F,
+            _ => {}
+        }
+    }
+
+    /// Watch the CPU set up DMA channels ($4300-$437F)
+    pub fn snoop_dma(&mut self, addr: u16, value: u8) {
+        let channel = ((addr >> 4) & 0x07) as usize;
+        let addr_byte = |addr: u32, shift: u32| (addr & !(0xFF << shift)) | ((value as u32) << shift);
+        match addr & 0x0F {
+            0x2 => self.dma_addr[channel] = addr_byte(self.dma_addr[channel], 0),
+            0x3 => self.dma_addr[channel] = addr_byte(self.dma_addr[channel], 8),
+            0x4 => self.dma_addr[channel] = addr_byte(self.dma_addr[channel], 16),
+            0x5 => self.dma_size[channel] = (self.dma_size[channel] & 0xFF00) | value as u16,
+            0x6 => self.dma_size[channel] = (self.dma_size[channel] & 0x00FF) | ((value as u16) << 8),
+            _ => {}
+        }
+    }
+
+    /// Cartridge read: LoROM at $00-$3F/$80-$BF, the MMC windows at
+    /// $C0-$FF and SRAM at $70-$7D
+    pub fn read(&mut self, cart: &SnesCartridge, addr: u32) -> Option<u8> {
+        if let Some(offset) = cart.sram_offset(addr) {
+            return Some(cart.sram[offset]);
+        }
+        let bank = (addr >> 16) as u8;
+        match bank {
+            0x00..=0x3F | 0x80..=0xBF if addr as u16 >= 0x8000 => {
+                let fold = if bank & 0x80 != 0 { self.banks[3] } else { self.banks[1] };
+                let addr = if bank & 0x20 != 0 && fold & 0x80 != 0 { addr & !0x200000 } else { addr };
+                let linear = ((addr >> 1) & 0x1F8000) | (addr & 0x7FFF);
+                Some(cart.rom[mirror(linear as usize, cart.rom.len())])
+            }
+            0xC0..=0xFF => Some(self.read_banked(cart, addr)),
+            _ => None,
+        }
+    }
+
+    pub fn write(&mut self, cart: &mut SnesCartridge, addr: u32, value: u8) {
+        if let Some(offset) = cart.sram_offset(addr) {
+            cart.sram[offset] = value;
+        }
+    }
+
+    /// $C0-$FF. DMA keeps reading the same address, which is how the chip
+    /// tells an armed channel's reads apart.
+    fn read_banked(&mut self, cart: &SnesCartridge, addr: u32) -> u8 {
+        let armed = self.dma_enable & self.dma_ready;
+        let banks = self.banks;
+        let rom = |addr: u32| mmc_read(cart, &banks, addr);
+
+        for channel in 0..8 {
+            if armed & (1 << channel) == 0 || self.dma_addr[channel] != addr {
+                continue;
+            }
+            if !self.streaming {
+                self.decompressor.init(&rom, addr);
+                self.streaming = true;
+            }
+            let value = self.decompressor.read(&rom);
+            self.dma_size[channel] = self.dma_size[channel].wrapping_sub(1);
+            if self.dma_size[channel] == 0 {
+                self.streaming = false;
+                self.dma_ready &= !(1 << channel);
+            }
+            return value;
+        }
+        rom(addr)
+    }
+}
+
+impl Default for Sdd1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// ROM behind $C0-$FF through the bank registers
+fn mmc_read(cart: &SnesCartridge, banks: &[u8; 4], addr: u32) -> u8 {
+    let bank = banks[((addr >> 20) & 0x03) as usize] as usize & 0x0F;
+    let linear = (bank << 20) | (addr as usize & 0x0FFFFF);
+    cart.rom[mirror(linear, cart.rom.len())]
+}
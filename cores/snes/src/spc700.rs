@@ -0,0 +1,754 @@
+/// Sony SPC700 Sound CPU Core
+///
+/// 8-bit CPU inside the S-SMP, running the sound driver out of the 64KB
+/// ARAM at 1.024 MHz:
+/// - A, X, Y, SP (stack in page 1) and the PSW; Y:A pair up as a 16-bit YA
+/// - The P flag moves the direct page between $00xx and $01xx
+/// - Bit operations on any of the first 8KB through `m.b` operands
+///
+/// Cycle counts come from a per-opcode table; taken branches add two.
+
+use bitflags::bitflags;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct SpcFlags: u8 {
+        const CARRY       = 0b0000_0001;  // C
+        const ZERO        = 0b0000_0010;  // Z
+        const INTERRUPT   = 0b0000_0100;  // I (unused, nothing drives the IRQ line)
+        const HALF_CARRY  = 0b0000_1000;  // H
+        const BREAK       = 0b0001_0000;  // B
+        const DIRECT_PAGE = 0b0010_0000;  // P
+        const OVERFLOW    = 0b0100_0000;  // V
+        const NEGATIVE    = 0b1000_0000;  // N
+    }
+}
+
+/// Memory interface seen by the SPC700: ARAM, the IPL ROM and $F0-$FF
+pub trait SpcBus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+}
+
+const RESET_VECTOR: u16 = 0xFFFE;
+/// BRK and TCALL 0; TCALL n uses the word 2n bytes below
+const TCALL_VECTOR: u16 = 0xFFDE;
+
+/// Cycles per opcode, without the extra 2 for taken branches
+#[rustfmt::skip]
+const CYCLES: [u8; 256] = [
+    2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 5, 4, 5, 4, 6, 8, // 0x
+    2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 6, 5, 2, 2, 4, 6, // 1x
+    2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 5, 4, 5, 4, 5, 4, // 2x
+    2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 6, 5, 2, 2, 3, 8, // 3x
+    2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 4, 4, 5, 4, 6, 6, // 4x
+    2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 4, 5, 2, 2, 4, 3, // 5x
+    2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 4, 4, 5, 4, 5, 5, // 6x
+    2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 5, 5, 2, 2, 3, 6, // 7x
+    2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 5, 4, 5, 2, 4, 5, // 8x
+    2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 5, 5, 2, 2, 12, 5, // 9x
+    3, 8, 4, 5, 3, 4, 3, 6, 2, 6, 4, 4, 5, 2, 4, 4, // Ax
+    2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 5, 5, 2, 2, 3, 4, // Bx
+    3, 8, 4, 5, 4, 5, 4, 7, 2, 5, 6, 4, 5, 2, 4, 9, // Cx
+    2, 8, 4, 5, 5, 6, 6, 7, 4, 5, 5, 5, 2, 2, 6, 3, // Dx
+    2, 8, 4, 5, 3, 4, 3, 6, 2, 4, 5, 3, 4, 3, 4, 3, // Ex
+    2, 8, 4, 5, 4, 5, 5, 6, 3, 4, 5, 4, 2, 2, 4, 3, // Fx
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Dp,
+    DpX,
+    DpY,
+    Abs,
+    AbsX,
+    AbsY,
+    /// (X)
+    IndX,
+    /// [dp+X]
+    DpIndX,
+    /// [dp]+Y
+    DpIndY,
+}
+
+pub struct SPC700 {
+    // Registers
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub pc: u16,
+    pub psw: SpcFlags,
+
+    // State
+    pub cycles: u64,
+    /// Set by SLEEP and STOP; only reset recovers
+    pub stopped: bool,
+
+    step_cycles: u32,
+}
+
+impl SPC700 {
+    pub fn new() -> Self {
+        Self {
+            a: 0,
+            x: 0,
+            y: 0,
+            sp: 0xEF,
+            pc: 0,
+            psw: SpcFlags::empty(),
+            cycles: 0,
+            stopped: false,
+            step_cycles: 0,
+        }
+    }
+
+    pub fn reset<B: SpcBus>(&mut self, bus: &mut B) {
+        self.a = 0;
+        self.x = 0;
+        self.y = 0;
+        self.sp = 0xEF;
+        self.psw = SpcFlags::empty();
+        self.stopped = false;
+        self.pc = self.read_word(bus, RESET_VECTOR);
+    }
+
+    /// Execute one instruction; returns SPC700 cycles
+    pub fn step<B: SpcBus>(&mut self, bus: &mut B) -> u32 {
+        if self.stopped {
+            // The clock keeps running so timers and the DSP still advance
+            self.cycles += 2;
+            return 2;
+        }
+
+        let opcode = self.fetch(bus);
+        self.step_cycles = CYCLES[opcode as usize] as u32;
+        self.execute(opcode, bus);
+        self.cycles += self.step_cycles as u64;
+        self.step_cycles
+    }
+
+    fn execute<B: SpcBus>(&mut self, opcode: u8, bus: &mut B) {
+        // OR/AND/EOR/CMP/ADC/SBC share columns 4-9 of rows 0-B
+        if opcode < 0xC0 && (0x04..=0x09).contains(&(opcode & 0x0F)) {
+            self.alu_group(opcode, bus);
+            return;
+        }
+
+        match opcode {
+            // Bit set/clear and branch on bit of a direct page byte
+            op if op & 0x0F == 0x02 => {
+                let addr = self.fetch_dp(bus);
+                let bit = 1 << (op >> 5);
+                let value = bus.read(addr);
+                bus.write(addr, if op & 0x10 == 0 { value | bit } else { value & !bit });
+            }
+            op if op & 0x0F == 0x03 => {
+                let addr = self.fetch_dp(bus);
+                let value = bus.read(addr);
+                let set = value & (1 << (op >> 5)) != 0;
+                self.branch(bus, set == (op & 0x10 == 0));
+            }
+            op if op & 0x0F == 0x01 => {
+                // TCALL n
+                let vector = TCALL_VECTOR - ((op >> 4) as u16) * 2;
+                self.push16(bus, self.pc);
+                self.pc = self.read_word(bus, vector);
+            }
+
+            // Branches
+            0x10 => self.branch(bus, !self.psw.contains(SpcFlags::NEGATIVE)),
+            0x30 => self.branch(bus, self.psw.contains(SpcFlags::NEGATIVE)),
+            0x50 => self.branch(bus, !self.psw.contains(SpcFlags::OVERFLOW)),
+            0x70 => self.branch(bus, self.psw.contains(SpcFlags::OVERFLOW)),
+            0x90 => self.branch(bus, !self.psw.contains(SpcFlags::CARRY)),
+            0xB0 => self.branch(bus, self.psw.contains(SpcFlags::CARRY)),
+            0xD0 => self.branch(bus, !self.psw.contains(SpcFlags::ZERO)),
+            0xF0 => self.branch(bus, self.psw.contains(SpcFlags::ZERO)),
+            0x2F => {
+                // BRA is always 4 cycles
+                let offset = self.fetch(bus) as i8;
+                self.pc = self.pc.wrapping_add(offset as u16);
+            }
+            0x2E | 0xDE => {
+                // CBNE dp,rel / CBNE dp+X,rel
+                let offset = self.fetch(bus);
+                let addr = if opcode == 0x2E { self.dp(offset) } else { self.dp(offset.wrapping_add(self.x)) };
+                let value = bus.read(addr);
+                self.branch(bus, self.a != value);
+            }
+            0x6E => {
+                // DBNZ dp,rel
+                let addr = self.fetch_dp(bus);
+                let value = bus.read(addr).wrapping_sub(1);
+                bus.write(addr, value);
+                self.branch(bus, value != 0);
+            }
+            0xFE => {
+                self.y = self.y.wrapping_sub(1);
+                self.branch(bus, self.y != 0);
+            }
+
+            // Jumps and calls
+            0x5F => self.pc = self.fetch16(bus),
+            0x1F => {
+                let addr = self.fetch16(bus).wrapping_add(self.x as u16);
+                self.pc = self.read_word(bus, addr);
+            }
+            0x3F => {
+                let target = self.fetch16(bus);
+                self.push16(bus, self.pc);
+                self.pc = target;
+            }
+            0x4F => {
+                // PCALL: call into the uppermost page
+                let offset = self.fetch(bus);
+                self.push16(bus, self.pc);
+                self.pc = 0xFF00 | offset as u16;
+            }
+            0x6F => self.pc = self.pull16(bus),
+            0x7F => {
+                let psw = self.pull8(bus);
+                self.psw = SpcFlags::from_bits_retain(psw);
+                self.pc = self.pull16(bus);
+            }
+            0x0F => {
+                // BRK
+                self.push16(bus, self.pc);
+                self.push8(bus, self.psw.bits());
+                self.psw.insert(SpcFlags::BREAK);
+                self.psw.remove(SpcFlags::INTERRUPT);
+                self.pc = self.read_word(bus, TCALL_VECTOR);
+            }
+
+            // Stack
+            0x2D => self.push8(bus, self.a),
+            0x4D => self.push8(bus, self.x),
+            0x6D => self.push8(bus, self.y),
+            0x0D => self.push8(bus, self.psw.bits()),
+            0xAE => self.a = self.pull8(bus),
+            0xCE => self.x = self.pull8(bus),
+            0xEE => self.y = self.pull8(bus),
+            0x8E => {
+                let psw = self.pull8(bus);
+                self.psw = SpcFlags::from_bits_retain(psw);
+            }
+
+            // Flags
+            0x20 => self.psw.remove(SpcFlags::DIRECT_PAGE),
+            0x40 => self.psw.insert(SpcFlags::DIRECT_PAGE),
+            0x60 => self.psw.remove(SpcFlags::CARRY),
+            0x80 => self.psw.insert(SpcFlags::CARRY),
+            0xA0 => self.psw.insert(SpcFlags::INTERRUPT),
+            0xC0 => self.psw.remove(SpcFlags::INTERRUPT),
+            0xE0 => self.psw.remove(SpcFlags::OVERFLOW | SpcFlags::HALF_CARRY),
+            0xED => self.psw.toggle(SpcFlags::CARRY),
+
+            // Moves into registers
+            0xE8 => { let v = self.fetch(bus); self.a = self.set_nz(v); }
+            0xCD => { let v = self.fetch(bus); self.x = self.set_nz(v); }
+            0x8D => { let v = self.fetch(bus); self.y = self.set_nz(v); }
+            0xE4 => { let v = self.operand(bus, Mode::Dp); self.a = self.set_nz(v); }
+            0xE5 => { let v = self.operand(bus, Mode::Abs); self.a = self.set_nz(v); }
+            0xE6 => { let v = self.operand(bus, Mode::IndX); self.a = self.set_nz(v); }
+            0xE7 => { let v = self.operand(bus, Mode::DpIndX); self.a = self.set_nz(v); }
+            0xF4 => { let v = self.operand(bus, Mode::DpX); self.a = self.set_nz(v); }
+            0xF5 => { let v = self.operand(bus, Mode::AbsX); self.a = self.set_nz(v); }
+            0xF6 => { let v = self.operand(bus, Mode::AbsY); self.a = self.set_nz(v); }
+            0xF7 => { let v = self.operand(bus, Mode::DpIndY); self.a = self.set_nz(v); }
+            0xBF => {
+                // MOV A,(X)+
+                let v = bus.read(self.dp(self.x));
+                self.x = self.x.wrapping_add(1);
+                self.a = self.set_nz(v);
+            }
+            0xF8 => { let v = self.operand(bus, Mode::Dp); self.x = self.set_nz(v); }
+            0xF9 => { let v = self.operand(bus, Mode::DpY); self.x = self.set_nz(v); }
+            0xE9 => { let v = self.operand(bus, Mode::Abs); self.x = self.set_nz(v); }
+            0xEB => { let v = self.operand(bus, Mode::Dp); self.y = self.set_nz(v); }
+            0xFB => { let v = self.operand(bus, Mode::DpX); self.y = self.set_nz(v); }
+            0xEC => { let v = self.operand(bus, Mode::Abs); self.y = self.set_nz(v); }
+
+            // Moves into memory
+            0xC4 => self.store(bus, Mode::Dp, self.a),
+            0xC5 => self.store(bus, Mode::Abs, self.a),
+            0xC6 => self.store(bus, Mode::IndX, self.a),
+            0xC7 => self.store(bus, Mode::DpIndX, self.a),
+            0xD4 => self.store(bus, Mode::DpX, self.a),
+            0xD5 => self.store(bus, Mode::AbsX, self.a),
+            0xD6 => self.store(bus, Mode::AbsY, self.a),
+            0xD7 => self.store(bus, Mode::DpIndY, self.a),
+            0xAF => {
+                // MOV (X)+,A
+                bus.write(self.dp(self.x), self.a);
+                self.x = self.x.wrapping_add(1);
+            }
+            0xD8 => self.store(bus, Mode::Dp, self.x),
+            0xD9 => self.store(bus, Mode::DpY, self.x),
+            0xC9 => self.store(bus, Mode::Abs, self.x),
+            0xCB => self.store(bus, Mode::Dp, self.y),
+            0xDB => self.store(bus, Mode::DpX, self.y),
+            0xCC => self.store(bus, Mode::Abs, self.y),
+            0xFA => {
+                // MOV dp,dp: source first, no flags
+                let value = self.operand(bus, Mode::Dp);
+                self.store(bus, Mode::Dp, value);
+            }
+            0x8F => {
+                // MOV dp,#imm: immediate first
+                let value = self.fetch(bus);
+                self.store(bus, Mode::Dp, value);
+            }
+
+            // Register transfers
+            0x7D => self.a = self.set_nz(self.x),
+            0xDD => self.a = self.set_nz(self.y),
+            0x5D => self.x = self.set_nz(self.a),
+            0xFD => self.y = self.set_nz(self.a),
+            0x9D => self.x = self.set_nz(self.sp),
+            0xBD => self.sp = self.x,
+
+            // Index compares
+            0xC8 => { let v = self.fetch(bus); self.compare(self.x, v); }
+            0x3E => { let v = self.operand(bus, Mode::Dp); self.compare(self.x, v); }
+            0x1E => { let v = self.operand(bus, Mode::Abs); self.compare(self.x, v); }
+            0xAD => { let v = self.fetch(bus); self.compare(self.y, v); }
+            0x7E => { let v = self.operand(bus, Mode::Dp); self.compare(self.y, v); }
+            0x5E => { let v = self.operand(bus, Mode::Abs); self.compare(self.y, v); }
+
+            // Read-modify-write: ASL, ROL, LSR, ROR, DEC, INC
+            0x0B => self.modify(bus, Mode::Dp, Self::asl),
+            0x0C => self.modify(bus, Mode::Abs, Self::asl),
+            0x1B => self.modify(bus, Mode::DpX, Self::asl),
+            0x1C => self.a = self.asl(self.a),
+            0x2B => self.modify(bus, Mode::Dp, Self::rol),
+            0x2C => self.modify(bus, Mode::Abs, Self::rol),
+            0x3B => self.modify(bus, Mode::DpX, Self::rol),
+            0x3C => self.a = self.rol(self.a),
+            0x4B => self.modify(bus, Mode::Dp, Self::lsr),
+            0x4C => self.modify(bus, Mode::Abs, Self::lsr),
+            0x5B => self.modify(bus, Mode::DpX, Self::lsr),
+            0x5C => self.a = self.lsr(self.a),
+            0x6B => self.modify(bus, Mode::Dp, Self::ror),
+            0x6C => self.modify(bus, Mode::Abs, Self::ror),
+            0x7B => self.modify(bus, Mode::DpX, Self::ror),
+            0x7C => self.a = self.ror(self.a),
+            0x8B => self.modify(bus, Mode::Dp, Self::dec),
+            0x8C => self.modify(bus, Mode::Abs, Self::dec),
+            0x9B => self.modify(bus, Mode::DpX, Self::dec),
+            0x9C => self.a = self.dec(self.a),
+            0x1D => self.x = self.dec(self.x),
+            0xDC => self.y = self.dec(self.y),
+            0xAB => self.modify(bus, Mode::Dp, Self::inc),
+            0xAC => self.modify(bus, Mode::Abs, Self::inc),
+            0xBB => self.modify(bus, Mode::DpX, Self::inc),
+            0xBC => self.a = self.inc(self.a),
+            0x3D => self.x = self.inc(self.x),
+            0xFC => self.y = self.inc(self.y),
+
+            // Test-and-set/clear against A
+            0x0E | 0x4E => {
+                let addr = self.fetch16(bus);
+                let value = bus.read(addr);
+                self.set_nz(self.a.wrapping_sub(value));
+                bus.write(addr, if opcode == 0x0E { value | self.a } else { value & !self.a });
+            }
+
+            // 16-bit operations on YA and direct page words
+            0xBA => {
+                let addr = self.fetch(bus);
+                let value = self.read_dp_word(bus, addr);
+                self.set_ya(value);
+                self.set_nz16(value);
+            }
+            0xDA => {
+                let addr = self.fetch(bus);
+                let ya = self.ya();
+                bus.write(self.dp(addr), ya as u8);
+                bus.write(self.dp(addr.wrapping_add(1)), (ya >> 8) as u8);
+            }
+            0x3A | 0x1A => {
+                // INCW / DECW
+                let addr = self.fetch(bus);
+                let value = self.read_dp_word(bus, addr);
+                let value = if opcode == 0x3A { value.wrapping_add(1) } else { value.wrapping_sub(1) };
+                bus.write(self.dp(addr), value as u8);
+                bus.write(self.dp(addr.wrapping_add(1)), (value >> 8) as u8);
+                self.set_nz16(value);
+            }
+            0x7A | 0x9A => {
+                // ADDW / SUBW: two byte-wide adds, Z over the whole word
+                let addr = self.fetch(bus);
+                let value = self.read_dp_word(bus, addr);
+                if opcode == 0x7A {
+                    self.psw.remove(SpcFlags::CARRY);
+                    self.a = self.adc(self.a, value as u8);
+                    self.y = self.adc(self.y, (value >> 8) as u8);
+                } else {
+                    self.psw.insert(SpcFlags::CARRY);
+                    self.a = self.sbc(self.a, value as u8);
+                    self.y = self.sbc(self.y, (value >> 8) as u8);
+                }
+                self.psw.set(SpcFlags::ZERO, self.ya() == 0);
+            }
+            0x5A => {
+                // CMPW
+                let addr = self.fetch(bus);
+                let value = self.read_dp_word(bus, addr);
+                let result = self.ya() as i32 - value as i32;
+                self.psw.set(SpcFlags::CARRY, result >= 0);
+                self.set_nz16(result as u16);
+            }
+
+            // Multiply and divide
+            0xCF => {
+                let result = self.y as u16 * self.a as u16;
+                self.set_ya(result);
+                self.set_nz(self.y);
+            }
+            0x9E => self.div(),
+
+            // Decimal adjust
+            0xDF => {
+                if self.psw.contains(SpcFlags::CARRY) || self.a > 0x99 {
+                    self.a = self.a.wrapping_add(0x60);
+                    self.psw.insert(SpcFlags::CARRY);
+                }
+                if self.psw.contains(SpcFlags::HALF_CARRY) || (self.a & 0x0F) > 0x09 {
+                    self.a = self.a.wrapping_add(0x06);
+                }
+                self.set_nz(self.a);
+            }
+            0xBE => {
+                if !self.psw.contains(SpcFlags::CARRY) || self.a > 0x99 {
+                    self.a = self.a.wrapping_sub(0x60);
+                    self.psw.remove(SpcFlags::CARRY);
+                }
+                if !self.psw.contains(SpcFlags::HALF_CARRY) || (self.a & 0x0F) > 0x09 {
+                    self.a = self.a.wrapping_sub(0x06);
+                }
+                self.set_nz(self.a);
+            }
+            0x9F => self.a = self.set_nz(self.a.rotate_right(4)),
+
+            // Carry bit operations on m.b (13-bit address, 3-bit bit number)
+            0x0A | 0x2A | 0x4A | 0x6A | 0x8A | 0xAA => {
+                let (addr, bit) = self.fetch_bit_operand(bus);
+                let value = bus.read(addr) & (1 << bit) != 0;
+                let carry = self.psw.contains(SpcFlags::CARRY);
+                let carry = match opcode {
+                    0x0A => carry | value,
+                    0x2A => carry | !value,
+                    0x4A => carry & value,
+                    0x6A => carry & !value,
+                    0x8A => carry ^ value,
+                    _ => value,
+                };
+                self.psw.set(SpcFlags::CARRY, carry);
+            }
+            0xCA | 0xEA => {
+                // MOV1 m.b,C / NOT1 m.b
+                let (addr, bit) = self.fetch_bit_operand(bus);
+                let value = bus.read(addr);
+                let value = if opcode == 0xCA {
+                    let carry = self.psw.contains(SpcFlags::CARRY) as u8;
+                    (value & !(1 << bit)) | (carry << bit)
+                } else {
+                    value ^ (1 << bit)
+                };
+                bus.write(addr, value);
+            }
+
+            // SLEEP / STOP
+            0xEF | 0xFF => self.stopped = true,
+            // NOP
+            _ => {}
+        }
+    }
+
+    /// Columns 4-9 of rows 0-B: the ALU op is the row pair, the operands
+    /// the column and whether the row is odd
+    fn alu_group<B: SpcBus>(&mut self, opcode: u8, bus: &mut B) {
+        let op = opcode >> 5;
+        let odd = opcode & 0x10 != 0;
+        match (opcode & 0x0F, odd) {
+            (0x08, false) => {
+                let value = self.fetch(bus);
+                self.a = self.alu(op, self.a, value);
+            }
+            (0x08, true) => {
+                // op dp,#imm
+                let value = self.fetch(bus);
+                let addr = self.fetch_dp(bus);
+                let target = bus.read(addr);
+                let result = self.alu(op, target, value);
+                if op != 3 {
+                    bus.write(addr, result);
+                }
+            }
+            (0x09, false) => {
+                // op dp,dp: source first
+                let value = self.operand(bus, Mode::Dp);
+                let addr = self.fetch_dp(bus);
+                let target = bus.read(addr);
+                let result = self.alu(op, target, value);
+                if op != 3 {
+                    bus.write(addr, result);
+                }
+            }
+            (0x09, true) => {
+                // op (X),(Y)
+                let value = bus.read(self.dp(self.y));
+                let addr = self.dp(self.x);
+                let target = bus.read(addr);
+                let result = self.alu(op, target, value);
+                if op != 3 {
+                    bus.write(addr, result);
+                }
+            }
+            (column, _) => {
+                let mode = match (column, odd) {
+                    (0x04, false) => Mode::Dp,
+                    (0x05, false) => Mode::Abs,
+                    (0x06, false) => Mode::IndX,
+                    (0x07, false) => Mode::DpIndX,
+                    (0x04, true) => Mode::DpX,
+                    (0x05, true) => Mode::AbsX,
+                    (0x06, true) => Mode::AbsY,
+                    _ => Mode::DpIndY,
+                };
+                let value = self.operand(bus, mode);
+                self.a = self.alu(op, self.a, value);
+            }
+        }
+    }
+
+    /// OR, AND, EOR, CMP, ADC, SBC; CMP returns `a` unchanged
+    fn alu(&mut self, op: u8, a: u8, b: u8) -> u8 {
+        match op {
+            0 => self.set_nz(a | b),
+            1 => self.set_nz(a & b),
+            2 => self.set_nz(a ^ b),
+            3 => {
+                self.compare(a, b);
+                a
+            }
+            4 => self.adc(a, b),
+            _ => self.sbc(a, b),
+        }
+    }
+
+    // Bus access
+
+    fn fetch<B: SpcBus>(&mut self, bus: &mut B) -> u8 {
+        let value = bus.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch16<B: SpcBus>(&mut self, bus: &mut B) -> u16 {
+        let lo = self.fetch(bus) as u16;
+        let hi = self.fetch(bus) as u16;
+        (hi << 8) | lo
+    }
+
+    fn fetch_dp<B: SpcBus>(&mut self, bus: &mut B) -> u16 {
+        let offset = self.fetch(bus);
+        self.dp(offset)
+    }
+
+    /// m.b operand: address in bits 0-12, bit number in 13-15
+    fn fetch_bit_operand<B: SpcBus>(&mut self, bus: &mut B) -> (u16, u8) {
+        let operand = self.fetch16(bus);
+        (operand & 0x1FFF, (operand >> 13) as u8)
+    }
+
+    fn read_word<B: SpcBus>(&mut self, bus: &mut B, addr: u16) -> u16 {
+        let lo = bus.read(addr) as u16;
+        let hi = bus.read(addr.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    /// Direct page word; the high byte wraps within the page
+    fn read_dp_word<B: SpcBus>(&mut self, bus: &mut B, offset: u8) -> u16 {
+        let lo = bus.read(self.dp(offset)) as u16;
+        let hi = bus.read(self.dp(offset.wrapping_add(1))) as u16;
+        (hi << 8) | lo
+    }
+
+    fn dp(&self, offset: u8) -> u16 {
+        ((self.psw.contains(SpcFlags::DIRECT_PAGE) as u16) << 8) | offset as u16
+    }
+
+    fn push8<B: SpcBus>(&mut self, bus: &mut B, value: u8) {
+        bus.write(0x0100 | self.sp as u16, value);
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    fn pull8<B: SpcBus>(&mut self, bus: &mut B) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        bus.read(0x0100 | self.sp as u16)
+    }
+
+    fn push16<B: SpcBus>(&mut self, bus: &mut B, value: u16) {
+        self.push8(bus, (value >> 8) as u8);
+        self.push8(bus, value as u8);
+    }
+
+    fn pull16<B: SpcBus>(&mut self, bus: &mut B) -> u16 {
+        let lo = self.pull8(bus) as u16;
+        let hi = self.pull8(bus) as u16;
+        (hi << 8) | lo
+    }
+
+    // Addressing
+
+    fn address<B: SpcBus>(&mut self, bus: &mut B, mode: Mode) -> u16 {
+        match mode {
+            Mode::Dp => self.fetch_dp(bus),
+            Mode::DpX => {
+                let offset = self.fetch(bus);
+                self.dp(offset.wrapping_add(self.x))
+            }
+            Mode::DpY => {
+                let offset = self.fetch(bus);
+                self.dp(offset.wrapping_add(self.y))
+            }
+            Mode::Abs => self.fetch16(bus),
+            Mode::AbsX => self.fetch16(bus).wrapping_add(self.x as u16),
+            Mode::AbsY => self.fetch16(bus).wrapping_add(self.y as u16),
+            Mode::IndX => self.dp(self.x),
+            Mode::DpIndX => {
+                let offset = self.fetch(bus);
+                self.read_dp_word(bus, offset.wrapping_add(self.x))
+            }
+            Mode::DpIndY => {
+                let offset = self.fetch(bus);
+                self.read_dp_word(bus, offset).wrapping_add(self.y as u16)
+            }
+        }
+    }
+
+    fn operand<B: SpcBus>(&mut self, bus: &mut B, mode: Mode) -> u8 {
+        let addr = self.address(bus, mode);
+        bus.read(addr)
+    }
+
+    fn store<B: SpcBus>(&mut self, bus: &mut B, mode: Mode, value: u8) {
+        let addr = self.address(bus, mode);
+        bus.write(addr, value);
+    }
+
+    fn modify<B: SpcBus>(&mut self, bus: &mut B, mode: Mode, op: fn(&mut Self, u8) -> u8) {
+        let addr = self.address(bus, mode);
+        let value = bus.read(addr);
+        let result = op(self, value);
+        bus.write(addr, result);
+    }
+
+    fn branch<B: SpcBus>(&mut self, bus: &mut B, condition: bool) {
+        let offset = self.fetch(bus) as i8;
+        if condition {
+            self.pc = self.pc.wrapping_add(offset as u16);
+            self.step_cycles += 2;
+        }
+    }
+
+    // Flags and registers
+
+    fn set_nz(&mut self, value: u8) -> u8 {
+        self.psw.set(SpcFlags::ZERO, value == 0);
+        self.psw.set(SpcFlags::NEGATIVE, value & 0x80 != 0);
+        value
+    }
+
+    fn set_nz16(&mut self, value: u16) {
+        self.psw.set(SpcFlags::ZERO, value == 0);
+        self.psw.set(SpcFlags::NEGATIVE, value & 0x8000 != 0);
+    }
+
+    fn ya(&self) -> u16 {
+        ((self.y as u16) << 8) | self.a as u16
+    }
+
+    fn set_ya(&mut self, value: u16) {
+        self.a = value as u8;
+        self.y = (value >> 8) as u8;
+    }
+
+    // ALU
+
+    fn compare(&mut self, register: u8, value: u8) {
+        let result = register as i16 - value as i16;
+        self.psw.set(SpcFlags::CARRY, result >= 0);
+        self.set_nz(result as u8);
+    }
+
+    fn adc(&mut self, a: u8, b: u8) -> u8 {
+        let carry = self.psw.contains(SpcFlags::CARRY) as u16;
+        let result = a as u16 + b as u16 + carry;
+        self.psw.set(SpcFlags::HALF_CARRY, (a & 0x0F) as u16 + (b & 0x0F) as u16 + carry > 0x0F);
+        self.psw.set(SpcFlags::OVERFLOW, !(a ^ b) & (a ^ result as u8) & 0x80 != 0);
+        self.psw.set(SpcFlags::CARRY, result > 0xFF);
+        self.set_nz(result as u8)
+    }
+
+    /// Subtract with borrow is an add of the complement; H is set when
+    /// there was no borrow out of the low nibble
+    fn sbc(&mut self, a: u8, b: u8) -> u8 {
+        self.adc(a, !b)
+    }
+
+    fn asl(&mut self, value: u8) -> u8 {
+        self.psw.set(SpcFlags::CARRY, value & 0x80 != 0);
+        self.set_nz(value << 1)
+    }
+
+    fn lsr(&mut self, value: u8) -> u8 {
+        self.psw.set(SpcFlags::CARRY, value & 0x01 != 0);
+        self.set_nz(value >> 1)
+    }
+
+    fn rol(&mut self, value: u8) -> u8 {
+        let carry = self.psw.contains(SpcFlags::CARRY) as u8;
+        self.psw.set(SpcFlags::CARRY, value & 0x80 != 0);
+        self.set_nz((value << 1) | carry)
+    }
+
+    fn ror(&mut self, value: u8) -> u8 {
+        let carry = self.psw.contains(SpcFlags::CARRY) as u8;
+        self.psw.set(SpcFlags::CARRY, value & 0x01 != 0);
+        self.set_nz((value >> 1) | (carry << 7))
+    }
+
+    fn inc(&mut self, value: u8) -> u8 {
+        self.set_nz(value.wrapping_add(1))
+    }
+
+    fn dec(&mut self, value: u8) -> u8 {
+        self.set_nz(value.wrapping_sub(1))
+    }
+
+    /// DIV YA,X: quotient in A, remainder in Y. A quotient that does not
+    /// fit in 9 bits comes out the way the hardware's shift divider leaves it.
+    fn div(&mut self) {
+        let ya = self.ya() as u32;
+        let x = self.x as u32;
+        self.psw.set(SpcFlags::HALF_CARRY, (self.y & 0x0F) >= (self.x & 0x0F));
+        self.psw.set(SpcFlags::OVERFLOW, self.y >= self.x);
+        if (self.y as u32) < x << 1 {
+            self.a = (ya / x) as u8;
+            self.y = (ya % x) as u8;
+        } else {
+            self.a = (255 - (ya - (x << 9)) / (256 - x)) as u8;
+            self.y = (x + (ya - (x << 9)) % (256 - x)) as u8;
+        }
+        self.set_nz(self.a);
+    }
+}
+
+impl Default for SPC700 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
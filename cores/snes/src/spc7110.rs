@@ -0,0 +1,676 @@
+/// SPC7110
+/// Data ROM controller of Far East of Eden Zero, Momotarou Dentetsu Happy
+/// and Super Power League 4. The board splits its ROM into a 1MB program
+/// ROM and a data ROM the chip banks and serves through:
+/// - a decompression unit that unpacks 1/2/4bpp tiles from an index table
+/// - a data port reading the data ROM with offsets and strides
+/// - a 16x16 multiplier and 32/16 divider
+/// - 1MB bank registers for $D0-$FF and an SRAM enable
+///
+/// Tengai Makyou Zero also has an RTC-4513 behind $4840-$4842.
+
+use crate::cartridge::{mirror, SnesCartridge};
+use crate::rtc4513::Rtc4513;
+
+/// Program ROM at the start of the image; the data ROM follows it
+pub const PROGRAM_ROM_SIZE: usize = 0x100000;
+
+/// Probability of the more probable symbol, and the next state after an
+/// MPS or an LPS
+const EVOLUTION: [(u8, [u8; 2]); 53] = [
+    (0x5A, [1, 1]), (0x25, [2, 6]), (0x11, [3, 8]),
+    (0x08, [4, 10]), (0x03, [5, 12]), (0x01, [5, 15]),
+
+    (0x5A, [7, 7]), (0x3F, [8, 19]), (0x2C, [9, 21]),
+    (0x20, [10, 22]), (0x17, [11, 23]), (0x11, [12, 25]),
+    (0x0C, [13, 26]), (0x09, [14, 28]), (0x07, [15, 29]),
+    (0x05, [16, 31]), (0x04, [17, 32]), (0x03, [18, 34]),
+    (0x02, [5, 35]),
+
+    (0x5A, [20, 20]), (0x48, [21, 39]), (0x3A, [22, 40]),
+    (0x2E, [23, 42]), (0x26, [24, 44]), (0x1F, [25, 45]),
+    (0x19, [26, 46]), (0x15, [27, 25]), (0x11, [28, 26]),
+    (0x0E, [29, 26]), (0x0B, [30, 27]), (0x09, [31, 28]),
+    (0x08, [32, 29]), (0x07, [33, 30]), (0x05, [34, 31]),
+    (0x04, [35, 33]), (0x04, [36, 33]), (0x03, [37, 34]),
+    (0x02, [38, 35]), (0x02, [5, 36]),
+
+    (0x58, [40, 39]), (0x4D, [41, 47]), (0x43, [42, 48]),
+    (0x3B, [43, 49]), (0x34, [44, 50]), (0x2E, [45, 51]),
+    (0x29, [46, 44]), (0x25, [24, 45]),
+
+    (0x56, [48, 47]), (0x4F, [49, 47]), (0x47, [50, 48]),
+    (0x41, [51, 49]), (0x3C, [52, 50]), (0x37, [43, 51]),
+];
+
+const RANGE_MAX: u16 = 0xFF;
+const PROBABILITY_HALF: u8 = 0x55;
+
+// $480B
+const DCU_SKIP_TILES: u8 = 0x01;
+const DCU_SEEK: u8 = 0x02;
+/// $480C bit 7: decompressed data is ready
+const DCU_READY: u8 = 0x80;
+
+// $4818
+const DATA_USE_STRIDE: u8 = 0x01;
+const DATA_USE_ADJUST: u8 = 0x02;
+const DATA_STRIDE_SIGNED: u8 = 0x04;
+const DATA_ADJUST_SIGNED: u8 = 0x08;
+const DATA_STRIDE_ADJUST: u8 = 0x10;
+
+/// $4830 bit 7: SRAM enable
+const SRAM_ENABLE: u8 = 0x80;
+/// $4834 bit 2: the program ROM is 2MB and also fills $20-$3F/$D0-$DF
+const PROGRAM_16MBIT: u8 = 0x04;
+
+/// $482F bit 7: the arithmetic unit is busy
+const ALU_BUSY: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Context {
+    /// State in `EVOLUTION`
+    prediction: u8,
+    /// The MPS and LPS swapped roles
+    swap: u8,
+}
+
+/// Binary arithmetic decoder with a context per pixel position and
+/// neighbourhood, plus a most-recently-used palette for 2/4bpp
+struct Decompressor {
+    /// Not every one of the 5x15 contexts exists; this keeps indexing simple
+    contexts: [[Context; 15]; 5],
+    bpp: u32,
+    /// Data ROM read position
+    offset: u32,
+    /// Bits left before the next input byte
+    bits: u32,
+    range: u16,
+    input: u16,
+    output: u8,
+    pixels: u64,
+    colormap: u64,
+    /// Eight decoded pixels, one bitplane per byte
+    result: u32,
+}
+
+impl Decompressor {
+    fn new() -> Self {
+        Self {
+            contexts: [[Context::default(); 15]; 5],
+            bpp: 1,
+            offset: 0,
+            bits: 8,
+            range: RANGE_MAX + 1,
+            input: 0,
+            output: 0,
+            pixels: 0,
+            colormap: 0,
+            result: 0,
+        }
+    }
+
+    fn next_byte(&mut self, rom: &impl Fn(u32) -> u8) -> u8 {
+        let value = rom(self.offset);
+        self.offset = self.offset.wrapping_add(1);
+        value
+    }
+
+    fn init(&mut self, rom: &impl Fn(u32) -> u8, mode: u8, origin: u32) {
+        self.contexts = [[Context::default(); 15]; 5];
+        self.bpp = 1 << mode;
+        self.offset = origin;
+        self.bits = 8;
+        self.range = RANGE_MAX + 1;
+        self.input = (self.next_byte(rom) as u16) << 8;
+        self.input |= self.next_byte(rom) as u16;
+        self.output = 0;
+        self.pixels = 0;
+        self.colormap = 0xFEDC_BA98_7654_3210;
+    }
+
+    /// Decode the next row of eight pixels into `result`
+    fn decode(&mut self, rom: &impl Fn(u32) -> u8) {
+        for pixel in 0..8u32 {
+            let mut map = self.colormap;
+            let mut diff = 0;
+
+            if self.bpp > 1 {
+                // Neighbours from the pixel history pick the context set
+                let (a, b, c) = if self.bpp == 2 {
+                    (self.pixels >> 2 & 3, self.pixels >> 14 & 3, self.pixels >> 16 & 3)
+                } else {
+                    (self.pixels & 15, self.pixels >> 28 & 15, self.pixels >> 32 & 15)
+                };
+                if a != b || b != c {
+                    let odd = a ^ b ^ c;
+                    diff = 4;
+                    if odd ^ c == 0 {
+                        diff = 3;
+                    }
+                    if odd ^ b == 0 {
+                        diff = 2;
+                    }
+                    if odd ^ a == 0 {
+                        diff = 1;
+                    }
+                }
+                self.colormap = move_to_front(self.colormap, a);
+                map = move_to_front(map, c);
+                map = move_to_front(map, b);
+                map = move_to_front(map, a);
+            }
+
+            for plane in 0..self.bpp {
+                let bit = if self.bpp > 1 { 1 << plane } else { 1 << (pixel & 3) };
+                let history = (bit - 1) & self.output as u32;
+                let mut set = match self.bpp {
+                    1 => (pixel >= 4) as usize,
+                    2 => diff,
+                    _ => 0,
+                };
+                if plane >= 2 && history <= 1 {
+                    set = diff;
+                }
+
+                let index = (bit + history - 1) as usize;
+                let context = self.contexts[set][index];
+                let (probability, next) = EVOLUTION[context.prediction as usize];
+                let lps_offset = self.range.wrapping_sub(probability as u16) & 0xFF;
+                let symbol = self.input >= lps_offset << 8;
+
+                self.output = (self.output << 1) | (symbol as u8 ^ context.swap);
+                if symbol {
+                    self.range -= lps_offset;
+                    self.input -= lps_offset << 8;
+                } else {
+                    self.range = lps_offset;
+                }
+
+                // Renormalise into [0.75, 1.5)
+                while self.range <= RANGE_MAX / 2 {
+                    self.contexts[set][index].prediction = next[symbol as usize];
+                    self.range <<= 1;
+                    self.input <<= 1;
+                    self.bits -= 1;
+                    if self.bits == 0 {
+                        self.bits = 8;
+                        let byte = self.next_byte(rom);
+                        self.input = self.input.wrapping_add(byte as u16);
+                    }
+                }
+
+                if symbol && probability > PROBABILITY_HALF {
+                    self.contexts[set][index].swap ^= 1;
+                }
+            }
+
+            let mut index = self.output as u64 & ((1 << self.bpp) - 1);
+            if self.bpp == 1 {
+                index ^= self.pixels >> 15 & 1;
+            }
+            self.pixels = (self.pixels << self.bpp) | (map >> (4 * index) & 15);
+        }
+
+        self.result = match self.bpp {
+            1 => self.pixels as u32,
+            2 => deinterleave(self.pixels, 16),
+            _ => deinterleave(deinterleave(self.pixels, 32) as u64, 32),
+        };
+    }
+}
+
+/// Unpack big-endian packed pixels: odd bits end up in the low half,
+/// even bits in the high half
+fn deinterleave(data: u64, bits: u32) -> u32 {
+    let mut data = data & ((1u64 << bits) - 1);
+    data = 0x5555_5555_5555_5555 & (data << bits | data >> 1);
+    data = 0x3333_3333_3333_3333 & (data | data >> 1);
+    data = 0x0F0F_0F0F_0F0F_0F0F & (data | data >> 2);
+    data = 0x00FF_00FF_00FF_00FF & (data | data >> 4);
+    data = 0x0000_FFFF_0000_FFFF & (data | data >> 8);
+    (data | data >> 16) as u32
+}
+
+/// Move a nibble to the front of a list of sixteen
+fn move_to_front(list: u64, nibble: u64) -> u64 {
+    let mut mask = !15u64;
+    for shift in (0..64).step_by(4) {
+        if list >> shift & 15 == nibble {
+            return (list & mask) + (list << 4 & !mask) + nibble;
+        }
+        mask <<= 4;
+    }
+    list
+}
+
+pub struct Spc7110 {
+    // Decompression unit
+    /// $4801-$4803: index table in the data ROM
+    dcu_table: u32,
+    /// $4804: entry in the table; writing it loads mode and address
+    dcu_index: u8,
+    /// $4805-$4806: rows to skip at the start; writing $4806 starts
+    dcu_seek: u16,
+    /// $4807: rows to skip between tiles
+    dcu_skip: u8,
+    /// $4809-$480A: bytes left, counted down by $4800 reads
+    dcu_length: u16,
+    /// $480B: seek/skip enables
+    dcu_control: u8,
+    /// $480C
+    dcu_status: u8,
+    /// Mode and data ROM address from the table entry
+    dcu_mode: u8,
+    dcu_address: u32,
+    dcu_tile: [u8; 32],
+    dcu_tile_offset: usize,
+    decompressor: Decompressor,
+
+    // Data port
+    /// $4810: byte at the port
+    data: u8,
+    /// $4811-$4813
+    data_offset: u32,
+    /// $4814-$4815
+    data_adjust: u16,
+    /// $4816-$4817
+    data_stride: u16,
+    /// $4818
+    data_mode: u8,
+
+    // Arithmetic unit
+    /// $4820-$4823: dividend, its low half the multiplicand
+    alu_dividend: u32,
+    /// $4824-$4825
+    alu_multiplier: u16,
+    /// $4826-$4827
+    alu_divisor: u16,
+    /// $4828-$482B: product or quotient
+    alu_result: u32,
+    /// $482C-$482D
+    alu_remainder: u16,
+    /// $482E bit 0: signed operands
+    alu_signed: u8,
+    /// $482F
+    alu_status: u8,
+
+    /// $4830-$4834: SRAM enable, 1MB data ROM banks for $C0-$FF, data ROM size
+    mmc: [u8; 5],
+
+    pub rtc: Option<Rtc4513>,
+}
+
+impl Spc7110 {
+    pub fn new(rtc: bool) -> Self {
+        Self {
+            dcu_table: 0,
+            dcu_index: 0,
+            dcu_seek: 0,
+            dcu_skip: 0,
+            dcu_length: 0,
+            dcu_control: 0,
+            dcu_status: 0,
+            dcu_mode: 0,
+            dcu_address: 0,
+            dcu_tile: [0; 32],
+            dcu_tile_offset: 0,
+            decompressor: Decompressor::new(),
+            data: 0,
+            data_offset: 0,
+            data_adjust: 0,
+            data_stride: 0,
+            data_mode: 0,
+            alu_dividend: 0,
+            alu_multiplier: 0,
+            alu_divisor: 0,
+            alu_result: 0,
+            alu_remainder: 0,
+            alu_signed: 0,
+            alu_status: 0,
+            mmc: [0, 0, 1, 2, 0],
+            rtc: rtc.then(Rtc4513::new),
+        }
+    }
+
+    /// The RTC keeps its time across resets
+    pub fn reset(&mut self) {
+        let rtc = self.rtc.take();
+        *self = Self::new(false);
+        self.rtc = rtc;
+    }
+
+    /// Let the RTC count along with the master clock
+    pub fn run(&mut self, master_cycles: u64, pal: bool) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.run(master_cycles, pal);
+        }
+    }
+
+    /// $4800-$4842; `None` for the unused registers
+    pub fn read_io(&mut self, cart: &SnesCartridge, addr: u16) -> Option<u8> {
+        Some(match addr {
+            0x4800 => {
+                self.dcu_length = self.dcu_length.wrapping_sub(1);
+                self.dcu_read(cart)
+            }
+            0x4801 => self.dcu_table as u8,
+            0x4802 => (self.dcu_table >> 8) as u8,
+            0x4803 => (self.dcu_table >> 16) as u8,
+            0x4804 => self.dcu_index,
+            0x4805 => self.dcu_seek as u8,
+            0x4806 => (self.dcu_seek >> 8) as u8,
+            0x4807 => self.dcu_skip,
+            0x4808 => 0,
+            0x4809 => self.dcu_length as u8,
+            0x480A => (self.dcu_length >> 8) as u8,
+            0x480B => self.dcu_control,
+            0x480C => self.dcu_status,
+
+            0x4810 => {
+                let value = self.data;
+                self.data_increment(cart);
+                value
+            }
+            0x4811 => self.data_offset as u8,
+            0x4812 => (self.data_offset >> 8) as u8,
+            0x4813 => (self.data_offset >> 16) as u8,
+            0x4814 => self.data_adjust as u8,
+            0x4815 => (self.data_adjust >> 8) as u8,
+            0x4816 => self.data_stride as u8,
+            0x4817 => (self.data_stride >> 8) as u8,
+            0x4818 => self.data_mode,
+            0x481A => {
+                self.data_apply_adjust(cart, 3);
+                0
+            }
+
+            0x4820..=0x4823 => (self.alu_dividend >> (8 * (addr & 3))) as u8,
+            0x4824 => self.alu_multiplier as u8,
+            0x4825 => (self.alu_multiplier >> 8) as u8,
+            0x4826 => self.alu_divisor as u8,
+            0x4827 => (self.alu_divisor >> 8) as u8,
+            0x4828..=0x482B => (self.alu_result >> (8 * (addr & 3))) as u8,
+            0x482C => self.alu_remainder as u8,
+            0x482D => (self.alu_remainder >> 8) as u8,
+            0x482E => self.alu_signed,
+            0x482F => self.alu_status,
+
+            0x4830..=0x4834 => self.mmc[(addr - 0x4830) as usize],
+            0x4840..=0x4842 => return self.rtc.as_mut().map(|rtc| rtc.read(addr)),
+            _ => return None,
+        })
+    }
+
+    pub fn write_io(&mut self, cart: &SnesCartridge, addr: u16, value: u8) {
+        let set_byte = |word: u32, shift: u16| (word & !(0xFF << shift)) | ((value as u32) << shift);
+        match addr {
+            0x4801 => self.dcu_table = set_byte(self.dcu_table, 0),
+            0x4802 => self.dcu_table = set_byte(self.dcu_table, 8),
+            0x4803 => self.dcu_table = set_byte(self.dcu_table, 16),
+            0x4804 => {
+                self.dcu_index = value;
+                self.dcu_load_address(cart);
+            }
+            0x4805 => self.dcu_seek = set_byte(self.dcu_seek as u32, 0) as u16,
+            0x4806 => {
+                self.dcu_seek = set_byte(self.dcu_seek as u32, 8) as u16;
+                self.dcu_status &= !DCU_READY;
+                self.dcu_begin(cart);
+            }
+            0x4807 => self.dcu_skip = value,
+            0x4809 => self.dcu_length = set_byte(self.dcu_length as u32, 0) as u16,
+            0x480A => self.dcu_length = set_byte(self.dcu_length as u32, 8) as u16,
+            0x480B => self.dcu_control = value & 0x03,
+
+            0x4811 => self.data_offset = set_byte(self.data_offset, 0),
+            0x4812 => self.data_offset = set_byte(self.data_offset, 8),
+            0x4813 => {
+                self.data_offset = set_byte(self.data_offset, 16) & 0x7FFFFF;
+                self.data_read(cart);
+            }
+            0x4814 => {
+                self.data_adjust = set_byte(self.data_adjust as u32, 0) as u16;
+                self.data_apply_adjust(cart, 1);
+            }
+            0x4815 => {
+                self.data_adjust = set_byte(self.data_adjust as u32, 8) as u16;
+                if self.data_mode & DATA_USE_ADJUST != 0 {
+                    self.data_read(cart);
+                }
+                self.data_apply_adjust(cart, 2);
+            }
+            0x4816 => self.data_stride = set_byte(self.data_stride as u32, 0) as u16,
+            0x4817 => self.data_stride = set_byte(self.data_stride as u32, 8) as u16,
+            0x4818 => {
+                self.data_mode = value & 0x7F;
+                self.data_read(cart);
+            }
+
+            0x4820..=0x4823 => self.alu_dividend = set_byte(self.alu_dividend, 8 * (addr & 3)),
+            0x4824 => self.alu_multiplier = set_byte(self.alu_multiplier as u32, 0) as u16,
+            0x4825 => {
+                self.alu_multiplier = set_byte(self.alu_multiplier as u32, 8) as u16;
+                self.alu_status |= ALU_BUSY;
+                self.multiply();
+            }
+            0x4826 => self.alu_divisor = set_byte(self.alu_divisor as u32, 0) as u16,
+            0x4827 => {
+                self.alu_divisor = set_byte(self.alu_divisor as u32, 8) as u16;
+                self.alu_status |= ALU_BUSY;
+                self.divide();
+            }
+            0x482E => self.alu_signed = value & 0x01,
+
+            0x4830 => self.mmc[0] = value & 0x87,
+            0x4831..=0x4834 => self.mmc[(addr - 0x4830) as usize] = value & 0x07,
+            0x4840..=0x4842 => {
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write(addr, value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Cartridge read: program ROM at $00-$1F/$80-$9F:$8000-$FFFF and
+    /// $C0-$CF, data ROM banks at $D0-$FF, SRAM at $6000-$7FFF, and the
+    /// decompression port mirrored over bank $50
+    pub fn read(&mut self, cart: &SnesCartridge, addr: u32) -> Option<u8> {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        match (bank, offset) {
+            (0x00..=0x3F | 0x80..=0xBF, 0x6000..=0x7FFF) => Some(match cart.sram_size() {
+                size if size > 0 && self.mmc[0] & SRAM_ENABLE != 0 => cart.sram[(offset as usize & 0x1FFF) % size],
+                _ => 0,
+            }),
+            (0x50, _) => self.read_io(cart, 0x4800),
+            (0x58, _) => Some(0),
+            (0x00..=0x1F | 0x80..=0x9F, 0x8000..=0xFFFF) | (0xC0..=0xCF, _) => {
+                Some(self.program_read(cart, addr & 0x0FFFFF, 0))
+            }
+            (0x20..=0x3F | 0xA0..=0xBF, 0x8000..=0xFFFF) | (0xD0..=0xDF, _) => {
+                Some(self.program_read(cart, addr & 0x0FFFFF, 1))
+            }
+            (0xE0..=0xFF, _) => {
+                let window = (bank as usize >> 4) - 0xC;
+                let addr = ((self.mmc[window] as u32 & 0x07) << 20) | (addr & 0x0FFFFF);
+                Some(data_rom_read(cart, self.mmc[4], addr))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn write(&mut self, cart: &mut SnesCartridge, addr: u32, value: u8) {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        let size = cart.sram_size();
+        if matches!(bank, 0x00..=0x3F | 0x80..=0xBF)
+            && (0x6000..0x8000).contains(&offset)
+            && size > 0
+            && self.mmc[0] & SRAM_ENABLE != 0
+        {
+            cart.sram[(offset as usize & 0x1FFF) % size] = value;
+        }
+    }
+
+    /// The first two 1MB windows hold program ROM, unless the data ROM is
+    /// banked in through $4830/$4831
+    fn program_read(&self, cart: &SnesCartridge, addr: u32, window: usize) -> u8 {
+        let program = &cart.rom[..PROGRAM_ROM_SIZE.min(cart.rom.len())];
+        if window == 0 || self.mmc[4] & PROGRAM_16MBIT != 0 {
+            let addr = window * 0x100000 + addr as usize;
+            return program[mirror(addr, program.len())];
+        }
+        let addr = ((self.mmc[window] as u32 & 0x07) << 20) | addr;
+        data_rom_read(cart, self.mmc[4], addr)
+    }
+
+    fn dcu_load_address(&mut self, cart: &SnesCartridge) {
+        let entry = self.dcu_table.wrapping_add((self.dcu_index as u32) << 2);
+        let read = |offset: u32| data_rom_read(cart, self.mmc[4], entry.wrapping_add(offset));
+        self.dcu_mode = read(0);
+        self.dcu_address = (read(1) as u32) << 16 | (read(2) as u32) << 8 | read(3) as u32;
+    }
+
+    fn dcu_begin(&mut self, cart: &SnesCartridge) {
+        // Mode 3 does not exist
+        if self.dcu_mode > 2 {
+            return;
+        }
+        let size = self.mmc[4];
+        let rom = |addr: u32| data_rom_read(cart, size, addr);
+        self.decompressor.init(&rom, self.dcu_mode, self.dcu_address);
+        self.decompressor.decode(&rom);
+
+        let seek = if self.dcu_control & DCU_SEEK != 0 { self.dcu_seek } else { 0 };
+        for _ in 0..seek {
+            self.decompressor.decode(&rom);
+        }
+        self.dcu_status |= DCU_READY;
+        self.dcu_tile_offset = 0;
+    }
+
+    /// Next byte of decompressed tile data; a whole tile is decoded at once
+    fn dcu_read(&mut self, cart: &SnesCartridge) -> u8 {
+        if self.dcu_status & DCU_READY == 0 {
+            return 0;
+        }
+        let bpp = self.decompressor.bpp as usize;
+
+        if self.dcu_tile_offset == 0 {
+            let size = self.mmc[4];
+            let rom = |addr: u32| data_rom_read(cart, size, addr);
+            for row in 0..8 {
+                let result = self.decompressor.result;
+                match bpp {
+                    1 => self.dcu_tile[row] = result as u8,
+                    2 => {
+                        self.dcu_tile[row * 2] = result as u8;
+                        self.dcu_tile[row * 2 + 1] = (result >> 8) as u8;
+                    }
+                    _ => {
+                        self.dcu_tile[row * 2] = result as u8;
+                        self.dcu_tile[row * 2 + 1] = (result >> 8) as u8;
+                        self.dcu_tile[row * 2 + 16] = (result >> 16) as u8;
+                        self.dcu_tile[row * 2 + 17] = (result >> 24) as u8;
+                    }
+                }
+
+                let skip = if self.dcu_control & DCU_SKIP_TILES != 0 { self.dcu_skip } else { 1 };
+                for _ in 0..skip {
+                    self.decompressor.decode(&rom);
+                }
+            }
+        }
+
+        let value = self.dcu_tile[self.dcu_tile_offset];
+        self.dcu_tile_offset = (self.dcu_tile_offset + 1) & (8 * bpp - 1);
+        value
+    }
+
+    /// Latch the byte at offset (+ adjust) into $4810
+    fn data_read(&mut self, cart: &SnesCartridge) {
+        let adjust = if self.data_mode & DATA_USE_ADJUST != 0 { self.adjust() } else { 0 };
+        self.data = data_rom_read(cart, self.mmc[4], self.data_offset.wrapping_add(adjust));
+    }
+
+    fn adjust(&self) -> u32 {
+        if self.data_mode & DATA_ADJUST_SIGNED != 0 {
+            self.data_adjust as i16 as u32
+        } else {
+            self.data_adjust as u32
+        }
+    }
+
+    /// After a $4810 read: step the offset, or the adjust value, by the stride
+    fn data_increment(&mut self, cart: &SnesCartridge) {
+        let stride = if self.data_mode & DATA_USE_STRIDE == 0 {
+            1
+        } else if self.data_mode & DATA_STRIDE_SIGNED != 0 {
+            self.data_stride as i16 as u32
+        } else {
+            self.data_stride as u32
+        };
+
+        if self.data_mode & DATA_STRIDE_ADJUST != 0 {
+            self.data_adjust = self.data_adjust.wrapping_add(stride as u16);
+        } else {
+            self.data_offset = self.data_offset.wrapping_add(stride) & 0xFFFFFF;
+        }
+        self.data_read(cart);
+    }
+
+    /// Add the adjust value to the offset when $4818 bits 5-6 pick this
+    /// trigger (1 = $4814 write, 2 = $4815 write, 3 = $481A read)
+    fn data_apply_adjust(&mut self, cart: &SnesCartridge, trigger: u8) {
+        if self.data_mode >> 5 != trigger {
+            return;
+        }
+        self.data_offset = self.data_offset.wrapping_add(self.adjust()) & 0xFFFFFF;
+        self.data_read(cart);
+    }
+
+    fn multiply(&mut self) {
+        self.alu_result = if self.alu_signed != 0 {
+            (self.alu_multiplier as i16 as i32 * self.alu_dividend as u16 as i16 as i32) as u32
+        } else {
+            self.alu_multiplier as u32 * (self.alu_dividend as u16) as u32
+        };
+        self.alu_status &= !ALU_BUSY;
+    }
+
+    /// Division by zero leaves a quotient of 0 and the dividend as remainder
+    fn divide(&mut self) {
+        let (quotient, remainder) = if self.alu_signed != 0 {
+            let dividend = self.alu_dividend as i32;
+            let divisor = self.alu_divisor as i16 as i32;
+            if divisor == 0 {
+                (0, dividend as u16)
+            } else {
+                (dividend.wrapping_div(divisor) as u32, dividend.wrapping_rem(divisor) as u16)
+            }
+        } else {
+            let dividend = self.alu_dividend;
+            let divisor = self.alu_divisor as u32;
+            match dividend.checked_div(divisor) {
+                Some(quotient) => (quotient, (dividend % divisor) as u16),
+                None => (0, dividend as u16),
+            }
+        };
+        self.alu_result = quotient;
+        self.alu_remainder = remainder;
+        self.alu_status &= !ALU_BUSY;
+    }
+}
+
+/// Data ROM read; $4834 bits 0-1 give its size in 1, 2, 4 or 8MB, and
+/// below 8MB the upper half of the 8MB space reads 0
+fn data_rom_read(cart: &SnesCartridge, size: u8, addr: u32) -> u8 {
+    let data = cart.rom.get(PROGRAM_ROM_SIZE..).unwrap_or_default();
+    if data.is_empty() || (size & 0x03 != 3 && addr & 0x400000 != 0) {
+        return 0;
+    }
+    let mask = (0x100000 << (size & 0x03)) - 1;
+    data[mirror(addr as usize & mask, data.len())]
+}
@@ -0,0 +1,442 @@
+/// SPC Music Files
+/// A `.spc` is a snapshot of the sound module: SPC700 registers, 64KB ARAM,
+/// the 128 DSP registers and the RAM hidden under the IPL ROM, plus ID666
+/// tags (text or binary layout) and an optional xid6 chunk with extended
+/// tags. `SpcPlayer` runs the APU on its own, with no 5A22 attached.
+
+use anyhow::{bail, Result};
+
+use crate::apu::{Apu, ARAM_SIZE};
+use crate::bus::{CYCLES_PER_LINE, NTSC_LINES};
+use crate::dsp::SAMPLE_RATE;
+
+const SIGNATURE: &[u8] = b"SNES-SPC700 Sound File Data";
+const HEADER_SIZE: usize = 0x100;
+const DSP_REGS_OFFSET: usize = 0x10100;
+const EXTRA_RAM_OFFSET: usize = 0x101C0;
+const XID6_OFFSET: usize = 0x10200;
+/// Header byte $23: 26 when the ID666 tag is present
+const HAS_ID666: u8 = 26;
+
+/// Default play time for untagged files, as most players use
+const DEFAULT_LENGTH_MS: u32 = 180_000;
+const DEFAULT_FADE_MS: u32 = 10_000;
+
+/// xid6 times are counted in 1/64000 s
+const XID6_TICKS_PER_MS: u32 = 64;
+
+/// Master cycles in one NTSC frame; the player runs in frame-sized steps
+const CYCLES_PER_FRAME: u64 = CYCLES_PER_LINE as u64 * NTSC_LINES as u64;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpcTags {
+    pub title: String,
+    pub game: String,
+    pub artist: String,
+    pub dumper: String,
+    pub comments: String,
+    /// Dump date as written in the file, e.g. "12/31/1999"
+    pub date: String,
+    /// Play time before the fade starts
+    pub length_ms: Option<u32>,
+    pub fade_ms: Option<u32>,
+    /// Voices the ripper suggests muting, bit n = voice n
+    pub muted_voices: u8,
+    /// xid6 extras
+    pub ost_title: String,
+    pub publisher: String,
+    pub track: Option<u8>,
+}
+
+#[derive(Clone)]
+pub struct SpcFile {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub psw: u8,
+    pub sp: u8,
+    pub ram: Vec<u8>,
+    pub dsp_regs: [u8; 128],
+    /// RAM at $FFC0-$FFFF, hidden while the IPL ROM is mapped
+    pub extra_ram: [u8; 64],
+    pub tags: SpcTags,
+}
+
+impl SpcFile {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < XID6_OFFSET || !data.starts_with(SIGNATURE) {
+            bail!("Not an SPC file");
+        }
+
+        let mut dsp_regs = [0; 128];
+        dsp_regs.copy_from_slice(&data[DSP_REGS_OFFSET..DSP_REGS_OFFSET + 128]);
+        let mut extra_ram = [0; 64];
+        extra_ram.copy_from_slice(&data[EXTRA_RAM_OFFSET..EXTRA_RAM_OFFSET + 64]);
+
+        let mut tags = if data[0x23] == HAS_ID666 {
+            parse_id666(data)
+        } else {
+            SpcTags::default()
+        };
+        if let Some(chunk) = data.get(XID6_OFFSET..) {
+            if chunk.starts_with(b"xid6") {
+                parse_xid6(chunk, &mut tags);
+            }
+        }
+
+        Ok(Self {
+            pc: u16::from_le_bytes([data[0x25], data[0x26]]),
+            a: data[0x27],
+            x: data[0x28],
+            y: data[0x29],
+            psw: data[0x2A],
+            sp: data[0x2B],
+            ram: data[HEADER_SIZE..HEADER_SIZE + ARAM_SIZE].to_vec(),
+            dsp_regs,
+            extra_ram,
+            tags,
+        })
+    }
+
+    /// Put the APU in the state the snapshot was taken in
+    pub fn restore(&self, apu: &mut Apu) {
+        apu.reset();
+        apu.bus.load_snapshot(&self.ram, &self.dsp_regs, &self.extra_ram);
+        let spc = &mut apu.spc;
+        spc.pc = self.pc;
+        spc.a = self.a;
+        spc.x = self.x;
+        spc.y = self.y;
+        spc.psw = crate::spc700::SpcFlags::from_bits_truncate(self.psw);
+        spc.sp = self.sp;
+    }
+}
+
+/// Text fields end at the first NUL
+fn text(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+fn ascii_number(bytes: &[u8]) -> Option<u32> {
+    let digits = text(bytes);
+    if digits.is_empty() { None } else { digits.parse().ok() }
+}
+
+/// The two ID666 layouts share a header; the text one only has digits,
+/// slashes and NULs in the date, length and fade fields
+fn is_text_id666(data: &[u8]) -> bool {
+    let fields = &data[0x9E..0xB1];
+    fields.iter().all(|&b| b == 0 || b == b'/' || b.is_ascii_digit())
+}
+
+/// Binary dates are stored as the decimal number YYYYMMDD
+fn binary_date(date: u32) -> String {
+    if date == 0 {
+        return String::new();
+    }
+    format!("{:02}/{:02}/{:04}", (date / 100) % 100, date % 100, date / 10000)
+}
+
+fn parse_id666(data: &[u8]) -> SpcTags {
+    let mut tags = SpcTags {
+        title: text(&data[0x2E..0x4E]),
+        game: text(&data[0x4E..0x6E]),
+        dumper: text(&data[0x6E..0x7E]),
+        comments: text(&data[0x7E..0x9E]),
+        ..Default::default()
+    };
+
+    if is_text_id666(data) {
+        tags.date = text(&data[0x9E..0xA9]);
+        tags.length_ms = ascii_number(&data[0xA9..0xAC]).map(|secs| secs.saturating_mul(1000));
+        tags.fade_ms = ascii_number(&data[0xAC..0xB1]);
+        tags.artist = text(&data[0xB1..0xD1]);
+        tags.muted_voices = data[0xD1];
+    } else {
+        tags.date = binary_date(u32::from_le_bytes([data[0x9E], data[0x9F], data[0xA0], data[0xA1]]));
+        let secs = u32::from_le_bytes([data[0xA9], data[0xAA], data[0xAB], 0]);
+        let fade = u32::from_le_bytes([data[0xAC], data[0xAD], data[0xAE], data[0xAF]]);
+        tags.length_ms = (secs != 0).then_some(secs.saturating_mul(1000));
+        tags.fade_ms = (fade != 0).then_some(fade);
+        tags.artist = text(&data[0xB0..0xD0]);
+        tags.muted_voices = data[0xD0];
+    }
+    tags
+}
+
+/// Sub-chunks: ID, type, 16-bit length. Type 0 keeps its value in the
+/// length field; strings and integers follow, padded to 4 bytes.
+fn parse_xid6(chunk: &[u8], tags: &mut SpcTags) {
+    if chunk.len() < 8 {
+        return;
+    }
+    // Trust the chunk size only as far as the file goes
+    let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+    let end = size.saturating_add(8).min(chunk.len());
+    let mut intro = None;
+    let mut loop_length = 0u32;
+    let mut loop_count = 1u32;
+    let mut outro = 0i32;
+
+    let mut pos = 8;
+    while pos + 4 <= end {
+        let id = chunk[pos];
+        let kind = chunk[pos + 1];
+        let length = u16::from_le_bytes([chunk[pos + 2], chunk[pos + 3]]);
+        pos += 4;
+
+        let (small, data) = if kind == 0 {
+            (length, &[][..])
+        } else {
+            let len = length as usize;
+            if pos + len > end {
+                break;
+            }
+            let data = &chunk[pos..pos + len];
+            pos += (len + 3) & !3;
+            (0, data)
+        };
+        let integer = || {
+            let mut bytes = [0; 4];
+            let n = data.len().min(4);
+            bytes[..n].copy_from_slice(&data[..n]);
+            u32::from_le_bytes(bytes)
+        };
+
+        match id {
+            0x01 => tags.title = text(data),
+            0x02 => tags.game = text(data),
+            0x03 => tags.artist = text(data),
+            0x04 => tags.dumper = text(data),
+            0x05 => tags.date = binary_date(integer()),
+            0x07 => tags.comments = text(data),
+            0x10 => tags.ost_title = text(data),
+            0x13 => tags.publisher = text(data),
+            // Track number in the high byte, optional letter in the low
+            0x12 => tags.track = Some((small >> 8) as u8),
+            0x30 => intro = Some(integer()),
+            0x31 => loop_length = integer(),
+            0x32 => outro = integer() as i32,
+            0x33 => tags.fade_ms = Some(integer() / XID6_TICKS_PER_MS),
+            0x34 => tags.muted_voices = small as u8,
+            0x35 => loop_count = small as u32,
+            _ => {}
+        }
+    }
+
+    // Intro + loop x count + end overrides the ID666 length
+    if let Some(intro) = intro {
+        let ticks = (intro as i64 + loop_length as i64 * loop_count as i64 + outro as i64).max(0);
+        tags.length_ms = Some((ticks / XID6_TICKS_PER_MS as i64).min(u32::MAX as i64) as u32);
+    }
+}
+
+/// Plays an SPC file on a bare APU, one video frame of audio at a time
+pub struct SpcPlayer {
+    file: SpcFile,
+    apu: Apu,
+    /// Stereo samples since the last restart
+    position: u64,
+    /// Ignore the tagged length and play on forever
+    pub looping: bool,
+    /// Interleaved stereo output of the last `run_frame`, faded
+    output: Vec<i16>,
+}
+
+impl SpcPlayer {
+    pub fn load(data: &[u8]) -> Result<Self> {
+        let file = SpcFile::parse(data)?;
+        let mut apu = Apu::new();
+        file.restore(&mut apu);
+        apu.bus.dsp.set_voice_mute(file.tags.muted_voices);
+        Ok(Self {
+            file,
+            apu,
+            position: 0,
+            looping: false,
+            output: Vec::new(),
+        })
+    }
+
+    pub fn tags(&self) -> &SpcTags {
+        &self.file.tags
+    }
+
+    /// Play time before the fade, falling back to 3 minutes
+    pub fn length_ms(&self) -> u32 {
+        self.file.tags.length_ms.filter(|&ms| ms > 0).unwrap_or(DEFAULT_LENGTH_MS)
+    }
+
+    pub fn fade_ms(&self) -> u32 {
+        self.file.tags.fade_ms.unwrap_or(DEFAULT_FADE_MS)
+    }
+
+    pub fn position_ms(&self) -> u32 {
+        (self.position * 1000 / SAMPLE_RATE as u64) as u32
+    }
+
+    /// True once the fade has ended, unless looping
+    pub fn finished(&self) -> bool {
+        !self.looping && self.position_ms() >= self.length_ms().saturating_add(self.fade_ms())
+    }
+
+    pub fn restart(&mut self) {
+        let muted = self.voice_mute();
+        self.file.restore(&mut self.apu);
+        self.apu.bus.dsp.set_voice_mute(muted);
+        self.position = 0;
+        self.output.clear();
+    }
+
+    pub fn voice_mute(&self) -> u8 {
+        self.apu.bus.dsp.voice_mute()
+    }
+
+    pub fn set_voice_mute(&mut self, mask: u8) {
+        self.apu.bus.dsp.set_voice_mute(mask);
+    }
+
+    /// Run one NTSC frame of APU time (about 533 samples)
+    pub fn run_frame(&mut self) {
+        self.output.clear();
+        if self.finished() {
+            return;
+        }
+        self.apu.bus.dsp.clear_samples();
+        self.apu.run(CYCLES_PER_FRAME, false);
+
+        let fade_start = self.length_ms() as u64 * SAMPLE_RATE as u64 / 1000;
+        let fade_length = (self.fade_ms() as u64 * SAMPLE_RATE as u64 / 1000).max(1);
+        for frame in self.apu.bus.dsp.samples().chunks_exact(2) {
+            let gain = if self.looping || self.position < fade_start {
+                1.0
+            } else {
+                1.0 - ((self.position - fade_start) as f32 / fade_length as f32).min(1.0)
+            };
+            self.output.extend(frame.iter().map(|&s| (s as f32 * gain) as i16));
+            self.position += 1;
+        }
+    }
+
+    /// Interleaved stereo at `dsp::SAMPLE_RATE` from the last frame
+    pub fn samples(&self) -> &[i16] {
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Smallest valid file: header, ARAM, DSP registers and IPL RAM
+    fn spc_image() -> Vec<u8> {
+        let mut data = vec![0; XID6_OFFSET];
+        data[..SIGNATURE.len()].copy_from_slice(SIGNATURE);
+        data[0x23] = HAS_ID666;
+        data[0x2E..0x33].copy_from_slice(b"Title");
+        data
+    }
+
+    fn xid6(data: &mut Vec<u8>, size: u32, body: &[u8]) {
+        data.extend_from_slice(b"xid6");
+        data.extend_from_slice(&size.to_le_bytes());
+        data.extend_from_slice(body);
+    }
+
+    #[test]
+    fn text_id666() {
+        let mut data = spc_image();
+        data[0x9E..0xA8].copy_from_slice(b"12/31/1999");
+        data[0xA9..0xAC].copy_from_slice(b"999");
+        data[0xAC..0xB0].copy_from_slice(b"5000");
+        data[0xB1..0xB7].copy_from_slice(b"Artist");
+
+        let tags = SpcFile::parse(&data).unwrap().tags;
+        assert_eq!(tags.title, "Title");
+        assert_eq!(tags.date, "12/31/1999");
+        assert_eq!(tags.length_ms, Some(999_000));
+        assert_eq!(tags.fade_ms, Some(5000));
+        assert_eq!(tags.artist, "Artist");
+    }
+
+    #[test]
+    fn binary_id666_oversized_times() {
+        let mut data = spc_image();
+        data[0x9E..0xA2].copy_from_slice(&19991231u32.to_le_bytes());
+        data[0xA9..0xAC].copy_from_slice(&[0xFF; 3]);
+        data[0xAC..0xB0].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let tags = SpcFile::parse(&data).unwrap().tags;
+        assert_eq!(tags.date, "12/31/1999");
+        assert_eq!(tags.length_ms, Some(u32::MAX));
+        assert_eq!(tags.fade_ms, Some(u32::MAX));
+
+        let player = SpcPlayer::load(&data).unwrap();
+        assert!(!player.finished());
+    }
+
+    #[test]
+    fn missing_or_wrong_signature() {
+        assert!(SpcFile::parse(&[]).is_err());
+        let mut data = spc_image();
+        data[0] = b'X';
+        assert!(SpcFile::parse(&data).is_err());
+        assert!(SpcFile::parse(&spc_image()[..XID6_OFFSET - 1]).is_err());
+    }
+
+    #[test]
+    fn truncated_xid6_header() {
+        for extra in 4..8 {
+            let mut data = spc_image();
+            data.extend_from_slice(&b"xid6\xFF\xFF\xFF\xFF"[..extra]);
+            let tags = SpcFile::parse(&data).unwrap().tags;
+            assert_eq!(tags.title, "Title");
+        }
+    }
+
+    #[test]
+    fn xid6_size_past_end_of_file() {
+        let mut data = spc_image();
+        // Title sub-chunk, then a string whose length runs off the end
+        xid6(&mut data, u32::MAX, b"\x01\x01\x04\x00Song\x07\x01\xFF\x00abc");
+        let tags = SpcFile::parse(&data).unwrap().tags;
+        assert_eq!(tags.title, "Song");
+        assert_eq!(tags.comments, "");
+    }
+
+    #[test]
+    fn xid6_times() {
+        let mut data = spc_image();
+        let mut body = Vec::new();
+        // Intro 1 s, loop 2 s played twice, fade 3 s
+        for (id, value) in [(0x30u8, 64_000u32), (0x31, 128_000), (0x33, 192_000)] {
+            body.extend_from_slice(&[id, 0x04, 0x04, 0x00]);
+            body.extend_from_slice(&value.to_le_bytes());
+        }
+        body.extend_from_slice(&[0x35, 0x00, 0x02, 0x00]);
+        xid6(&mut data, body.len() as u32, &body);
+
+        let tags = SpcFile::parse(&data).unwrap().tags;
+        assert_eq!(tags.length_ms, Some(5000));
+        assert_eq!(tags.fade_ms, Some(3000));
+    }
+
+    #[test]
+    fn xid6_oversized_times() {
+        let mut data = spc_image();
+        let mut body = Vec::new();
+        for id in [0x30u8, 0x31, 0x33] {
+            body.extend_from_slice(&[id, 0x04, 0x04, 0x00]);
+            body.extend_from_slice(&u32::MAX.to_le_bytes());
+        }
+        body.extend_from_slice(&[0x35, 0x00, 0xFF, 0xFF]);
+        xid6(&mut data, body.len() as u32, &body);
+
+        let tags = SpcFile::parse(&data).unwrap().tags;
+        assert_eq!(tags.length_ms, Some(u32::MAX));
+        let player = SpcPlayer::load(&data).unwrap();
+        assert!(!player.finished());
+    }
+}
@@ -0,0 +1,1040 @@
+/// Super FX (GSU-1/GSU-2)
+/// RISC coprocessor that renders into game pak RAM in SNES tile format.
+/// Code runs through a 512-byte cache, ROM is read through a one-byte
+/// buffer refilled whenever R14 changes and RAM is written through a
+/// one-byte buffer, so memory accesses overlap with execution. PLOT
+/// collects pixels in two 8-pixel caches that are flushed as bitplanes.
+/// The 5A22 sees the registers at $3000-$32FF and loses the ROM/RAM buses
+/// while SCMR.RON/RAN hand them to the GSU.
+
+use crate::cartridge::{mirror, SnesCartridge};
+
+// SFR bits
+const SFR_Z: u16 = 0x0002;
+const SFR_CY: u16 = 0x0004;
+const SFR_S: u16 = 0x0008;
+const SFR_OV: u16 = 0x0010;
+const SFR_G: u16 = 0x0020;
+const SFR_R: u16 = 0x0040;
+const SFR_ALT1: u16 = 0x0100;
+const SFR_ALT2: u16 = 0x0200;
+const SFR_B: u16 = 0x1000;
+const SFR_IRQ: u16 = 0x8000;
+
+// SCMR bits
+const SCMR_RAN: u8 = 0x08;
+const SCMR_RON: u8 = 0x10;
+
+// POR bits
+const POR_TRANSPARENT: u8 = 0x01;
+const POR_DITHER: u8 = 0x02;
+const POR_HIGH_NIBBLE: u8 = 0x04;
+const POR_FREEZE_HIGH: u8 = 0x08;
+const POR_OBJ: u8 = 0x10;
+
+// CFGR bits
+const CFGR_MS0: u8 = 0x20;
+const CFGR_IRQ_MASK: u8 = 0x80;
+
+/// Version code in VCR
+const GSU2_VERSION: u8 = 0x04;
+
+const NOP: u8 = 0x01;
+
+/// What the 5A22 reads from ROM while the GSU owns it: vectors into WRAM,
+/// so a CPU waiting for the GSU can still take interrupts
+pub const ROM_VECTORS: [u8; 16] = [
+    0x00, 0x01, 0x00, 0x01, 0x04, 0x01, 0x00, 0x01,
+    0x00, 0x01, 0x08, 0x01, 0x00, 0x01, 0x0C, 0x01,
+];
+
+/// Eight pixels of one tile row waiting to be written as bitplanes
+#[derive(Clone, Copy)]
+struct PixelCache {
+    offset: u16,
+    /// Pixels set so far, bit 7 = leftmost
+    bitpend: u8,
+    data: [u8; 8],
+}
+
+impl PixelCache {
+    const EMPTY: Self = Self { offset: 0xFFFF, bitpend: 0, data: [0; 8] };
+}
+
+pub struct SuperFx {
+    r: [u16; 16],
+    sfr: u16,
+    /// Program bank
+    pbr: u8,
+    /// ROM buffer bank (ROMB)
+    rombr: u8,
+    /// RAM bank (RAMB)
+    rambr: u8,
+    /// Cache base, 16-byte aligned
+    cbr: u16,
+    /// Screen base in 1KB units
+    scbr: u8,
+    scmr: u8,
+    colr: u8,
+    por: u8,
+    bramr: u8,
+    vcr: u8,
+    cfgr: u8,
+    /// Clock select: 0 = 10.74 MHz, 1 = 21.47 MHz
+    clsr: u8,
+
+    /// FROM/TO/WITH register selection
+    sreg: usize,
+    dreg: usize,
+    /// Opcode fetched ahead of R15
+    pipeline: u8,
+    r14_modified: bool,
+    r15_modified: bool,
+    /// Last RAM address used by a load or store, for SBK
+    ram_addr: u16,
+
+    rom_delay: u32,
+    rom_data: u8,
+    ram_delay: u32,
+    ram_buffer_addr: u16,
+    ram_buffer_data: u8,
+
+    cache: [u8; 512],
+    cache_valid: [bool; 32],
+    pixel_cache: [PixelCache; 2],
+
+    /// GSU time owed to the master clock, in master cycles x overclock
+    budget: i64,
+    /// Clock multiplier over the stock 10.74/21.47 MHz
+    pub overclock: u32,
+}
+
+impl SuperFx {
+    pub fn new() -> Self {
+        let mut gsu = Self {
+            r: [0; 16],
+            sfr: 0,
+            pbr: 0,
+            rombr: 0,
+            rambr: 0,
+            cbr: 0,
+            scbr: 0,
+            scmr: 0,
+            colr: 0,
+            por: 0,
+            bramr: 0,
+            vcr: GSU2_VERSION,
+            cfgr: 0,
+            clsr: 0,
+            sreg: 0,
+            dreg: 0,
+            pipeline: NOP,
+            r14_modified: false,
+            r15_modified: false,
+            ram_addr: 0,
+            rom_delay: 0,
+            rom_data: 0,
+            ram_delay: 0,
+            ram_buffer_addr: 0,
+            ram_buffer_data: 0,
+            cache: [0; 512],
+            cache_valid: [false; 32],
+            pixel_cache: [PixelCache::EMPTY; 2],
+            budget: 0,
+            overclock: 1,
+        };
+        gsu.reset();
+        gsu
+    }
+
+    pub fn reset(&mut self) {
+        self.r = [0; 16];
+        self.sfr = 0;
+        self.pbr = 0;
+        self.rombr = 0;
+        self.rambr = 0;
+        self.cbr = 0;
+        self.scbr = 0;
+        self.scmr = 0;
+        self.colr = 0;
+        self.por = 0;
+        self.bramr = 0;
+        self.cfgr = 0;
+        self.clsr = 0;
+        self.sreg = 0;
+        self.dreg = 0;
+        self.pipeline = NOP;
+        self.r14_modified = false;
+        self.r15_modified = false;
+        self.ram_addr = 0;
+        self.rom_delay = 0;
+        self.rom_data = 0;
+        self.ram_delay = 0;
+        self.ram_buffer_addr = 0;
+        self.ram_buffer_data = 0;
+        self.cache_valid = [false; 32];
+        self.pixel_cache = [PixelCache::EMPTY; 2];
+        self.budget = 0;
+    }
+
+    pub fn running(&self) -> bool {
+        self.sfr & SFR_G != 0
+    }
+
+    /// IRQ line to the 5A22, raised by STOP unless masked in CFGR
+    pub fn irq(&self) -> bool {
+        self.sfr & SFR_IRQ != 0
+    }
+
+    /// The 5A22 sees `ROM_VECTORS` instead of ROM
+    pub fn owns_rom(&self) -> bool {
+        self.running() && self.scmr & SCMR_RON != 0
+    }
+
+    /// The 5A22 sees open bus instead of game pak RAM
+    pub fn owns_ram(&self) -> bool {
+        self.running() && self.scmr & SCMR_RAN != 0
+    }
+
+    /// Catch up with `master_cycles` of 5A22 time
+    pub fn run(&mut self, master_cycles: u64, cart: &mut SnesCartridge) {
+        if !self.running() {
+            // Finish buffered accesses the STOP left behind
+            self.sync_rom_buffer(cart);
+            self.sync_ram_buffer(cart);
+            self.budget = 0;
+            return;
+        }
+        self.budget += master_cycles as i64 * self.overclock as i64;
+        while self.budget > 0 && self.running() {
+            if self.waiting_for_bus() {
+                self.budget = 0;
+                break;
+            }
+            self.execute(cart);
+        }
+    }
+
+    /// The GSU stalls while the 5A22 holds a bus the next instruction needs
+    fn waiting_for_bus(&self) -> bool {
+        let offset = self.r[15].wrapping_sub(self.cbr);
+        let fetch_from_bus = offset >= 512 || !self.cache_valid[offset as usize >> 4];
+        let alt = self.sfr & (SFR_ALT1 | SFR_ALT2) != 0;
+        let needs_rom = (fetch_from_bus && self.pbr <= 0x5F)
+            || self.rom_delay > 0
+            || self.pipeline == 0xEF
+            || (self.pipeline == 0xDF && self.sfr & SFR_ALT2 == 0);
+        let needs_ram = (fetch_from_bus && self.pbr >= 0x60)
+            || self.ram_delay > 0
+            || matches!(self.pipeline, 0x30..=0x3B | 0x40..=0x4C | 0x90)
+            || (matches!(self.pipeline, 0xA0..=0xAF | 0xF0..=0xFF) && alt);
+        (needs_rom && self.scmr & SCMR_RON == 0) || (needs_ram && self.scmr & SCMR_RAN == 0)
+    }
+
+    // --- 5A22 side ---
+
+    pub fn read_io(&mut self, addr: u16) -> u8 {
+        let addr = 0x3000 | (addr & 0x3FF);
+        match addr {
+            0x3000..=0x301F => (self.r[(addr as usize >> 1) & 0x0F] >> ((addr & 1) * 8)) as u8,
+            0x3030 => self.sfr as u8,
+            0x3031 => {
+                let value = (self.sfr >> 8) as u8;
+                self.sfr &= !SFR_IRQ;
+                value
+            }
+            0x3034 => self.pbr,
+            0x3036 => self.rombr,
+            0x303B => self.vcr,
+            0x303C => self.rambr,
+            0x303E => self.cbr as u8,
+            0x303F => (self.cbr >> 8) as u8,
+            0x3100..=0x32FF => self.cache[(addr as usize - 0x3100 + self.cbr as usize) & 0x1FF],
+            _ => 0,
+        }
+    }
+
+    pub fn write_io(&mut self, addr: u16, value: u8) {
+        let addr = 0x3000 | (addr & 0x3FF);
+        match addr {
+            0x3000..=0x301F => {
+                let n = (addr as usize >> 1) & 0x0F;
+                self.r[n] = if addr & 1 == 0 {
+                    (self.r[n] & 0xFF00) | value as u16
+                } else {
+                    ((value as u16) << 8) | (self.r[n] & 0x00FF)
+                };
+                if n == 14 {
+                    self.update_rom_buffer();
+                }
+                // Writing R15 starts the GSU
+                if addr == 0x301F {
+                    self.sfr |= SFR_G;
+                }
+            }
+            0x3030 => {
+                let was_running = self.running();
+                self.sfr = (self.sfr & 0xFF00) | value as u16;
+                if was_running && !self.running() {
+                    self.cbr = 0;
+                    self.flush_cache();
+                }
+            }
+            0x3031 => self.sfr = ((value as u16) << 8) | (self.sfr & 0x00FF),
+            0x3033 => self.bramr = value & 0x01,
+            0x3034 => {
+                self.pbr = value & 0x7F;
+                self.flush_cache();
+            }
+            0x3037 => self.cfgr = value,
+            0x3038 => self.scbr = value,
+            0x3039 => self.clsr = value & 0x01,
+            0x303A => self.scmr = value,
+            0x3100..=0x32FF => self.write_cache(addr - 0x3100, value),
+            _ => {}
+        }
+    }
+
+    // --- GSU bus ---
+
+    /// ROM at $00-$3F (LoROM) and $40-$5F (linear), RAM at $60-$7F
+    fn read(&self, cart: &SnesCartridge, addr: u32) -> u8 {
+        let bank = (addr >> 16) as usize & 0x7F;
+        let offset = addr as usize & 0xFFFF;
+        match bank {
+            0x00..=0x3F => cart.rom[mirror(bank * 0x8000 + (offset & 0x7FFF), cart.rom.len())],
+            0x40..=0x5F => cart.rom[mirror((bank - 0x40) * 0x10000 + offset, cart.rom.len())],
+            _ if cart.sram.is_empty() => 0,
+            _ => cart.sram[(((bank & 1) << 16) | offset) % cart.sram.len()],
+        }
+    }
+
+    fn write(&self, cart: &mut SnesCartridge, addr: u32, value: u8) {
+        let bank = (addr >> 16) as usize & 0x7F;
+        let offset = addr as usize & 0xFFFF;
+        if bank >= 0x60 && !cart.sram.is_empty() {
+            let len = cart.sram.len();
+            cart.sram[(((bank & 1) << 16) | offset) % len] = value;
+        }
+    }
+
+    /// Master cycles for a ROM/RAM access
+    fn memory_cycles(&self) -> u32 {
+        if self.clsr != 0 { 5 } else { 6 }
+    }
+
+    /// Master cycles for a cache hit or an internal cycle
+    fn cycle(&self) -> u32 {
+        if self.clsr != 0 { 1 } else { 2 }
+    }
+
+    /// Advance the clock, completing buffered accesses that come due
+    fn step(&mut self, cart: &mut SnesCartridge, clocks: u32) {
+        if self.rom_delay > 0 {
+            self.rom_delay -= clocks.min(self.rom_delay);
+            if self.rom_delay == 0 {
+                self.sfr &= !SFR_R;
+                self.rom_data = self.read(cart, ((self.rombr as u32) << 16) | self.r[14] as u32);
+            }
+        }
+        if self.ram_delay > 0 {
+            self.ram_delay -= clocks.min(self.ram_delay);
+            if self.ram_delay == 0 {
+                let addr = 0x700000 + ((self.rambr as u32) << 16) + self.ram_buffer_addr as u32;
+                self.write(cart, addr, self.ram_buffer_data);
+            }
+        }
+        self.budget -= clocks as i64;
+    }
+
+    fn update_rom_buffer(&mut self) {
+        self.sfr |= SFR_R;
+        self.rom_delay = self.memory_cycles();
+    }
+
+    fn sync_rom_buffer(&mut self, cart: &mut SnesCartridge) {
+        if self.rom_delay > 0 {
+            self.step(cart, self.rom_delay);
+        }
+    }
+
+    fn read_rom_buffer(&mut self, cart: &mut SnesCartridge) -> u8 {
+        self.sync_rom_buffer(cart);
+        self.rom_data
+    }
+
+    fn sync_ram_buffer(&mut self, cart: &mut SnesCartridge) {
+        if self.ram_delay > 0 {
+            self.step(cart, self.ram_delay);
+        }
+    }
+
+    fn read_ram_buffer(&mut self, cart: &mut SnesCartridge, addr: u16) -> u8 {
+        self.sync_ram_buffer(cart);
+        self.read(cart, 0x700000 + ((self.rambr as u32) << 16) + addr as u32)
+    }
+
+    fn write_ram_buffer(&mut self, cart: &mut SnesCartridge, addr: u16, value: u8) {
+        self.sync_ram_buffer(cart);
+        self.ram_delay = self.memory_cycles();
+        self.ram_buffer_addr = addr;
+        self.ram_buffer_data = value;
+    }
+
+    fn read_ram_word(&mut self, cart: &mut SnesCartridge, addr: u16) -> u16 {
+        let lo = self.read_ram_buffer(cart, addr);
+        let hi = self.read_ram_buffer(cart, addr ^ 1);
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn write_ram_word(&mut self, cart: &mut SnesCartridge, addr: u16, value: u16) {
+        self.write_ram_buffer(cart, addr, value as u8);
+        self.write_ram_buffer(cart, addr ^ 1, (value >> 8) as u8);
+    }
+
+    // --- Code cache ---
+
+    fn flush_cache(&mut self) {
+        self.cache_valid = [false; 32];
+    }
+
+    fn write_cache(&mut self, addr: u16, value: u8) {
+        let addr = (addr as usize + self.cbr as usize) & 0x1FF;
+        self.cache[addr] = value;
+        if addr & 0x0F == 0x0F {
+            self.cache_valid[addr >> 4] = true;
+        }
+    }
+
+    /// Code inside the cache window is fetched a 16-byte line at a time
+    fn read_opcode(&mut self, cart: &mut SnesCartridge, addr: u16) -> u8 {
+        let offset = addr.wrapping_sub(self.cbr) as usize;
+        if offset < 512 {
+            let line = offset >> 4;
+            if !self.cache_valid[line] {
+                let base = self.cbr.wrapping_add(offset as u16 & 0xFFF0);
+                for n in 0..16 {
+                    self.step(cart, self.memory_cycles());
+                    let source = ((self.pbr as u32) << 16) | base.wrapping_add(n) as u32;
+                    self.cache[(offset & 0x1F0) + n as usize] = self.read(cart, source);
+                }
+                self.cache_valid[line] = true;
+            } else {
+                self.step(cart, self.cycle());
+            }
+            return self.cache[offset];
+        }
+
+        if self.pbr <= 0x5F {
+            self.sync_rom_buffer(cart);
+        } else {
+            self.sync_ram_buffer(cart);
+        }
+        self.step(cart, self.memory_cycles());
+        self.read(cart, ((self.pbr as u32) << 16) | addr as u32)
+    }
+
+    /// Current opcode; fetches the byte at R15 into the pipeline
+    fn peek_pipe(&mut self, cart: &mut SnesCartridge) -> u8 {
+        let opcode = self.pipeline;
+        self.pipeline = self.read_opcode(cart, self.r[15]);
+        self.r15_modified = false;
+        opcode
+    }
+
+    /// Operand byte; advances R15 and refills the pipeline
+    fn pipe(&mut self, cart: &mut SnesCartridge) -> u8 {
+        let value = self.pipeline;
+        self.r[15] = self.r[15].wrapping_add(1);
+        self.pipeline = self.read_opcode(cart, self.r[15]);
+        self.r15_modified = false;
+        value
+    }
+
+    // --- Registers ---
+
+    fn sr(&self) -> u16 {
+        self.r[self.sreg]
+    }
+
+    fn set_reg(&mut self, n: usize, value: u16) {
+        self.r[n] = value;
+        match n {
+            14 => self.r14_modified = true,
+            15 => self.r15_modified = true,
+            _ => {}
+        }
+    }
+
+    fn set_dr(&mut self, value: u16) {
+        self.set_reg(self.dreg, value);
+    }
+
+    fn set_flag(&mut self, flag: u16, on: bool) {
+        if on {
+            self.sfr |= flag;
+        } else {
+            self.sfr &= !flag;
+        }
+    }
+
+    fn flag(&self, flag: u16) -> bool {
+        self.sfr & flag != 0
+    }
+
+    fn set_sz(&mut self, value: u16) {
+        self.set_flag(SFR_S, value & 0x8000 != 0);
+        self.set_flag(SFR_Z, value == 0);
+    }
+
+    /// Result-writing instructions end the ALT/B prefixes and selection
+    fn end_prefix(&mut self) {
+        self.sfr &= !(SFR_B | SFR_ALT1 | SFR_ALT2);
+        self.sreg = 0;
+        self.dreg = 0;
+    }
+
+    // --- Instructions ---
+
+    fn execute(&mut self, cart: &mut SnesCartridge) {
+        let opcode = self.peek_pipe(cart);
+        self.instruction(cart, opcode);
+        if self.r14_modified {
+            self.r14_modified = false;
+            self.update_rom_buffer();
+        }
+        if self.r15_modified {
+            self.r15_modified = false;
+        } else {
+            self.r[15] = self.r[15].wrapping_add(1);
+        }
+    }
+
+    fn instruction(&mut self, cart: &mut SnesCartridge, opcode: u8) {
+        let n = (opcode & 0x0F) as usize;
+        let alt1 = self.flag(SFR_ALT1);
+        let alt2 = self.flag(SFR_ALT2);
+        match opcode {
+            0x00 => self.stop(),
+            0x01 => self.end_prefix(),
+            0x02 => self.cache(),
+            0x03 => self.lsr(),
+            0x04 => self.rol(),
+            0x05 => self.branch(cart, true),
+            0x06 => self.branch(cart, self.flag(SFR_S) == self.flag(SFR_OV)),
+            0x07 => self.branch(cart, self.flag(SFR_S) != self.flag(SFR_OV)),
+            0x08 => self.branch(cart, !self.flag(SFR_Z)),
+            0x09 => self.branch(cart, self.flag(SFR_Z)),
+            0x0A => self.branch(cart, !self.flag(SFR_S)),
+            0x0B => self.branch(cart, self.flag(SFR_S)),
+            0x0C => self.branch(cart, !self.flag(SFR_CY)),
+            0x0D => self.branch(cart, self.flag(SFR_CY)),
+            0x0E => self.branch(cart, !self.flag(SFR_OV)),
+            0x0F => self.branch(cart, self.flag(SFR_OV)),
+            0x10..=0x1F => {
+                if self.flag(SFR_B) {
+                    // MOVE
+                    self.set_reg(n, self.sr());
+                    self.end_prefix();
+                } else {
+                    // TO
+                    self.dreg = n;
+                }
+            }
+            0x20..=0x2F => {
+                // WITH
+                self.sreg = n;
+                self.dreg = n;
+                self.sfr |= SFR_B;
+            }
+            0x30..=0x3B => {
+                // STW/STB
+                self.ram_addr = self.r[n];
+                let value = self.sr();
+                if alt1 {
+                    self.write_ram_buffer(cart, self.ram_addr, value as u8);
+                } else {
+                    self.write_ram_word(cart, self.ram_addr, value);
+                }
+                self.end_prefix();
+            }
+            0x3C => self.loop_(),
+            0x3D => self.sfr = (self.sfr & !SFR_B) | SFR_ALT1,
+            0x3E => self.sfr = (self.sfr & !SFR_B) | SFR_ALT2,
+            0x3F => self.sfr = (self.sfr & !SFR_B) | SFR_ALT1 | SFR_ALT2,
+            0x40..=0x4B => {
+                // LDW/LDB
+                self.ram_addr = self.r[n];
+                let value = if alt1 {
+                    self.read_ram_buffer(cart, self.ram_addr) as u16
+                } else {
+                    self.read_ram_word(cart, self.ram_addr)
+                };
+                self.set_dr(value);
+                self.end_prefix();
+            }
+            0x4C => {
+                if alt1 {
+                    let value = self.rpix(cart, self.r[1] as u8, self.r[2] as u8) as u16;
+                    self.set_dr(value);
+                    self.set_sz(value);
+                } else {
+                    self.plot(cart, self.r[1] as u8, self.r[2] as u8);
+                    self.r[1] = self.r[1].wrapping_add(1);
+                }
+                self.end_prefix();
+            }
+            0x4D => {
+                // SWAP
+                let value = self.sr().rotate_left(8);
+                self.set_dr(value);
+                self.set_sz(value);
+                self.end_prefix();
+            }
+            0x4E => {
+                if alt1 {
+                    // CMODE
+                    self.por = self.sr() as u8;
+                } else {
+                    // COLOR
+                    self.colr = self.color(self.sr() as u8);
+                }
+                self.end_prefix();
+            }
+            0x4F => {
+                // NOT
+                let value = !self.sr();
+                self.set_dr(value);
+                self.set_sz(value);
+                self.end_prefix();
+            }
+            0x50..=0x5F => self.add(n, alt1, alt2),
+            0x60..=0x6F => self.sub(n, alt1, alt2),
+            0x70 => self.merge(),
+            0x71..=0x7F => {
+                // AND/BIC
+                let operand = if alt2 { n as u16 } else { self.r[n] };
+                let value = self.sr() & if alt1 { !operand } else { operand };
+                self.set_dr(value);
+                self.set_sz(value);
+                self.end_prefix();
+            }
+            0x80..=0x8F => {
+                // MULT/UMULT
+                let operand = if alt2 { n as u16 } else { self.r[n] };
+                let value = if alt1 {
+                    (self.sr() as u8 as u16) * (operand as u8 as u16)
+                } else {
+                    ((self.sr() as i8 as i16) * (operand as i8 as i16)) as u16
+                };
+                self.set_dr(value);
+                self.set_sz(value);
+                self.end_prefix();
+                if self.cfgr & CFGR_MS0 == 0 {
+                    self.step(cart, self.cycle());
+                }
+            }
+            0x90 => {
+                // SBK
+                self.write_ram_word(cart, self.ram_addr, self.sr());
+                self.end_prefix();
+            }
+            0x91..=0x94 => {
+                // LINK
+                self.r[11] = self.r[15].wrapping_add(n as u16);
+                self.end_prefix();
+            }
+            0x95 => {
+                // SEX
+                let value = self.sr() as i8 as u16;
+                self.set_dr(value);
+                self.set_sz(value);
+                self.end_prefix();
+            }
+            0x96 => {
+                // ASR/DIV2: DIV2 rounds -1 to 0
+                let source = self.sr();
+                self.set_flag(SFR_CY, source & 1 != 0);
+                let mut value = ((source as i16) >> 1) as u16;
+                if alt1 && source == 0xFFFF {
+                    value = 0;
+                }
+                self.set_dr(value);
+                self.set_sz(value);
+                self.end_prefix();
+            }
+            0x97 => {
+                // ROR
+                let source = self.sr();
+                let value = ((self.flag(SFR_CY) as u16) << 15) | (source >> 1);
+                self.set_dr(value);
+                self.set_sz(value);
+                self.set_flag(SFR_CY, source & 1 != 0);
+                self.end_prefix();
+            }
+            0x98..=0x9D => {
+                if alt1 {
+                    // LJMP
+                    self.pbr = self.r[n] as u8 & 0x7F;
+                    self.set_reg(15, self.sr());
+                    self.cbr = self.r[15] & 0xFFF0;
+                    self.flush_cache();
+                } else {
+                    // JMP
+                    self.set_reg(15, self.r[n]);
+                }
+                self.end_prefix();
+            }
+            0x9E => {
+                // LOB
+                let value = self.sr() & 0x00FF;
+                self.set_dr(value);
+                self.set_flag(SFR_S, value & 0x80 != 0);
+                self.set_flag(SFR_Z, value == 0);
+                self.end_prefix();
+            }
+            0x9F => self.fmult(cart, alt1),
+            0xA0..=0xAF => {
+                if alt1 {
+                    // LMS
+                    self.ram_addr = (self.pipe(cart) as u16) << 1;
+                    let value = self.read_ram_word(cart, self.ram_addr);
+                    self.set_reg(n, value);
+                } else if alt2 {
+                    // SMS
+                    self.ram_addr = (self.pipe(cart) as u16) << 1;
+                    self.write_ram_word(cart, self.ram_addr, self.r[n]);
+                } else {
+                    // IBT
+                    let value = self.pipe(cart) as i8 as u16;
+                    self.set_reg(n, value);
+                }
+                self.end_prefix();
+            }
+            0xB0..=0xBF => {
+                if self.flag(SFR_B) {
+                    // MOVES
+                    let value = self.r[n];
+                    self.set_dr(value);
+                    self.set_flag(SFR_OV, value & 0x80 != 0);
+                    self.set_sz(value);
+                    self.end_prefix();
+                } else {
+                    // FROM
+                    self.sreg = n;
+                }
+            }
+            0xC0 => {
+                // HIB
+                let value = self.sr() >> 8;
+                self.set_dr(value);
+                self.set_flag(SFR_S, value & 0x80 != 0);
+                self.set_flag(SFR_Z, value == 0);
+                self.end_prefix();
+            }
+            0xC1..=0xCF => {
+                // OR/XOR
+                let operand = if alt2 { n as u16 } else { self.r[n] };
+                let value = if alt1 { self.sr() ^ operand } else { self.sr() | operand };
+                self.set_dr(value);
+                self.set_sz(value);
+                self.end_prefix();
+            }
+            0xD0..=0xDE => {
+                // INC
+                let value = self.r[n].wrapping_add(1);
+                self.set_reg(n, value);
+                self.set_sz(value);
+                self.end_prefix();
+            }
+            0xDF => {
+                if !alt2 {
+                    // GETC
+                    let source = self.read_rom_buffer(cart);
+                    self.colr = self.color(source);
+                } else if !alt1 {
+                    // RAMB
+                    self.sync_ram_buffer(cart);
+                    self.rambr = self.sr() as u8 & 0x01;
+                } else {
+                    // ROMB
+                    self.sync_rom_buffer(cart);
+                    self.rombr = self.sr() as u8 & 0x7F;
+                }
+                self.end_prefix();
+            }
+            0xE0..=0xEE => {
+                // DEC
+                let value = self.r[n].wrapping_sub(1);
+                self.set_reg(n, value);
+                self.set_sz(value);
+                self.end_prefix();
+            }
+            0xEF => {
+                // GETB/GETBH/GETBL/GETBS
+                let byte = self.read_rom_buffer(cart) as u16;
+                let value = match (alt2, alt1) {
+                    (false, false) => byte,
+                    (false, true) => (byte << 8) | (self.sr() & 0x00FF),
+                    (true, false) => (self.sr() & 0xFF00) | byte,
+                    (true, true) => byte as u8 as i8 as u16,
+                };
+                self.set_dr(value);
+                self.end_prefix();
+            }
+            0xF0..=0xFF => {
+                let lo = self.pipe(cart);
+                let hi = self.pipe(cart);
+                let word = u16::from_le_bytes([lo, hi]);
+                if alt1 {
+                    // LM
+                    self.ram_addr = word;
+                    let value = self.read_ram_word(cart, word);
+                    self.set_reg(n, value);
+                } else if alt2 {
+                    // SM
+                    self.ram_addr = word;
+                    self.write_ram_word(cart, word, self.r[n]);
+                } else {
+                    // IWT
+                    self.set_reg(n, word);
+                }
+                self.end_prefix();
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        if self.cfgr & CFGR_IRQ_MASK == 0 {
+            self.sfr |= SFR_IRQ;
+        }
+        self.sfr &= !SFR_G;
+        self.pipeline = NOP;
+        self.end_prefix();
+    }
+
+    fn cache(&mut self) {
+        if self.cbr != self.r[15] & 0xFFF0 {
+            self.cbr = self.r[15] & 0xFFF0;
+            self.flush_cache();
+        }
+        self.end_prefix();
+    }
+
+    fn lsr(&mut self) {
+        let source = self.sr();
+        self.set_flag(SFR_CY, source & 1 != 0);
+        let value = source >> 1;
+        self.set_dr(value);
+        self.set_sz(value);
+        self.end_prefix();
+    }
+
+    fn rol(&mut self) {
+        let source = self.sr();
+        let value = (source << 1) | self.flag(SFR_CY) as u16;
+        self.set_dr(value);
+        self.set_sz(value);
+        self.set_flag(SFR_CY, source & 0x8000 != 0);
+        self.end_prefix();
+    }
+
+    /// Branches leave the prefixes alone; the byte after them still runs
+    fn branch(&mut self, cart: &mut SnesCartridge, take: bool) {
+        let displacement = self.pipe(cart) as i8;
+        if take {
+            let target = self.r[15].wrapping_add(displacement as u16);
+            self.set_reg(15, target);
+        }
+    }
+
+    fn loop_(&mut self) {
+        let count = self.r[12].wrapping_sub(1);
+        self.r[12] = count;
+        self.set_sz(count);
+        if count != 0 {
+            self.set_reg(15, self.r[13]);
+        }
+        self.end_prefix();
+    }
+
+    /// ADD/ADC/ADD #/ADC #
+    fn add(&mut self, n: usize, alt1: bool, alt2: bool) {
+        let operand = if alt2 { n as u16 } else { self.r[n] };
+        let source = self.sr();
+        let carry = (alt1 && self.flag(SFR_CY)) as u32;
+        let result = source as u32 + operand as u32 + carry;
+        let value = result as u16;
+        self.set_flag(SFR_OV, !(source ^ operand) & (operand ^ value) & 0x8000 != 0);
+        self.set_flag(SFR_CY, result >= 0x10000);
+        self.set_sz(value);
+        self.set_dr(value);
+        self.end_prefix();
+    }
+
+    /// SUB/SBC/SUB #/CMP
+    fn sub(&mut self, n: usize, alt1: bool, alt2: bool) {
+        let operand = if !alt2 || alt1 { self.r[n] } else { n as u16 };
+        let source = self.sr();
+        let borrow = (!alt2 && alt1 && !self.flag(SFR_CY)) as i32;
+        let result = source as i32 - operand as i32 - borrow;
+        let value = result as u16;
+        self.set_flag(SFR_OV, (source ^ operand) & (source ^ value) & 0x8000 != 0);
+        self.set_flag(SFR_CY, result >= 0);
+        self.set_sz(value);
+        if !(alt1 && alt2) {
+            self.set_dr(value);
+        }
+        self.end_prefix();
+    }
+
+    /// High bytes of R7 and R8; the flags test the upper bits of each
+    fn merge(&mut self) {
+        let value = (self.r[7] & 0xFF00) | (self.r[8] >> 8);
+        self.set_dr(value);
+        self.set_flag(SFR_OV, value & 0xC0C0 != 0);
+        self.set_flag(SFR_S, value & 0x8080 != 0);
+        self.set_flag(SFR_CY, value & 0xE0E0 != 0);
+        self.set_flag(SFR_Z, value & 0xF0F0 != 0);
+        self.end_prefix();
+    }
+
+    /// FMULT keeps the high word of Sreg x R6; LMULT also puts the low word in R4
+    fn fmult(&mut self, cart: &mut SnesCartridge, alt1: bool) {
+        let result = ((self.sr() as i16 as i32) * (self.r[6] as i16 as i32)) as u32;
+        if alt1 {
+            self.r[4] = result as u16;
+        }
+        let value = (result >> 16) as u16;
+        self.set_dr(value);
+        self.set_flag(SFR_S, value & 0x8000 != 0);
+        self.set_flag(SFR_CY, result & 0x8000 != 0);
+        self.set_flag(SFR_Z, value == 0);
+        self.end_prefix();
+        let cycles = if self.cfgr & CFGR_MS0 != 0 { 3 } else { 7 };
+        self.step(cart, cycles * self.cycle());
+    }
+
+    // --- Plotting ---
+
+    fn color(&self, source: u8) -> u8 {
+        if self.por & POR_HIGH_NIBBLE != 0 {
+            (self.colr & 0xF0) | (source >> 4)
+        } else if self.por & POR_FREEZE_HIGH != 0 {
+            (self.colr & 0xF0) | (source & 0x0F)
+        } else {
+            source
+        }
+    }
+
+    fn bpp(&self) -> u32 {
+        match self.scmr & 0x03 {
+            0 => 2,
+            3 => 8,
+            _ => 4,
+        }
+    }
+
+    /// Address of row `y & 7` of the tile holding (x, y). Screen height
+    /// (SCMR HT bits) decides how tiles are numbered; OBJ mode lays the
+    /// screen out as four 128x128 sprite pages.
+    fn tile_row_address(&self, x: u8, y: u8) -> u32 {
+        let (x, y) = (x as u32, y as u32);
+        let height = ((self.scmr & 0x20) >> 4) | ((self.scmr & 0x04) >> 2);
+        let tile = match if self.por & POR_OBJ != 0 { 3 } else { height } {
+            0 => ((x & 0xF8) << 1) + ((y & 0xF8) >> 3),
+            1 => ((x & 0xF8) << 1) + ((x & 0xF8) >> 1) + ((y & 0xF8) >> 3),
+            2 => ((x & 0xF8) << 1) + (x & 0xF8) + ((y & 0xF8) >> 3),
+            _ => ((y & 0x80) << 2) + ((x & 0x80) << 1) + ((y & 0x78) << 1) + ((x & 0x78) >> 3),
+        };
+        0x700000 + tile * (self.bpp() << 3) + ((self.scbr as u32) << 10) + (y & 0x07) * 2
+    }
+
+    fn plot(&mut self, cart: &mut SnesCartridge, x: u8, y: u8) {
+        let mode = self.scmr & 0x03;
+        if self.por & POR_TRANSPARENT == 0 {
+            let transparent = if mode == 3 && self.por & POR_FREEZE_HIGH == 0 {
+                self.colr == 0
+            } else {
+                self.colr & 0x0F == 0
+            };
+            if transparent {
+                return;
+            }
+        }
+
+        let mut color = self.colr;
+        if self.por & POR_DITHER != 0 && mode != 3 {
+            if (x ^ y) & 1 != 0 {
+                color >>= 4;
+            }
+            color &= 0x0F;
+        }
+
+        let offset = ((y as u16) << 5) + (x as u16 >> 3);
+        if offset != self.pixel_cache[0].offset {
+            self.flush_pixel_cache(cart, 1);
+            self.pixel_cache[1] = self.pixel_cache[0];
+            self.pixel_cache[0].bitpend = 0;
+            self.pixel_cache[0].offset = offset;
+        }
+
+        let bit = (x & 7) ^ 7;
+        self.pixel_cache[0].data[bit as usize] = color;
+        self.pixel_cache[0].bitpend |= 1 << bit;
+        if self.pixel_cache[0].bitpend == 0xFF {
+            self.flush_pixel_cache(cart, 1);
+            self.pixel_cache[1] = self.pixel_cache[0];
+            self.pixel_cache[0].bitpend = 0;
+        }
+    }
+
+    fn rpix(&mut self, cart: &mut SnesCartridge, x: u8, y: u8) -> u8 {
+        self.flush_pixel_cache(cart, 1);
+        self.flush_pixel_cache(cart, 0);
+
+        let addr = self.tile_row_address(x, y);
+        let bit = (x & 7) ^ 7;
+        let mut value = 0;
+        for plane in 0..self.bpp() {
+            // Bitplane pairs are 16 bytes apart
+            let byte = ((plane >> 1) << 4) + (plane & 1);
+            self.step(cart, self.memory_cycles());
+            value |= ((self.read(cart, addr + byte) >> bit) & 1) << plane;
+        }
+        value
+    }
+
+    /// Write a cached row out as bitplanes, merging with RAM when partial
+    fn flush_pixel_cache(&mut self, cart: &mut SnesCartridge, index: usize) {
+        let cache = self.pixel_cache[index];
+        if cache.bitpend == 0 {
+            return;
+        }
+
+        let x = (cache.offset << 3) as u8;
+        let y = (cache.offset >> 5) as u8;
+        let addr = self.tile_row_address(x, y);
+        for plane in 0..self.bpp() {
+            let byte = ((plane >> 1) << 4) + (plane & 1);
+            let mut data = 0u8;
+            for (pixel, &color) in cache.data.iter().enumerate() {
+                data |= ((color >> plane) & 1) << pixel;
+            }
+            if cache.bitpend != 0xFF {
+                self.step(cart, self.memory_cycles());
+                data &= cache.bitpend;
+                data |= self.read(cart, addr + byte) & !cache.bitpend;
+            }
+            self.step(cart, self.memory_cycles());
+            self.write(cart, addr + byte, data);
+        }
+        self.pixel_cache[index].bitpend = 0;
+    }
+}
+
+impl Default for SuperFx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
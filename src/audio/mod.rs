@@ -1,18 +1,81 @@
-// Audio module - placeholder for future implementation
+// Audio module - queues core output to an SDL2 audio device
 
 use anyhow::Result;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+/// Rate asked of the device; SDL may hand back another
+const DEVICE_RATE: i32 = 48_000;
+/// Queued audio past this is dropped so latency can't build up
+const MAX_QUEUED_MS: u32 = 100;
 
 pub struct AudioOutput {
-    // TODO: SDL2 audio device
+    queue: AudioQueue<i16>,
+    /// Rate of the interleaved stereo passed to `queue_samples`
+    input_rate: u32,
+    /// Linear resampler: position between `last` and the next input frame
+    phase: f64,
+    last: [i16; 2],
+    buffer: Vec<i16>,
 }
 
 impl AudioOutput {
-    pub fn new() -> Result<Self> {
-        Ok(Self {})
+    pub fn new(audio: &AudioSubsystem, input_rate: u32) -> Result<Self> {
+        let desired = AudioSpecDesired {
+            freq: Some(DEVICE_RATE),
+            channels: Some(2),
+            samples: Some(1024),
+        };
+        let queue = audio
+            .open_queue::<i16, _>(None, &desired)
+            .map_err(|e| anyhow::anyhow!("Audio device failed: {}", e))?;
+        log::info!("Audio: {} Hz stereo, resampled from {} Hz", queue.spec().freq, input_rate);
+        queue.resume();
+
+        Ok(Self {
+            queue,
+            input_rate,
+            phase: 0.0,
+            last: [0; 2],
+            buffer: Vec::new(),
+        })
     }
-    
-    pub fn queue_samples(&mut self, _samples: &[i16]) -> Result<()> {
-        // TODO: Queue audio samples to SDL2 audio device
-        Ok(())
+
+    /// Queue interleaved stereo at the input rate
+    pub fn queue_samples(&mut self, samples: &[i16]) -> Result<()> {
+        if samples.is_empty() || self.input_rate == 0 {
+            return Ok(());
+        }
+
+        let device_rate = self.queue.spec().freq as u32;
+        let step = self.input_rate as f64 / device_rate as f64;
+        self.buffer.clear();
+        for frame in samples.chunks_exact(2) {
+            while self.phase < 1.0 {
+                for (last, &next) in self.last.iter().zip(frame) {
+                    let value = *last as f64 + (next as f64 - *last as f64) * self.phase;
+                    self.buffer.push(value as i16);
+                }
+                self.phase += step;
+            }
+            self.phase -= 1.0;
+            self.last = [frame[0], frame[1]];
+        }
+
+        // 2 channels of 2 bytes
+        let max_queued = device_rate * 4 * MAX_QUEUED_MS / 1000;
+        if self.queue.size() > max_queued {
+            return Ok(());
+        }
+        self.queue
+            .queue_audio(&self.buffer)
+            .map_err(|e| anyhow::anyhow!("Audio queue failed: {}", e))
+    }
+
+    /// Drop everything queued, e.g. on restart
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.phase = 0.0;
+        self.last = [0; 2];
     }
 }
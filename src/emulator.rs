@@ -1,10 +1,15 @@
 use anyhow::Result;
+use emu_common::cdl::CodeDataLogger;
 use std::path::Path;
 use crate::input_state::InputState;
 
 // Import emulator cores
 use nes_core::NES;
+use nes_core::controller::Buttons;
+use nes_core::ppu_viewer::PpuSnapshot;
+use nes_core::vs_system::VsConfig;
 use snes_core::SNES;
+use snes_core::controller::Buttons as SnesButtons;
 use genesis_core::Genesis;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -15,12 +20,76 @@ pub enum SystemType {
 }
 
 pub trait EmulatorCore {
+    fn load_rom(&mut self, data: &[u8]) -> Result<()>;
     fn reset(&mut self);
     fn run_frame(&mut self, input: &InputState) -> Result<()>;
     fn get_framebuffer(&self) -> &[u8];
+    /// Width and height of the buffer returned by `get_framebuffer`
+    fn frame_size(&self) -> (u32, u32);
     fn get_audio_samples(&mut self) -> &[i16];
+    /// Rate of the interleaved stereo from `get_audio_samples`
+    fn audio_sample_rate(&self) -> u32 {
+        44_100
+    }
     fn save_state(&self) -> Result<Vec<u8>>;
     fn load_state(&mut self, data: &[u8]) -> Result<()>;
+
+    /// Vs. System board settings; only meaningful for the NES core
+    fn configure_vs_system(&mut self, _config: VsConfig) {}
+
+    /// Super FX clock multiplier; only meaningful for the SNES core
+    fn set_superfx_overclock(&mut self, _factor: u32) {}
+
+    /// Mode 7 at `scale` times the resolution; only meaningful for the SNES core
+    fn set_mode7_hd(&mut self, _scale: u32, _perspective: bool) {}
+
+    /// Replace tiles with the HD pack in `dir`; NES only
+    fn load_hd_pack(&mut self, _dir: &Path) -> Result<()> {
+        anyhow::bail!("HD packs are only supported by the NES core")
+    }
+
+    /// Collect drawn tiles until `finish_tile_dump`; NES only
+    fn start_tile_dump(&mut self) -> Result<()> {
+        anyhow::bail!("Tile dumping is only supported by the NES core")
+    }
+
+    fn finish_tile_dump(&mut self, _dir: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Start a Code/Data Log of ROM usage
+    fn start_cdl(&mut self) -> Result<()> {
+        anyhow::bail!("Code/Data Logging is not supported by this core yet")
+    }
+
+    fn cdl(&self) -> Option<&CodeDataLogger> {
+        None
+    }
+
+    fn cdl_mut(&mut self) -> Option<&mut CodeDataLogger> {
+        None
+    }
+
+    /// Capture PPU memory for the debug viewer at `scanline`; NES only
+    fn set_viewer_scanline(&mut self, _scanline: Option<u16>) {}
+
+    fn ppu_snapshot(&self) -> Option<&PpuSnapshot> {
+        None
+    }
+
+    /// File name of the coprocessor firmware the loaded cartridge can use
+    fn firmware_name(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn load_firmware(&mut self, _data: &[u8]) -> Result<()> {
+        anyhow::bail!("This core does not use coprocessor firmware")
+    }
+
+    /// Attach an MSU-1 data file; its tracks are found beside it
+    fn load_msu1(&mut self, _data_path: &Path) -> Result<()> {
+        anyhow::bail!("This core does not support MSU-1")
+    }
 }
 
 pub struct Emulator {
@@ -48,11 +117,47 @@ impl Emulator {
         })
     }
     
-    pub fn load_rom(&mut self, _path: &Path) -> Result<()> {
-        // ROM loading will be implemented when cores are complete
+    pub fn load_rom(&mut self, path: &Path) -> Result<()> {
+        let rom_data = std::fs::read(path)?;
+        self.core.load_rom(&rom_data)?;
+
+        // Coprocessor firmware goes next to the ROM or in firmware/
+        if let Some(name) = self.core.firmware_name() {
+            let candidates = [path.with_file_name(name), Path::new("firmware").join(name)];
+            match candidates.iter().find(|candidate| candidate.is_file()) {
+                Some(firmware) => {
+                    log::info!("Loading coprocessor firmware {}", firmware.display());
+                    self.core.load_firmware(&std::fs::read(firmware)?)?;
+                }
+                None => log::info!("No {} found next to the ROM or in firmware/", name),
+            }
+        }
+
+        // MSU-1 media is game.msu plus game-N.pcm next to game.sfc
+        let msu = path.with_extension("msu");
+        if self.system_type == SystemType::SNES && msu.is_file() {
+            log::info!("Loading MSU-1 data {}", msu.display());
+            if let Err(e) = self.core.load_msu1(&msu) {
+                log::warn!("MSU-1 disabled: {}", e);
+            }
+        }
         Ok(())
     }
+
+    pub fn configure_vs_system(&mut self, config: VsConfig) {
+        if self.system_type == SystemType::NES {
+            self.core.configure_vs_system(config);
+        }
+    }
     
+    pub fn set_superfx_overclock(&mut self, factor: u32) {
+        self.core.set_superfx_overclock(factor);
+    }
+
+    pub fn set_mode7_hd(&mut self, scale: u32, perspective: bool) {
+        self.core.set_mode7_hd(scale, perspective);
+    }
+
     pub fn run_frame(&mut self, input: &InputState) -> Result<()> {
         self.core.run_frame(input)
     }
@@ -60,6 +165,61 @@ impl Emulator {
     pub fn get_framebuffer(&self) -> &[u8] {
         self.core.get_framebuffer()
     }
+
+    pub fn frame_size(&self) -> (u32, u32) {
+        self.core.frame_size()
+    }
+
+    pub fn get_audio_samples(&mut self) -> &[i16] {
+        self.core.get_audio_samples()
+    }
+
+    pub fn audio_sample_rate(&self) -> u32 {
+        self.core.audio_sample_rate()
+    }
+
+    pub fn load_hd_pack(&mut self, dir: &Path) -> Result<()> {
+        self.core.load_hd_pack(dir)
+    }
+
+    pub fn start_tile_dump(&mut self) -> Result<()> {
+        self.core.start_tile_dump()
+    }
+
+    pub fn finish_tile_dump(&mut self, dir: &Path) -> Result<()> {
+        self.core.finish_tile_dump(dir)
+    }
+
+    /// Start logging ROM usage, merging the log already at `path` if any
+    pub fn start_cdl(&mut self, path: &Path) -> Result<()> {
+        self.core.start_cdl()?;
+        if path.exists() {
+            let saved = std::fs::read(path)?;
+            if let Some(logger) = self.core.cdl_mut() {
+                logger.merge(&saved)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the Code/Data Log to `path`; returns the coverage report
+    pub fn save_cdl(&self, path: &Path) -> Result<String> {
+        let logger = self.core.cdl().ok_or_else(|| anyhow::anyhow!("Code/Data Logging is not running"))?;
+        std::fs::write(path, logger.to_bytes())?;
+        Ok(logger.report())
+    }
+
+    pub fn system_type(&self) -> SystemType {
+        self.system_type
+    }
+
+    pub fn set_viewer_scanline(&mut self, scanline: Option<u16>) {
+        self.core.set_viewer_scanline(scanline);
+    }
+
+    pub fn ppu_snapshot(&self) -> Option<&PpuSnapshot> {
+        self.core.ppu_snapshot()
+    }
     
     pub fn save_state(&self, path: &Path) -> Result<()> {
         let state_data = self.core.save_state()?;
@@ -85,25 +245,41 @@ impl NESCore {
             nes: NES::new(),
         }
     }
-    
-    fn load_rom(&mut self, data: &[u8]) -> Result<()> {
-        self.nes.load_rom(data)
-    }
 }
 
 impl EmulatorCore for NESCore {
+    fn load_rom(&mut self, data: &[u8]) -> Result<()> {
+        self.nes.load_rom(data)
+    }
+    
     fn reset(&mut self) {
         self.nes.reset();
     }
     
-    fn run_frame(&mut self, _input: &InputState) -> Result<()> {
+    fn run_frame(&mut self, input: &InputState) -> Result<()> {
+        let mut buttons = Buttons::empty();
+        buttons.set(Buttons::A, input.a);
+        buttons.set(Buttons::B, input.b);
+        buttons.set(Buttons::SELECT, input.select);
+        buttons.set(Buttons::START, input.start);
+        buttons.set(Buttons::UP, input.up);
+        buttons.set(Buttons::DOWN, input.down);
+        buttons.set(Buttons::LEFT, input.left);
+        buttons.set(Buttons::RIGHT, input.right);
+        self.nes.set_buttons(0, buttons);
+        self.nes.set_vs_inputs(input.coin1, input.coin2, input.service);
+        
         // Run one frame worth of emulation
         self.nes.run_frame();
         Ok(())
     }
     
     fn get_framebuffer(&self) -> &[u8] {
-        self.nes.ppu.get_framebuffer()
+        self.nes.get_framebuffer()
+    }
+
+    fn frame_size(&self) -> (u32, u32) {
+        self.nes.frame_size()
     }
     
     fn get_audio_samples(&mut self) -> &[i16] {
@@ -120,6 +296,49 @@ impl EmulatorCore for NESCore {
         // TODO: Deserialize emulator state
         Ok(())
     }
+    
+    fn configure_vs_system(&mut self, config: VsConfig) {
+        self.nes.configure_vs_system(config);
+    }
+
+    fn load_hd_pack(&mut self, dir: &Path) -> Result<()> {
+        let pack = crate::video::hd_pack::load(dir)?;
+        self.nes.load_hd_pack(pack);
+        Ok(())
+    }
+
+    fn start_tile_dump(&mut self) -> Result<()> {
+        self.nes.start_tile_dump();
+        Ok(())
+    }
+
+    fn finish_tile_dump(&mut self, dir: &Path) -> Result<()> {
+        if let Some((text, sheets)) = self.nes.finish_tile_dump() {
+            crate::video::hd_pack::save_dump(dir, &text, &sheets)?;
+        }
+        Ok(())
+    }
+
+    fn start_cdl(&mut self) -> Result<()> {
+        self.nes.start_cdl();
+        Ok(())
+    }
+
+    fn cdl(&self) -> Option<&CodeDataLogger> {
+        self.nes.bus.cdl.as_ref()
+    }
+
+    fn cdl_mut(&mut self) -> Option<&mut CodeDataLogger> {
+        self.nes.bus.cdl.as_mut()
+    }
+
+    fn set_viewer_scanline(&mut self, scanline: Option<u16>) {
+        self.nes.ppu.set_viewer_scanline(scanline);
+    }
+
+    fn ppu_snapshot(&self) -> Option<&PpuSnapshot> {
+        self.nes.ppu.snapshot()
+    }
 }
 
 struct SNESCore {
@@ -132,28 +351,52 @@ impl SNESCore {
             snes: SNES::new(),
         }
     }
-    
-    fn load_rom(&mut self, data: &[u8]) -> Result<()> {
-        self.snes.load_rom(data)
-    }
 }
 
 impl EmulatorCore for SNESCore {
+    fn load_rom(&mut self, data: &[u8]) -> Result<()> {
+        self.snes.load_rom(data)
+    }
+    
     fn reset(&mut self) {
         self.snes.reset();
     }
     
-    fn run_frame(&mut self, _input: &InputState) -> Result<()> {
+    fn run_frame(&mut self, input: &InputState) -> Result<()> {
+        let mut buttons = SnesButtons::empty();
+        buttons.set(SnesButtons::A, input.a);
+        buttons.set(SnesButtons::B, input.b);
+        buttons.set(SnesButtons::X, input.x);
+        buttons.set(SnesButtons::Y, input.y);
+        buttons.set(SnesButtons::L, input.l);
+        buttons.set(SnesButtons::R, input.r);
+        buttons.set(SnesButtons::SELECT, input.select);
+        buttons.set(SnesButtons::START, input.start);
+        buttons.set(SnesButtons::UP, input.up);
+        buttons.set(SnesButtons::DOWN, input.down);
+        buttons.set(SnesButtons::LEFT, input.left);
+        buttons.set(SnesButtons::RIGHT, input.right);
+        self.snes.set_buttons(0, buttons);
+
         self.snes.run_frame();
         Ok(())
     }
     
     fn get_framebuffer(&self) -> &[u8] {
-        self.snes.get_framebuffer()
+        self.snes.get_scaled_framebuffer()
+    }
+
+    fn frame_size(&self) -> (u32, u32) {
+        self.snes.scaled_frame_size()
     }
     
     fn get_audio_samples(&mut self) -> &[i16] {
-        &[]
+        // 32 kHz stereo, resampled by the audio output
+        self.snes.get_audio_samples()
+    }
+
+    fn audio_sample_rate(&self) -> u32 {
+        snes_core::dsp::SAMPLE_RATE
     }
     
     fn save_state(&self) -> Result<Vec<u8>> {
@@ -163,6 +406,26 @@ impl EmulatorCore for SNESCore {
     fn load_state(&mut self, _data: &[u8]) -> Result<()> {
         Ok(())
     }
+
+    fn set_superfx_overclock(&mut self, factor: u32) {
+        self.snes.set_superfx_overclock(factor);
+    }
+
+    fn set_mode7_hd(&mut self, scale: u32, perspective: bool) {
+        self.snes.set_mode7_hd(scale, perspective);
+    }
+
+    fn firmware_name(&self) -> Option<&'static str> {
+        self.snes.firmware_name()
+    }
+
+    fn load_firmware(&mut self, data: &[u8]) -> Result<()> {
+        self.snes.load_firmware(data)
+    }
+
+    fn load_msu1(&mut self, data_path: &Path) -> Result<()> {
+        self.snes.load_msu1(data_path)
+    }
 }
 
 struct GenesisCore {
@@ -175,13 +438,13 @@ impl GenesisCore {
             genesis: Genesis::new(),
         }
     }
-    
-    fn load_rom(&mut self, data: &[u8]) -> Result<()> {
-        self.genesis.load_rom(data)
-    }
 }
 
 impl EmulatorCore for GenesisCore {
+    fn load_rom(&mut self, data: &[u8]) -> Result<()> {
+        self.genesis.load_rom(data)
+    }
+    
     fn reset(&mut self) {
         self.genesis.reset();
     }
@@ -194,6 +457,10 @@ impl EmulatorCore for GenesisCore {
     fn get_framebuffer(&self) -> &[u8] {
         self.genesis.get_framebuffer()
     }
+
+    fn frame_size(&self) -> (u32, u32) {
+        (320, 224)
+    }
     
     fn get_audio_samples(&mut self) -> &[i16] {
         &[]
@@ -13,4 +13,8 @@ pub struct InputState {
     pub select: bool,
     pub l: bool,
     pub r: bool,
+    // Arcade cabinet inputs (Vs. System)
+    pub coin1: bool,
+    pub coin2: bool,
+    pub service: bool,
 }
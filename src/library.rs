@@ -2,6 +2,7 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::fs;
+use nes_core::vs_system::VsConfig;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Game {
@@ -11,6 +12,9 @@ pub struct Game {
     pub box_art_path: Option<PathBuf>,
     pub description: Option<String>,
     pub year: Option<u16>,
+    /// Vs. System PPU, DIP switches and wiring for arcade NES dumps
+    #[serde(default)]
+    pub vs_system: Option<VsConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -49,6 +53,10 @@ impl GameLibrary {
         self.games.iter().filter(|g| g.system == system).collect()
     }
 
+    pub fn find_by_rom_path(&self, rom_path: &Path) -> Option<&Game> {
+        self.games.iter().find(|g| g.rom_path == rom_path)
+    }
+
     pub fn scan_roms_directory(&mut self, roms_dir: &Path) -> Result<()> {
         if !roms_dir.exists() {
             fs::create_dir_all(roms_dir)?;
@@ -95,6 +103,7 @@ impl GameLibrary {
                         box_art_path: box_art,
                         description: None,
                         year: None,
+                        vs_system: None,
                     });
                 }
             }
@@ -1,8 +1,8 @@
 use anyhow::Result;
 use log::{info, warn};
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
 mod audio;
@@ -13,11 +13,14 @@ mod video;
 mod utils;
 mod library;
 mod launcher;
+mod spc_player;
 
 use emulator::{Emulator, SystemType};
 use input::ControllerManager;
 use input_state::InputState;
+use library::GameLibrary;
 use video::Renderer;
+use video::ppu_viewer::PpuViewer;
 
 /// Command line arguments
 #[derive(Debug)]
@@ -25,6 +28,13 @@ struct Args {
     system: Option<SystemType>,
     rom_path: Option<PathBuf>,
     state_path: Option<PathBuf>,
+    hd_pack: Option<PathBuf>,
+    dump_tiles: Option<PathBuf>,
+    cdl_path: Option<PathBuf>,
+    spc_path: Option<PathBuf>,
+    superfx_overclock: Option<u32>,
+    mode7_scale: Option<u32>,
+    mode7_perspective: bool,
     debug: bool,
     launcher_mode: bool,
 }
@@ -38,6 +48,13 @@ fn parse_args() -> Result<Args> {
             system: None,
             rom_path: None,
             state_path: None,
+            hd_pack: None,
+            dump_tiles: None,
+            cdl_path: None,
+            spc_path: None,
+            superfx_overclock: None,
+            mode7_scale: None,
+            mode7_perspective: false,
             debug: false,
             launcher_mode: true,
         });
@@ -46,6 +63,13 @@ fn parse_args() -> Result<Args> {
     let mut system = None;
     let mut rom_path = None;
     let mut state_path = None;
+    let mut hd_pack = None;
+    let mut dump_tiles = None;
+    let mut cdl_path = None;
+    let mut spc_path = None;
+    let mut superfx_overclock = None;
+    let mut mode7_scale = None;
+    let mut mode7_perspective = false;
     let mut debug = false;
     
     let mut i = 1;
@@ -68,6 +92,36 @@ fn parse_args() -> Result<Args> {
                 i += 1;
                 state_path = Some(PathBuf::from(&args[i]));
             }
+            "--hdpack" => {
+                i += 1;
+                hd_pack = Some(PathBuf::from(&args[i]));
+            }
+            "--dump-tiles" => {
+                i += 1;
+                dump_tiles = Some(PathBuf::from(&args[i]));
+            }
+            "--cdl" => {
+                i += 1;
+                cdl_path = Some(PathBuf::from(&args[i]));
+            }
+            "--spc" => {
+                i += 1;
+                spc_path = Some(PathBuf::from(&args[i]));
+            }
+            "--superfx-overclock" => {
+                i += 1;
+                superfx_overclock = Some(args[i].parse().map_err(|_| anyhow::anyhow!("Invalid Super FX overclock: {}", args[i]))?);
+            }
+            "--mode7-scale" => {
+                i += 1;
+                mode7_scale = match args[i].parse() {
+                    Ok(scale @ 1..=4) => Some(scale),
+                    _ => anyhow::bail!("Invalid Mode 7 scale (1-4): {}", args[i]),
+                };
+            }
+            "--mode7-perspective" => {
+                mode7_perspective = true;
+            }
             "--debug" => {
                 debug = true;
             }
@@ -76,6 +130,13 @@ fn parse_args() -> Result<Args> {
                     system: None,
                     rom_path: None,
                     state_path: None,
+                    hd_pack: None,
+                    dump_tiles: None,
+                    cdl_path: None,
+                    spc_path: None,
+                    superfx_overclock: None,
+                    mode7_scale: None,
+                    mode7_perspective: false,
                     debug,
                     launcher_mode: true,
                 });
@@ -85,8 +146,26 @@ fn parse_args() -> Result<Args> {
         i += 1;
     }
     
+    // SPC files play without a system or ROM
+    if spc_path.is_some() {
+        return Ok(Args {
+            system: None,
+            rom_path: None,
+            state_path: None,
+            hd_pack: None,
+            dump_tiles: None,
+            cdl_path: None,
+            spc_path,
+            superfx_overclock: None,
+            mode7_scale: None,
+            mode7_perspective: false,
+            debug,
+            launcher_mode: false,
+        });
+    }
+    
     if rom_path.is_none() || system.is_none() {
-        anyhow::bail!("Usage: {} --system <nes|snes|genesis> --rom <path>", args[0]);
+        anyhow::bail!("Usage: {} --system <nes|snes|genesis> --rom <path> | --spc <path>", args[0]);
     }
     
     let rom = rom_path.unwrap();
@@ -98,6 +177,13 @@ fn parse_args() -> Result<Args> {
         system,
         rom_path: Some(rom),
         state_path,
+        hd_pack,
+        dump_tiles,
+        cdl_path,
+        spc_path: None,
+        superfx_overclock,
+        mode7_scale,
+        mode7_perspective,
         debug,
         launcher_mode: false,
     })
@@ -119,13 +205,17 @@ fn main() -> Result<()> {
         return launch_gui();
     }
     
+    if let Some(path) = args.spc_path {
+        return launch_spc_player(path);
+    }
+    
     // Otherwise launch emulator directly
     let system = args.system.unwrap();
-    let rom_path = args.rom_path.unwrap();
+    let rom_path = args.rom_path.clone().unwrap();
     
     info!("System: {:?}, ROM: {:?}", system, rom_path);
     
-    run_emulator(system, rom_path, args.state_path)
+    run_emulator(system, rom_path, &args)
 }
 
 fn launch_gui() -> Result<()> {
@@ -147,12 +237,40 @@ fn launch_gui() -> Result<()> {
     Ok(())
 }
 
-fn run_emulator(system: SystemType, rom_path: PathBuf, state_path: Option<PathBuf>) -> Result<()> {
+fn launch_spc_player(path: PathBuf) -> Result<()> {
+    use snes_core::spc_file::SpcPlayer;
+    use spc_player::SpcPlayerApp;
+    
+    let player = SpcPlayer::load(&std::fs::read(&path)?)?;
+    let sdl_context = sdl2::init().map_err(|e| anyhow::anyhow!("SDL2 init failed: {}", e))?;
+    let audio_subsystem = sdl_context.audio().map_err(|e| anyhow::anyhow!("Audio init failed: {}", e))?;
+    let audio = audio::AudioOutput::new(&audio_subsystem, snes_core::dsp::SAMPLE_RATE)?;
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    info!("Playing SPC: {:?}", path);
+    
+    let native_options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_inner_size([480.0, 360.0])
+            .with_title(format!("RetroBlazeEmulator - {}", file_name)),
+        ..Default::default()
+    };
+    
+    eframe::run_native(
+        "RetroBlazeEmulator SPC Player",
+        native_options,
+        Box::new(|cc| Box::new(SpcPlayerApp::new(cc, player, audio, file_name))),
+    ).map_err(|e| anyhow::anyhow!("GUI error: {}", e))?;
+    
+    Ok(())
+}
+
+fn run_emulator(system: SystemType, rom_path: PathBuf, args: &Args) -> Result<()> {
+    let state_path = args.state_path.clone();
     
     // Initialize SDL2
     let sdl_context = sdl2::init().map_err(|e| anyhow::anyhow!("SDL2 init failed: {}", e))?;
     let video_subsystem = sdl_context.video().map_err(|e| anyhow::anyhow!("Video init failed: {}", e))?;
-    let _audio_subsystem = sdl_context.audio().map_err(|e| anyhow::anyhow!("Audio init failed: {}", e))?;
+    let audio_subsystem = sdl_context.audio().map_err(|e| anyhow::anyhow!("Audio init failed: {}", e))?;
     
     // Create window
     let window_title = format!("RetroBlazeEmulator - {}", rom_path.file_name().unwrap().to_string_lossy());
@@ -162,6 +280,8 @@ fn run_emulator(system: SystemType, rom_path: PathBuf, state_path: Option<PathBu
         .resizable()
         .build()?;
     
+    let main_window_id = window.id();
+    
     // Initialize renderer
     let mut renderer = Renderer::new(window)?;
     
@@ -173,17 +293,80 @@ fn run_emulator(system: SystemType, rom_path: PathBuf, state_path: Option<PathBu
     let mut emulator = Emulator::new(system)?;
     emulator.load_rom(&rom_path)?;
     
+    // Per-game settings from the launcher's library (Vs. System boards)
+    if let Ok(library) = GameLibrary::load_from_file(Path::new("game_library.json")) {
+        if let Some(vs_config) = library.find_by_rom_path(&rom_path).and_then(|g| g.vs_system) {
+            emulator.configure_vs_system(vs_config);
+        }
+    }
+    
+    if let Some(factor) = args.superfx_overclock {
+        info!("Super FX overclocked {}x", factor);
+        emulator.set_superfx_overclock(factor);
+    }
+
+    // Perspective correction needs sub-lines to interpolate across
+    let mode7_scale = args.mode7_scale.or(args.mode7_perspective.then_some(2));
+    if let Some(scale) = mode7_scale.filter(|&scale| scale > 1) {
+        info!("Mode 7 at {}x{}", scale, if args.mode7_perspective { ", perspective correct" } else { "" });
+        emulator.set_mode7_hd(scale, args.mode7_perspective);
+    }
+    
+    // HD pack: --hdpack, or hdpacks/<rom name>/ when it exists
+    let hd_pack_dir = args.hd_pack.clone().or_else(|| {
+        let dir = Path::new("hdpacks").join(rom_path.file_stem()?);
+        dir.join("hires.txt").exists().then_some(dir)
+    });
+    if let Some(dir) = &hd_pack_dir {
+        info!("Loading HD pack: {:?}", dir);
+        if let Err(e) = emulator.load_hd_pack(dir) {
+            warn!("Failed to load HD pack: {:#}", e);
+        }
+    }
+    if args.dump_tiles.is_some() {
+        emulator.start_tile_dump()?;
+    }
+    if let Some(path) = &args.cdl_path {
+        info!("Code/Data Logging to {:?}", path);
+        emulator.start_cdl(path)?;
+    }
+    
+    // PPU viewer window (--debug, NES only)
+    let mut ppu_viewer = None;
+    if args.debug {
+        if emulator.system_type() == SystemType::NES {
+            let viewer = PpuViewer::new(&video_subsystem)?;
+            emulator.set_viewer_scanline(Some(viewer.scanline));
+            ppu_viewer = Some(viewer);
+        } else {
+            warn!("The PPU viewer is only available for the NES");
+        }
+    }
+    
     if let Some(ref save_state_path) = state_path {
         info!("Loading save state: {:?}", save_state_path);
         emulator.load_state(save_state_path)?;
     }
     
+    // Keep running silently if there is no usable audio device
+    let mut audio = match audio::AudioOutput::new(&audio_subsystem, emulator.audio_sample_rate()) {
+        Ok(audio) => Some(audio),
+        Err(e) => {
+            warn!("No audio output: {}", e);
+            None
+        }
+    };
+    
     info!("✅ Emulator initialized successfully!");
     info!("Controls:");
     info!("  ESC - Quit");
     info!("  F5 - Save State");
     info!("  F9 - Load State");
     info!("  F11 - Toggle Fullscreen");
+    info!("  5/6 - Insert Coin (Vs. System), 9 - Service");
+    if ppu_viewer.is_some() {
+        info!("  PPU Viewer: Up/Down - Capture scanline, Left/Right - Palette, Click - Tile addresses");
+    }
     info!("  PS4 Controller - Auto-detected if connected");
     
     // Main loop
@@ -196,17 +379,34 @@ fn run_emulator(system: SystemType, rom_path: PathBuf, state_path: Option<PathBu
     let mut frame_count = 0u64;
     let mut last_fps_time = Instant::now();
     let mut _fps = 0.0;
-    let debug = false;
+    let debug = args.debug;
+    let mut arcade_keys = InputState::default();
     
     while running {
         let frame_start = Instant::now();
         
         // Handle events
         for event in event_pump.poll_iter() {
+            // Events for the PPU viewer window stay there
+            if let Some(viewer) = &mut ppu_viewer {
+                if event.get_window_id() == Some(viewer.window_id()) {
+                    if let Event::Window { win_event: WindowEvent::Close, .. } = event {
+                        ppu_viewer = None;
+                        emulator.set_viewer_scanline(None);
+                    } else if viewer.handle_event(&event, emulator.ppu_snapshot()) {
+                        emulator.set_viewer_scanline(Some(viewer.scanline));
+                    }
+                    continue;
+                }
+            }
+            
             match event {
                 Event::Quit { .. } => {
                     running = false;
                 }
+                Event::Window { win_event: WindowEvent::Close, window_id, .. } if window_id == main_window_id => {
+                    running = false;
+                }
                 Event::KeyDown { keycode: Some(key), .. } => {
                     match key {
                         Keycode::Escape => running = false,
@@ -235,6 +435,17 @@ fn run_emulator(system: SystemType, rom_path: PathBuf, state_path: Option<PathBu
                             paused = !paused;
                             info!("{}", if paused { "⏸ Paused" } else { "▶ Resumed" });
                         }
+                        Keycode::Num5 => arcade_keys.coin1 = true,
+                        Keycode::Num6 => arcade_keys.coin2 = true,
+                        Keycode::Num9 => arcade_keys.service = true,
+                        _ => {}
+                    }
+                }
+                Event::KeyUp { keycode: Some(key), .. } => {
+                    match key {
+                        Keycode::Num5 => arcade_keys.coin1 = false,
+                        Keycode::Num6 => arcade_keys.coin2 = false,
+                        Keycode::Num9 => arcade_keys.service = false,
                         _ => {}
                     }
                 }
@@ -244,16 +455,27 @@ fn run_emulator(system: SystemType, rom_path: PathBuf, state_path: Option<PathBu
         
         // Update controller input
         controller_manager.update();
-        let input_state = controller_manager.get_state();
+        let mut input_state = controller_manager.get_state();
+        input_state.coin1 |= arcade_keys.coin1;
+        input_state.coin2 |= arcade_keys.coin2;
+        input_state.service |= arcade_keys.service;
         
         // Run emulation frame
         if !paused {
             emulator.run_frame(&input_state)?;
+            if let Some(audio) = &mut audio {
+                if let Err(e) = audio.queue_samples(emulator.get_audio_samples()) {
+                    warn!("Audio output failed: {}", e);
+                }
+            }
         }
         
         // Render
-        let framebuffer = emulator.get_framebuffer();
-        renderer.render(framebuffer)?;
+        let (width, height) = emulator.frame_size();
+        renderer.render(emulator.get_framebuffer(), width, height)?;
+        if let (Some(viewer), Some(snapshot)) = (&mut ppu_viewer, emulator.ppu_snapshot()) {
+            viewer.render(snapshot)?;
+        }
         
         // FPS counter
         frame_count += 1;
@@ -273,6 +495,16 @@ fn run_emulator(system: SystemType, rom_path: PathBuf, state_path: Option<PathBu
         }
     }
     
+    if let Some(dir) = &args.dump_tiles {
+        emulator.finish_tile_dump(dir)?;
+        info!("Tile dump written to {:?}", dir);
+    }
+    
+    if let Some(path) = &args.cdl_path {
+        let report = emulator.save_cdl(path)?;
+        info!("Code/Data Log written to {:?}\n{}", path, report);
+    }
+    
     info!("👋 Shutting down...");
     Ok(())
 }
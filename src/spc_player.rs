@@ -0,0 +1,145 @@
+use eframe::egui;
+use egui::{Color32, RichText};
+use snes_core::spc_file::SpcPlayer;
+use std::time::{Duration, Instant};
+use crate::audio::AudioOutput;
+
+/// The player steps the APU in NTSC frames, like the emulator loop
+const FRAME_DURATION: Duration = Duration::from_nanos(16_639_267);
+
+pub struct SpcPlayerApp {
+    player: SpcPlayer,
+    audio: AudioOutput,
+    file_name: String,
+    paused: bool,
+    last_update: Instant,
+    /// Wall-clock time the APU has yet to catch up on
+    owed: Duration,
+}
+
+impl SpcPlayerApp {
+    pub fn new(_cc: &eframe::CreationContext<'_>, player: SpcPlayer, audio: AudioOutput, file_name: String) -> Self {
+        Self {
+            player,
+            audio,
+            file_name,
+            paused: false,
+            last_update: Instant::now(),
+            owed: Duration::ZERO,
+        }
+    }
+
+    fn run(&mut self) {
+        let now = Instant::now();
+        if !self.paused {
+            self.owed += now - self.last_update;
+            while self.owed >= FRAME_DURATION {
+                self.owed -= FRAME_DURATION;
+                self.player.run_frame();
+                if let Err(e) = self.audio.queue_samples(self.player.samples()) {
+                    log::warn!("Audio output failed: {}", e);
+                }
+            }
+        }
+        self.last_update = now;
+    }
+
+    fn render_track_info(&self, ui: &mut egui::Ui) {
+        let tags = self.player.tags();
+        let title = if tags.title.is_empty() { &self.file_name } else { &tags.title };
+        ui.heading(RichText::new(title).size(24.0));
+        ui.add_space(5.0);
+
+        egui::Grid::new("track_info").num_columns(2).spacing([20.0, 4.0]).show(ui, |ui| {
+            let mut row = |label: &str, value: String| {
+                if !value.is_empty() {
+                    ui.label(RichText::new(label).color(Color32::GRAY));
+                    ui.label(value);
+                    ui.end_row();
+                }
+            };
+            row("Game", tags.game.clone());
+            row("Artist", tags.artist.clone());
+            row("Soundtrack", match tags.track {
+                Some(track) => format!("{} #{}", tags.ost_title, track),
+                None => tags.ost_title.clone(),
+            });
+            row("Publisher", tags.publisher.clone());
+            row("Dumper", tags.dumper.clone());
+            row("Date", tags.date.clone());
+            row("Comments", tags.comments.clone());
+            row("Length", format!("{} + {:.1}s fade",
+                format_time(self.player.length_ms()), self.player.fade_ms() as f32 / 1000.0));
+        });
+    }
+
+    fn render_transport(&mut self, ui: &mut egui::Ui) {
+        let position = self.player.position_ms();
+        let total = self.player.length_ms().saturating_add(self.player.fade_ms());
+        let text = if self.player.looping {
+            format!("{} (looping)", format_time(position))
+        } else {
+            format!("{} / {}", format_time(position), format_time(total))
+        };
+        let progress = if self.player.looping { 0.0 } else { position as f32 / total as f32 };
+        ui.add(egui::ProgressBar::new(progress.min(1.0)).text(text));
+        ui.add_space(5.0);
+
+        ui.horizontal(|ui| {
+            let label = if self.paused { "▶ Play" } else { "⏸ Pause" };
+            if ui.button(label).clicked() {
+                self.paused = !self.paused;
+            }
+            if ui.button("⏮ Restart").clicked() {
+                self.player.restart();
+                self.audio.clear();
+                self.owed = Duration::ZERO;
+            }
+            ui.checkbox(&mut self.player.looping, "Loop");
+            if self.player.finished() {
+                ui.label(RichText::new("Finished").color(Color32::GRAY));
+            }
+        });
+    }
+
+    /// One toggle per S-DSP voice; lit voices are audible
+    fn render_voice_toolbar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Voices:");
+            let mut mute = self.player.voice_mute();
+            for voice in 0..8 {
+                let bit = 1 << voice;
+                let audible = mute & bit == 0;
+                if ui.selectable_label(audible, format!(" {} ", voice + 1)).clicked() {
+                    mute ^= bit;
+                }
+            }
+            if ui.button("All").clicked() {
+                mute = 0;
+            }
+            self.player.set_voice_mute(mute);
+        });
+    }
+}
+
+impl eframe::App for SpcPlayerApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.run();
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.add_space(10.0);
+            self.render_track_info(ui);
+            ui.separator();
+            self.render_transport(ui);
+            ui.separator();
+            self.render_voice_toolbar(ui);
+        });
+
+        ctx.request_repaint_after(FRAME_DURATION);
+    }
+}
+
+fn format_time(ms: u32) -> String {
+    let secs = ms / 1000;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
@@ -0,0 +1,38 @@
+// HD pack files on disk: PNG decoding and tile dump output for the NES core
+
+use anyhow::{Context, Result};
+use nes_core::hd_pack::{HdImage, HdPack};
+use std::path::Path;
+
+/// Load `<dir>/hires.txt` and the images it references
+pub fn load(dir: &Path) -> Result<HdPack> {
+    let text = std::fs::read_to_string(dir.join("hires.txt"))
+        .with_context(|| format!("No hires.txt in {:?}", dir))?;
+
+    HdPack::parse(&text, |file| {
+        let image = image::open(dir.join(file))
+            .with_context(|| format!("Failed to load HD pack image {}", file))?
+            .to_rgba8();
+        Ok(HdImage {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.into_raw(),
+        })
+    })
+}
+
+/// Write a tile dump as a starter pack: `hires.txt` plus `tiles_N.png`
+pub fn save_dump(dir: &Path, text: &str, sheets: &[HdImage]) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(dir.join("hires.txt"), text)?;
+    for (i, sheet) in sheets.iter().enumerate() {
+        image::save_buffer(
+            dir.join(format!("tiles_{}.png", i)),
+            &sheet.pixels,
+            sheet.width,
+            sheet.height,
+            image::ColorType::Rgba8,
+        )?;
+    }
+    Ok(())
+}
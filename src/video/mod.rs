@@ -1,3 +1,6 @@
+pub mod hd_pack;
+pub mod ppu_viewer;
+
 use anyhow::Result;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
@@ -29,14 +32,12 @@ impl Renderer {
         })
     }
     
-    pub fn render(&mut self, framebuffer: &[u8]) -> Result<()> {
-        // Determine framebuffer dimensions
-        let (width, height) = match framebuffer.len() {
-            246_784 => (256, 240),  // NES: 256x240x4
-            229_376 => (256, 224),  // SNES: 256x224x4
-            286_720 => (320, 224),  // Genesis: 320x224x4
-            _ => (256, 240),        // Default to NES
-        };
+    pub fn render(&mut self, framebuffer: &[u8], width: u32, height: u32) -> Result<()> {
+        if (width, height) != (self.current_width, self.current_height) {
+            log::info!("Output resolution {}x{}", width, height);
+            self.current_width = width;
+            self.current_height = height;
+        }
         
         // Create texture for this frame
         let texture_creator = self.canvas.texture_creator();
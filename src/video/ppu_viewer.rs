@@ -0,0 +1,195 @@
+// `--debug` PPU viewer window for the NES core
+//
+// Layout (window pixels, scaled with the window):
+// - nametables 512x480 at the left, viewport outlined
+// - pattern tables 0 and 1 side by side at the top right
+// - the 64 OAM entries below them, 8 per row
+// - palette RAM next to the sprites, selected palette outlined
+//
+// Up/Down move the capture scanline, Left/Right pick the pattern table
+// palette, clicking shows the addresses behind a tile.
+
+use anyhow::Result;
+use nes_core::ppu_viewer::{PpuSnapshot, NAMETABLES_HEIGHT, NAMETABLES_WIDTH, PATTERN_TABLE_SIZE};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+
+const WIDTH: usize = NAMETABLES_WIDTH + 2 * PATTERN_TABLE_SIZE;
+const HEIGHT: usize = NAMETABLES_HEIGHT;
+
+const PATTERN_X: usize = NAMETABLES_WIDTH;
+const SPRITES_X: usize = NAMETABLES_WIDTH;
+const SPRITES_Y: usize = PATTERN_TABLE_SIZE + 8;
+const SPRITE_CELL_W: usize = 16;
+const SPRITE_CELL_H: usize = 24;
+const PALETTE_X: usize = NAMETABLES_WIDTH + PATTERN_TABLE_SIZE;
+const PALETTE_Y: usize = SPRITES_Y;
+const PALETTE_CELL: usize = 16;
+
+/// Default capture point: the first vblank line, after the frame is drawn
+pub const DEFAULT_SCANLINE: u16 = 241;
+
+pub struct PpuViewer {
+    canvas: Canvas<Window>,
+    pixels: Vec<u8>,
+    pub scanline: u16,
+    palette: usize,
+}
+
+impl PpuViewer {
+    pub fn new(video: &VideoSubsystem) -> Result<Self> {
+        let window = video
+            .window("PPU Viewer", WIDTH as u32 * 2, HEIGHT as u32 * 2)
+            .resizable()
+            .build()?;
+        let mut canvas = window.into_canvas().accelerated().build()?;
+        // Mouse coordinates then arrive in layout pixels
+        canvas.set_logical_size(WIDTH as u32, HEIGHT as u32)?;
+
+        let mut viewer = Self {
+            canvas,
+            pixels: vec![0; WIDTH * HEIGHT * 4],
+            scanline: DEFAULT_SCANLINE,
+            palette: 0,
+        };
+        viewer.update_title(None);
+        Ok(viewer)
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    /// Handle an event aimed at the viewer window. Returns true when the
+    /// capture scanline changed.
+    pub fn handle_event(&mut self, event: &Event, snapshot: Option<&PpuSnapshot>) -> bool {
+        match event {
+            Event::KeyDown { keycode: Some(key), .. } => {
+                match *key {
+                    Keycode::Up => self.scanline = (self.scanline + 261) % 262,
+                    Keycode::Down => self.scanline = (self.scanline + 1) % 262,
+                    Keycode::Left => self.palette = (self.palette + 7) % 8,
+                    Keycode::Right => self.palette = (self.palette + 1) % 8,
+                    _ => return false,
+                }
+                self.update_title(None);
+                matches!(*key, Keycode::Up | Keycode::Down)
+            }
+            Event::MouseButtonDown { x, y, .. } => {
+                if let Some(snapshot) = snapshot {
+                    if let Some(text) = Self::describe(snapshot, *x as usize, *y as usize) {
+                        log::info!("{}", text);
+                        self.update_title(Some(&text));
+                    }
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn describe(snapshot: &PpuSnapshot, x: usize, y: usize) -> Option<String> {
+        if x < NAMETABLES_WIDTH && y < NAMETABLES_HEIGHT {
+            return Some(snapshot.describe_nametable(x, y));
+        }
+        if (PATTERN_X..PATTERN_X + 2 * PATTERN_TABLE_SIZE).contains(&x) && y < PATTERN_TABLE_SIZE {
+            let table = (x - PATTERN_X) / PATTERN_TABLE_SIZE;
+            let tile = (y / 8) * 16 + ((x - PATTERN_X) % PATTERN_TABLE_SIZE) / 8;
+            return Some(snapshot.describe_pattern(table, tile));
+        }
+        if (SPRITES_X..SPRITES_X + 8 * SPRITE_CELL_W).contains(&x)
+            && (SPRITES_Y..SPRITES_Y + 8 * SPRITE_CELL_H).contains(&y)
+        {
+            let index = ((y - SPRITES_Y) / SPRITE_CELL_H) * 8 + (x - SPRITES_X) / SPRITE_CELL_W;
+            return Some(snapshot.describe_sprite(index));
+        }
+        if (PALETTE_X..PALETTE_X + 8 * PALETTE_CELL).contains(&x)
+            && (PALETTE_Y..PALETTE_Y + 4 * PALETTE_CELL).contains(&y)
+        {
+            let index = ((y - PALETTE_Y) / PALETTE_CELL) * 8 + (x - PALETTE_X) / PALETTE_CELL;
+            return Some(snapshot.describe_palette(index));
+        }
+        None
+    }
+
+    fn update_title(&mut self, selection: Option<&str>) {
+        let mut title = format!("PPU Viewer - scanline {}, palette {}", self.scanline, self.palette);
+        if let Some(selection) = selection {
+            title = format!("{} - {}", title, selection);
+        }
+        // Only fails on interior NULs, which the formatted text never has
+        let _ = self.canvas.window_mut().set_title(&title);
+    }
+
+    pub fn render(&mut self, snapshot: &PpuSnapshot) -> Result<()> {
+        self.pixels.fill(0);
+
+        self.blit(&snapshot.nametable_image(), NAMETABLES_WIDTH, 0, 0);
+        for table in 0..2 {
+            let image = snapshot.pattern_table(table, self.palette);
+            self.blit(&image, PATTERN_TABLE_SIZE, PATTERN_X + table * PATTERN_TABLE_SIZE, 0);
+        }
+        for index in 0..64 {
+            let x = SPRITES_X + (index % 8) * SPRITE_CELL_W + 4;
+            let y = SPRITES_Y + (index / 8) * SPRITE_CELL_H + 4;
+            self.blit(&snapshot.sprite_image(index), 8, x, y);
+        }
+        for (index, rgb) in snapshot.palette_colors().iter().enumerate() {
+            let x = PALETTE_X + (index % 8) * PALETTE_CELL;
+            let y = PALETTE_Y + (index / 8) * PALETTE_CELL;
+            self.fill_rect(x + 1, y + 1, PALETTE_CELL - 2, PALETTE_CELL - 2, *rgb);
+        }
+
+        // Outline the palette used for the pattern tables
+        let x = PALETTE_X + (self.palette % 2) * 4 * PALETTE_CELL;
+        let y = PALETTE_Y + (self.palette / 2) * PALETTE_CELL;
+        self.outline(x, y, 4 * PALETTE_CELL, PALETTE_CELL, [255, 255, 255]);
+
+        let texture_creator = self.canvas.texture_creator();
+        let mut texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGBA32, WIDTH as u32, HEIGHT as u32)?;
+        texture.update(None, &self.pixels, WIDTH * 4)
+            .map_err(|e| anyhow::anyhow!("Texture update failed: {}", e))?;
+
+        self.canvas.clear();
+        self.canvas.copy(&texture, None, None)
+            .map_err(|e| anyhow::anyhow!("Failed to copy texture: {}", e))?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn blit(&mut self, image: &[u8], width: usize, x: usize, y: usize) {
+        for (row, line) in image.chunks(width * 4).enumerate() {
+            let start = ((y + row) * WIDTH + x) * 4;
+            self.pixels[start..start + line.len()].copy_from_slice(line);
+        }
+    }
+
+    fn fill_rect(&mut self, x: usize, y: usize, w: usize, h: usize, rgb: [u8; 3]) {
+        for py in y..y + h {
+            for px in x..x + w {
+                self.put(px, py, rgb);
+            }
+        }
+    }
+
+    fn outline(&mut self, x: usize, y: usize, w: usize, h: usize, rgb: [u8; 3]) {
+        for px in x..x + w {
+            self.put(px, y, rgb);
+            self.put(px, y + h - 1, rgb);
+        }
+        for py in y..y + h {
+            self.put(x, py, rgb);
+            self.put(x + w - 1, py, rgb);
+        }
+    }
+
+    fn put(&mut self, x: usize, y: usize, rgb: [u8; 3]) {
+        let i = (y * WIDTH + x) * 4;
+        self.pixels[i..i + 4].copy_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+    }
+}